@@ -76,7 +76,7 @@ fn run() -> Result<(), Box<dyn std::error::Error>> {
         controller
             .lock()
             .unwrap()
-            .dispatch_task(ChatTask::Send(bot_id.clone()));
+            .dispatch_task(ChatTask::Send(bot_id.clone(), SendOptions::default()));
 
         while let Ok(event) = rx.recv() {
             match event {
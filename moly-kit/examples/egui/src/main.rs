@@ -1,7 +1,7 @@
 use std::sync::{Arc, Mutex};
 
 use moly_kit::{
-    BotId, EntityId, Message, OpenAIClient,
+    BotId, EntityId, Message, OpenAIClient, SendOptions,
     controllers::chat::{
         ChatController, ChatControllerPlugin, ChatState, ChatStateMutation, ChatTask,
     },
@@ -100,7 +100,8 @@ impl eframe::App for App {
                     message.content.text = prompt;
 
                     controller.dispatch_mutation(VecMutation::Push(message));
-                    controller.dispatch_task(ChatTask::Send(self.bot_id.clone()));
+                    controller
+                        .dispatch_task(ChatTask::Send(self.bot_id.clone(), SendOptions::default()));
                 }
             })
         });
@@ -4,6 +4,9 @@ cfg_if::cfg_if! {
         pub mod openai;
         pub use openai::OpenAIClient;
 
+        pub mod anthropic;
+        pub use anthropic::AnthropicClient;
+
         pub mod openai_image;
         pub use openai_image::OpenAIImageClient;
 
@@ -21,5 +24,8 @@ pub mod multi;
 pub use map::*;
 pub mod map;
 
+pub use retry::*;
+pub mod retry;
+
 pub use tester_client::*;
 pub mod tester_client;
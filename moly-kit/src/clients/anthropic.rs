@@ -0,0 +1,1297 @@
+//! Native client for the Anthropic Messages API.
+
+use crate::protocol::Tool;
+use async_stream::stream;
+use makepad_widgets::*;
+use reqwest::header::{HeaderMap, HeaderName};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use crate::utils::asynchronous::{BoxPlatformSendFuture, BoxPlatformSendStream};
+use crate::utils::sse::parse_sse;
+use crate::{
+    protocol::*,
+    utils::errors::{enrich_http_error, parse_retry_after},
+};
+
+/// The version of the Messages API this client speaks.
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Anthropic requires `max_tokens` on every request.
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// Smallest thinking budget accepted by the API.
+const MIN_THINKING_BUDGET: u32 = 1024;
+
+/// Largest page size accepted by the models endpoint.
+const MODELS_PAGE_SIZE: u32 = 1000;
+
+/// A model from the models endpoint.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+struct Model {
+    id: String,
+}
+
+/// A page of the response from the models endpoint.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+struct Models {
+    pub data: Vec<Model>,
+    #[serde(default)]
+    pub has_more: bool,
+    #[serde(default)]
+    pub last_id: Option<String>,
+}
+
+/// Role of a message in the Messages API.
+///
+/// There is no `system` role, the system prompt is a top-level field instead.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Role {
+    User,
+    Assistant,
+}
+
+/// Base64 source of an image or document block.
+#[derive(Clone, Debug, Serialize)]
+struct Base64Source {
+    #[serde(rename = "type")]
+    source_type: &'static str,
+    media_type: String,
+    data: String,
+}
+
+impl Base64Source {
+    fn new(media_type: &str, data: String) -> Self {
+        Self {
+            source_type: "base64",
+            media_type: media_type.to_string(),
+            data,
+        }
+    }
+}
+
+/// A content block being sent to the Messages API.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum OutgoingContentBlock {
+    Text {
+        text: String,
+    },
+    Image {
+        source: Base64Source,
+    },
+    Document {
+        source: Base64Source,
+        title: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        /// Only text and image blocks are accepted here.
+        content: Vec<OutgoingContentBlock>,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
+}
+
+/// A thinking block of a response, that must be sent back unchanged with the
+/// assistant turn it belongs to, so the API can verify its signature.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum ThinkingBlock {
+    Thinking { thinking: String, signature: String },
+    RedactedThinking { data: String },
+}
+
+impl From<ThinkingBlock> for OutgoingContentBlock {
+    fn from(block: ThinkingBlock) -> Self {
+        match block {
+            ThinkingBlock::Thinking {
+                thinking,
+                signature,
+            } => OutgoingContentBlock::Thinking {
+                thinking,
+                signature,
+            },
+            ThinkingBlock::RedactedThinking { data } => {
+                OutgoingContentBlock::RedactedThinking { data }
+            }
+        }
+    }
+}
+
+/// What this client keeps in [`MessageContent::data`].
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct MessageData {
+    thinking_blocks: Vec<ThinkingBlock>,
+}
+
+/// A message being sent to the Messages API.
+#[derive(Clone, Debug, Serialize)]
+struct OutgoingMessage {
+    role: Role,
+    content: Vec<OutgoingContentBlock>,
+}
+
+/// Tool definition for the Messages API.
+#[derive(Serialize)]
+struct AnthropicTool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+impl From<&Tool> for AnthropicTool {
+    fn from(tool: &Tool) -> Self {
+        let mut input_schema = (*tool.input_schema).clone();
+
+        // Anthropic rejects schemas without an explicit object type.
+        input_schema
+            .entry("type")
+            .or_insert_with(|| serde_json::Value::String("object".to_string()));
+
+        AnthropicTool {
+            name: tool.name.clone(),
+            description: tool.description.as_deref().unwrap_or("").to_string(),
+            input_schema: serde_json::Value::Object(input_schema),
+        }
+    }
+}
+
+/// A content block announced by a `content_block_start` event.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum IncomingContentBlock {
+    Text {
+        #[serde(default)]
+        text: String,
+    },
+    Thinking {
+        #[serde(default)]
+        thinking: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+/// The incremental update carried by a `content_block_delta` event.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum Delta {
+    TextDelta {
+        text: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ApiError {
+    #[serde(rename = "type")]
+    error_type: String,
+    message: String,
+}
+
+impl ApiError {
+    /// HTTP status Anthropic uses for this error type when it's not streaming,
+    /// so errors sent mid-stream can be handled the same way (e.g. retried).
+    fn status(&self) -> Option<u16> {
+        match self.error_type.as_str() {
+            "invalid_request_error" => Some(400),
+            "authentication_error" => Some(401),
+            "permission_error" => Some(403),
+            "not_found_error" => Some(404),
+            "request_too_large" => Some(413),
+            "rate_limit_error" => Some(429),
+            "api_error" => Some(500),
+            "overloaded_error" => Some(529),
+            _ => None,
+        }
+    }
+}
+
+/// Token counts as reported by `message_start` and `message_delta` events.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+struct Usage {
+    input_tokens: u64,
+    output_tokens: u64,
+    cache_creation_input_tokens: u64,
+    cache_read_input_tokens: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct MessageStart {
+    #[serde(default)]
+    usage: Usage,
+}
+
+/// Server-sent event of a streaming response.
+///
+/// See https://docs.anthropic.com/en/docs/build-with-claude/streaming.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart {
+        message: MessageStart,
+    },
+    /// Carries the final (cumulative) output token count.
+    MessageDelta {
+        #[serde(default)]
+        usage: Usage,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: IncomingContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: Delta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    Error {
+        error: ApiError,
+    },
+    /// `message_stop`, `ping` and any event type added in the future.
+    #[serde(other)]
+    Other,
+}
+
+/// A `tool_use` block whose input is still being streamed.
+#[derive(Clone, Debug, Default)]
+struct PendingToolUse {
+    id: String,
+    name: String,
+    partial_json: String,
+}
+
+impl PendingToolUse {
+    fn into_tool_call(self) -> ToolCall {
+        let arguments = match serde_json::from_str::<serde_json::Value>(&self.partial_json) {
+            Ok(serde_json::Value::Object(arguments)) => arguments,
+            _ => serde_json::Map::new(),
+        };
+
+        ToolCall {
+            id: self.id,
+            name: self.name,
+            arguments,
+            ..Default::default()
+        }
+    }
+}
+
+async fn to_outgoing_blocks(message: Message) -> Result<Vec<OutgoingContentBlock>, String> {
+    let mut blocks = Vec::new();
+
+    // Thinking must come first in the assistant turn. Data left by other
+    // clients just doesn't parse.
+    if let EntityId::Bot(_) = message.from {
+        let data = message
+            .content
+            .data
+            .as_deref()
+            .and_then(|data| serde_json::from_str::<MessageData>(data).ok())
+            .unwrap_or_default();
+        blocks.extend(data.thinking_blocks.into_iter().map(Into::into));
+    }
+
+    for result in message.content.tool_results {
+        let mut content = Vec::new();
+
+        // Empty text blocks are rejected by the API.
+        if !result.content.is_empty() {
+            content.push(OutgoingContentBlock::Text {
+                text: result.content.clone(),
+            });
+        }
+
+        let images = result
+            .attachments()
+            .filter(|attachment| attachment.is_image() && attachment.is_available());
+
+        for image in images {
+            let data = image
+                .read_base64()
+                .await
+                .map_err(|e| format!("Failed to read tool output '{}': {}", image.name, e))?;
+            content.push(OutgoingContentBlock::Image {
+                source: Base64Source::new(image.content_type_or_octet_stream(), data),
+            });
+        }
+
+        blocks.push(OutgoingContentBlock::ToolResult {
+            tool_use_id: result.tool_call_id,
+            content,
+            is_error: result.is_error,
+        });
+    }
+
+    for attachment in message.content.attachments {
+        if !attachment.is_available() {
+            makepad_widgets::warning!("Skipping unavailable attachment: {}", attachment.name);
+            continue;
+        }
+
+        let content = attachment
+            .read()
+            .await
+            .map_err(|e| format!("Failed to read attachment '{}': {}", attachment.name, e))?;
+
+        if attachment.is_image() || attachment.is_pdf() {
+            use base64::Engine;
+            let source = Base64Source::new(
+                attachment.content_type_or_octet_stream(),
+                base64::engine::general_purpose::STANDARD.encode(&content),
+            );
+
+            if attachment.is_image() {
+                blocks.push(OutgoingContentBlock::Image { source });
+            } else {
+                blocks.push(OutgoingContentBlock::Document {
+                    source,
+                    title: attachment.name,
+                });
+            }
+        } else {
+            match String::from_utf8(content.to_vec()) {
+                Ok(text) => blocks.push(OutgoingContentBlock::Text {
+                    text: format!("[File: {}]\n{}", attachment.name, text),
+                }),
+                Err(_) => {
+                    return Err(format!(
+                        "File '{}' is not supported. Only images, PDFs, and text files can be sent through the Messages API.",
+                        attachment.name
+                    ));
+                }
+            }
+        }
+    }
+
+    // Empty text blocks are rejected by the API.
+    if !message.content.text.is_empty() {
+        blocks.push(OutgoingContentBlock::Text {
+            text: message.content.text,
+        });
+    }
+
+    for tool_call in message.content.tool_calls {
+        blocks.push(OutgoingContentBlock::ToolUse {
+            id: tool_call.id,
+            name: tool_call.name,
+            input: serde_json::Value::Object(tool_call.arguments),
+        });
+    }
+
+    Ok(blocks)
+}
+
+/// Splits the conversation into the top-level system prompt and the list of
+/// user/assistant turns expected by the Messages API.
+///
+/// Tool results are sent back as `user` turns, and consecutive turns with the
+/// same role are merged together.
+async fn to_outgoing_messages(
+    messages: Vec<Message>,
+) -> Result<(Option<String>, Vec<OutgoingMessage>), String> {
+    let mut system = Vec::new();
+    let mut outgoing: Vec<OutgoingMessage> = Vec::with_capacity(messages.len());
+
+    for message in messages {
+        let role = match message.from {
+            EntityId::System => {
+                system.push(message.content.text);
+                continue;
+            }
+            EntityId::User | EntityId::Tool => Role::User,
+            EntityId::Bot(_) => Role::Assistant,
+            EntityId::App => return Err("App messages cannot be sent to Anthropic".to_string()),
+        };
+
+        let blocks = to_outgoing_blocks(message).await?;
+        if blocks.is_empty() {
+            continue;
+        }
+
+        match outgoing.last_mut() {
+            Some(last) if last.role == role => last.content.extend(blocks),
+            _ => outgoing.push(OutgoingMessage {
+                role,
+                content: blocks,
+            }),
+        }
+    }
+
+    let system = if system.is_empty() {
+        None
+    } else {
+        Some(system.join("\n\n"))
+    };
+
+    Ok((system, outgoing))
+}
+
+#[derive(Clone, Debug)]
+struct AnthropicClientInner {
+    url: String,
+    headers: HeaderMap,
+    client: reqwest::Client,
+    tools_enabled: bool,
+}
+
+/// A client for the Anthropic Messages API (`/v1/messages`).
+///
+/// Reasoning from `thinking` blocks is exposed as [`MessageContent::reasoning`]
+/// and `tool_use` blocks as [`MessageContent::tool_calls`].
+#[derive(Debug)]
+pub struct AnthropicClient(Arc<RwLock<AnthropicClientInner>>);
+
+impl Clone for AnthropicClient {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl From<AnthropicClientInner> for AnthropicClient {
+    fn from(inner: AnthropicClientInner) -> Self {
+        Self(Arc::new(RwLock::new(inner)))
+    }
+}
+
+impl AnthropicClient {
+    /// Creates a new client with the given API URL (e.g. `https://api.anthropic.com/v1`).
+    pub fn new(url: String) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-version", ANTHROPIC_VERSION.parse().unwrap());
+        let client = default_client();
+
+        AnthropicClientInner {
+            url,
+            headers,
+            client,
+            tools_enabled: true,
+        }
+        .into()
+    }
+
+    pub fn set_header(&mut self, key: &str, value: &str) -> Result<(), &'static str> {
+        let header_name = HeaderName::from_str(key).map_err(|_| "Invalid header name")?;
+
+        let header_value = value.parse().map_err(|_| "Invalid header value")?;
+
+        self.0
+            .write()
+            .unwrap()
+            .headers
+            .insert(header_name, header_value);
+
+        Ok(())
+    }
+
+    pub fn set_key(&mut self, key: &str) -> Result<(), &'static str> {
+        self.set_header("x-api-key", key)
+    }
+
+    pub fn set_tools_enabled(&mut self, enabled: bool) {
+        self.0.write().unwrap().tools_enabled = enabled;
+    }
+}
+
+impl BotClient for AnthropicClient {
+    fn bots(&self) -> BoxPlatformSendFuture<'static, ClientResult<Vec<Bot>>> {
+        let inner = self.0.read().unwrap().clone();
+
+        let url = format!("{}/models", inner.url);
+
+        let future = async move {
+            let mut models = Vec::new();
+            let mut after_id: Option<String> = None;
+
+            loop {
+                let mut request = inner
+                    .client
+                    .get(&url)
+                    .headers(inner.headers.clone())
+                    .query(&[("limit", MODELS_PAGE_SIZE.to_string())]);
+                if let Some(after_id) = &after_id {
+                    request = request.query(&[("after_id", after_id)]);
+                }
+
+                let response = match request.send().await {
+                    Ok(response) => response,
+                    Err(error) => {
+                        return ClientError::new_with_source(
+                            ClientErrorKind::Network,
+                            format!("An error ocurred sending a request to {url}."),
+                            Some(error),
+                        )
+                        .into();
+                    }
+                };
+
+                if !response.status().is_success() {
+                    let code = response.status().as_u16();
+                    let retry_after = parse_retry_after(response.headers());
+                    return ClientError::new(
+                        ClientErrorKind::Response,
+                        format!("Got unexpected HTTP status code {code} from {url}."),
+                    )
+                    .with_status(code)
+                    .with_retry_after(retry_after)
+                    .into();
+                }
+
+                let text = match response.text().await {
+                    Ok(text) => text,
+                    Err(error) => {
+                        return ClientError::new_with_source(
+                            ClientErrorKind::Format,
+                            format!("Could not parse the response from {url} as valid text."),
+                            Some(error),
+                        )
+                        .into();
+                    }
+                };
+
+                let page: Models = match serde_json::from_str(&text) {
+                    Ok(page) => page,
+                    Err(error) => {
+                        return ClientError::new_with_source(
+                            ClientErrorKind::Format,
+                            format!("Could not parse the response from {url} as JSON or its structure does not match the expected format."),
+                            Some(error),
+                        ).into();
+                    }
+                };
+
+                models.extend(page.data);
+                match page.last_id {
+                    Some(last_id) if page.has_more => after_id = Some(last_id),
+                    _ => break,
+                }
+            }
+
+            let mut bots: Vec<Bot> = models
+                .iter()
+                .map(|m| Bot {
+                    id: BotId::new(&m.id, &inner.url),
+                    name: m.id.clone(),
+                    avatar: Picture::Grapheme("A".into()),
+                    capabilities: BotCapabilities::new()
+                        .with_capability(BotCapability::Attachments)
+                        .with_capability(BotCapability::FunctionCalling),
+                })
+                .collect();
+
+            bots.sort_by(|a, b| a.name.cmp(&b.name));
+
+            ClientResult::new_ok(bots)
+        };
+
+        Box::pin(future)
+    }
+
+    fn clone_box(&self) -> Box<dyn BotClient> {
+        Box::new(self.clone())
+    }
+
+    fn send(
+        &mut self,
+        bot_id: &BotId,
+        messages: &[Message],
+        tools: &[Tool],
+        options: &SendOptions,
+    ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
+        let bot_id = bot_id.clone();
+        let messages = messages.to_vec();
+        let generation = options.generation.clone();
+
+        let inner = self.0.read().unwrap().clone();
+        let url = format!("{}/messages", inner.url);
+        let headers = inner.headers;
+
+        let tools: Vec<AnthropicTool> = if inner.tools_enabled {
+            tools.iter().map(|t| t.into()).collect()
+        } else {
+            Vec::new()
+        };
+
+        let stream = stream! {
+            let (system, outgoing_messages) = match to_outgoing_messages(messages).await {
+                Ok(result) => result,
+                Err(err) => {
+                    error!("Could not convert messages to outgoing format: {}", err);
+                    yield ClientError::new(ClientErrorKind::Format, err).into();
+                    return;
+                }
+            };
+
+            let mut json = serde_json::json!({
+                "model": bot_id.id(),
+                "messages": outgoing_messages,
+                "max_tokens": generation.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+                "stream": true
+            });
+
+            if let Some(budget) = generation.reasoning_budget {
+                let budget = budget.max(MIN_THINKING_BUDGET);
+                json["thinking"] = serde_json::json!({
+                    "type": "enabled",
+                    "budget_tokens": budget,
+                });
+                // The budget is part of `max_tokens`, which must stay above it,
+                // so the answer keeps the room it had without thinking.
+                json["max_tokens"] = serde_json::json!(
+                    generation.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS) + budget
+                );
+            }
+
+            // Penalties are not supported by the Messages API, and sampling
+            // can't be changed while thinking.
+            if generation.reasoning_budget.is_none() {
+                if let Some(temperature) = generation.temperature {
+                    json["temperature"] = serde_json::json!(temperature);
+                }
+
+                if let Some(top_p) = generation.top_p {
+                    json["top_p"] = serde_json::json!(top_p);
+                }
+            }
+
+            if !generation.stop.is_empty() {
+                json["stop_sequences"] = serde_json::json!(generation.stop);
+            }
+
+            if let Some(system) = system {
+                json["system"] = serde_json::json!(system);
+            }
+
+            if !tools.is_empty() {
+                json["tools"] = serde_json::json!(tools);
+            }
+
+            let request = inner
+                .client
+                .post(&url)
+                .headers(headers)
+                .json(&json);
+
+            let response = match request.send().await {
+                Ok(response) => {
+                    if response.status().is_success() {
+                        response
+                    } else {
+                        let status_code = response.status();
+                        let retry_after = parse_retry_after(response.headers());
+                        let body = response.text().await.unwrap_or_default();
+                        let original = format!("Request failed with status {}", status_code);
+                        let enriched = enrich_http_error(status_code, &original, Some(&body));
+
+                        error!("Error sending request to {}: status {}", url, status_code);
+                        yield ClientError::new(
+                            ClientErrorKind::Response,
+                            enriched,
+                        )
+                        .with_status(status_code.as_u16())
+                        .with_retry_after(retry_after)
+                        .into();
+                        return;
+                    }
+                }
+                Err(error) => {
+                    error!("Error sending request to {}: {:?}", url, error);
+                    yield ClientError::new_with_source(
+                        ClientErrorKind::Network,
+                        format!("Could not send request to {url}. Verify your connection and the server status."),
+                        Some(error),
+                    ).into();
+                    return;
+                }
+            };
+
+            let mut content = MessageContent::default();
+            // Tool use and thinking blocks being streamed, keyed by their
+            // content block index.
+            let mut pending_tool_uses: HashMap<usize, PendingToolUse> = HashMap::new();
+            let mut pending_thinking: HashMap<usize, ThinkingBlock> = HashMap::new();
+            let mut data = MessageData::default();
+            let events = parse_sse(response.bytes_stream());
+
+            for await event in events {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(error) => {
+                        error!("Response streaming got interrupted while reading from {}: {:?}", url, error);
+                        yield ClientError::new_with_source(
+                            ClientErrorKind::Network,
+                            format!("Response streaming got interrupted while reading from {url}. This may be a problem with your connection or the server."),
+                            Some(error),
+                        ).into();
+                        return;
+                    }
+                };
+
+                let event: StreamEvent = match serde_json::from_str(&event) {
+                    Ok(event) => event,
+                    Err(error) => {
+                        error!("Could not parse the SSE message from {url} as JSON or its structure does not match the expected format. {}", error);
+                        yield ClientError::new_with_source(
+                            ClientErrorKind::Format,
+                            format!("Could not parse the SSE message from {url} as JSON or its structure does not match the expected format."),
+                            Some(error),
+                        ).into();
+                        return;
+                    }
+                };
+
+                match event {
+                    StreamEvent::MessageStart { message } => {
+                        let usage = message.usage;
+                        // Cached input is still part of the prompt, even if billed differently.
+                        content.usage = Some(TokenUsage {
+                            prompt_tokens: usage.input_tokens
+                                + usage.cache_creation_input_tokens
+                                + usage.cache_read_input_tokens,
+                            cached_prompt_tokens: usage.cache_read_input_tokens,
+                            completion_tokens: usage.output_tokens,
+                            ..Default::default()
+                        });
+                        continue;
+                    }
+                    StreamEvent::MessageDelta { usage } => {
+                        let total = content.usage.get_or_insert_default();
+                        total.completion_tokens = usage.output_tokens;
+                    }
+                    StreamEvent::ContentBlockStart { index, content_block } => match content_block {
+                        IncomingContentBlock::Text { text } => content.text.push_str(&text),
+                        IncomingContentBlock::Thinking { thinking } => {
+                            content.reasoning.push_str(&thinking);
+                            pending_thinking.insert(index, ThinkingBlock::Thinking {
+                                thinking,
+                                signature: String::new(),
+                            });
+                        }
+                        IncomingContentBlock::RedactedThinking { data } => {
+                            pending_thinking.insert(index, ThinkingBlock::RedactedThinking { data });
+                            continue;
+                        }
+                        IncomingContentBlock::ToolUse { id, name } => {
+                            pending_tool_uses.insert(index, PendingToolUse {
+                                id,
+                                name,
+                                ..Default::default()
+                            });
+                            continue;
+                        }
+                        IncomingContentBlock::Unknown => continue,
+                    },
+                    StreamEvent::ContentBlockDelta { index, delta } => match delta {
+                        Delta::TextDelta { text } => content.text.push_str(&text),
+                        Delta::ThinkingDelta { thinking } => {
+                            content.reasoning.push_str(&thinking);
+                            if let Some(ThinkingBlock::Thinking { thinking: text, .. }) = pending_thinking.get_mut(&index) {
+                                text.push_str(&thinking);
+                            }
+                        }
+                        Delta::SignatureDelta { signature } => {
+                            if let Some(ThinkingBlock::Thinking { signature: sig, .. }) = pending_thinking.get_mut(&index) {
+                                sig.push_str(&signature);
+                            }
+                            continue;
+                        }
+                        Delta::InputJsonDelta { partial_json } => {
+                            if let Some(pending) = pending_tool_uses.get_mut(&index) {
+                                pending.partial_json.push_str(&partial_json);
+                            }
+                            // Wait until the arguments are complete.
+                            continue;
+                        }
+                        Delta::Unknown => continue,
+                    },
+                    StreamEvent::ContentBlockStop { index } => {
+                        if let Some(block) = pending_thinking.remove(&index) {
+                            data.thinking_blocks.push(block);
+                            content.data = Some(serde_json::to_string(&data).unwrap());
+                        } else if let Some(pending) = pending_tool_uses.remove(&index) {
+                            content.tool_calls.push(pending.into_tool_call());
+                        } else {
+                            continue;
+                        }
+                    }
+                    StreamEvent::Error { error } => {
+                        error!("Anthropic stream from {} returned an error: {:?}", url, error);
+                        let status = error.status();
+                        let client_error = ClientError::new(
+                            ClientErrorKind::Response,
+                            format!("{}: {}", error.error_type, error.message),
+                        );
+                        yield match status {
+                            Some(status) => client_error.with_status(status),
+                            None => client_error,
+                        }
+                        .into();
+                        return;
+                    }
+                    StreamEvent::Other => continue,
+                }
+
+                yield ClientResult::new_ok(content.clone());
+            }
+        };
+
+        Box::pin(stream)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_client() -> reqwest::Client {
+    use std::time::Duration;
+
+    // On native, there are no default timeouts. Connection may hang if we don't
+    // configure them.
+    reqwest::Client::builder()
+        // Only considered while establishing the connection.
+        .connect_timeout(Duration::from_secs(90))
+        // Considered while reading the response and reset on every chunk
+        // received.
+        //
+        // Warning: Do not use normal `timeout` method as it doesn't consider
+        // this.
+        .read_timeout(Duration::from_secs(90))
+        .build()
+        .unwrap()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn default_client() -> reqwest::Client {
+    // On web, reqwest timeouts are not configurable, but it uses the browser's
+    // fetch API under the hood, which handles connection issues properly.
+    reqwest::Client::new()
+}
+
+#[cfg(all(test, feature = "async-rt", not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::{TestResponse, TestServer, block_on};
+    use futures::StreamExt;
+
+    fn event(event_type: &str, data: serde_json::Value) -> (Option<&str>, String) {
+        (Some(event_type), data.to_string())
+    }
+
+    fn streamed_response() -> TestResponse {
+        TestResponse::sse([
+            event(
+                "message_start",
+                serde_json::json!({"type": "message_start", "message": {"id": "msg_1", "usage": {"input_tokens": 12, "cache_read_input_tokens": 8, "output_tokens": 1}}}),
+            ),
+            event(
+                "content_block_start",
+                serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": ""}}),
+            ),
+            event(
+                "content_block_delta",
+                serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "Let me think"}}),
+            ),
+            event(
+                "content_block_delta",
+                serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "abc"}}),
+            ),
+            event(
+                "content_block_stop",
+                serde_json::json!({"type": "content_block_stop", "index": 0}),
+            ),
+            event("ping", serde_json::json!({"type": "ping"})),
+            event(
+                "content_block_start",
+                serde_json::json!({"type": "content_block_start", "index": 1, "content_block": {"type": "text", "text": ""}}),
+            ),
+            event(
+                "content_block_delta",
+                serde_json::json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "Hello"}}),
+            ),
+            event(
+                "content_block_delta",
+                serde_json::json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": " world"}}),
+            ),
+            event(
+                "content_block_stop",
+                serde_json::json!({"type": "content_block_stop", "index": 1}),
+            ),
+            event(
+                "content_block_start",
+                serde_json::json!({"type": "content_block_start", "index": 2, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "fs__read", "input": {}}}),
+            ),
+            event(
+                "content_block_delta",
+                serde_json::json!({"type": "content_block_delta", "index": 2, "delta": {"type": "input_json_delta", "partial_json": "{\"path\": "}}),
+            ),
+            event(
+                "content_block_delta",
+                serde_json::json!({"type": "content_block_delta", "index": 2, "delta": {"type": "input_json_delta", "partial_json": "\"a.txt\"}"}}),
+            ),
+            event(
+                "content_block_stop",
+                serde_json::json!({"type": "content_block_stop", "index": 2}),
+            ),
+            event(
+                "message_delta",
+                serde_json::json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 30}}),
+            ),
+            event("message_stop", serde_json::json!({"type": "message_stop"})),
+        ])
+    }
+
+    #[test]
+    fn test_send_maps_stream_events() {
+        let server = TestServer::start(|_| streamed_response());
+        let mut client = AnthropicClient::new(server.url());
+        client.set_key("secret").unwrap();
+
+        let bot_id = BotId::new("claude-test", &server.url());
+        let messages = vec![
+            Message {
+                from: EntityId::System,
+                content: MessageContent {
+                    text: "Be brief.".into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Message {
+                from: EntityId::User,
+                content: MessageContent {
+                    text: "Hi".into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ];
+        let tools = vec![Tool::new("fs__read".into(), Some("Read a file".into()))];
+        let options = SendOptions {
+            generation: GenerationParams {
+                temperature: Some(0.5),
+                stop: vec!["END".into()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let results: Vec<_> = block_on(
+            client
+                .send(&bot_id, &messages, &tools, &options)
+                .collect::<Vec<_>>(),
+        );
+        let last = results.last().unwrap().clone().into_result().unwrap();
+
+        assert_eq!(last.reasoning, "Let me think");
+        let data: MessageData = serde_json::from_str(last.data.as_deref().unwrap()).unwrap();
+        assert_eq!(
+            data.thinking_blocks,
+            vec![ThinkingBlock::Thinking {
+                thinking: "Let me think".into(),
+                signature: "abc".into(),
+            }]
+        );
+        assert_eq!(last.text, "Hello world");
+        assert_eq!(last.tool_calls.len(), 1);
+        assert_eq!(last.tool_calls[0].id, "toolu_1");
+        assert_eq!(last.tool_calls[0].name, "fs__read");
+        assert_eq!(last.tool_calls[0].arguments["path"], "a.txt");
+        assert_eq!(
+            last.usage,
+            Some(TokenUsage {
+                prompt_tokens: 20,
+                cached_prompt_tokens: 8,
+                completion_tokens: 30,
+                reasoning_tokens: 0,
+            })
+        );
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.path, "/messages");
+        assert_eq!(request.header("x-api-key"), Some("secret"));
+        assert_eq!(request.header("anthropic-version"), Some(ANTHROPIC_VERSION));
+
+        let body = request.json();
+        assert_eq!(body["model"], "claude-test");
+        assert_eq!(body["system"], "Be brief.");
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["stop_sequences"][0], "END");
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["messages"][0]["content"][0]["text"], "Hi");
+        assert_eq!(body["tools"][0]["input_schema"]["type"], "object");
+        assert!(body.get("thinking").is_none());
+    }
+
+    #[test]
+    fn test_thinking_is_enabled_and_sent_back() {
+        let server = TestServer::start(|_| streamed_response());
+        let mut client = AnthropicClient::new(server.url());
+        let bot_id = BotId::new("claude-test", &server.url());
+
+        let data = MessageData {
+            thinking_blocks: vec![
+                ThinkingBlock::Thinking {
+                    thinking: "Let me think".into(),
+                    signature: "abc".into(),
+                },
+                ThinkingBlock::RedactedThinking { data: "xyz".into() },
+            ],
+        };
+        let messages = vec![
+            Message {
+                from: EntityId::User,
+                content: MessageContent {
+                    text: "Hi".into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Message {
+                from: EntityId::Bot(bot_id.clone()),
+                content: MessageContent {
+                    text: "Hello".into(),
+                    reasoning: "Let me think".into(),
+                    data: Some(serde_json::to_string(&data).unwrap()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ];
+        let options = SendOptions {
+            generation: GenerationParams {
+                temperature: Some(0.5),
+                max_tokens: Some(1000),
+                reasoning_budget: Some(2000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let _ = block_on(
+            client
+                .send(&bot_id, &messages, &[], &options)
+                .collect::<Vec<_>>(),
+        );
+
+        let body = server.requests()[0].json();
+        assert_eq!(body["thinking"]["type"], "enabled");
+        assert_eq!(body["thinking"]["budget_tokens"], 2000);
+        assert_eq!(body["max_tokens"], 3000);
+        assert!(body.get("temperature").is_none());
+
+        let assistant = &body["messages"][1]["content"];
+        assert_eq!(assistant[0]["type"], "thinking");
+        assert_eq!(assistant[0]["signature"], "abc");
+        assert_eq!(assistant[1]["type"], "redacted_thinking");
+        assert_eq!(assistant[1]["data"], "xyz");
+        assert_eq!(assistant[2]["type"], "text");
+    }
+
+    #[test]
+    fn test_tool_round_trip_is_sent_as_blocks() {
+        let server = TestServer::start(|_| streamed_response());
+        let mut client = AnthropicClient::new(server.url());
+
+        let bot_id = BotId::new("claude-test", &server.url());
+        let mut arguments = serde_json::Map::new();
+        arguments.insert("path".into(), "a.txt".into());
+
+        let messages = vec![
+            Message {
+                from: EntityId::User,
+                content: MessageContent {
+                    text: "Read a.txt".into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Message {
+                from: EntityId::Bot(bot_id.clone()),
+                content: MessageContent {
+                    tool_calls: vec![ToolCall {
+                        id: "toolu_1".into(),
+                        name: "fs__read".into(),
+                        arguments,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Message {
+                from: EntityId::Tool,
+                content: MessageContent {
+                    text: "🔧 Tool executed".into(),
+                    tool_results: vec![ToolResult {
+                        tool_call_id: "toolu_1".into(),
+                        content: "file contents".into(),
+                        is_error: false,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ];
+
+        let _ = block_on(
+            client
+                .send(&bot_id, &messages, &[], &SendOptions::default())
+                .collect::<Vec<_>>(),
+        );
+
+        let body = server.requests()[0].json();
+        let outgoing = body["messages"].as_array().unwrap();
+        assert_eq!(outgoing.len(), 3);
+        assert_eq!(outgoing[1]["role"], "assistant");
+        assert_eq!(outgoing[1]["content"][0]["type"], "tool_use");
+        assert_eq!(outgoing[1]["content"][0]["input"]["path"], "a.txt");
+        assert_eq!(outgoing[2]["role"], "user");
+        assert_eq!(outgoing[2]["content"][0]["type"], "tool_result");
+        assert_eq!(outgoing[2]["content"][0]["tool_use_id"], "toolu_1");
+        assert_eq!(
+            outgoing[2]["content"][0]["content"][0]["text"],
+            "file contents"
+        );
+        assert!(body.get("tools").is_none());
+    }
+
+    #[test]
+    fn test_tool_result_images_are_sent_as_blocks() {
+        let image = Attachment::from_bytes("chart.png".into(), Some("image/png".into()), b"png");
+        let message = Message {
+            from: EntityId::Tool,
+            content: MessageContent {
+                tool_results: vec![ToolResult {
+                    tool_call_id: "toolu_1".into(),
+                    content: "Chart drawn".into(),
+                    parts: vec![
+                        ToolResultPart::Text("Chart drawn".into()),
+                        ToolResultPart::Attachment(image),
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let blocks = block_on(to_outgoing_blocks(message)).unwrap();
+        let blocks = serde_json::to_value(&blocks).unwrap();
+
+        assert_eq!(blocks.as_array().unwrap().len(), 1);
+        assert_eq!(blocks[0]["type"], "tool_result");
+        let content = blocks[0]["content"].as_array().unwrap();
+        assert_eq!(content.len(), 2);
+        assert_eq!(content[0]["type"], "text");
+        assert_eq!(content[0]["text"], "Chart drawn");
+        assert_eq!(content[1]["type"], "image");
+        assert_eq!(content[1]["source"]["type"], "base64");
+        assert_eq!(content[1]["source"]["media_type"], "image/png");
+        assert_eq!(content[1]["source"]["data"], "cG5n");
+    }
+
+    #[test]
+    fn test_stream_error_event() {
+        let server = TestServer::start(|_| {
+            TestResponse::sse([event(
+                "error",
+                serde_json::json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}}),
+            )])
+        });
+        let mut client = AnthropicClient::new(server.url());
+        let bot_id = BotId::new("claude-test", &server.url());
+        let messages = vec![Message {
+            from: EntityId::User,
+            content: MessageContent {
+                text: "Hi".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }];
+
+        let results: Vec<_> = block_on(
+            client
+                .send(&bot_id, &messages, &[], &SendOptions::default())
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(results.len(), 1);
+        let errors = results[0].errors();
+        assert_eq!(errors[0].kind(), ClientErrorKind::Response);
+        assert!(errors[0].message().contains("Overloaded"));
+        assert_eq!(errors[0].status(), Some(529));
+        assert!(errors[0].is_transient());
+    }
+
+    #[test]
+    fn test_bots() {
+        let server = TestServer::start(|request| {
+            if request.path.contains("after_id=claude-c") {
+                TestResponse::json(
+                    200,
+                    serde_json::json!({"data": [{"id": "claude-a", "type": "model"}], "has_more": false, "last_id": "claude-a"}),
+                )
+            } else {
+                TestResponse::json(
+                    200,
+                    serde_json::json!({"data": [{"id": "claude-b", "type": "model"}, {"id": "claude-c", "type": "model"}], "has_more": true, "last_id": "claude-c"}),
+                )
+            }
+        });
+        let client = AnthropicClient::new(server.url());
+
+        let bots = block_on(client.bots()).into_result().unwrap();
+        assert_eq!(bots.len(), 3);
+        assert_eq!(bots[0].name, "claude-a");
+        assert_eq!(bots[0].id.id(), "claude-a");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].path, "/models?limit=1000");
+        assert_eq!(requests[1].path, "/models?limit=1000&after_id=claude-c");
+    }
+}
@@ -1,6 +1,7 @@
+use crate::clients::openai::apply_generation_params;
 use crate::protocol::Tool;
 use crate::utils::asynchronous::{BoxPlatformSendFuture, BoxPlatformSendStream};
-use crate::utils::errors::enrich_http_error;
+use crate::utils::errors::{enrich_http_error, parse_retry_after};
 use crate::{protocol::*, utils::sse::parse_sse};
 use async_stream::stream;
 use makepad_widgets::*;
@@ -219,6 +220,7 @@ impl BotClient for DeepInquireClient {
         bot_id: &BotId,
         messages: &[Message],
         _tools: &[Tool],
+        options: &SendOptions,
     ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
         let inner = self.0.read().unwrap().clone();
 
@@ -230,15 +232,14 @@ impl BotClient for DeepInquireClient {
             .filter_map(|m| m.clone().try_into().ok())
             .collect();
 
-        let request = inner
-            .client
-            .post(&url)
-            .headers(headers)
-            .json(&serde_json::json!({
-                "model": bot_id.id(),
-                "messages": moly_messages,
-                "stream": true
-            }));
+        let mut json = serde_json::json!({
+            "model": bot_id.id(),
+            "messages": moly_messages,
+            "stream": true
+        });
+        apply_generation_params(&mut json, &options.generation);
+
+        let request = inner.client.post(&url).headers(headers).json(&json);
 
         let stream = stream! {
             let response = match request.send().await {
@@ -247,6 +248,7 @@ impl BotClient for DeepInquireClient {
                         response
                     } else {
                         let status_code = response.status();
+                        let retry_after = parse_retry_after(response.headers());
                         let body = response.text().await.unwrap();
                         let original = format!("Request failed with status {}", status_code);
                         let enriched = enrich_http_error(status_code, &original, Some(&body));
@@ -254,7 +256,10 @@ impl BotClient for DeepInquireClient {
                         yield ClientError::new(
                             ClientErrorKind::Response,
                             enriched,
-                        ).into();
+                        )
+                        .with_status(status_code.as_u16())
+                        .with_retry_after(retry_after)
+                        .into();
                         return;
                     }
                 }
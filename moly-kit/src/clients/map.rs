@@ -87,11 +87,20 @@ impl<C: BotClient + 'static> BotClient for MapClient<C> {
         })
     }
 
+    fn stop(
+        &mut self,
+        bot_id: &BotId,
+        token: &CancellationToken,
+    ) -> BoxPlatformSendFuture<'static, ClientResult<()>> {
+        self.inner.lock().unwrap().client.stop(bot_id, token)
+    }
+
     fn send(
         &mut self,
         bot_id: &BotId,
         messages: &[Message],
         tools: &[Tool],
+        options: &SendOptions,
     ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
         let inner = self.inner.clone();
         let stream = self
@@ -99,7 +108,7 @@ impl<C: BotClient + 'static> BotClient for MapClient<C> {
             .lock()
             .unwrap()
             .client
-            .send(bot_id, messages, tools);
+            .send(bot_id, messages, tools, options);
 
         let stream = async_stream::stream! {
             for await result in stream {
@@ -3,41 +3,62 @@ use makepad_widgets::{Cx, LiveId, LivePtr, WidgetRef};
 
 use crate::protocol::*;
 use crate::utils::asynchronous::{BoxPlatformSendFuture, BoxPlatformSendStream};
+use futures::StreamExt;
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
 };
 
 /// A client that can be composed from multiple subclients to interact with all of them as one.
+///
+/// Bots can be given an ordered list of equivalent bots (usually the same model
+/// on other providers) to fall back to when their own client fails.
 #[derive(Clone)]
 pub struct MultiClient {
     clients_with_bots: Arc<Mutex<Vec<(Box<dyn BotClient>, Vec<Bot>)>>>,
+    fallbacks: Arc<Mutex<HashMap<BotId, Vec<BotId>>>>,
 }
 
 impl MultiClient {
     pub fn new() -> Self {
         MultiClient {
             clients_with_bots: Arc::new(Mutex::new(Vec::new())),
+            fallbacks: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
+    /// Sets the bots to try, in order, when sending to `bot_id` fails with a
+    /// network or remote error before any content is received.
+    ///
+    /// When a fallback answers, its id is reported in [`MessageContent::answered_by`].
+    pub fn set_fallbacks(&mut self, bot_id: BotId, fallbacks: Vec<BotId>) {
+        let mut all_fallbacks = self.fallbacks.lock().unwrap();
+        if fallbacks.is_empty() {
+            all_fallbacks.remove(&bot_id);
+        } else {
+            all_fallbacks.insert(bot_id, fallbacks);
+        }
+    }
+
+    /// The given bot followed by its fallbacks.
+    fn candidates(&self, bot_id: &BotId) -> Vec<BotId> {
+        let mut candidates = vec![bot_id.clone()];
+        if let Some(fallbacks) = self.fallbacks.lock().unwrap().get(bot_id) {
+            candidates.extend(fallbacks.iter().filter(|f| *f != bot_id).cloned());
+        }
+        candidates
+    }
+
     pub fn add_client(&mut self, client: Box<dyn BotClient>) {
         self.clients_with_bots
             .lock()
             .unwrap()
             .push((client, Vec::new()));
     }
-}
 
-impl BotClient for MultiClient {
-    fn send(
-        &mut self,
-        bot_id: &BotId,
-        messages: &[Message],
-        tools: &[Tool],
-    ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
-        let client = self
-            .clients_with_bots
+    /// Finds the sub-client that loaded the given bot.
+    fn client_for_bot(&self, bot_id: &BotId) -> Option<Box<dyn BotClient>> {
+        self.clients_with_bots
             .lock()
             .unwrap()
             .iter()
@@ -47,24 +68,122 @@ impl BotClient for MultiClient {
                 } else {
                     None
                 }
-            });
-
-        match client {
-            Some(mut client) => client.send(bot_id, messages, tools),
-            None => {
-                let bot_id_clone = bot_id.clone();
-                Box::pin(futures::stream::once(async move {
-                    ClientError::new(
-                        ClientErrorKind::Unknown,
-                        format!(
-                            "Can't find a client to communicate with the bot {:?}",
-                            bot_id_clone
-                        ),
-                    )
-                    .into()
-                }))
-            }
+            })
+    }
+}
+
+/// Whether the error allows trying with another equivalent bot.
+fn can_fall_back(error: &ClientError) -> bool {
+    matches!(
+        error.kind(),
+        ClientErrorKind::Network | ClientErrorKind::Response
+    )
+}
+
+impl BotClient for MultiClient {
+    fn send(
+        &mut self,
+        bot_id: &BotId,
+        messages: &[Message],
+        tools: &[Tool],
+        options: &SendOptions,
+    ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
+        let candidates = self
+            .candidates(bot_id)
+            .into_iter()
+            .filter_map(|id| self.client_for_bot(&id).map(|client| (id, client)))
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            let bot_id_clone = bot_id.clone();
+            return Box::pin(futures::stream::once(async move {
+                ClientError::new(
+                    ClientErrorKind::Unknown,
+                    format!(
+                        "Can't find a client to communicate with the bot {:?}",
+                        bot_id_clone
+                    ),
+                )
+                .into()
+            }));
         }
+
+        let bot_id = bot_id.clone();
+        let messages = messages.to_vec();
+        let tools = tools.to_vec();
+        let options = options.clone();
+
+        let stream = async_stream::stream! {
+            let mut errors = Vec::new();
+
+            for (candidate_id, mut client) in candidates {
+                let answered_by = (candidate_id != bot_id).then(|| candidate_id.clone());
+                let mut stream = client.send(&candidate_id, &messages, &tools, &options);
+                let mut started = false;
+
+                while let Some(result) = stream.next().await {
+                    if !started && !result.has_value() {
+                        let (_, candidate_errors) = result.into_value_and_errors();
+                        if candidate_errors.iter().all(can_fall_back) {
+                            log::warn!("Bot {} failed, trying the next fallback", candidate_id);
+                            errors.extend(candidate_errors);
+                            break;
+                        }
+
+                        yield ClientResult::new_err(candidate_errors);
+                        return;
+                    }
+
+                    started = true;
+                    yield match &answered_by {
+                        Some(answered_by) => {
+                            let (value, value_errors) = result.into_value_and_errors();
+                            let value = value.map(|mut content| {
+                                content.answered_by = Some(answered_by.clone());
+                                content
+                            });
+                            ClientResult::new_unchecked(value, value_errors)
+                        }
+                        None => result,
+                    };
+                }
+
+                if started {
+                    return;
+                }
+            }
+
+            yield ClientResult::new_err(errors);
+        };
+
+        Box::pin(stream)
+    }
+
+    fn stop(
+        &mut self,
+        bot_id: &BotId,
+        token: &CancellationToken,
+    ) -> BoxPlatformSendFuture<'static, ClientResult<()>> {
+        // Any of the candidates may be the one answering right now.
+        let futures = self
+            .candidates(bot_id)
+            .into_iter()
+            .filter_map(|id| self.client_for_bot(&id).map(|mut c| c.stop(&id, token)))
+            .collect::<Vec<_>>();
+
+        Box::pin(async move {
+            let errors = futures::future::join_all(futures)
+                .await
+                .into_iter()
+                .flat_map(|result| result.into_errors())
+                .collect::<Vec<_>>();
+
+            if errors.is_empty() {
+                ClientResult::new_ok(())
+            } else {
+                ClientResult::new_err(errors)
+            }
+        })
     }
 
     // TODO: Add `send` implementation to take adventage of `send` implementation in sub-clients.
@@ -134,3 +253,138 @@ impl BotClient for MultiClient {
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves a single bot, answering with its own id or failing with `error`.
+    #[derive(Clone)]
+    struct FakeClient {
+        bot_id: BotId,
+        error: Option<ClientErrorKind>,
+        /// Bots this client was asked to stop, shared between all the clients.
+        stopped: Arc<Mutex<Vec<BotId>>>,
+    }
+
+    impl BotClient for FakeClient {
+        fn send(
+            &mut self,
+            _bot_id: &BotId,
+            _messages: &[Message],
+            _tools: &[Tool],
+            _options: &SendOptions,
+        ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
+            let result = match self.error {
+                Some(kind) => ClientError::new(kind, "Failed".into()).into(),
+                None => ClientResult::new_ok(MessageContent {
+                    text: self.bot_id.id().to_string(),
+                    ..Default::default()
+                }),
+            };
+
+            Box::pin(futures::stream::once(async move { result }))
+        }
+
+        fn stop(
+            &mut self,
+            bot_id: &BotId,
+            _token: &CancellationToken,
+        ) -> BoxPlatformSendFuture<'static, ClientResult<()>> {
+            self.stopped.lock().unwrap().push(bot_id.clone());
+            Box::pin(futures::future::ready(ClientResult::new_ok(())))
+        }
+
+        fn bots(&self) -> BoxPlatformSendFuture<'static, ClientResult<Vec<Bot>>> {
+            let bot = Bot {
+                id: self.bot_id.clone(),
+                name: self.bot_id.id().to_string(),
+                avatar: Picture::Grapheme("B".into()),
+                capabilities: BotCapabilities::new(),
+            };
+
+            Box::pin(futures::future::ready(ClientResult::new_ok(vec![bot])))
+        }
+
+        fn clone_box(&self) -> Box<dyn BotClient> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn client(bots: &[(&BotId, Option<ClientErrorKind>)]) -> MultiClient {
+        client_with_stops(bots).0
+    }
+
+    /// Also returns the bots stopped through the client.
+    fn client_with_stops(
+        bots: &[(&BotId, Option<ClientErrorKind>)],
+    ) -> (MultiClient, Arc<Mutex<Vec<BotId>>>) {
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let mut client = MultiClient::new();
+        for (bot_id, error) in bots {
+            client.add_client(Box::new(FakeClient {
+                bot_id: (*bot_id).clone(),
+                error: *error,
+                stopped: stopped.clone(),
+            }));
+        }
+        futures::executor::block_on(client.bots())
+            .into_result()
+            .unwrap();
+        (client, stopped)
+    }
+
+    fn send(client: &mut MultiClient, bot_id: &BotId) -> Vec<ClientResult<MessageContent>> {
+        let stream = client.send(bot_id, &[], &[], &SendOptions::default());
+        futures::executor::block_on(stream.collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_send_falls_back_to_equivalent_bot() {
+        let primary = BotId::new("model", "remote");
+        let broken = BotId::new("model", "broken");
+        let local = BotId::new("model-local", "local");
+        let mut client = client(&[
+            (&primary, Some(ClientErrorKind::Network)),
+            (&broken, Some(ClientErrorKind::Response)),
+            (&local, None),
+        ]);
+        client.set_fallbacks(primary.clone(), vec![broken, local.clone()]);
+
+        let results = send(&mut client, &primary);
+
+        assert_eq!(results.len(), 1);
+        let content = results[0].value().unwrap();
+        assert_eq!(content.text, "model-local");
+        assert_eq!(content.answered_by, Some(local));
+    }
+
+    #[test]
+    fn test_send_does_not_fall_back_on_format_errors() {
+        let primary = BotId::new("model", "remote");
+        let local = BotId::new("model", "local");
+        let mut client = client(&[(&primary, Some(ClientErrorKind::Format)), (&local, None)]);
+        client.set_fallbacks(primary.clone(), vec![local]);
+
+        let results = send(&mut client, &primary);
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].errors()[0].kind(), ClientErrorKind::Format);
+    }
+
+    #[test]
+    fn test_stop_reaches_the_bot_and_its_fallbacks() {
+        let primary = BotId::new("model", "remote");
+        let local = BotId::new("model-local", "local");
+        let other = BotId::new("other", "remote");
+        let (mut client, stopped) =
+            client_with_stops(&[(&primary, None), (&local, None), (&other, None)]);
+        client.set_fallbacks(primary.clone(), vec![local.clone()]);
+
+        let token = CancellationToken::new();
+        let result = futures::executor::block_on(client.stop(&primary, &token));
+
+        assert!(result.into_result().is_ok());
+        assert_eq!(*stopped.lock().unwrap(), vec![primary, local]);
+    }
+}
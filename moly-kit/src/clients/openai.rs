@@ -11,7 +11,10 @@ use std::{
 
 use crate::utils::asynchronous::{BoxPlatformSendFuture, BoxPlatformSendStream};
 use crate::utils::{serde::deserialize_null_default, sse::parse_sse};
-use crate::{protocol::*, utils::errors::enrich_http_error};
+use crate::{
+    protocol::*,
+    utils::errors::{enrich_http_error, parse_retry_after},
+};
 
 /// A model from the models endpoint.
 #[derive(Clone, Debug, Deserialize, PartialEq)]
@@ -193,10 +196,10 @@ struct OutgoingMessage {
     pub tool_call_id: Option<String>,
 }
 
-async fn to_outgoing_message(message: Message) -> Result<OutgoingMessage, String> {
+async fn to_outgoing_messages(message: Message) -> Result<Vec<OutgoingMessage>, String> {
     // Handle tool results differently
     if !message.content.tool_results.is_empty() {
-        return outgoing_tool_result_message(message);
+        return outgoing_tool_result_messages(message).await;
     }
 
     let role = match message.from {
@@ -284,41 +287,68 @@ async fn to_outgoing_message(message: Message) -> Result<OutgoingMessage, String
             None
         };
 
-    Ok(OutgoingMessage {
+    Ok(vec![OutgoingMessage {
         content,
         role,
         tool_calls,
         tool_call_id: None,
-    })
+    }])
 }
 
-/// Converts a message with tool results to an outgoing message.
+/// Converts a message with tool results to outgoing messages, one per result
+/// as each tool call expects its own answer.
 ///
-/// This is used to send tool results back to the AI.
-fn outgoing_tool_result_message(message: Message) -> Result<OutgoingMessage, String> {
-    let role = Role::Tool;
-    let content = Content::Text(
-        message
-            .content
-            .tool_results
-            .iter()
-            .map(|result| truncate_tool_result(&result.content))
-            .collect::<Vec<_>>()
-            .join("\n"),
-    );
-
-    let tool_call_id = message
-        .content
-        .tool_results
-        .first()
-        .map(|r| r.tool_call_id.clone());
-
-    return Ok(OutgoingMessage {
-        content,
-        role,
-        tool_calls: None,
-        tool_call_id,
-    });
+/// This is used to send tool results back to the AI. Tool messages can only
+/// contain text, so the images returned by the tools are sent in a `user`
+/// message following them.
+async fn outgoing_tool_result_messages(message: Message) -> Result<Vec<OutgoingMessage>, String> {
+    let mut outgoing_messages = Vec::with_capacity(message.content.tool_results.len() + 1);
+    let mut image_parts = Vec::new();
+
+    for result in &message.content.tool_results {
+        outgoing_messages.push(OutgoingMessage {
+            content: Content::Text(truncate_tool_result(&result.content)),
+            role: Role::Tool,
+            tool_calls: None,
+            tool_call_id: Some(result.tool_call_id.clone()),
+        });
+
+        let images = result
+            .attachments()
+            .filter(|attachment| attachment.is_image() && attachment.is_available());
+
+        for image in images {
+            let content = image
+                .read_base64()
+                .await
+                .map_err(|e| format!("Failed to read tool output '{}': {}", image.name, e))?;
+            image_parts.push(ContentPart::ImageUrl {
+                image_url: ImageUrlDetail {
+                    url: format!(
+                        "data:{};base64,{}",
+                        image.content_type_or_octet_stream(),
+                        content
+                    ),
+                },
+            });
+        }
+    }
+
+    if !image_parts.is_empty() {
+        let mut parts = vec![ContentPart::Text {
+            text: "Images returned by the tools above:".to_string(),
+        }];
+        parts.extend(image_parts);
+
+        outgoing_messages.push(OutgoingMessage {
+            content: Content::Parts(parts),
+            role: Role::User,
+            tool_calls: None,
+            tool_call_id: None,
+        });
+    }
+
+    Ok(outgoing_messages)
 }
 
 fn truncate_tool_result(content: &str) -> String {
@@ -386,6 +416,35 @@ fn finalize_remaining_tool_calls(
     tool_call_ids_by_index.clear();
 }
 
+/// Sets the given generation parameters on a chat completions request body.
+///
+/// Parameters left as `None` are not sent, so the provider defaults apply.
+pub(crate) fn apply_generation_params(json: &mut serde_json::Value, params: &GenerationParams) {
+    if let Some(temperature) = params.temperature {
+        json["temperature"] = serde_json::json!(temperature);
+    }
+
+    if let Some(top_p) = params.top_p {
+        json["top_p"] = serde_json::json!(top_p);
+    }
+
+    if let Some(max_tokens) = params.max_tokens {
+        json["max_tokens"] = serde_json::json!(max_tokens);
+    }
+
+    if let Some(frequency_penalty) = params.frequency_penalty {
+        json["frequency_penalty"] = serde_json::json!(frequency_penalty);
+    }
+
+    if let Some(presence_penalty) = params.presence_penalty {
+        json["presence_penalty"] = serde_json::json!(presence_penalty);
+    }
+
+    if !params.stop.is_empty() {
+        json["stop"] = serde_json::json!(params.stop);
+    }
+}
+
 /// Role of a message that is part of the conversation context.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 enum Role {
@@ -409,12 +468,58 @@ struct Choice {
     pub finish_reason: Option<String>,
 }
 
+#[derive(Clone, Debug, Default, Deserialize)]
+struct CompletionTokensDetails {
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u64,
+}
+
+/// Token usage, only sent in the last chunk when `stream_options.include_usage`
+/// is set.
+#[derive(Clone, Debug, Deserialize)]
+struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+    #[serde(default)]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+impl From<Usage> for TokenUsage {
+    fn from(usage: Usage) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            cached_prompt_tokens: usage
+                .prompt_tokens_details
+                .map(|d| d.cached_tokens)
+                .unwrap_or_default(),
+            completion_tokens: usage.completion_tokens,
+            reasoning_tokens: usage
+                .completion_tokens_details
+                .map(|d| d.reasoning_tokens)
+                .unwrap_or_default(),
+        }
+    }
+}
+
 /// Response from the completions endpoint
 #[derive(Clone, Debug, Deserialize)]
 struct Completion {
+    #[serde(default)]
     pub choices: Vec<Choice>,
     #[serde(default)]
     pub citations: Vec<String>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
 }
 
 #[derive(Clone, Debug)]
@@ -423,6 +528,11 @@ struct OpenAIClientInner {
     headers: HeaderMap,
     client: reqwest::Client,
     tools_enabled: bool,
+    /// Whether to ask for token usage with `stream_options.include_usage`.
+    usage_enabled: bool,
+    /// Endpoint (relative to `url`) that aborts an ongoing generation, for
+    /// servers that keep generating after the connection is closed.
+    stop_endpoint: Option<String>,
 }
 
 /// A client capable of interacting with Moly Server and other OpenAI-compatible APIs.
@@ -452,6 +562,8 @@ impl OpenAIClient {
             headers,
             client,
             tools_enabled: true, // Default to enabled for backward compatibility
+            usage_enabled: true,
+            stop_endpoint: None,
         }
         .into()
     }
@@ -471,22 +583,27 @@ impl OpenAIClient {
     }
 
     pub fn set_key(&mut self, key: &str) -> Result<(), &'static str> {
-        self.set_header("Authorization", &format!("Bearer {}", key))?;
-
-        // Anthropic requires a different header for the API key, even with the OpenAI API compatibility layer.
-        if self.0.read().unwrap().url.contains("anthropic") {
-            self.set_header("x-api-key", key)?;
-            // Also needed for every Anthropic request.
-            // TODO: remove this once we support a native Anthropic client.
-            self.set_header("anthropic-version", "2023-06-01")?;
-        }
-
-        Ok(())
+        self.set_header("Authorization", &format!("Bearer {}", key))
     }
 
     pub fn set_tools_enabled(&mut self, enabled: bool) {
         self.0.write().unwrap().tools_enabled = enabled;
     }
+
+    /// Sets whether token usage is requested with `stream_options.include_usage`.
+    ///
+    /// Enabled by default. It's also disabled automatically the first time the
+    /// server rejects a request because of it.
+    pub fn set_usage_enabled(&mut self, enabled: bool) {
+        self.0.write().unwrap().usage_enabled = enabled;
+    }
+
+    /// Sets an endpoint to be called with a `POST` when a generation is stopped.
+    ///
+    /// Not part of the OpenAI API, but some servers (like MolyServer) need it.
+    pub fn set_stop_endpoint(&mut self, endpoint: Option<String>) {
+        self.0.write().unwrap().stop_endpoint = endpoint;
+    }
 }
 
 impl BotClient for OpenAIClient {
@@ -513,10 +630,13 @@ impl BotClient for OpenAIClient {
 
             if !response.status().is_success() {
                 let code = response.status().as_u16();
+                let retry_after = parse_retry_after(response.headers());
                 return ClientError::new(
                     ClientErrorKind::Response,
                     format!("Got unexpected HTTP status code {code} from {url}."),
                 )
+                .with_status(code)
+                .with_retry_after(retry_after)
                 .into();
             }
 
@@ -583,16 +703,60 @@ impl BotClient for OpenAIClient {
         Box::new(self.clone())
     }
 
+    fn stop(
+        &mut self,
+        bot_id: &BotId,
+        _token: &CancellationToken,
+    ) -> BoxPlatformSendFuture<'static, ClientResult<()>> {
+        let inner = self.0.read().unwrap().clone();
+
+        // Dropping the stream already closes the connection, which is enough
+        // for most servers.
+        let Some(endpoint) = inner.stop_endpoint else {
+            return Box::pin(futures::future::ready(ClientResult::new_ok(())));
+        };
+
+        let url = format!("{}/{}", inner.url, endpoint.trim_start_matches('/'));
+        let request = inner
+            .client
+            .post(&url)
+            .headers(inner.headers)
+            .json(&serde_json::json!({ "model": bot_id.id() }));
+
+        Box::pin(async move {
+            match request.send().await {
+                Ok(response) if response.status().is_success() => ClientResult::new_ok(()),
+                Ok(response) => ClientError::new(
+                    ClientErrorKind::Response,
+                    format!(
+                        "Got unexpected HTTP status code {} from {url}.",
+                        response.status().as_u16()
+                    ),
+                )
+                .into(),
+                Err(error) => ClientError::new_with_source(
+                    ClientErrorKind::Network,
+                    format!("An error ocurred sending a request to {url}."),
+                    Some(error),
+                )
+                .into(),
+            }
+        })
+    }
+
     /// Stream pieces of content back as a ChatDelta instead of just a String.
     fn send(
         &mut self,
         bot_id: &BotId,
         messages: &[Message],
         tools: &[Tool],
+        options: &SendOptions,
     ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
         let bot_id = bot_id.clone();
         let messages = messages.to_vec();
+        let generation = options.generation.clone();
 
+        let shared = self.0.clone();
         let inner = self.0.read().unwrap().clone();
         let url = format!("{}/chat/completions", inner.url);
         let headers = inner.headers;
@@ -607,8 +771,8 @@ impl BotClient for OpenAIClient {
         let stream = stream! {
             let mut outgoing_messages: Vec<OutgoingMessage> = Vec::with_capacity(messages.len());
             for message in messages {
-                match to_outgoing_message(message.clone()).await {
-                    Ok(outgoing_message) => outgoing_messages.push(outgoing_message),
+                match to_outgoing_messages(message.clone()).await {
+                    Ok(outgoing) => outgoing_messages.extend(outgoing),
                     Err(err) => {
                         error!("Could not convert message to outgoing format: {}", err);
                         yield ClientError::new(
@@ -623,30 +787,48 @@ impl BotClient for OpenAIClient {
             let mut json = serde_json::json!({
                 "model": bot_id.id(),
                 "messages": outgoing_messages,
-                // Note: o1 only supports 1.0, it will error if other value is used.
-                // "temperature": 0.7,
-                "stream": true
+                "stream": true,
             });
 
+            if inner.usage_enabled {
+                json["stream_options"] = serde_json::json!({ "include_usage": true });
+            }
+
+            // Note: o1 only supports a temperature of 1.0, it will error if other
+            // value is used. So we only send what was explicitly configured.
+            apply_generation_params(&mut json, &generation);
+
             // Only include tools if there are any available
             if !tools.is_empty() {
                 json["tools"] = serde_json::json!(tools);
             }
 
 
-            let request = inner
-                .client
-                .post(&url)
-                .headers(headers)
-                .json(&json);
+            let response = loop {
+                let request = inner
+                    .client
+                    .post(&url)
+                    .headers(headers.clone())
+                    .json(&json);
 
-            let response = match request.send().await {
-                Ok(response) => {
-                    if response.status().is_success() {
-                        response
-                    } else {
+                match request.send().await {
+                    Ok(response) if response.status().is_success() => break response,
+                    Ok(response) => {
                         let status_code = response.status();
-                        let body = response.text().await.unwrap();
+                        let retry_after = parse_retry_after(response.headers());
+                        let body = response.text().await.unwrap_or_default();
+
+                        // Some OpenAI-compatible servers reject `stream_options`,
+                        // so retry without it and stop asking them for usage.
+                        if status_code.is_client_error()
+                            && body.contains("stream_options")
+                            && json.as_object_mut().unwrap().remove("stream_options").is_some()
+                        {
+                            warning!("{} rejected stream_options, retrying without usage", url);
+                            shared.write().unwrap().usage_enabled = false;
+                            continue;
+                        }
+
                         let original = format!("Request failed with status {}", status_code);
                         let enriched = enrich_http_error(status_code, &original, Some(&body));
 
@@ -654,19 +836,22 @@ impl BotClient for OpenAIClient {
                         yield ClientError::new(
                             ClientErrorKind::Response,
                             enriched,
+                        )
+                        .with_status(status_code.as_u16())
+                        .with_retry_after(retry_after)
+                        .into();
+                        return;
+                    }
+                    Err(error) => {
+                        error!("Error sending request to {}: {:?}", url, error);
+                        yield ClientError::new_with_source(
+                            ClientErrorKind::Network,
+                            format!("Could not send request to {url}. Verify your connection and the server status."),
+                            Some(error),
                         ).into();
                         return;
                     }
                 }
-                Err(error) => {
-                    error!("Error sending request to {}: {:?}", url, error);
-                    yield ClientError::new_with_source(
-                        ClientErrorKind::Network,
-                        format!("Could not send request to {url}. Verify your connection and the server status."),
-                        Some(error),
-                    ).into();
-                    return;
-                }
             };
 
             let mut content = MessageContent::default();
@@ -814,6 +999,10 @@ impl BotClient for OpenAIClient {
                     }
                 }
 
+                if let Some(usage) = completion.usage {
+                    content.usage = Some(usage.into());
+                }
+
                 if should_yield_content {
                     yield ClientResult::new_ok(content.clone());
                 }
@@ -864,3 +1053,161 @@ fn split_reasoning_tag(text: &str) -> (&str, &str) {
         ("", text)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::{TestResponse, TestServer, block_on};
+    use futures::StreamExt;
+
+    fn streamed_response() -> TestResponse {
+        TestResponse::sse([
+            (
+                None,
+                serde_json::json!({"choices": [{"delta": {"content": "Hi"}, "finish_reason": "stop"}]})
+                    .to_string(),
+            ),
+            (
+                None,
+                serde_json::json!({"choices": [], "usage": {"prompt_tokens": 10, "completion_tokens": 2, "prompt_tokens_details": {"cached_tokens": 4}}})
+                    .to_string(),
+            ),
+            (None, "[DONE]".to_string()),
+        ])
+    }
+
+    fn send(client: &mut OpenAIClient, url: &str) -> MessageContent {
+        let bot_id = BotId::new("gpt-test", url);
+        let messages = vec![Message {
+            from: EntityId::User,
+            content: MessageContent {
+                text: "Hello".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }];
+
+        let results: Vec<_> = block_on(
+            client
+                .send(&bot_id, &messages, &[], &SendOptions::default())
+                .collect::<Vec<_>>(),
+        );
+        results.last().unwrap().clone().into_result().unwrap()
+    }
+
+    #[test]
+    fn test_usage_is_requested_and_parsed() {
+        let server = TestServer::start(|_| streamed_response());
+        let mut client = OpenAIClient::new(server.url());
+
+        let content = send(&mut client, &server.url());
+
+        assert_eq!(content.text, "Hi");
+        assert_eq!(
+            content.usage,
+            Some(TokenUsage {
+                prompt_tokens: 10,
+                cached_prompt_tokens: 4,
+                completion_tokens: 2,
+                reasoning_tokens: 0,
+            })
+        );
+        let body = server.requests()[0].json();
+        assert_eq!(body["stream_options"]["include_usage"], true);
+    }
+
+    #[test]
+    fn test_rejected_stream_options_are_dropped() {
+        let server = TestServer::start(|request| {
+            if request.json().get("stream_options").is_some() {
+                TestResponse::json(
+                    400,
+                    serde_json::json!({"error": {"message": "Unrecognized request argument supplied: stream_options"}}),
+                )
+            } else {
+                streamed_response()
+            }
+        });
+        let mut client = OpenAIClient::new(server.url());
+
+        assert_eq!(send(&mut client, &server.url()).text, "Hi");
+        assert_eq!(send(&mut client, &server.url()).text, "Hi");
+
+        // Only the first request had to be retried.
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[2].json().get("stream_options").is_none());
+    }
+
+    #[test]
+    fn test_usage_can_be_disabled() {
+        let server = TestServer::start(|_| streamed_response());
+        let mut client = OpenAIClient::new(server.url());
+        client.set_usage_enabled(false);
+
+        send(&mut client, &server.url());
+
+        assert!(server.requests()[0].json().get("stream_options").is_none());
+    }
+
+    #[test]
+    fn test_stop_posts_to_the_stop_endpoint() {
+        let server = TestServer::start(|_| TestResponse::json(200, serde_json::json!({})));
+        let mut client = OpenAIClient::new(server.url());
+        let bot_id = BotId::new("gpt-test", &server.url());
+        let token = CancellationToken::new();
+
+        // Without an endpoint, closing the stream is enough.
+        assert!(block_on(client.stop(&bot_id, &token)).into_result().is_ok());
+        assert!(server.requests().is_empty());
+
+        client.set_stop_endpoint(Some("/chat/completions/stop".into()));
+        assert!(block_on(client.stop(&bot_id, &token)).into_result().is_ok());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/chat/completions/stop");
+        assert_eq!(requests[0].json()["model"], "gpt-test");
+    }
+
+    #[test]
+    fn test_tool_result_images_are_sent_in_a_user_message() {
+        let image = Attachment::from_bytes("chart.png".into(), Some("image/png".into()), b"png");
+        let message = Message {
+            from: EntityId::Tool,
+            content: MessageContent {
+                tool_results: vec![
+                    ToolResult {
+                        tool_call_id: "call_1".into(),
+                        content: "Chart drawn".into(),
+                        parts: vec![ToolResultPart::Attachment(image)],
+                        ..Default::default()
+                    },
+                    ToolResult {
+                        tool_call_id: "call_2".into(),
+                        content: "Done".into(),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let outgoing = block_on(outgoing_tool_result_messages(message)).unwrap();
+        let outgoing = serde_json::to_value(&outgoing).unwrap();
+
+        assert_eq!(outgoing.as_array().unwrap().len(), 3);
+        assert_eq!(outgoing[0]["role"], "tool");
+        assert_eq!(outgoing[0]["tool_call_id"], "call_1");
+        assert_eq!(outgoing[0]["content"], "Chart drawn");
+        assert_eq!(outgoing[1]["role"], "tool");
+        assert_eq!(outgoing[1]["content"], "Done");
+        assert_eq!(outgoing[2]["role"], "user");
+        assert_eq!(
+            outgoing[2]["content"][1]["image_url"]["url"],
+            "data:image/png;base64,cG5n"
+        );
+    }
+}
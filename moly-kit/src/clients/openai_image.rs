@@ -187,6 +187,7 @@ impl BotClient for OpenAIImageClient {
         bot_id: &BotId,
         messages: &[Message],
         _tools: &[Tool],
+        _options: &SendOptions,
     ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
         let self_clone = self.clone();
         let bot_id = bot_id.clone();
@@ -27,6 +27,8 @@ pub enum OpenAIRealtimeMessage {
     InputAudioBufferCommit,
     #[serde(rename = "response.create")]
     ResponseCreate { response: ResponseConfig },
+    #[serde(rename = "response.cancel")]
+    ResponseCancel,
     #[serde(rename = "conversation.item.create")]
     ConversationItemCreate { item: serde_json::Value },
     #[serde(rename = "conversation.item.truncate")]
@@ -250,12 +252,24 @@ pub struct ErrorDetails {
 // Use the protocol definitions
 pub use crate::protocol::{RealtimeChannel, RealtimeCommand, RealtimeEvent};
 
+/// Command senders of the open sessions, keyed by the cancellation token they
+/// were created with.
+type Sessions = Arc<
+    Mutex<
+        Vec<(
+            CancellationToken,
+            futures::channel::mpsc::UnboundedSender<RealtimeCommand>,
+        )>,
+    >,
+>;
+
 #[derive(Clone, Debug)]
 pub struct OpenAIRealtimeClient {
     address: String,
     api_key: Option<String>,
     system_prompt: Option<String>,
     tools_enabled: bool,
+    sessions: Sessions,
 }
 
 impl OpenAIRealtimeClient {
@@ -265,6 +279,7 @@ impl OpenAIRealtimeClient {
             api_key: None,
             system_prompt: None,
             tools_enabled: true, // Default to enabled for backward compatibility
+            sessions: Sessions::default(),
         }
     }
 
@@ -286,6 +301,7 @@ impl OpenAIRealtimeClient {
         &self,
         bot_id: &BotId,
         tools: &[Tool],
+        generation: &GenerationParams,
     ) -> BoxPlatformSendFuture<'static, ClientResult<RealtimeChannel>> {
         let address = self.address.clone();
         let is_local = address.contains("127.0.0.1") || address.contains("localhost");
@@ -305,6 +321,12 @@ impl OpenAIRealtimeClient {
             Vec::new()
         };
         let system_prompt = self.system_prompt.clone();
+        // The Realtime API only accepts temperatures between 0.6 and 1.2.
+        let temperature = generation
+            .temperature
+            .map(|t| t.clamp(0.6, 1.2))
+            .unwrap_or(0.8);
+        let max_output_tokens = generation.max_tokens.unwrap_or(4096);
         let future = async move {
             let (event_sender, event_receiver) = futures::channel::mpsc::unbounded();
             let (command_sender, mut command_receiver) = futures::channel::mpsc::unbounded();
@@ -545,8 +567,8 @@ impl OpenAIRealtimeClient {
                                     } else {
                                         "auto".to_string()
                                     },
-                                    temperature: 0.8,
-                                    max_response_output_tokens: Some(4096),
+                                    temperature,
+                                    max_response_output_tokens: Some(max_output_tokens),
                                 };
 
                                 let session_message = OpenAIRealtimeMessage::SessionUpdate {
@@ -576,8 +598,8 @@ impl OpenAIRealtimeClient {
                                     output_audio_format: Some("pcm16".to_string()),
                                     tools: vec![],
                                     tool_choice: "none".to_string(),
-                                    temperature: Some(0.8),
-                                    max_output_tokens: Some(4096),
+                                    temperature: Some(temperature),
+                                    max_output_tokens: Some(max_output_tokens),
                                 };
 
                                 let message = OpenAIRealtimeMessage::ResponseCreate {
@@ -615,6 +637,13 @@ impl OpenAIRealtimeClient {
                                     send_message!(json);
                                 }
                             }
+                            RealtimeCommand::CancelResponse => {
+                                let message = OpenAIRealtimeMessage::ResponseCancel;
+                                if let Ok(json) = serde_json::to_string(&message) {
+                                    log::debug!("Sending response cancel: {}", json);
+                                    send_message!(json);
+                                }
+                            }
                             RealtimeCommand::Interrupt => {
                                 // Send truncate message to interrupt current response
                                 let message = OpenAIRealtimeMessage::InputAudioBufferCommit;
@@ -645,8 +674,8 @@ impl OpenAIRealtimeClient {
                                     output_audio_format: Some("pcm16".to_string()),
                                     tools: vec![],
                                     tool_choice: "auto".to_string(),
-                                    temperature: Some(0.8),
-                                    max_output_tokens: Some(4096),
+                                    temperature: Some(temperature),
+                                    max_output_tokens: Some(max_output_tokens),
                                 };
 
                                 let response_message = OpenAIRealtimeMessage::ResponseCreate {
@@ -1033,13 +1062,22 @@ impl BotClient for OpenAIRealtimeClient {
         bot_id: &BotId,
         _messages: &[crate::protocol::Message],
         tools: &[Tool],
+        options: &SendOptions,
     ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
         // For realtime, we create a session and return the upgrade in the message content
-        let future = self.create_realtime_session(bot_id, tools);
+        let future = self.create_realtime_session(bot_id, tools, &options.generation);
+        let sessions = self.sessions.clone();
+        let token = options.cancellation.clone();
 
         let stream = async_stream::stream! {
             match future.await.into_result() {
                 Ok(channel) => {
+                    if let Some(token) = token {
+                        let mut sessions = sessions.lock().unwrap();
+                        sessions.retain(|(_, sender)| !sender.is_closed());
+                        sessions.push((token, channel.command_sender.clone()));
+                    }
+
                     // Return a message with the realtime upgrade
                     let content = MessageContent {
                         text: "Realtime session established. Starting voice conversation...".to_string(),
@@ -1063,6 +1101,21 @@ impl BotClient for OpenAIRealtimeClient {
         Box::pin(stream)
     }
 
+    fn stop(
+        &mut self,
+        _bot_id: &BotId,
+        token: &CancellationToken,
+    ) -> BoxPlatformSendFuture<'static, ClientResult<()>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|(_, sender)| !sender.is_closed());
+
+        for (_, sender) in sessions.iter().filter(|(t, _)| t == token) {
+            let _ = sender.unbounded_send(RealtimeCommand::CancelResponse);
+        }
+
+        Box::pin(futures::future::ready(ClientResult::new_ok(())))
+    }
+
     fn bots(&self) -> BoxPlatformSendFuture<'static, ClientResult<Vec<Bot>>> {
         // For Realtime, we're currently using `bots` for listing the supported models by the client,
         // rather than the specific supported models by the associated provider (makes things easier elsewhere).
@@ -0,0 +1,421 @@
+use crate::protocol::Tool;
+
+use crate::protocol::*;
+use crate::utils::asynchronous::{BoxPlatformSendFuture, BoxPlatformSendStream, sleep};
+use futures::StreamExt;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Backoff configuration used by [`RetryClient`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum amount of retries after the first attempt.
+    pub max_retries: u32,
+    /// Delay before the first retry.
+    pub initial_delay: Duration,
+    /// Upper bound for the computed delays.
+    ///
+    /// If the server asks (with `Retry-After`) to wait longer than this, the
+    /// error is reported instead of retrying.
+    pub max_delay: Duration,
+    /// Factor applied to the delay after each retry.
+    pub multiplier: f64,
+    /// Fraction of the delay, between `0.0` and `1.0`, that is randomly added
+    /// or removed to avoid many clients retrying at the same time.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Computes how long to wait before the given retry (starting at `0`), or
+    /// `None` if the errors should be reported instead.
+    fn delay(&self, retry: u32, errors: &[ClientError]) -> Option<Duration> {
+        if retry >= self.max_retries || errors.is_empty() {
+            return None;
+        }
+
+        if !errors.iter().all(|e| e.is_transient()) {
+            return None;
+        }
+
+        if let Some(retry_after) = errors.iter().filter_map(|e| e.retry_after()).max() {
+            return (retry_after <= self.max_delay).then_some(retry_after);
+        }
+
+        let delay = self
+            .initial_delay
+            .mul_f64(self.multiplier.max(1.0).powi(retry as i32))
+            .min(self.max_delay);
+
+        Some(with_jitter(delay, self.jitter))
+    }
+}
+
+/// Randomly moves the delay up or down by the given fraction of it.
+fn with_jitter(delay: Duration, jitter: f64) -> Duration {
+    let jitter = jitter.clamp(0.0, 1.0);
+    if jitter == 0.0 {
+        return delay;
+    }
+
+    // Good enough randomness for this, without pulling a dependency.
+    let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+    delay.mul_f64(1.0 + jitter * (random * 2.0 - 1.0))
+}
+
+/// Wraps the errors of the last attempt into a single one mentioning the amount
+/// of attempts made.
+fn give_up<T>(errors: Vec<ClientError>, attempts: u32) -> ClientResult<T> {
+    if attempts <= 1 {
+        return ClientResult::new_err(errors);
+    }
+
+    let errors = errors
+        .into_iter()
+        .map(|error| {
+            let kind = error.kind();
+            let status = error.status();
+            let retry_after = error.retry_after();
+            let message = format!("Failed after {} attempts. {}", attempts, error.message());
+            let wrapped = ClientError::new_with_source(kind, message, Some(error))
+                .with_retry_after(retry_after);
+            match status {
+                Some(status) => wrapped.with_status(status),
+                None => wrapped,
+            }
+        })
+        .collect();
+
+    ClientResult::new_err(errors)
+}
+
+struct Inner<C: BotClient> {
+    client: C,
+    policy: RetryPolicy,
+}
+
+/// Utility wrapper client that retries transient failures of the underlying client.
+///
+/// Network errors, rate limits (HTTP 429) and server errors (HTTP 5xx) are retried
+/// with exponential backoff, honoring `Retry-After` when the server sends it.
+///
+/// For `send`, only the start of the stream is retried. Once any content has been
+/// yielded, later errors are passed through as they are, so content is never
+/// duplicated.
+pub struct RetryClient<C: BotClient> {
+    inner: Arc<Mutex<Inner<C>>>,
+}
+
+impl<C: BotClient> Clone for RetryClient<C> {
+    fn clone(&self) -> Self {
+        RetryClient {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<C: BotClient> RetryClient<C> {
+    pub fn new(client: C) -> Self {
+        RetryClient {
+            inner: Arc::new(Mutex::new(Inner {
+                client,
+                policy: RetryPolicy::default(),
+            })),
+        }
+    }
+
+    /// Sets the backoff configuration used for future requests.
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.inner.lock().unwrap().policy = policy;
+    }
+
+    pub fn policy(&self) -> RetryPolicy {
+        self.inner.lock().unwrap().policy.clone()
+    }
+}
+
+impl<C: BotClient> From<C> for RetryClient<C> {
+    fn from(client: C) -> Self {
+        RetryClient::new(client)
+    }
+}
+
+impl<C: BotClient + 'static> BotClient for RetryClient<C> {
+    fn clone_box(&self) -> Box<dyn BotClient> {
+        Box::new(self.clone())
+    }
+
+    fn bots(&self) -> BoxPlatformSendFuture<'static, ClientResult<Vec<Bot>>> {
+        let inner = self.inner.clone();
+
+        Box::pin(async move {
+            let policy = inner.lock().unwrap().policy.clone();
+            let mut retry = 0;
+
+            loop {
+                let future = inner.lock().unwrap().client.bots();
+                let result = future.await;
+
+                if result.has_value() {
+                    return result;
+                }
+
+                let errors = result.into_errors();
+                match policy.delay(retry, &errors) {
+                    Some(delay) => {
+                        log::warn!("Loading bots failed, retrying in {:?}", delay);
+                        sleep(delay).await;
+                        retry += 1;
+                    }
+                    None => return give_up(errors, retry + 1),
+                }
+            }
+        })
+    }
+
+    fn stop(
+        &mut self,
+        bot_id: &BotId,
+        token: &CancellationToken,
+    ) -> BoxPlatformSendFuture<'static, ClientResult<()>> {
+        self.inner.lock().unwrap().client.stop(bot_id, token)
+    }
+
+    fn send(
+        &mut self,
+        bot_id: &BotId,
+        messages: &[Message],
+        tools: &[Tool],
+        options: &SendOptions,
+    ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
+        let inner = self.inner.clone();
+        let bot_id = bot_id.clone();
+        let messages = messages.to_vec();
+        let tools = tools.to_vec();
+        let options = options.clone();
+
+        let stream = async_stream::stream! {
+            let policy = inner.lock().unwrap().policy.clone();
+            let mut retry = 0;
+
+            loop {
+                let mut stream = inner
+                    .lock()
+                    .unwrap()
+                    .client
+                    .send(&bot_id, &messages, &tools, &options);
+
+                let mut started = false;
+                let mut failure = None;
+
+                while let Some(result) = stream.next().await {
+                    if !started && !result.has_value() {
+                        failure = Some(result.into_errors());
+                        break;
+                    }
+
+                    started = true;
+                    yield result;
+                }
+
+                let Some(errors) = failure else {
+                    return;
+                };
+
+                let cancelled = options
+                    .cancellation
+                    .as_ref()
+                    .is_some_and(|token| token.is_cancelled());
+
+                match policy.delay(retry, &errors).filter(|_| !cancelled) {
+                    Some(delay) => {
+                        log::warn!("Sending to {} failed, retrying in {:?}", bot_id, delay);
+                        sleep(delay).await;
+                        retry += 1;
+                    }
+                    None => {
+                        yield give_up(errors, retry + 1);
+                        return;
+                    }
+                }
+            }
+        };
+
+        Box::pin(stream)
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "json",
+    feature = "http",
+    feature = "async-rt",
+    not(target_arch = "wasm32")
+))]
+mod tests {
+    use super::*;
+    use crate::clients::{AnthropicClient, OpenAIClient};
+    use crate::utils::test_server::{TestResponse, TestServer, block_on};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            initial_delay: Duration::from_millis(1),
+            jitter: 0.0,
+            ..Default::default()
+        }
+    }
+
+    /// Starts a server failing with `status` the first `failures` times.
+    fn flaky_server(failures: usize, status: u16, success: TestResponse) -> TestServer {
+        let count = AtomicUsize::new(0);
+        TestServer::start(move |_| {
+            if count.fetch_add(1, Ordering::SeqCst) < failures {
+                TestResponse::json(status, serde_json::json!({"error": "try later"}))
+                    .with_header("Retry-After", "0")
+            } else {
+                success.clone()
+            }
+        })
+    }
+
+    fn completion(text: &str) -> (Option<&'static str>, String) {
+        let data =
+            serde_json::json!({"choices": [{"delta": {"content": text}, "finish_reason": null}]});
+        (None, data.to_string())
+    }
+
+    fn send(
+        client: &mut RetryClient<OpenAIClient>,
+        url: &str,
+    ) -> Vec<ClientResult<MessageContent>> {
+        let bot_id = BotId::new("model", url);
+        let messages = vec![Message {
+            from: EntityId::User,
+            content: MessageContent {
+                text: "Hi".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }];
+
+        block_on(
+            client
+                .send(&bot_id, &messages, &[], &SendOptions::default())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_send_retries_transient_errors_before_content() {
+        let success = TestResponse::sse([
+            completion("Hello"),
+            completion(" world"),
+            (None, "[DONE]".to_string()),
+        ]);
+        let server = flaky_server(2, 503, success);
+        let mut client = RetryClient::new(OpenAIClient::new(server.url()));
+        client.set_policy(fast_policy());
+
+        let results = send(&mut client, &server.url());
+
+        assert!(results.iter().all(|r| !r.has_errors()));
+        let last = results.last().unwrap().value().unwrap();
+        assert_eq!(last.text, "Hello world");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_send_reports_attempts_when_giving_up() {
+        let server = flaky_server(usize::MAX, 429, TestResponse::new(200, ""));
+        let mut client = RetryClient::new(OpenAIClient::new(server.url()));
+        client.set_policy(RetryPolicy {
+            max_retries: 2,
+            ..fast_policy()
+        });
+
+        let results = send(&mut client, &server.url());
+
+        assert_eq!(results.len(), 1);
+        let errors = results[0].errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].status(), Some(429));
+        assert_eq!(errors[0].retry_after(), Some(Duration::ZERO));
+        assert!(errors[0].message().starts_with("Failed after 3 attempts."));
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_anthropic_overloaded_stream_is_retried() {
+        let count = AtomicUsize::new(0);
+        let server = TestServer::start(move |_| {
+            let data = if count.fetch_add(1, Ordering::SeqCst) == 0 {
+                serde_json::json!({"type": "error", "error": {"type": "overloaded_error", "message": "Overloaded"}})
+            } else {
+                serde_json::json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": "Hello"}})
+            };
+            let event = data["type"].as_str().unwrap().to_string();
+            TestResponse::sse([(Some(event.as_str()), data.to_string())])
+        });
+        let mut client = RetryClient::new(AnthropicClient::new(server.url()));
+        client.set_policy(fast_policy());
+
+        let bot_id = BotId::new("claude-test", &server.url());
+        let messages = vec![Message {
+            from: EntityId::User,
+            content: MessageContent {
+                text: "Hi".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }];
+        let results = block_on(
+            client
+                .send(&bot_id, &messages, &[], &SendOptions::default())
+                .collect::<Vec<_>>(),
+        );
+
+        assert!(results.iter().all(|r| !r.has_errors()));
+        assert_eq!(results.last().unwrap().value().unwrap().text, "Hello");
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_client_errors_are_not_retried() {
+        let server = flaky_server(usize::MAX, 400, TestResponse::new(200, ""));
+        let mut client = RetryClient::new(OpenAIClient::new(server.url()));
+        client.set_policy(fast_policy());
+
+        let result = block_on(client.bots());
+
+        assert_eq!(result.errors()[0].status(), Some(400));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_retry_after_longer_than_max_delay_is_not_waited() {
+        let policy = fast_policy();
+        let error = ClientError::new(ClientErrorKind::Response, "Rate limited".into())
+            .with_status(429)
+            .with_retry_after(Some(Duration::from_secs(3600)));
+
+        assert_eq!(policy.delay(0, &[error.clone()]), None);
+        assert_eq!(
+            policy.delay(0, &[error.with_retry_after(Some(Duration::from_secs(2)))]),
+            Some(Duration::from_secs(2))
+        );
+    }
+}
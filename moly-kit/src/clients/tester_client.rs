@@ -38,6 +38,7 @@ impl BotClient for TesterClient {
         _bot_id: &BotId,
         messages: &[Message],
         _tools: &[Tool],
+        _options: &SendOptions,
     ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>> {
         let messages = messages.to_vec();
 
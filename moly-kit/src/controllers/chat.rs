@@ -1,16 +1,20 @@
 //! Framework-agnostic state management to implement a `Chat` component/widget/element.
 
 use crate::{
-    McpManagerClient, display_name_from_namespaced,
+    McpManagerClient, ToolPolicy, display_name_from_namespaced,
     protocol::*,
     utils::{
-        asynchronous::{AbortOnDropHandle, spawn_abort_on_drop},
+        asynchronous::{AbortOnDropHandle, spawn, spawn_abort_on_drop},
         vec::VecMutation,
     },
 };
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
 
 use futures::StreamExt;
+use serde_json::{Map, Value};
 
 mod plugin;
 mod state;
@@ -74,8 +78,24 @@ pub struct ChatController {
     send_abort_on_drop: Option<AbortOnDropHandle>,
     load_bots_abort_on_drop: Option<AbortOnDropHandle>,
     execute_tools_abort_on_drop: Option<AbortOnDropHandle>,
+    /// Tool calls being executed, until all of their results are in.
+    executing_tool_calls: Vec<ToolCall>,
+    /// Bot which called the tools being executed.
+    executing_bot_id: Option<BotId>,
+    /// When the execution of the current tool calls started.
+    executing_started_at: DateTime<Utc>,
+    /// How tool calls decided without the user were decided, by call id.
+    tool_approvals: HashMap<String, ToolApproval>,
+    /// Tools the user allowed to run without asking in this chat, by
+    /// namespaced name.
+    chat_allowed_tools: HashSet<String>,
     client: Option<Box<dyn BotClient>>,
     tool_manager: Option<McpManagerClient>,
+    /// Options of the last `Send` task, reused when sending tool results back.
+    send_options: SendOptions,
+    /// Token given to the client on every send, so a stop request can reach
+    /// this chat's work only. Replaced after each stop.
+    cancellation_token: CancellationToken,
 }
 
 impl ChatController {
@@ -94,8 +114,15 @@ impl ChatController {
                 send_abort_on_drop: None,
                 load_bots_abort_on_drop: None,
                 execute_tools_abort_on_drop: None,
+                executing_tool_calls: Vec::new(),
+                executing_bot_id: None,
+                executing_started_at: Utc::now(),
+                tool_approvals: HashMap::new(),
+                chat_allowed_tools: HashSet::new(),
                 client: None,
                 tool_manager: None,
+                send_options: SendOptions::default(),
+                cancellation_token: CancellationToken::new(),
             })
         })
     }
@@ -265,11 +292,17 @@ impl ChatController {
         }
 
         match task {
-            ChatTask::Send(bot_id) => {
-                self.handle_send(bot_id);
+            ChatTask::Send(bot_id, options) => {
+                self.handle_send(None, bot_id, options);
+            }
+            ChatTask::Regenerate(index, bot_id, options) => {
+                self.handle_send(Some(index), bot_id, options);
+            }
+            ChatTask::UsePrompt(prompt, arguments, bot_id, options) => {
+                self.handle_use_prompt(prompt, arguments, bot_id, options);
             }
             ChatTask::Stop => {
-                self.clear_streaming_artifacts();
+                self.handle_stop();
             }
             ChatTask::Load => {
                 self.handle_load();
@@ -280,10 +313,15 @@ impl ChatController {
         }
     }
 
-    fn handle_send(&mut self, bot_id: BotId) {
+    /// Starts streaming a new bot message, at the end of the conversation or
+    /// as a new branch replacing the messages from `fork_index` onwards.
+    fn handle_send(&mut self, fork_index: Option<usize>, bot_id: BotId, mut options: SendOptions) {
         // Clean previous streaming artifacts if any.
         self.clear_streaming_artifacts();
 
+        options.cancellation = Some(self.cancellation_token.clone());
+        self.send_options = options.clone();
+
         let Some(mut client) = self.client.clone() else {
             self.dispatch_mutation(VecMutation::Push(Message::app_error(
                 "No bot client configured",
@@ -292,7 +330,7 @@ impl ChatController {
             return;
         };
 
-        self.dispatch_mutation(VecMutation::Push(Message {
+        let message = Message {
             from: EntityId::Bot(bot_id.clone()),
             content: MessageContent::default(),
             metadata: MessageMetadata {
@@ -300,7 +338,14 @@ impl ChatController {
                 ..Default::default()
             },
             ..Default::default()
-        }));
+        };
+
+        match fork_index {
+            Some(index) => {
+                self.dispatch_mutation(self.state.fork_messages(index, vec![message]));
+            }
+            None => self.dispatch_mutation(VecMutation::Push(message)),
+        }
 
         self.dispatch_mutation(ChatStateMutation::SetIsStreaming(true));
 
@@ -323,7 +368,8 @@ impl ChatController {
                 return;
             };
 
-            let message_stream = amortize(client.send(&bot_id, &messages_context, &tools));
+            let message_stream =
+                amortize(client.send(&bot_id, &messages_context, &tools, &options));
             let mut message_stream = std::pin::pin!(message_stream);
             while let Some(result) = message_stream.next().await {
                 let should_break = controller
@@ -334,10 +380,131 @@ impl ChatController {
                     break;
                 }
             }
-            controller.lock_with(|c| c.clear_streaming_artifacts());
+            controller.lock_with(|c| {
+                c.clear_streaming_artifacts();
+                c.apply_tool_policies(bot_id);
+            });
         }));
     }
 
+    /// Decides on the tool calls of the message just received according to
+    /// the tool policies, executing them right away if none of them needs
+    /// the user's approval.
+    fn apply_tool_policies(&mut self, bot_id: BotId) {
+        let Some(tool_manager) = self.tool_manager.as_ref() else {
+            return;
+        };
+
+        let Some(message) = self.state.messages.last() else {
+            return;
+        };
+
+        let pending = message.from == EntityId::Bot(bot_id.clone())
+            && !message.content.tool_calls.is_empty()
+            && message
+                .content
+                .tool_calls
+                .iter()
+                .all(|tc| tc.permission_status == ToolCallPermissionStatus::Pending);
+
+        if !pending {
+            return;
+        }
+
+        let mut needs_approval = false;
+        let mut tool_calls = message.content.tool_calls.clone();
+        for tool_call in &mut tool_calls {
+            let approval = match tool_manager.configured_tool_policy(&tool_call.name) {
+                Some(_) => ToolApproval::Policy,
+                None => ToolApproval::DangerousMode,
+            };
+
+            tool_call.permission_status = match tool_manager.tool_policy(&tool_call.name) {
+                ToolPolicy::Allow => ToolCallPermissionStatus::Approved,
+                ToolPolicy::AllowForChat if self.chat_allowed_tools.contains(&tool_call.name) => {
+                    ToolCallPermissionStatus::Approved
+                }
+                ToolPolicy::Deny => ToolCallPermissionStatus::Denied,
+                ToolPolicy::Ask | ToolPolicy::AllowForChat => {
+                    needs_approval = true;
+                    ToolCallPermissionStatus::Pending
+                }
+            };
+
+            if tool_call.permission_status != ToolCallPermissionStatus::Pending {
+                self.tool_approvals.insert(tool_call.id.clone(), approval);
+            }
+        }
+
+        self.dispatch_mutation(VecMutation::update_last_with(&self.state.messages, |m| {
+            m.content.tool_calls = tool_calls.clone();
+        }));
+
+        if !needs_approval {
+            self.dispatch_task(ChatTask::Execute(tool_calls, Some(bot_id)));
+        }
+    }
+
+    /// Lets the given tools (by namespaced name) run without asking for the
+    /// rest of this chat, if their policy allows it.
+    pub fn allow_tools_for_chat(&mut self, tool_names: impl IntoIterator<Item = String>) {
+        self.chat_allowed_tools.extend(tool_names);
+    }
+
+    /// Stops the current streaming operation on user request.
+    ///
+    /// Besides dropping the local stream, the client is notified through
+    /// [`BotClient::stop`] so it can abort any server-side work.
+    fn handle_stop(&mut self) {
+        if !self.executing_tool_calls.is_empty() {
+            self.stop_tool_execution();
+            return;
+        }
+
+        if self.send_abort_on_drop.is_none() {
+            return;
+        }
+
+        let writing = self
+            .state
+            .messages
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, m)| m.metadata.is_writing)
+            .map(|(index, m)| (index, m.from.clone(), m.content.is_empty()));
+
+        let mut bot_id = None;
+        if let Some((index, from, is_empty)) = writing {
+            if let EntityId::Bot(id) = from {
+                bot_id = Some(id);
+            }
+
+            if !is_empty {
+                self.dispatch_mutation(VecMutation::update_with(
+                    &self.state.messages,
+                    index,
+                    |m| m.metadata.stopped_by_user = true,
+                ));
+            }
+        }
+
+        let token = std::mem::take(&mut self.cancellation_token);
+        token.cancel();
+
+        if let (Some(bot_id), Some(mut client)) = (bot_id, self.client.clone()) {
+            spawn(async move {
+                if let Err(errors) = client.stop(&bot_id, &token).await.into_result() {
+                    for error in errors {
+                        log::error!("Failed to stop bot {}: {}", bot_id, error);
+                    }
+                }
+            });
+        }
+
+        self.clear_streaming_artifacts();
+    }
+
     /// Aborts current streaming operation and cleans up artifacts.
     fn clear_streaming_artifacts(&mut self) {
         if self.send_abort_on_drop.is_none() {
@@ -356,7 +523,9 @@ impl ChatController {
         for (index, message) in self.state.messages.iter().enumerate() {
             if message.metadata.is_writing {
                 if message.content.is_empty() {
-                    indices_to_remove.push(index);
+                    if message.branches.is_empty() {
+                        indices_to_remove.push(index);
+                    }
                 } else {
                     updates_to_dispatch.push(
                         VecMutation::update_with(&self.state.messages, index, |m| {
@@ -370,6 +539,17 @@ impl ChatController {
 
         self.dispatch_mutations(updates_to_dispatch);
         self.dispatch_mutation(VecMutation::RemoveMany::<Message>(indices_to_remove.into()));
+
+        // A failed regeneration must not lose the branches it was holding, so
+        // these are discarded as a branch instead, activating the previous one.
+        let failed_fork = self
+            .state
+            .messages
+            .iter()
+            .position(|m| m.metadata.is_writing && m.content.is_empty());
+        if let Some(index) = failed_fork {
+            self.dispatch_mutation(self.state.discard_branch(index));
+        }
     }
 
     /// Changes the client used by this controller when sending messages and laoding bots.
@@ -381,6 +561,46 @@ impl ChatController {
         self.dispatch_mutation(ChatStateMutation::SetLoadStatus(Status::Idle));
     }
 
+    fn handle_use_prompt(
+        &mut self,
+        prompt: String,
+        arguments: Map<String, Value>,
+        bot_id: BotId,
+        options: SendOptions,
+    ) {
+        let Some(tool_manager) = self.tool_manager.clone() else {
+            self.dispatch_mutation(VecMutation::Push(Message::app_error(
+                "No MCP servers configured",
+            )));
+            return;
+        };
+
+        let controller = self.accessor.clone();
+        self.send_abort_on_drop = Some(spawn_abort_on_drop(async move {
+            let result = tool_manager
+                .get_prompt(&prompt, arguments, &bot_id)
+                .await
+                .map_err(|e| e.to_string());
+
+            controller.lock_with(move |c| match result {
+                Ok(messages) => {
+                    // The bot only answers if the prompt ends with the user's turn.
+                    let send = messages.last().is_some_and(|m| m.from == EntityId::User);
+                    c.dispatch_mutation(VecMutation::Extend(messages));
+                    if send {
+                        c.dispatch_task(ChatTask::Send(bot_id, options));
+                    }
+                }
+                Err(e) => {
+                    c.dispatch_mutation(VecMutation::Push(Message::app_error(format!(
+                        "Failed to use the prompt '{}': {}",
+                        prompt, e
+                    ))));
+                }
+            });
+        }));
+    }
+
     fn handle_load(&mut self) {
         self.dispatch_mutation(ChatStateMutation::SetLoadStatus(Status::Working));
 
@@ -437,12 +657,25 @@ impl ChatController {
                     None => {}
                 }
 
+                // Usage and the answering bot are tracked as metadata, not as
+                // part of the content.
+                let usage = content.usage.take();
+                let answered_by = content.answered_by.take();
+
                 self.dispatch_mutation(VecMutation::update_last_with(
                     &self.state.messages,
                     |message| {
                         message.update_content(|c| {
                             *c = content.clone();
                         });
+
+                        if usage.is_some() {
+                            message.metadata.usage = usage;
+                        }
+
+                        if answered_by.is_some() {
+                            message.metadata.answered_by = answered_by;
+                        }
                     },
                 ));
 
@@ -512,77 +745,265 @@ impl ChatController {
         self.dispatch_mutation(VecMutation::Push(loading_message));
         self.dispatch_mutation(ChatStateMutation::SetIsStreaming(true));
 
-        self.execute_tools_abort_on_drop = Some(spawn_abort_on_drop(async move {
-            // Execute tool calls using MCP manager
-            let tool_results = tool_manager.execute_tool_calls(tool_calls.clone()).await;
+        self.executing_tool_calls = tool_calls.clone();
+        self.executing_bot_id = bot_id.clone();
+        self.executing_started_at = Utc::now();
 
-            // Create formatted text for tool results
-            let results_text = if tool_results.len() == 1 {
-                let result = &tool_results[0];
-                let tool_name = tool_calls
-                    .iter()
-                    .find(|tc| tc.id == result.tool_call_id)
-                    .map(|tc| tc.name.as_str())
-                    .unwrap_or("unknown");
+        // Denied calls still need a result, telling the bot they didn't run.
+        let (denied, allowed): (Vec<_>, Vec<_>) = tool_calls
+            .iter()
+            .cloned()
+            .partition(|tc| tc.permission_status == ToolCallPermissionStatus::Denied);
+        let denied_results = denied.iter().map(denied_tool_result).collect::<Vec<_>>();
 
-                let display_name = display_name_from_namespaced(tool_name);
-                if result.is_error {
-                    format!("🔧 Tool '{}' failed:\n{}", display_name, result.content)
-                } else {
-                    let summary = crate::utils::tool_execution::create_tool_output_summary(
-                        tool_name,
-                        &result.content,
-                    );
-                    format!(
-                        "🔧 Tool '{}' executed successfully:\n`{}`",
-                        display_name, summary
-                    )
-                }
-            } else {
-                let mut text = format!("🔧 Executed {} tools:\n\n", tool_results.len());
-                for result in &tool_results {
-                    let tool_name = tool_calls
-                        .iter()
-                        .find(|tc| tc.id == result.tool_call_id)
-                        .map(|tc| tc.name.as_str())
-                        .unwrap_or("unknown");
-
-                    let display_name = display_name_from_namespaced(tool_name);
-                    if result.is_error {
-                        text.push_str(&format!("**{}** ❌: {}\n\n", display_name, result.content));
-                    } else {
-                        let summary = crate::utils::tool_execution::create_tool_output_summary(
-                            tool_name,
-                            &result.content,
+        for (tool_call, result) in denied.iter().zip(&denied_results) {
+            self.record_tool_execution(tool_call, Utc::now(), Duration::ZERO, result);
+        }
+
+        self.execute_tools_abort_on_drop = Some(spawn_abort_on_drop(async move {
+            // Results are shown as each call finishes, the bot only gets them
+            // once all are in.
+            let mut stream = tool_manager.execute_timed_tool_calls_stream(allowed);
+            let mut tool_results: Vec<ToolResult> = denied_results;
+
+            while let Some(execution) = stream.next().await {
+                tool_results.push(execution.result.clone());
+                let tool_results = tool_results.clone();
+                let tool_call = tool_calls
+                    .iter()
+                    .find(|tc| tc.id == execution.result.tool_call_id);
+
+                controller.lock_with(|c| {
+                    if let Some(tool_call) = tool_call {
+                        c.record_tool_execution(
+                            tool_call,
+                            execution.started_at,
+                            execution.duration,
+                            &execution.result,
                         );
-                        text.push_str(&format!("**{}** ✅: `{}`\n\n", display_name, summary));
                     }
-                }
-                text
-            };
+
+                    c.update_executing_tool_message(|m| {
+                        m.content.text = tool_results_text(&tool_calls, &tool_results);
+                        m.content.tool_results = tool_results;
+                    });
+                });
+            }
+
+            // Keep the order of the calls, as expected by some clients.
+            tool_results.sort_by_key(|r| tool_calls.iter().position(|tc| tc.id == r.tool_call_id));
 
             controller.lock_with(|c| {
-                c.dispatch_mutation(ChatStateMutation::SetIsStreaming(false));
-                c.dispatch_mutation(VecMutation::remove_many_with_retain(
-                    &c.state.messages,
-                    |_, m| !(m.metadata.is_writing && m.from == EntityId::Tool),
-                ));
-                c.dispatch_mutation(VecMutation::Push(Message {
-                    from: EntityId::Tool, // Tool results use the tool role
-                    content: MessageContent {
-                        text: results_text,
-                        tool_results: tool_results,
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                }));
+                c.finish_tool_execution(&tool_calls, tool_results, false);
 
                 if let Some(bot_id) = bot_id {
-                    c.dispatch_task(ChatTask::Send(bot_id));
+                    let options = c.send_options.clone();
+                    c.dispatch_task(ChatTask::Send(bot_id, options));
                 }
             });
         }));
     }
+
+    /// Applies changes to the tool message holding the results of the calls
+    /// being executed.
+    fn update_executing_tool_message(&mut self, updater: impl FnOnce(&mut Message)) {
+        let index = self
+            .state
+            .messages
+            .iter()
+            .rposition(|m| m.metadata.is_writing && m.from == EntityId::Tool);
+
+        if let Some(index) = index {
+            self.dispatch_mutation(VecMutation::update_with(
+                &self.state.messages,
+                index,
+                updater,
+            ));
+        }
+    }
+
+    /// Cancels the tool calls still running, and completes their results so
+    /// every call in the conversation keeps its result.
+    fn stop_tool_execution(&mut self) {
+        // Dropping the task drops the calls, which notifies their servers.
+        self.execute_tools_abort_on_drop = None;
+        let tool_calls = self.executing_tool_calls.clone();
+
+        let mut tool_results = self
+            .state
+            .messages
+            .iter()
+            .rfind(|m| m.metadata.is_writing && m.from == EntityId::Tool)
+            .map(|m| m.content.tool_results.clone())
+            .unwrap_or_default();
+
+        for tool_call in &tool_calls {
+            if tool_results.iter().any(|r| r.tool_call_id == tool_call.id) {
+                continue;
+            }
+
+            let result = ToolResult {
+                tool_call_id: tool_call.id.clone(),
+                content: format!(
+                    "Tool execution was cancelled by the user. Tool '{}' did not finish.",
+                    display_name_from_namespaced(&tool_call.name)
+                ),
+                is_error: true,
+                ..Default::default()
+            };
+
+            let started_at = self.executing_started_at;
+            let duration = (Utc::now() - started_at).to_std().unwrap_or_default();
+            self.record_tool_execution(tool_call, started_at, duration, &result);
+            tool_results.push(result);
+        }
+
+        self.finish_tool_execution(&tool_calls, tool_results, true);
+    }
+
+    /// Tells the plugins what happened with a tool call.
+    fn record_tool_execution(
+        &mut self,
+        tool_call: &ToolCall,
+        started_at: DateTime<Utc>,
+        duration: Duration,
+        result: &ToolResult,
+    ) {
+        let record = ToolExecutionRecord {
+            bot_id: self.executing_bot_id.clone(),
+            tool_call: tool_call.clone(),
+            approval: self
+                .tool_approvals
+                .remove(&tool_call.id)
+                .unwrap_or(ToolApproval::Manual),
+            started_at,
+            duration,
+            result: result.clone(),
+        };
+
+        self.notify_tool_executed(&record);
+    }
+
+    /// Tells the plugins about a tool call. Calls executed by this controller
+    /// are reported on their own, this is for the ones handled elsewhere, like
+    /// in realtime conversations.
+    pub fn notify_tool_executed(&mut self, record: &ToolExecutionRecord) {
+        for (_, plugin) in &mut self.plugins {
+            plugin.on_tool_executed(record);
+        }
+    }
+
+    /// Tells the plugins about tool calls the user denied without asking this
+    /// controller to execute them.
+    pub fn notify_tool_calls_denied(&mut self, tool_calls: &[ToolCall], bot_id: Option<BotId>) {
+        for tool_call in tool_calls {
+            self.notify_tool_executed(&ToolExecutionRecord {
+                bot_id: bot_id.clone(),
+                tool_call: tool_call.clone(),
+                approval: ToolApproval::Manual,
+                started_at: Utc::now(),
+                duration: Duration::ZERO,
+                result: denied_tool_result(tool_call),
+            });
+        }
+    }
+
+    /// Replaces the in-progress tool message with the final one.
+    fn finish_tool_execution(
+        &mut self,
+        tool_calls: &[ToolCall],
+        tool_results: Vec<ToolResult>,
+        stopped_by_user: bool,
+    ) {
+        self.executing_tool_calls.clear();
+        self.executing_bot_id = None;
+        self.tool_approvals.clear();
+        self.dispatch_mutation(ChatStateMutation::SetIsStreaming(false));
+        self.dispatch_mutation(VecMutation::remove_many_with_retain(
+            &self.state.messages,
+            |_, m| !(m.metadata.is_writing && m.from == EntityId::Tool),
+        ));
+        self.dispatch_mutation(VecMutation::Push(Message {
+            from: EntityId::Tool, // Tool results use the tool role
+            content: MessageContent {
+                text: tool_results_text(tool_calls, &tool_results),
+                tool_results,
+                ..Default::default()
+            },
+            metadata: MessageMetadata {
+                stopped_by_user,
+                ..MessageMetadata::new()
+            },
+            ..Default::default()
+        }));
+    }
+}
+
+/// The result given to the bot for a tool call the user didn't allow.
+pub fn denied_tool_result(tool_call: &ToolCall) -> ToolResult {
+    ToolResult {
+        tool_call_id: tool_call.id.clone(),
+        content: format!(
+            "Tool execution was denied by the user. Tool '{}' was not executed.",
+            display_name_from_namespaced(&tool_call.name)
+        ),
+        is_error: true,
+        ..Default::default()
+    }
+}
+
+/// Formats the results of a tool execution for display, while in progress
+/// or once all the results are in.
+fn tool_results_text(tool_calls: &[ToolCall], tool_results: &[ToolResult]) -> String {
+    let tool_name = |result: &ToolResult| {
+        tool_calls
+            .iter()
+            .find(|tc| tc.id == result.tool_call_id)
+            .map(|tc| tc.name.clone())
+            .unwrap_or_else(|| "unknown".to_string())
+    };
+
+    if let ([_], [result]) = (tool_calls, tool_results) {
+        let tool_name = tool_name(result);
+        let display_name = display_name_from_namespaced(&tool_name);
+        return if result.is_error {
+            format!("🔧 Tool '{}' failed:\n{}", display_name, result.content)
+        } else {
+            let summary = crate::utils::tool_execution::create_tool_output_summary(
+                &tool_name,
+                &result.content,
+            );
+            format!(
+                "🔧 Tool '{}' executed successfully:\n`{}`",
+                display_name, summary
+            )
+        };
+    }
+
+    let mut text = if tool_results.len() < tool_calls.len() {
+        format!(
+            "🔧 Executing {} tools ({} finished):\n\n",
+            tool_calls.len(),
+            tool_results.len()
+        )
+    } else {
+        format!("🔧 Executed {} tools:\n\n", tool_results.len())
+    };
+
+    for result in tool_results {
+        let tool_name = tool_name(result);
+        let display_name = display_name_from_namespaced(&tool_name);
+        if result.is_error {
+            text.push_str(&format!("**{}** ❌: {}\n\n", display_name, result.content));
+        } else {
+            let summary = crate::utils::tool_execution::create_tool_output_summary(
+                &tool_name,
+                &result.content,
+            );
+            text.push_str(&format!("**{}** ✅: `{}`\n\n", display_name, summary));
+        }
+    }
+    text
 }
 
 pub struct ChatControllerBuilder(Arc<Mutex<ChatController>>);
@@ -1,6 +1,8 @@
 use super::{state::*, task::*};
 use crate::protocol::*;
+use chrono::{DateTime, Utc};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
 /// Controls if remaining callbacks and default behavior should be executed.
 pub enum ChatControl {
@@ -53,12 +55,44 @@ pub trait ChatControllerPlugin: Send {
     /// > Note: Mutations are the focus of this method, so they are given as the first parameter.
     fn on_state_mutation(&mut self, _mutation: &ChatStateMutation, _state: &ChatState) {}
 
+    /// Called once for every tool call the controller handled, after it
+    /// finished, failed, was cancelled or was denied.
+    ///
+    /// Useful to keep a record of what tools did.
+    fn on_tool_executed(&mut self, _record: &ToolExecutionRecord) {}
+
     // TODO: Remove this very specific method later.
     fn on_upgrade(&mut self, upgrade: Upgrade, _bot_id: &BotId) -> Option<Upgrade> {
         Some(upgrade)
     }
 }
 
+/// Who decided whether a tool call could run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToolApproval {
+    /// The user approved or denied it.
+    Manual,
+    /// It ran without asking because dangerous mode is enabled.
+    DangerousMode,
+    /// The policy configured for the tool (or a decision the user remembered
+    /// for it) decided.
+    Policy,
+}
+
+/// What happened with a tool call, given to [`ChatControllerPlugin::on_tool_executed`].
+#[derive(Clone, Debug)]
+pub struct ToolExecutionRecord {
+    /// The bot which called the tool, if known.
+    pub bot_id: Option<BotId>,
+    /// Its permission status tells if it was allowed to run.
+    pub tool_call: ToolCall,
+    pub approval: ToolApproval,
+    pub started_at: DateTime<Utc>,
+    /// Zero for denied calls.
+    pub duration: Duration,
+    pub result: ToolResult,
+}
+
 /// Unique identifier for a registered plugin. Can be used to unregister it later.
 // TODO: Consider identifying plugins just by their type for simplicity on most use cases.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
@@ -46,6 +46,79 @@ impl ChatState {
     pub fn get_bot(&self, bot_id: &BotId) -> Option<&Bot> {
         self.bots.iter().find(|b| &b.id == bot_id)
     }
+
+    /// Position of the message at `index` among its siblings and the amount of
+    /// siblings, if there is more than one version of it.
+    pub fn branch_position(&self, index: usize) -> Option<(usize, usize)> {
+        let message = self.messages.get(index)?;
+        let total = message.branches.len() + 1;
+        (total > 1).then_some((message.branch_index.min(total - 1), total))
+    }
+
+    /// Creates a mutation that replaces the messages from `index` onwards with
+    /// `continuation`, keeping the current ones as a sibling branch.
+    ///
+    /// The new branch becomes the last sibling and the active one.
+    pub fn fork_messages(&self, index: usize, continuation: Vec<Message>) -> VecMutation<Message> {
+        if index >= self.messages.len() {
+            return VecMutation::Extend(continuation);
+        }
+
+        let mut siblings = siblings_at(&self.messages, index);
+        siblings.push(continuation);
+        let active = siblings.len() - 1;
+        VecMutation::Splice(index, self.messages.len(), join_siblings(siblings, active))
+    }
+
+    /// Creates a mutation that makes the sibling at `branch` the active version
+    /// of the conversation from `index` onwards.
+    pub fn switch_branch(&self, index: usize, branch: usize) -> Option<VecMutation<Message>> {
+        let (_, total) = self.branch_position(index)?;
+        if branch >= total {
+            return None;
+        }
+
+        let siblings = siblings_at(&self.messages, index);
+        Some(VecMutation::Splice(
+            index,
+            self.messages.len(),
+            join_siblings(siblings, branch),
+        ))
+    }
+
+    /// Creates a mutation that removes the active version of the conversation
+    /// from `index` onwards, activating the previous sibling if any.
+    pub fn discard_branch(&self, index: usize) -> VecMutation<Message> {
+        let mut siblings = siblings_at(&self.messages, index);
+        let active = self.messages[index].branch_index.min(siblings.len() - 1);
+        siblings.remove(active);
+
+        if siblings.is_empty() {
+            return VecMutation::RemoveRange(index, self.messages.len());
+        }
+
+        let active = active.saturating_sub(1);
+        VecMutation::Splice(index, self.messages.len(), join_siblings(siblings, active))
+    }
+}
+
+/// All the versions of the conversation from `index` onwards, in sibling order.
+fn siblings_at(messages: &[Message], index: usize) -> Vec<Vec<Message>> {
+    let mut current = messages[index..].to_vec();
+    let mut siblings = std::mem::take(&mut current[0].branches);
+    let active = current[0].branch_index.min(siblings.len());
+    current[0].branch_index = 0;
+    siblings.insert(active, current);
+    siblings
+}
+
+/// Inverse of [`siblings_at`], builds the active continuation holding the rest
+/// of the siblings as branches.
+fn join_siblings(mut siblings: Vec<Vec<Message>>, active: usize) -> Vec<Message> {
+    let mut continuation = siblings.remove(active);
+    continuation[0].branches = siblings;
+    continuation[0].branch_index = active;
+    continuation
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -86,3 +159,69 @@ impl From<VecMutation<Bot>> for ChatStateMutation {
         ChatStateMutation::MutateBots(mutation)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(from: EntityId, text: &str) -> Message {
+        Message {
+            from,
+            content: MessageContent {
+                text: text.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn texts(state: &ChatState) -> Vec<&str> {
+        state
+            .messages
+            .iter()
+            .map(|m| m.content.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_fork_and_switch_branches() {
+        let bot = EntityId::Bot(BotId::new("model", "provider"));
+        let mut state = ChatState {
+            messages: vec![
+                message(EntityId::User, "Hi"),
+                message(bot.clone(), "Hello"),
+                message(EntityId::User, "Bye"),
+            ],
+            ..Default::default()
+        };
+
+        state
+            .fork_messages(1, vec![message(bot.clone(), "Hey")])
+            .apply(&mut state.messages);
+        state
+            .fork_messages(1, vec![message(bot.clone(), "Howdy")])
+            .apply(&mut state.messages);
+
+        assert_eq!(texts(&state), ["Hi", "Howdy"]);
+        assert_eq!(state.branch_position(1), Some((2, 3)));
+        assert_eq!(state.branch_position(0), None);
+
+        state
+            .switch_branch(1, 0)
+            .unwrap()
+            .apply(&mut state.messages);
+        assert_eq!(texts(&state), ["Hi", "Hello", "Bye"]);
+        assert_eq!(state.branch_position(1), Some((0, 3)));
+
+        state
+            .switch_branch(1, 1)
+            .unwrap()
+            .apply(&mut state.messages);
+        assert_eq!(texts(&state), ["Hi", "Hey"]);
+
+        state.discard_branch(1).apply(&mut state.messages);
+        assert_eq!(texts(&state), ["Hi", "Hello", "Bye"]);
+        assert_eq!(state.branch_position(1), Some((0, 2)));
+        assert!(state.switch_branch(1, 2).is_none());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::protocol::*;
+use serde_json::{Map, Value};
 
 /// Represents complex (mostly async) operations that may cause multiple mutations
 /// over time.
@@ -6,10 +7,19 @@ use crate::protocol::*;
 pub enum ChatTask {
     /// Causes the whole list of messages to be sent to the specified bot and starts
     /// the streaming response work in the background.
-    Send(BotId),
+    ///
+    /// The options are also reused for the follow-up request made after executing
+    /// tools.
+    Send(BotId, SendOptions),
+    /// Like `Send`, but the response replaces the messages from the given index
+    /// onwards as a new branch, keeping the previous ones as a sibling.
+    Regenerate(usize, BotId, SendOptions),
     /// Calls the given MCP tools. If a bot is specified, successful tool calls
     /// will be processed by that bot.
     Execute(Vec<ToolCall>, Option<BotId>),
+    /// Expands an MCP prompt (by namespaced name) with the given arguments into
+    /// messages, then sends them to the bot like `Send`.
+    UsePrompt(String, Map<String, Value>, BotId, SendOptions),
     /// Interrupts the streaming started by `Send`.
     Stop,
     /// Should be triggered to start fetching async data (e.g. bots).
@@ -1,3 +1,14 @@
+#[cfg(not(target_arch = "wasm32"))]
+mod concurrent_http;
+pub mod local_tools;
 pub mod mcp_manager;
+#[cfg(not(target_arch = "wasm32"))]
+mod oauth;
+pub mod permissions;
+pub mod resources;
+pub mod schema;
 
+pub use local_tools::{BuiltinTool, BuiltinToolProvider, LocalToolProvider};
 pub use mcp_manager::*;
+pub use permissions::ToolPolicy;
+pub use resources::{McpPrompt, McpPromptArgument, McpResource, parse_prompt_command};
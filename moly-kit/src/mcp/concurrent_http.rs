@@ -0,0 +1,101 @@
+//! Streamable HTTP client which doesn't wait for the answer to a request before
+//! sending the next message to the server.
+//!
+//! The rmcp transport posts one message at a time, waiting for the response
+//! of each. Requests are answered here with an event stream which does the
+//! post in the background instead, so calls to the same server run
+//! concurrently, and cancellations reach it while the call is still running.
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use rmcp::model::{ClientJsonRpcMessage, ClientRequest, ErrorData, ServerJsonRpcMessage};
+use rmcp::transport::common::client_side_sse::BoxedSseResponse;
+use rmcp::transport::streamable_http_client::{
+    StreamableHttpClient, StreamableHttpError, StreamableHttpPostResponse,
+};
+use sse_stream::Sse;
+
+/// Wraps the client which sends the messages.
+#[derive(Clone)]
+pub(crate) struct ConcurrentHttpClient<C>(pub C);
+
+impl<C: StreamableHttpClient + Sync> StreamableHttpClient for ConcurrentHttpClient<C> {
+    type Error = C::Error;
+
+    async fn post_message(
+        &self,
+        uri: Arc<str>,
+        message: ClientJsonRpcMessage,
+        session_id: Option<Arc<str>>,
+        auth_header: Option<String>,
+    ) -> Result<StreamableHttpPostResponse, StreamableHttpError<Self::Error>> {
+        let id = match &message {
+            ClientJsonRpcMessage::Request(request)
+                if !matches!(request.request, ClientRequest::InitializeRequest(_)) =>
+            {
+                request.id.clone()
+            }
+            // The handshake needs the session id from the response, and
+            // notifications are answered right away.
+            _ => {
+                return self
+                    .0
+                    .post_message(uri, message, session_id, auth_header)
+                    .await;
+            }
+        };
+
+        let client = self.0.clone();
+        let events = async move {
+            match client
+                .post_message(uri, message, session_id, auth_header)
+                .await
+            {
+                Ok(StreamableHttpPostResponse::Sse(events, _)) => events,
+                Ok(StreamableHttpPostResponse::Json(message, _)) => single_event(&message),
+                Ok(StreamableHttpPostResponse::Accepted) => futures::stream::empty().boxed(),
+                // An error in the stream would make the transport reconnect to
+                // it, so the request is answered with the error instead.
+                Err(e) => single_event(&ServerJsonRpcMessage::error(
+                    ErrorData::internal_error(e.to_string(), None),
+                    id,
+                )),
+            }
+        };
+
+        Ok(StreamableHttpPostResponse::Sse(
+            futures::stream::once(events).flatten().boxed(),
+            None,
+        ))
+    }
+
+    async fn delete_session(
+        &self,
+        uri: Arc<str>,
+        session_id: Arc<str>,
+        auth_header: Option<String>,
+    ) -> Result<(), StreamableHttpError<Self::Error>> {
+        self.0.delete_session(uri, session_id, auth_header).await
+    }
+
+    async fn get_stream(
+        &self,
+        uri: Arc<str>,
+        session_id: Arc<str>,
+        last_event_id: Option<String>,
+        auth_header: Option<String>,
+    ) -> Result<BoxedSseResponse, StreamableHttpError<Self::Error>> {
+        self.0
+            .get_stream(uri, session_id, last_event_id, auth_header)
+            .await
+    }
+}
+
+fn single_event(message: &ServerJsonRpcMessage) -> BoxedSseResponse {
+    let event = Sse {
+        data: serde_json::to_string(message).ok(),
+        ..Default::default()
+    };
+    futures::stream::iter([Ok(event)]).boxed()
+}
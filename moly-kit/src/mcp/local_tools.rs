@@ -0,0 +1,712 @@
+//! Tools run in-process, without an external MCP server.
+//!
+//! A [`LocalToolProvider`] is registered in the
+//! [`McpManagerClient`](super::McpManagerClient) like a server would be, so
+//! its tools are namespaced by its id and go through the same argument
+//! validation, policies and timeouts.
+
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::{Map, Value, json};
+use url::{Host, Url};
+
+use crate::protocol::Tool;
+use crate::utils::asynchronous::{BoxPlatformSendFuture, run_blocking};
+use crate::utils::scraping::{extract_text, extract_title, fetch_html_with};
+
+/// Offers tools implemented in Rust, called without going through MCP.
+pub trait LocalToolProvider: Send + Sync {
+    /// Namespace of the tools, used like the id of a server. It must not be
+    /// the id of a configured server.
+    fn id(&self) -> &str;
+
+    /// The tools offered, named without the namespace.
+    fn tools(&self) -> Vec<Tool>;
+
+    /// Runs one of the tools, returning the text given back to the model.
+    ///
+    /// The arguments were already validated against the schema of the tool.
+    fn call_tool(
+        &self,
+        name: &str,
+        arguments: Map<String, Value>,
+    ) -> BoxPlatformSendFuture<'static, Result<String, String>>;
+}
+
+/// Namespace of the tools offered by [`BuiltinToolProvider`].
+pub const BUILTIN_TOOLS_ID: &str = "builtin";
+
+/// Files bigger than this are not read, to avoid flooding the context.
+const MAX_FILE_SIZE: u64 = 256 * 1024;
+
+/// Fetched pages are cut after this amount of characters.
+const MAX_FETCHED_CHARS: usize = 20_000;
+
+/// Redirects followed when fetching a page.
+#[cfg(not(target_arch = "wasm32"))]
+const MAX_REDIRECTS: usize = 10;
+
+/// The tools shipped with Moly Kit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BuiltinTool {
+    /// Reads a text file inside the workspace directory.
+    ReadFile,
+    /// Lists a directory inside the workspace directory.
+    ListDirectory,
+    /// Fetches a web page and extracts its readable text.
+    FetchUrl,
+    /// Evaluates an arithmetic expression.
+    Calculate,
+}
+
+impl BuiltinTool {
+    pub const ALL: [BuiltinTool; 4] = [
+        BuiltinTool::ReadFile,
+        BuiltinTool::ListDirectory,
+        BuiltinTool::FetchUrl,
+        BuiltinTool::Calculate,
+    ];
+
+    /// Name of the tool, without the namespace.
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinTool::ReadFile => "read_file",
+            BuiltinTool::ListDirectory => "list_directory",
+            BuiltinTool::FetchUrl => "fetch_url",
+            BuiltinTool::Calculate => "calculate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|tool| tool.name() == name)
+    }
+
+    /// If the tool only works with a workspace directory configured.
+    pub fn needs_workspace(self) -> bool {
+        matches!(self, BuiltinTool::ReadFile | BuiltinTool::ListDirectory)
+    }
+
+    fn definition(self) -> Tool {
+        let (description, schema) = match self {
+            BuiltinTool::ReadFile => (
+                "Reads a text file from the user's workspace directory.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path of the file, relative to the workspace directory"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+            ),
+            BuiltinTool::ListDirectory => (
+                "Lists the files and directories in a directory of the user's workspace. Directories end with a slash.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path of the directory, relative to the workspace directory. Defaults to the workspace directory itself"
+                        }
+                    }
+                }),
+            ),
+            BuiltinTool::FetchUrl => (
+                "Fetches a web page and returns its title and readable text.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "url": {"type": "string", "description": "An http or https URL"}
+                    },
+                    "required": ["url"]
+                }),
+            ),
+            BuiltinTool::Calculate => (
+                "Evaluates an arithmetic expression with +, -, *, /, %, ^ and parentheses.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "expression": {"type": "string", "description": "Like `(2 + 3) * 4.5 ^ 2`"}
+                    },
+                    "required": ["expression"]
+                }),
+            ),
+        };
+
+        let Value::Object(input_schema) = schema else {
+            unreachable!();
+        };
+
+        Tool {
+            name: self.name().to_string(),
+            description: Some(description.to_string()),
+            input_schema: Arc::new(input_schema),
+        }
+    }
+}
+
+/// Offers the enabled [`BuiltinTool`]s under the [`BUILTIN_TOOLS_ID`]
+/// namespace.
+///
+/// File tools can't access anything outside of the workspace directory, and
+/// are not offered without one.
+#[derive(Clone, Debug, Default)]
+pub struct BuiltinToolProvider {
+    workspace: Option<PathBuf>,
+    enabled: Vec<BuiltinTool>,
+}
+
+impl BuiltinToolProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_workspace(mut self, workspace: impl Into<PathBuf>) -> Self {
+        self.workspace = Some(workspace.into());
+        self
+    }
+
+    pub fn with_tool(mut self, tool: BuiltinTool) -> Self {
+        if !self.enabled.contains(&tool) {
+            self.enabled.push(tool);
+        }
+        self
+    }
+
+    /// The enabled tools which can actually be used.
+    pub fn available_tools(&self) -> Vec<BuiltinTool> {
+        self.enabled
+            .iter()
+            .copied()
+            .filter(|tool| !tool.needs_workspace() || self.workspace.is_some())
+            .collect()
+    }
+}
+
+impl LocalToolProvider for BuiltinToolProvider {
+    fn id(&self) -> &str {
+        BUILTIN_TOOLS_ID
+    }
+
+    fn tools(&self) -> Vec<Tool> {
+        self.available_tools()
+            .into_iter()
+            .map(BuiltinTool::definition)
+            .collect()
+    }
+
+    fn call_tool(
+        &self,
+        name: &str,
+        arguments: Map<String, Value>,
+    ) -> BoxPlatformSendFuture<'static, Result<String, String>> {
+        let tool = BuiltinTool::from_name(name).filter(|t| self.available_tools().contains(t));
+        let name = name.to_string();
+        let workspace = self.workspace.clone();
+
+        Box::pin(async move {
+            let Some(tool) = tool else {
+                return Err(format!("Unknown tool '{}'", name));
+            };
+
+            let argument = |key: &str| arguments.get(key).and_then(|v| v.as_str());
+            let workspace_dir = || {
+                workspace
+                    .as_deref()
+                    .ok_or_else(|| "No workspace directory is configured".to_string())
+            };
+
+            match tool {
+                BuiltinTool::ReadFile => {
+                    let workspace = workspace_dir()?.to_path_buf();
+                    let path = argument("path").unwrap_or("").to_string();
+                    run_blocking(move || read_file(&workspace, &path)).await
+                }
+                BuiltinTool::ListDirectory => {
+                    let workspace = workspace_dir()?.to_path_buf();
+                    let path = argument("path").unwrap_or(".").to_string();
+                    run_blocking(move || list_directory(&workspace, &path)).await
+                }
+                BuiltinTool::FetchUrl => fetch_url(argument("url").unwrap_or("")).await,
+                BuiltinTool::Calculate => {
+                    evaluate(argument("expression").unwrap_or("")).map(format_number)
+                }
+            }
+        })
+    }
+}
+
+/// Resolves the path relative to the workspace, failing if the result (with
+/// symlinks followed) is outside of it.
+fn resolve_in_workspace(workspace: &Path, path: &str) -> Result<PathBuf, String> {
+    let workspace = workspace
+        .canonicalize()
+        .map_err(|e| format!("The workspace directory is not accessible: {}", e))?;
+
+    let resolved = workspace
+        .join(path)
+        .canonicalize()
+        .map_err(|e| format!("Can't access '{}': {}", path, e))?;
+
+    if !resolved.starts_with(&workspace) {
+        return Err(format!("'{}' is outside of the workspace directory", path));
+    }
+
+    Ok(resolved)
+}
+
+fn read_file(workspace: &Path, path: &str) -> Result<String, String> {
+    let resolved = resolve_in_workspace(workspace, path)?;
+    let metadata = std::fs::metadata(&resolved).map_err(|e| e.to_string())?;
+
+    if !metadata.is_file() {
+        return Err(format!("'{}' is not a file", path));
+    }
+
+    if metadata.len() > MAX_FILE_SIZE {
+        return Err(format!(
+            "'{}' is too big to be read ({} bytes, the limit is {})",
+            path,
+            metadata.len(),
+            MAX_FILE_SIZE
+        ));
+    }
+
+    let bytes = std::fs::read(&resolved).map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|_| format!("'{}' is not a text file", path))
+}
+
+fn list_directory(workspace: &Path, path: &str) -> Result<String, String> {
+    let resolved = resolve_in_workspace(workspace, path)?;
+    let entries = std::fs::read_dir(&resolved).map_err(|e| format!("'{}': {}", path, e))?;
+
+    let mut names = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let mut name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                name.push('/');
+            }
+            name
+        })
+        .collect::<Vec<_>>();
+
+    if names.is_empty() {
+        return Ok("The directory is empty.".to_string());
+    }
+
+    names.sort();
+    Ok(names.join("\n"))
+}
+
+async fn fetch_url(url: &str) -> Result<String, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+    check_public_url(&parsed)?;
+
+    let document = fetch_html_with(&public_client()?, url)
+        .await
+        .map_err(|e| format!("Failed to fetch '{}': {}", url, e))?;
+
+    let mut text = String::new();
+    if let Some(title) = extract_title(&document) {
+        text.push_str(&format!("Title: {}\n\n", title));
+    }
+
+    let body = extract_text(&document);
+    if body.chars().count() > MAX_FETCHED_CHARS {
+        text.extend(body.chars().take(MAX_FETCHED_CHARS));
+        text.push_str("\n[Truncated]");
+    } else {
+        text.push_str(&body);
+    }
+
+    Ok(text)
+}
+
+/// Fails for URLs which are not http or https, or point to an address which is
+/// not public, see [`is_public_address`].
+///
+/// Domains are checked once resolved, by [`PublicResolver`].
+fn check_public_url(url: &Url) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "Only http and https URLs can be fetched, got '{}'",
+            url
+        ));
+    }
+
+    let ip = match url.host() {
+        Some(Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(Host::Ipv6(ip)) => IpAddr::V6(ip),
+        Some(Host::Domain(_)) => return Ok(()),
+        None => return Err(format!("'{}' has no host", url)),
+    };
+
+    if !is_public_address(ip) {
+        return Err(format!("{} is not a public address", ip));
+    }
+
+    Ok(())
+}
+
+/// If the address is reachable from the internet, as opposed to the user's
+/// machine, their local network or the metadata service of cloud providers
+/// (`169.254.169.254`), which the model must not reach.
+fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || a == 0
+                // Shared address space used by carrier-grade NAT.
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_address(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            }
+        },
+    }
+}
+
+/// Client which only connects to public addresses, including on redirects.
+#[cfg(not(target_arch = "wasm32"))]
+fn public_client() -> Result<reqwest::Client, String> {
+    let redirect = reqwest::redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("Too many redirects");
+        }
+
+        match check_public_url(attempt.url()) {
+            Ok(()) => attempt.follow(),
+            Err(e) => attempt.error(e),
+        }
+    });
+
+    reqwest::Client::builder()
+        .dns_resolver(Arc::new(PublicResolver))
+        .redirect(redirect)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// The browser doesn't let pages reach local addresses on its own.
+#[cfg(target_arch = "wasm32")]
+fn public_client() -> Result<reqwest::Client, String> {
+    Ok(reqwest::Client::new())
+}
+
+/// Resolves domains with the system resolver, failing for those with any
+/// address which is not public.
+///
+/// The checked addresses are the ones connected to, so a domain can't resolve
+/// to a public address when checked and to a private one when connecting.
+#[cfg(not(target_arch = "wasm32"))]
+struct PublicResolver;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            let addresses = run_blocking(move || {
+                use std::net::ToSocketAddrs;
+                (host.as_str(), 0)
+                    .to_socket_addrs()
+                    .map(|addresses| addresses.collect::<Vec<_>>())
+                    .map_err(|e| format!("Can't resolve '{}': {}", host, e))
+            })
+            .await?;
+
+            if let Some(address) = addresses.iter().find(|a| !is_public_address(a.ip())) {
+                return Err(format!("{} is not a public address", address.ip()).into());
+            }
+
+            Ok(Box::new(addresses.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// Evaluates an arithmetic expression with the usual precedence, where `^` is
+/// exponentiation.
+fn evaluate(expression: &str) -> Result<f64, String> {
+    let mut parser = ExpressionParser {
+        chars: expression.chars().filter(|c| !c.is_whitespace()).collect(),
+        position: 0,
+        depth: 0,
+    };
+
+    let value = parser.expression()?;
+    if let Some(c) = parser.peek() {
+        return Err(format!("Unexpected '{}' in the expression", c));
+    }
+
+    if !value.is_finite() {
+        return Err("The result is not a finite number".to_string());
+    }
+
+    Ok(value)
+}
+
+/// Integers are shown without decimals.
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
+
+/// How deep parentheses, signs and exponents can be nested, so a crafted
+/// expression can't overflow the stack.
+const MAX_EXPRESSION_DEPTH: usize = 64;
+
+/// Recursive descent parser evaluating as it goes.
+struct ExpressionParser {
+    chars: Vec<char>,
+    position: usize,
+    /// Nesting of the part being parsed, see [`MAX_EXPRESSION_DEPTH`].
+    depth: usize,
+}
+
+impl ExpressionParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// `term (('+' | '-') term)*`
+    fn expression(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// `unary (('*' | '/' | '%') unary)*`
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err("Division by zero".to_string());
+                }
+                value /= divisor;
+            } else if self.eat('%') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    return Err("Division by zero".to_string());
+                }
+                value %= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// `('-' | '+') unary | power`
+    ///
+    /// Every nested part is parsed through here, so this is where the depth
+    /// is limited.
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.depth == MAX_EXPRESSION_DEPTH {
+            return Err("The expression is nested too deeply".to_string());
+        }
+
+        self.depth += 1;
+        let value = if self.eat('-') {
+            self.unary().map(|value| -value)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        };
+        self.depth -= 1;
+        value
+    }
+
+    /// `primary ('^' unary)?`, so `2^3^2` is `2^(3^2)` and `-2^2` is `-4`.
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            Ok(base.powf(self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    /// `number | '(' expression ')'`
+    fn primary(&mut self) -> Result<f64, String> {
+        if self.eat('(') {
+            let value = self.expression()?;
+            if !self.eat(')') {
+                return Err("Missing closing parenthesis".to_string());
+            }
+            return Ok(value);
+        }
+
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.position += 1;
+        }
+
+        let number = self.chars[start..self.position].iter().collect::<String>();
+        if number.is_empty() {
+            return match self.peek() {
+                Some(c) => Err(format!("Unexpected '{}' in the expression", c)),
+                None => Err("Unexpected end of the expression".to_string()),
+            };
+        }
+
+        number
+            .parse()
+            .map_err(|_| format!("Invalid number '{}'", number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::{TestResponse, TestServer, block_on};
+
+    #[test]
+    fn test_evaluate_arithmetic() {
+        assert_eq!(evaluate("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(evaluate("-2^2 + 2^3^2"), Ok(508.0));
+        assert_eq!(evaluate("7 % 4 / 2"), Ok(1.5));
+        assert_eq!(format_number(evaluate("10 / 4").unwrap()), "2.5");
+        assert_eq!(format_number(evaluate("10 / 5").unwrap()), "2");
+
+        assert_eq!(evaluate("1 / (2 - 2)"), Err("Division by zero".into()));
+        assert_eq!(
+            evaluate("(1 + 2"),
+            Err("Missing closing parenthesis".into())
+        );
+        assert_eq!(
+            evaluate("2 * x"),
+            Err("Unexpected 'x' in the expression".into())
+        );
+        assert!(evaluate("1..2").is_err());
+    }
+
+    #[test]
+    fn test_deeply_nested_expressions_are_rejected() {
+        let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(evaluate(&nested(20)), Ok(1.0));
+
+        let too_deep = Err("The expression is nested too deeply".to_string());
+        assert_eq!(evaluate(&nested(100_000)), too_deep);
+        assert_eq!(evaluate(&format!("{}1", "-".repeat(100_000))), too_deep);
+        assert_eq!(evaluate(&vec!["2"; 100_000].join("^")), too_deep);
+    }
+
+    #[test]
+    fn test_file_tools_stay_in_workspace() {
+        let root =
+            std::env::temp_dir().join(format!("moly-kit-local-tools-{}", std::process::id()));
+        let workspace = root.join("workspace");
+        std::fs::create_dir_all(workspace.join("docs")).unwrap();
+        std::fs::write(workspace.join("docs/notes.txt"), "hello").unwrap();
+        std::fs::write(root.join("secret.txt"), "secret").unwrap();
+
+        assert_eq!(read_file(&workspace, "docs/notes.txt"), Ok("hello".into()));
+        assert_eq!(list_directory(&workspace, "."), Ok("docs/".into()));
+        assert_eq!(list_directory(&workspace, "docs"), Ok("notes.txt".into()));
+
+        assert_eq!(
+            read_file(&workspace, "../secret.txt"),
+            Err("'../secret.txt' is outside of the workspace directory".into())
+        );
+        let absolute = root.join("secret.txt");
+        assert!(read_file(&workspace, absolute.to_str().unwrap()).is_err());
+        assert!(list_directory(&workspace, "..").is_err());
+        assert!(read_file(&workspace, "docs").is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_only_public_addresses_are_fetched() {
+        assert!(is_public_address("93.184.216.34".parse().unwrap()));
+        assert!(is_public_address("2606:2800:220:1::".parse().unwrap()));
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+
+        let server = TestServer::start(|_| TestResponse::new(200, "<title>Local</title>"));
+        let error = block_on(fetch_url(&server.url())).unwrap_err();
+        assert!(
+            error.contains("127.0.0.1 is not a public address"),
+            "{}",
+            error
+        );
+
+        let url = server.url().replace("127.0.0.1", "localhost");
+        let error = block_on(fetch_url(&url)).unwrap_err();
+        assert!(error.contains("is not a public address"), "{}", error);
+
+        assert!(block_on(fetch_url("file:///etc/passwd")).is_err());
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn test_file_tools_need_a_workspace() {
+        let provider = BuiltinToolProvider::new()
+            .with_tool(BuiltinTool::ReadFile)
+            .with_tool(BuiltinTool::Calculate);
+
+        assert_eq!(provider.available_tools(), vec![BuiltinTool::Calculate]);
+
+        let provider = provider.with_workspace("/tmp");
+        let names = provider
+            .tools()
+            .into_iter()
+            .map(|t| t.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["read_file", "calculate"]);
+    }
+}
@@ -1,18 +1,50 @@
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
 #[cfg(not(target_arch = "wasm32"))]
 use rmcp::{
-    model::{CallToolRequestParam, CallToolResult},
-    service::{RoleClient, RunningService, ServiceExt},
+    ClientHandler,
+    model::{
+        CallToolRequestParam, CallToolResult, CancelledNotificationParam, ClientRequest,
+        GetPromptRequestParam, RawContent, ReadResourceRequestParam, Request, RequestId,
+        ResourceContents, ServerResult,
+    },
+    service::{
+        NotificationContext, Peer, PeerRequestOptions, RoleClient, RunningService, ServiceExt,
+    },
     transport::{
         SseClientTransport, TokioChildProcess,
-        streamable_http_client::{StreamableHttpClientTransport, StreamableHttpClientWorker},
+        sse_client::SseClientConfig,
+        streamable_http_client::{
+            StreamableHttpClientTransport, StreamableHttpClientTransportConfig,
+        },
     },
 };
 use serde_json::{Map, Value};
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Weak;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::protocol::ToolResultPart;
+use crate::protocol::{Attachment, BotId, Message, Tool, ToolCall, ToolResult};
+use crate::utils::asynchronous::BoxPlatformSendStream;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::asynchronous::{AbortOnDropHandle, sleep, spawn, spawn_abort_on_drop};
 
-use crate::protocol::{Tool, ToolCall, ToolResult};
+#[cfg(not(target_arch = "wasm32"))]
+use super::concurrent_http::ConcurrentHttpClient;
+#[cfg(not(target_arch = "wasm32"))]
+use super::local_tools::LocalToolProvider;
+#[cfg(not(target_arch = "wasm32"))]
+use super::oauth::OAuthHttpClient;
+use super::permissions::{ToolPolicies, ToolPolicy};
+use super::resources::{McpPrompt, McpResource};
+#[cfg(not(target_arch = "wasm32"))]
+use super::resources::{McpPromptArgument, prompt_messages, resource_attachment, uri_file_name};
+use super::schema::{SchemaViolation, validate_arguments};
 
 /// Creates a namespaced tool name using double underscores as separator
 /// Preserves original naming including hyphens and casing
@@ -58,6 +90,18 @@ pub fn parse_tool_arguments(arguments: &str) -> Result<Map<String, Value>, Strin
     }
 }
 
+/// Lists the schema violations of a tool call, one per line.
+fn invalid_arguments_message(tool_name: &str, violations: &[SchemaViolation]) -> String {
+    let mut message = format!(
+        "Invalid arguments for tool '{}'. Fix the following and call the tool again:",
+        tool_name
+    );
+    for violation in violations {
+        message.push_str(&format!("\n- {}", violation));
+    }
+    message
+}
+
 #[derive(Clone, Debug)]
 pub struct ToolRegistryEntry {
     pub server_id: String,
@@ -82,6 +126,9 @@ impl ToolRegistry {
     }
 
     fn add_server_tools(&mut self, server_id: &str, tools: Vec<Tool>) {
+        // Tools no longer offered by the server must not linger.
+        self.remove_server(server_id);
+
         let mut tool_names = Vec::new();
 
         for tool in tools {
@@ -126,14 +173,190 @@ impl ToolRegistry {
     }
 }
 
+/// How many tool calls run at the same time, unless configured otherwise.
+const DEFAULT_MAX_CONCURRENT_TOOL_CALLS: usize = 4;
+
+/// Time limits for tool calls, from the most to the least specific.
+#[derive(Default)]
+struct ToolTimeouts {
+    /// Keyed by namespaced tool name.
+    tools: HashMap<String, Duration>,
+    /// Keyed by server id.
+    servers: HashMap<String, Duration>,
+    default: Option<Duration>,
+}
+
+impl ToolTimeouts {
+    fn get(&self, server_id: &str, namespaced_tool_name: &str) -> Option<Duration> {
+        self.tools
+            .get(namespaced_tool_name)
+            .or_else(|| self.servers.get(server_id))
+            .copied()
+            .or(self.default)
+    }
+}
+
+/// How often connected servers are pinged, unless configured otherwise.
+const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time to wait for the answer to a ping.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A server missing this many pings in a row is considered down, so a single
+/// slow answer doesn't restart it.
+const MAX_MISSED_PINGS: u32 = 3;
+
+/// Bounds of the delay between reconnection attempts, doubled after each
+/// failed attempt.
+const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Connection state of an MCP server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum McpServerStatus {
+    /// Connecting for the first time, or reconnecting after a failure.
+    Connecting,
+    /// Connected, with the number of tools it offers.
+    Ready { tool_count: usize },
+    /// Disconnected because of the given error. Reconnection is retried
+    /// periodically until the server is removed.
+    Failed { error: String },
+}
+
+/// The result of a tool call, with its timing.
+#[derive(Clone, Debug)]
+pub struct ToolExecution {
+    pub result: ToolResult,
+    pub started_at: DateTime<Utc>,
+    pub duration: Duration,
+}
+
+/// Called with the id and the new status of a server every time it changes.
+pub type McpServerStatusListener = Arc<dyn Fn(&str, &McpServerStatus) + Send + Sync>;
+
+/// OAuth 2.0 client credentials used to get an access token for an MCP server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct McpOAuthCredentials {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: Option<String>,
+}
+
+/// Connection settings for the network based transports.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct McpHttpTransport {
+    pub url: String,
+    /// Extra headers sent with every request, like `Authorization`.
+    pub headers: Vec<(String, String)>,
+    /// If set, an access token is requested with the client credentials grant
+    /// and sent as a bearer token. It's requested again when it expires.
+    pub oauth: Option<McpOAuthCredentials>,
+}
+
+impl McpHttpTransport {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_headers(
+        mut self,
+        headers: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Self {
+        self.headers
+            .extend(headers.into_iter().map(|(n, v)| (n.into(), v.into())));
+        self
+    }
+
+    pub fn with_oauth(mut self, oauth: McpOAuthCredentials) -> Self {
+        self.oauth = Some(oauth);
+        self
+    }
+
+    /// Builds the HTTP client used by the transport, with the configured
+    /// headers applied to every request.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn client(&self) -> Result<reqwest::Client, Box<dyn std::error::Error>> {
+        use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| format!("Invalid header name '{}': {}", name, e))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| format!("Invalid value for header '{}': {}", name, e))?;
+            headers.insert(name, value);
+        }
+
+        Ok(reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?)
+    }
+}
+
+impl From<String> for McpHttpTransport {
+    fn from(url: String) -> Self {
+        Self::new(url)
+    }
+}
+
+impl From<&str> for McpHttpTransport {
+    fn from(url: &str) -> Self {
+        Self::new(url)
+    }
+}
+
 // The transport to use for the MCP server
 pub enum McpTransport {
-    Http(String), // The URL for the HTTP endpoint (streamable)
-    Sse(String),  // The URL for the SSE endpoint
+    Http(McpHttpTransport), // The HTTP endpoint (streamable)
+    Sse(McpHttpTransport),  // The SSE endpoint
     #[cfg(not(target_arch = "wasm32"))]
     Stdio(tokio::process::Command), // The command to launch the child process
 }
 
+/// Kept by the manager to reconnect to a server, or restart its process.
+impl Clone for McpTransport {
+    fn clone(&self) -> Self {
+        match self {
+            McpTransport::Http(http) => McpTransport::Http(http.clone()),
+            McpTransport::Sse(http) => McpTransport::Sse(http.clone()),
+            #[cfg(not(target_arch = "wasm32"))]
+            McpTransport::Stdio(command) => McpTransport::Stdio(clone_command(command)),
+        }
+    }
+}
+
+/// Copies the program, arguments, environment and working directory of a
+/// command. Other settings are not kept, but the stdio transport sets the ones
+/// it needs.
+#[cfg(not(target_arch = "wasm32"))]
+fn clone_command(command: &tokio::process::Command) -> tokio::process::Command {
+    let command = command.as_std();
+    let mut clone = tokio::process::Command::new(command.get_program());
+    clone.args(command.get_args());
+
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => clone.env(key, value),
+            None => clone.env_remove(key),
+        };
+    }
+
+    if let Some(dir) = command.get_current_dir() {
+        clone.current_dir(dir);
+    }
+
+    clone
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 type DynService = Box<dyn rmcp::service::DynService<RoleClient>>;
 
@@ -151,8 +374,31 @@ struct McpManagerInner {
     services: Mutex<McpServiceRegistry>,
     #[cfg(not(target_arch = "wasm32"))]
     registry: Mutex<ToolRegistry>,
+    /// Keyed by their id, which is used as their server id.
+    #[cfg(not(target_arch = "wasm32"))]
+    local_providers: Mutex<HashMap<String, Arc<dyn LocalToolProvider>>>,
     latest_tools: Mutex<Vec<Tool>>,
     dangerous_mode_enabled: AtomicBool,
+    max_concurrent_tool_calls: AtomicUsize,
+    timeouts: Mutex<ToolTimeouts>,
+    policies: Mutex<ToolPolicies>,
+    /// Keyed by server id.
+    resources: Mutex<HashMap<String, Vec<McpResource>>>,
+    /// Keyed by server id.
+    prompts: Mutex<HashMap<String, Vec<McpPrompt>>>,
+    statuses: Mutex<HashMap<String, McpServerStatus>>,
+    status_listener: Mutex<Option<McpServerStatusListener>>,
+    /// Used to reconnect to the servers.
+    #[cfg(not(target_arch = "wasm32"))]
+    transports: Mutex<HashMap<String, McpTransport>>,
+    /// Tasks checking the health of each server, aborted on removal.
+    #[cfg(not(target_arch = "wasm32"))]
+    supervisors: Mutex<HashMap<String, AbortOnDropHandle>>,
+    /// Number of tool calls running on each server, which is not pinged
+    /// meanwhile.
+    #[cfg(not(target_arch = "wasm32"))]
+    calls_in_flight: Mutex<HashMap<String, usize>>,
+    health_check_interval: Mutex<Duration>,
 }
 
 /// Manages MCP servers and provides a unified interface for tool discovery and invocation.
@@ -176,32 +422,124 @@ impl McpManagerClient {
                 services: Mutex::new(HashMap::new()),
                 #[cfg(not(target_arch = "wasm32"))]
                 registry: Mutex::new(ToolRegistry::new()),
+                #[cfg(not(target_arch = "wasm32"))]
+                local_providers: Mutex::new(HashMap::new()),
                 latest_tools: Mutex::new(Vec::new()),
                 dangerous_mode_enabled: AtomicBool::new(false),
+                max_concurrent_tool_calls: AtomicUsize::new(DEFAULT_MAX_CONCURRENT_TOOL_CALLS),
+                timeouts: Mutex::new(ToolTimeouts::default()),
+                policies: Mutex::new(ToolPolicies::default()),
+                resources: Mutex::new(HashMap::new()),
+                prompts: Mutex::new(HashMap::new()),
+                statuses: Mutex::new(HashMap::new()),
+                status_listener: Mutex::new(None),
+                #[cfg(not(target_arch = "wasm32"))]
+                transports: Mutex::new(HashMap::new()),
+                #[cfg(not(target_arch = "wasm32"))]
+                supervisors: Mutex::new(HashMap::new()),
+                #[cfg(not(target_arch = "wasm32"))]
+                calls_in_flight: Mutex::new(HashMap::new()),
+                health_check_interval: Mutex::new(DEFAULT_HEALTH_CHECK_INTERVAL),
             }),
         }
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
+    fn upgrade(inner: &Weak<McpManagerInner>) -> Option<Self> {
+        inner.upgrade().map(|inner| Self { inner })
+    }
+
     /// Registers a new MCP server in the registry, and discovers tools from the server.
+    ///
+    /// The server is then supervised: it's pinged periodically while no tool
+    /// call is running on it, and if it crashes, the connection drops or it
+    /// misses several pings in a row, it's reconnected (or its process
+    /// restarted) with an increasing delay between attempts. This also happens
+    /// if this first connection fails, in which case the error is returned.
     #[cfg(not(target_arch = "wasm32"))]
     pub async fn add_server(
         &self,
         id: &str,
         transport: McpTransport,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.inner.local_providers.lock().unwrap().contains_key(id) {
+            return Err(format!("The server id '{}' is reserved for local tools", id).into());
+        }
+
+        self.inner
+            .transports
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), transport.clone());
+
+        let result = self.connect(id, transport).await;
+        if let Err(e) = &result {
+            self.set_status(
+                id,
+                McpServerStatus::Failed {
+                    error: e.to_string(),
+                },
+            );
+        }
+
+        self.supervise(id, result.is_ok());
+        result
+    }
+
+    /// Connects to the server and discovers its tools, resources and prompts.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn connect(
+        &self,
+        id: &str,
+        transport: McpTransport,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_status(id, McpServerStatus::Connecting);
+
+        let handler = McpClientHandler {
+            server_id: id.to_string(),
+            manager: Arc::downgrade(&self.inner),
+        };
+
         let running_service = match transport {
-            McpTransport::Http(url) => {
-                let worker = StreamableHttpClientWorker::<reqwest::Client>::new_simple(url);
-                let transport = StreamableHttpClientTransport::spawn(worker);
-                ().into_dyn().serve(transport).await?
+            McpTransport::Http(http) => {
+                let client = http.client()?;
+                let config = StreamableHttpClientTransportConfig::with_uri(http.url);
+                match http.oauth {
+                    Some(oauth) => {
+                        let client = ConcurrentHttpClient(OAuthHttpClient::new(client, oauth));
+                        let transport = StreamableHttpClientTransport::with_client(client, config);
+                        handler.into_dyn().serve(transport).await?
+                    }
+                    None => {
+                        let client = ConcurrentHttpClient(client);
+                        let transport = StreamableHttpClientTransport::with_client(client, config);
+                        handler.into_dyn().serve(transport).await?
+                    }
+                }
             }
-            McpTransport::Sse(url) => {
-                let transport = SseClientTransport::start(url).await?;
-                ().into_dyn().serve(transport).await?
+            McpTransport::Sse(http) => {
+                let client = http.client()?;
+                let config = SseClientConfig {
+                    sse_endpoint: http.url.into(),
+                    ..Default::default()
+                };
+                match http.oauth {
+                    Some(oauth) => {
+                        let client = OAuthHttpClient::new(client, oauth);
+                        let transport =
+                            SseClientTransport::start_with_client(client, config).await?;
+                        handler.into_dyn().serve(transport).await?
+                    }
+                    None => {
+                        let transport =
+                            SseClientTransport::start_with_client(client, config).await?;
+                        handler.into_dyn().serve(transport).await?
+                    }
+                }
             }
             McpTransport::Stdio(command) => {
                 let transport = TokioChildProcess::new(command)?;
-                ().into_dyn().serve(transport).await?
+                handler.into_dyn().serve(transport).await?
             }
         };
 
@@ -211,23 +549,315 @@ impl McpManagerClient {
             .unwrap()
             .insert(id.to_string(), Arc::new(running_service));
 
-        // Discover tools from the newly added server
-        match self.discover_tools_for_server(id).await {
-            Ok(tools) => {
-                self.inner
-                    .registry
-                    .lock()
-                    .unwrap()
-                    .add_server_tools(id, tools);
-                ::log::debug!("Successfully discovered tools for MCP server: {}", id);
+        if let Err(e) = self.refresh_tools(id).await {
+            self.disconnect(id);
+            return Err(e.into());
+        }
+        self.refresh_resources(id).await;
+        self.refresh_prompts(id).await;
+        Ok(())
+    }
+
+    /// Discovers the tools of the server again, replacing the known ones.
+    ///
+    /// If discovery fails, the known tools are kept.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn refresh_tools(&self, id: &str) -> Result<(), String> {
+        let tools = self
+            .discover_tools_for_server(id)
+            .await
+            .map_err(|e| format!("Failed to discover tools: {}", e))?;
+
+        let tool_count = tools.len();
+        self.inner
+            .registry
+            .lock()
+            .unwrap()
+            .add_server_tools(id, tools);
+        ::log::debug!("Successfully discovered tools for MCP server: {}", id);
+        self.set_status(id, McpServerStatus::Ready { tool_count });
+        Ok(())
+    }
+
+    /// Lists the resources of the server again, if it offers any.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn refresh_resources(&self, id: &str) {
+        let Some(service) = self.service(id) else {
+            return;
+        };
+
+        let supported = service
+            .peer_info()
+            .is_some_and(|info| info.capabilities.resources.is_some());
+
+        let resources = if supported {
+            match service.list_all_resources().await {
+                Ok(resources) => resources
+                    .into_iter()
+                    .map(|resource| McpResource {
+                        server_id: id.to_string(),
+                        uri: resource.raw.uri,
+                        name: resource.raw.name,
+                        description: resource.raw.description,
+                        mime_type: resource.raw.mime_type,
+                    })
+                    .collect(),
+                Err(e) => {
+                    ::log::warn!("Failed to list resources of MCP server '{}': {}", id, e);
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        self.inner
+            .resources
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), resources);
+    }
+
+    /// Lists the prompts of the server again, if it offers any.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn refresh_prompts(&self, id: &str) {
+        let Some(service) = self.service(id) else {
+            return;
+        };
+
+        let supported = service
+            .peer_info()
+            .is_some_and(|info| info.capabilities.prompts.is_some());
+
+        let prompts = if supported {
+            match service.list_all_prompts().await {
+                Ok(prompts) => prompts
+                    .into_iter()
+                    .map(|prompt| McpPrompt {
+                        server_id: id.to_string(),
+                        namespaced_name: namespaced_name(id, &prompt.name),
+                        name: prompt.name,
+                        description: prompt.description,
+                        arguments: prompt
+                            .arguments
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|argument| McpPromptArgument {
+                                name: argument.name,
+                                description: argument.description,
+                                required: argument.required.unwrap_or(false),
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+                Err(e) => {
+                    ::log::warn!("Failed to list prompts of MCP server '{}': {}", id, e);
+                    Vec::new()
+                }
             }
-            Err(e) => {
-                ::log::warn!("Failed to discover tools for MCP server '{}': {}", id, e);
-                // Don't fail the entire server addition if tool discovery fails
+        } else {
+            Vec::new()
+        };
+
+        self.inner
+            .prompts
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), prompts);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn service(&self, id: &str) -> Option<McpServiceHandle> {
+        self.inner.services.lock().unwrap().get(id).cloned()
+    }
+
+    /// Starts the task keeping the server connected, replacing the previous
+    /// one if any.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn supervise(&self, id: &str, connected: bool) {
+        let inner = Arc::downgrade(&self.inner);
+        let server_id = id.to_string();
+
+        // Only a weak reference is kept while waiting, so the supervisor
+        // doesn't keep a dropped manager alive.
+        let supervisor = spawn_abort_on_drop(async move {
+            let mut connected = connected;
+            let mut delay = RECONNECT_MIN_DELAY;
+            let mut missed_pings = 0;
+
+            loop {
+                if connected {
+                    let Some(interval) = Self::upgrade(&inner).map(|m| m.health_check_interval())
+                    else {
+                        return;
+                    };
+                    sleep(interval).await;
+
+                    let Some(manager) = Self::upgrade(&inner) else {
+                        return;
+                    };
+
+                    // A long tool call may keep the server from answering, and
+                    // shows it's alive anyway.
+                    if manager.has_calls_in_flight(&server_id) {
+                        continue;
+                    }
+
+                    match manager.ping(&server_id).await {
+                        Ok(()) => missed_pings = 0,
+                        Err(error) if missed_pings + 1 < MAX_MISSED_PINGS => {
+                            missed_pings += 1;
+                            ::log::warn!(
+                                "MCP server '{}' missed a ping ({}/{}): {}",
+                                server_id,
+                                missed_pings,
+                                MAX_MISSED_PINGS,
+                                error
+                            );
+                        }
+                        Err(error) => {
+                            ::log::warn!("MCP server '{}' is down: {}", server_id, error);
+                            manager.disconnect(&server_id);
+                            manager.set_status(&server_id, McpServerStatus::Failed { error });
+                            connected = false;
+                            delay = RECONNECT_MIN_DELAY;
+                            missed_pings = 0;
+                        }
+                    }
+                } else {
+                    sleep(delay).await;
+
+                    let Some(manager) = Self::upgrade(&inner) else {
+                        return;
+                    };
+
+                    let transport = manager
+                        .inner
+                        .transports
+                        .lock()
+                        .unwrap()
+                        .get(&server_id)
+                        .cloned();
+
+                    let Some(transport) = transport else {
+                        return;
+                    };
+
+                    let error = match manager.connect(&server_id, transport).await {
+                        Ok(()) => None,
+                        Err(e) => Some(e.to_string()),
+                    };
+
+                    match error {
+                        None => {
+                            ::log::info!("Reconnected to MCP server '{}'", server_id);
+                            connected = true;
+                        }
+                        Some(error) => {
+                            manager.set_status(&server_id, McpServerStatus::Failed { error });
+                            delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+                        }
+                    }
+                }
             }
+        });
+
+        self.inner
+            .supervisors
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), supervisor);
+    }
+
+    /// Checks that the server is still connected and responsive.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn ping(&self, server_id: &str) -> Result<(), String> {
+        let service = {
+            let services_guard = self.inner.services.lock().unwrap();
+            services_guard.get(server_id).map(|s| Arc::clone(s))
+        };
+
+        let Some(service) = service else {
+            return Err("not connected".to_string());
+        };
+
+        let ping = service.send_request(ClientRequest::PingRequest(Default::default()));
+        let timeout = sleep(PING_TIMEOUT);
+        futures::pin_mut!(ping, timeout);
+        match futures::future::select(ping, timeout).await {
+            futures::future::Either::Left((Ok(_), _)) => Ok(()),
+            futures::future::Either::Left((Err(e), _)) => Err(e.to_string()),
+            futures::future::Either::Right(_) => Err(format!(
+                "no answer to ping after {} seconds",
+                PING_TIMEOUT.as_secs()
+            )),
         }
+    }
 
-        Ok(())
+    #[cfg(not(target_arch = "wasm32"))]
+    fn has_calls_in_flight(&self, server_id: &str) -> bool {
+        self.inner
+            .calls_in_flight
+            .lock()
+            .unwrap()
+            .get(server_id)
+            .is_some_and(|count| *count > 0)
+    }
+
+    /// Forgets the connection to the server and its tools, without stopping
+    /// its supervision.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn disconnect(&self, id: &str) {
+        self.inner.services.lock().unwrap().remove(id);
+        self.inner.registry.lock().unwrap().remove_server(id);
+        self.inner.resources.lock().unwrap().remove(id);
+        self.inner.prompts.lock().unwrap().remove(id);
+    }
+
+    fn set_status(&self, id: &str, status: McpServerStatus) {
+        self.inner
+            .statuses
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), status.clone());
+
+        let listener = self.inner.status_listener.lock().unwrap().clone();
+        if let Some(listener) = listener {
+            listener(id, &status);
+        }
+    }
+
+    /// The status of every registered server, by server id.
+    pub fn server_statuses(&self) -> HashMap<String, McpServerStatus> {
+        self.inner.statuses.lock().unwrap().clone()
+    }
+
+    /// Whether any server is registered, without copying their statuses.
+    pub fn has_servers(&self) -> bool {
+        !self.inner.statuses.lock().unwrap().is_empty()
+    }
+
+    pub fn server_status(&self, id: &str) -> Option<McpServerStatus> {
+        self.inner.statuses.lock().unwrap().get(id).cloned()
+    }
+
+    /// Sets the function called every time the status of a server changes.
+    ///
+    /// It may be called from any thread.
+    pub fn set_status_listener(
+        &self,
+        listener: impl Fn(&str, &McpServerStatus) + Send + Sync + 'static,
+    ) {
+        *self.inner.status_listener.lock().unwrap() = Some(Arc::new(listener));
+    }
+
+    /// Sets how often connected servers are pinged to detect failures.
+    pub fn set_health_check_interval(&self, interval: Duration) {
+        *self.inner.health_check_interval.lock().unwrap() = interval;
+    }
+
+    pub fn health_check_interval(&self) -> Duration {
+        *self.inner.health_check_interval.lock().unwrap()
     }
 
     #[cfg(target_arch = "wasm32")]
@@ -236,10 +866,17 @@ impl McpManagerClient {
         id: &str,
         _transport: McpTransport,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let _ = id;
-        Err("MCP servers are not supported in web builds".into())
+        let error = "MCP servers are not supported in web builds";
+        self.set_status(
+            id,
+            McpServerStatus::Failed {
+                error: error.to_string(),
+            },
+        );
+        Err(error.into())
     }
 
+    /// Runs every tool without asking, unless a policy says otherwise.
     pub fn set_dangerous_mode_enabled(&self, enabled: bool) {
         self.inner
             .dangerous_mode_enabled
@@ -250,6 +887,95 @@ impl McpManagerClient {
         self.inner.dangerous_mode_enabled.load(Ordering::Relaxed)
     }
 
+    /// Sets the policy for the tools of the given server without their own.
+    pub fn set_server_tool_policy(&self, server_id: &str, policy: Option<ToolPolicy>) {
+        self.inner
+            .policies
+            .lock()
+            .unwrap()
+            .set_server(server_id, policy);
+    }
+
+    /// Sets the policy for a single tool of the given server.
+    pub fn set_tool_policy(&self, server_id: &str, tool_name: &str, policy: Option<ToolPolicy>) {
+        self.inner
+            .policies
+            .lock()
+            .unwrap()
+            .set_tool(&namespaced_name(server_id, tool_name), policy);
+    }
+
+    /// Removes all the policies, of servers and tools.
+    pub fn clear_tool_policies(&self) {
+        *self.inner.policies.lock().unwrap() = ToolPolicies::default();
+    }
+
+    /// The policy configured for the tool or its server, by namespaced name,
+    /// if any.
+    pub fn configured_tool_policy(&self, namespaced_tool_name: &str) -> Option<ToolPolicy> {
+        let server_id = parse_namespaced_tool_name(namespaced_tool_name)
+            .map(|(server_id, _)| server_id)
+            .unwrap_or_default();
+
+        self.inner
+            .policies
+            .lock()
+            .unwrap()
+            .get(&server_id, namespaced_tool_name)
+    }
+
+    /// The policy to apply when the model calls the tool, by namespaced name.
+    ///
+    /// Tools without a configured policy are allowed in dangerous mode, and
+    /// need the user's approval otherwise.
+    pub fn tool_policy(&self, namespaced_tool_name: &str) -> ToolPolicy {
+        match self.configured_tool_policy(namespaced_tool_name) {
+            Some(policy) => policy,
+            None if self.get_dangerous_mode_enabled() => ToolPolicy::Allow,
+            None => ToolPolicy::Ask,
+        }
+    }
+
+    /// Sets how many tool calls may run at the same time. At least one.
+    pub fn set_max_concurrent_tool_calls(&self, limit: usize) {
+        self.inner
+            .max_concurrent_tool_calls
+            .store(limit.max(1), Ordering::Relaxed);
+    }
+
+    pub fn get_max_concurrent_tool_calls(&self) -> usize {
+        self.inner.max_concurrent_tool_calls.load(Ordering::Relaxed)
+    }
+
+    /// Sets the time limit for tool calls without a more specific one.
+    pub fn set_default_tool_timeout(&self, timeout: Option<Duration>) {
+        self.inner.timeouts.lock().unwrap().default = timeout;
+    }
+
+    /// Sets the time limit for the calls to any tool of the given server.
+    pub fn set_server_tool_timeout(&self, server_id: &str, timeout: Option<Duration>) {
+        let servers = &mut self.inner.timeouts.lock().unwrap().servers;
+        match timeout {
+            Some(timeout) => servers.insert(server_id.to_string(), timeout),
+            None => servers.remove(server_id),
+        };
+    }
+
+    /// Sets the time limit for the calls to a single tool of the given server.
+    pub fn set_tool_timeout(&self, server_id: &str, tool_name: &str, timeout: Option<Duration>) {
+        let name = namespaced_name(server_id, tool_name);
+        let tools = &mut self.inner.timeouts.lock().unwrap().tools;
+        match timeout {
+            Some(timeout) => tools.insert(name, timeout),
+            None => tools.remove(&name),
+        };
+    }
+
+    /// Removes all the time limits, including the default one.
+    pub fn clear_tool_timeouts(&self) {
+        *self.inner.timeouts.lock().unwrap() = ToolTimeouts::default();
+    }
+
     /// Discovers tools from an MCP server.
     #[cfg(not(target_arch = "wasm32"))]
     async fn discover_tools_for_server(
@@ -326,15 +1052,131 @@ impl McpManagerClient {
         Vec::new()
     }
 
-    /// Calls a tool on an MCP server.
-    #[cfg(not(target_arch = "wasm32"))]
-    async fn call_tool(
-        &self,
-        namespaced_tool_name: &str,
-        arguments: serde_json::Map<String, serde_json::Value>,
-    ) -> Result<CallToolResult, Box<dyn std::error::Error>> {
-        // Parse the namespaced tool name to get server_id and original tool name
-        let (server_id, original_tool_name) = parse_namespaced_tool_name(namespaced_tool_name)?;
+    /// The resources offered by all the connected servers.
+    pub fn get_all_resources(&self) -> Vec<McpResource> {
+        let mut resources = self
+            .inner
+            .resources
+            .lock()
+            .unwrap()
+            .values()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        resources.sort_by(|a, b| (&a.server_id, &a.name).cmp(&(&b.server_id, &b.name)));
+        resources
+    }
+
+    /// The prompts offered by all the connected servers.
+    pub fn get_all_prompts(&self) -> Vec<McpPrompt> {
+        let mut prompts = self
+            .inner
+            .prompts
+            .lock()
+            .unwrap()
+            .values()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+        prompts.sort_by(|a, b| a.namespaced_name.cmp(&b.namespaced_name));
+        prompts
+    }
+
+    /// Finds a prompt by its namespaced name, or by its plain name if only one
+    /// server offers a prompt with that name.
+    pub fn find_prompt(&self, name: &str) -> Option<McpPrompt> {
+        let prompts = self.get_all_prompts();
+        if let Some(prompt) = prompts.iter().find(|p| p.namespaced_name == name) {
+            return Some(prompt.clone());
+        }
+
+        let mut matching = prompts.into_iter().filter(|p| p.name == name);
+        match (matching.next(), matching.next()) {
+            (Some(prompt), None) => Some(prompt),
+            _ => None,
+        }
+    }
+
+    /// Reads a resource from its server, as attachments (a resource may have
+    /// several contents).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn read_resource(
+        &self,
+        server_id: &str,
+        uri: &str,
+    ) -> Result<Vec<Attachment>, Box<dyn std::error::Error>> {
+        let Some(service) = self.service(server_id) else {
+            return Err(format!("MCP server '{}' not found or disconnected", server_id).into());
+        };
+
+        let result = service
+            .read_resource(ReadResourceRequestParam {
+                uri: uri.to_string(),
+            })
+            .await?;
+
+        let attachments = result
+            .contents
+            .iter()
+            .map(resource_attachment)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(attachments)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub async fn read_resource(
+        &self,
+        _server_id: &str,
+        _uri: &str,
+    ) -> Result<Vec<Attachment>, Box<dyn std::error::Error>> {
+        Err("MCP servers are not supported in web builds".into())
+    }
+
+    /// Expands a prompt (by namespaced name) with the given arguments into
+    /// messages, with the assistant ones attributed to the given bot.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn get_prompt(
+        &self,
+        namespaced_prompt_name: &str,
+        arguments: Map<String, Value>,
+        bot_id: &BotId,
+    ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+        let (server_id, prompt_name) = parse_namespaced_tool_name(namespaced_prompt_name)?;
+
+        let Some(service) = self.service(&server_id) else {
+            return Err(format!("MCP server '{}' not found or disconnected", server_id).into());
+        };
+
+        let result = service
+            .get_prompt(GetPromptRequestParam {
+                name: prompt_name,
+                arguments: Some(arguments),
+            })
+            .await?;
+
+        Ok(prompt_messages(result.messages, bot_id))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub async fn get_prompt(
+        &self,
+        _namespaced_prompt_name: &str,
+        _arguments: Map<String, Value>,
+        _bot_id: &BotId,
+    ) -> Result<Vec<Message>, Box<dyn std::error::Error>> {
+        Err("MCP servers are not supported in web builds".into())
+    }
+
+    /// Calls a tool on an MCP server.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn call_tool(
+        &self,
+        namespaced_tool_name: &str,
+        arguments: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<CallToolResult, Box<dyn std::error::Error>> {
+        // Parse the namespaced tool name to get server_id and original tool name
+        let (server_id, original_tool_name) = parse_namespaced_tool_name(namespaced_tool_name)?;
 
         // Get the tool entry from registry for validation
         let tool_entry = {
@@ -342,7 +1184,7 @@ impl McpManagerClient {
             registry.get_tool_entry(namespaced_tool_name).cloned()
         };
 
-        let Some(_tool_entry) = tool_entry else {
+        let Some(tool_entry) = tool_entry else {
             return Err(format!("Tool '{}' not found in registry. Available tools can be retrieved with get_all_namespaced_tools()", namespaced_tool_name).into());
         };
 
@@ -356,23 +1198,70 @@ impl McpManagerClient {
             return Err(format!("MCP server '{}' not found or disconnected", server_id).into());
         };
 
-        // TODO: Add argument validation against tool_entry.schema here
+        let _in_flight = CallInFlight::new(&self.inner, &server_id);
+
+        // Malformed arguments are reported back to the model instead of being
+        // sent to the server, so it can fix them on the next turn.
+        let violations = validate_arguments(&tool_entry.schema.input_schema, &arguments);
+        if !violations.is_empty() {
+            return Err(invalid_arguments_message(&original_tool_name, &violations).into());
+        }
 
-        // Call the tool directly on the service
-        let request = CallToolRequestParam {
+        let timeout = self
+            .inner
+            .timeouts
+            .lock()
+            .unwrap()
+            .get(&server_id, namespaced_tool_name);
+
+        let request = ClientRequest::CallToolRequest(Request::new(CallToolRequestParam {
             name: original_tool_name.clone().into(),
             arguments: Some(arguments),
+        }));
+
+        let failed = |e: &dyn std::fmt::Display| -> Box<dyn std::error::Error> {
+            format!(
+                "Tool '{}' failed on server '{}': {}",
+                original_tool_name, server_id, e
+            )
+            .into()
         };
 
-        match service.call_tool(request).await {
-            Ok(result) => Ok(result),
-            Err(e) => {
-                let error_message = format!(
-                    "Tool '{}' failed on server '{}': {}",
-                    original_tool_name, server_id, e
-                );
-                Err(error_message.into())
+        let handle = service
+            .send_cancellable_request(request, PeerRequestOptions::no_options())
+            .await
+            .map_err(|e| failed(&e))?;
+
+        // If this future is dropped before the response arrives (e.g. the user
+        // stopped the chat), the server is told to stop working on the call.
+        let mut guard = CancelOnDrop::new(service.peer().clone(), handle.id.clone());
+
+        let response = match timeout {
+            Some(timeout) => {
+                let response = handle.await_response();
+                let sleep = crate::utils::asynchronous::sleep(timeout);
+                futures::pin_mut!(response, sleep);
+                match futures::future::select(response, sleep).await {
+                    futures::future::Either::Left((response, _)) => response,
+                    futures::future::Either::Right(_) => {
+                        guard.reason = "timed out";
+                        return Err(format!(
+                            "Tool '{}' on server '{}' timed out after {} seconds",
+                            original_tool_name,
+                            server_id,
+                            timeout.as_secs_f32()
+                        )
+                        .into());
+                    }
+                }
             }
+            None => handle.await_response().await,
+        };
+        guard.disarm();
+
+        match response.map_err(|e| failed(&e))? {
+            ServerResult::CallToolResult(result) => Ok(result),
+            _ => Err(failed(&"unexpected response")),
         }
     }
 
@@ -391,8 +1280,11 @@ impl McpManagerClient {
 
     #[cfg(not(target_arch = "wasm32"))]
     pub async fn remove_server(&self, id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        self.inner.services.lock().unwrap().remove(id);
-        self.inner.registry.lock().unwrap().remove_server(id);
+        self.inner.local_providers.lock().unwrap().remove(id);
+        self.inner.supervisors.lock().unwrap().remove(id);
+        self.inner.transports.lock().unwrap().remove(id);
+        self.inner.statuses.lock().unwrap().remove(id);
+        self.disconnect(id);
         Ok(())
     }
 
@@ -401,6 +1293,84 @@ impl McpManagerClient {
         Ok(())
     }
 
+    /// Registers the tools of a local provider, under its id as if it was a
+    /// server. They can be removed with [`Self::remove_server`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn add_local_provider(&self, provider: impl LocalToolProvider + 'static) {
+        let id = provider.id().to_string();
+        let tools = provider.tools();
+        let tool_count = tools.len();
+
+        self.inner
+            .registry
+            .lock()
+            .unwrap()
+            .add_server_tools(&id, tools);
+        self.inner
+            .local_providers
+            .lock()
+            .unwrap()
+            .insert(id.clone(), Arc::new(provider));
+        self.set_status(&id, McpServerStatus::Ready { tool_count });
+    }
+
+    /// Local tools are not supported in web builds yet, so this does nothing.
+    #[cfg(target_arch = "wasm32")]
+    pub fn add_local_provider(&self, _provider: impl super::local_tools::LocalToolProvider) {}
+
+    /// Calls a tool of a local provider, with the same validation and time
+    /// limits as the tools of MCP servers.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn call_local_tool(
+        &self,
+        provider: Arc<dyn LocalToolProvider>,
+        namespaced_tool_name: &str,
+        arguments: Map<String, Value>,
+    ) -> Result<String, String> {
+        let (server_id, original_tool_name) =
+            parse_namespaced_tool_name(namespaced_tool_name).map_err(|e| e.to_string())?;
+
+        let tool_entry = self
+            .inner
+            .registry
+            .lock()
+            .unwrap()
+            .get_tool_entry(namespaced_tool_name)
+            .cloned();
+
+        let Some(tool_entry) = tool_entry else {
+            return Err(format!("Tool '{}' not found", namespaced_tool_name));
+        };
+
+        let violations = validate_arguments(&tool_entry.schema.input_schema, &arguments);
+        if !violations.is_empty() {
+            return Err(invalid_arguments_message(&original_tool_name, &violations));
+        }
+
+        let timeout = self
+            .inner
+            .timeouts
+            .lock()
+            .unwrap()
+            .get(&server_id, namespaced_tool_name);
+
+        let call = provider.call_tool(&original_tool_name, arguments);
+        let Some(timeout) = timeout else {
+            return call.await;
+        };
+
+        let sleep = sleep(timeout);
+        futures::pin_mut!(sleep);
+        match futures::future::select(call, sleep).await {
+            futures::future::Either::Left((result, _)) => result,
+            futures::future::Either::Right(_) => Err(format!(
+                "Tool '{}' timed out after {} seconds",
+                original_tool_name,
+                timeout.as_secs_f32()
+            )),
+        }
+    }
+
     /// Executes a tool call and returns the result
     #[cfg(not(target_arch = "wasm32"))]
     pub async fn execute_tool_call(
@@ -409,33 +1379,60 @@ impl McpManagerClient {
         tool_call_id: &str,
         arguments: Map<String, Value>,
     ) -> ToolResult {
+        let local_provider =
+            parse_namespaced_tool_name(tool_name)
+                .ok()
+                .and_then(|(server_id, _)| {
+                    let providers = self.inner.local_providers.lock().unwrap();
+                    providers.get(&server_id).cloned()
+                });
+
+        if let Some(provider) = local_provider {
+            let result = self.call_local_tool(provider, tool_name, arguments).await;
+            let is_error = result.is_err();
+            return ToolResult {
+                tool_call_id: tool_call_id.to_string(),
+                content: result.unwrap_or_else(|e| e),
+                is_error,
+                ..Default::default()
+            };
+        }
+
         match self.call_tool(tool_name, arguments).await {
             Ok(result) => {
-                // Convert result to content string
-                let content = result
+                let parts = result
                     .content
                     .iter()
-                    .filter_map(|item| {
-                        // Convert ContentPart to text - for now we just serialize it
-                        if let Ok(text) = serde_json::to_string(item) {
-                            Some(text)
-                        } else {
-                            None
-                        }
-                    })
+                    .enumerate()
+                    .map(|(i, item)| to_tool_result_part(&item.raw, i))
+                    .collect::<Vec<_>>();
+
+                // Text representation for the clients which can't send typed parts.
+                let mut content = parts
+                    .iter()
+                    .map(describe_tool_result_part)
                     .collect::<Vec<_>>()
                     .join("\n");
 
+                if content.is_empty() {
+                    if let Some(structured) = &result.structured_content {
+                        content = structured.to_string();
+                    }
+                }
+
                 ToolResult {
                     tool_call_id: tool_call_id.to_string(),
                     content,
-                    is_error: false,
+                    parts,
+                    structured_content: result.structured_content,
+                    is_error: result.is_error.unwrap_or(false),
                 }
             }
             Err(e) => ToolResult {
                 tool_call_id: tool_call_id.to_string(),
                 content: e.to_string(),
                 is_error: true,
+                ..Default::default()
             },
         }
     }
@@ -454,21 +1451,912 @@ impl McpManagerClient {
                 tool_name
             ),
             is_error: true,
+            ..Default::default()
         }
     }
 
-    /// Executes multiple tool calls sequentially and returns the results
+    /// Executes multiple tool calls and returns the results, in the same order
+    /// as the calls.
     pub async fn execute_tool_calls(&self, tool_calls: Vec<ToolCall>) -> Vec<ToolResult> {
-        let mut tool_results = Vec::new();
+        let order = tool_calls
+            .iter()
+            .map(|tc| tc.id.clone())
+            .collect::<Vec<_>>();
 
-        // Execute all tool calls sequentially
-        for tool_call in tool_calls {
-            let result = self
-                .execute_tool_call(&tool_call.name, &tool_call.id, tool_call.arguments.clone())
-                .await;
-            tool_results.push(result);
-        }
+        let mut tool_results = self
+            .execute_tool_calls_stream(tool_calls)
+            .collect::<Vec<_>>()
+            .await;
 
+        tool_results.sort_by_key(|r| order.iter().position(|id| *id == r.tool_call_id));
         tool_results
     }
+
+    /// Executes multiple tool calls concurrently, up to the configured limit,
+    /// yielding each result as soon as its call finishes.
+    ///
+    /// Dropping the stream cancels the calls still running.
+    pub fn execute_tool_calls_stream(
+        &self,
+        tool_calls: Vec<ToolCall>,
+    ) -> BoxPlatformSendStream<'static, ToolResult> {
+        Box::pin(
+            self.execute_timed_tool_calls_stream(tool_calls)
+                .map(|execution| execution.result),
+        )
+    }
+
+    /// Like [`Self::execute_tool_calls_stream`], but also telling when each
+    /// call actually started and how long it took.
+    pub fn execute_timed_tool_calls_stream(
+        &self,
+        tool_calls: Vec<ToolCall>,
+    ) -> BoxPlatformSendStream<'static, ToolExecution> {
+        let limit = self.get_max_concurrent_tool_calls();
+        let manager = self.clone();
+
+        let stream = futures::stream::iter(tool_calls)
+            .map(move |tool_call| {
+                let manager = manager.clone();
+                async move {
+                    let started_at = Utc::now();
+                    let result = manager
+                        .execute_tool_call(&tool_call.name, &tool_call.id, tool_call.arguments)
+                        .await;
+
+                    ToolExecution {
+                        result,
+                        started_at,
+                        duration: (Utc::now() - started_at).to_std().unwrap_or_default(),
+                    }
+                }
+            })
+            .buffer_unordered(limit);
+
+        Box::pin(stream)
+    }
+}
+
+/// Handles the notifications sent by a server.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+struct McpClientHandler {
+    server_id: String,
+    manager: Weak<McpManagerInner>,
+}
+
+/// What to list again after a server notified about a change.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug)]
+enum ListChanged {
+    Tools,
+    Resources,
+    Prompts,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl McpClientHandler {
+    fn refresh(&self, changed: ListChanged) -> impl Future<Output = ()> + Send + 'static {
+        let server_id = self.server_id.clone();
+        let manager = McpManagerClient::upgrade(&self.manager);
+
+        // Listing waits for the server, which must not block the handling of
+        // its messages.
+        if let Some(manager) = manager {
+            spawn(async move {
+                ::log::debug!("{:?} of MCP server '{}' changed", changed, server_id);
+                match changed {
+                    ListChanged::Tools => {
+                        if let Err(e) = manager.refresh_tools(&server_id).await {
+                            ::log::warn!("MCP server '{}': {}", server_id, e);
+                        }
+                    }
+                    ListChanged::Resources => manager.refresh_resources(&server_id).await,
+                    ListChanged::Prompts => manager.refresh_prompts(&server_id).await,
+                }
+            });
+        }
+
+        std::future::ready(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ClientHandler for McpClientHandler {
+    fn on_tool_list_changed(
+        &self,
+        _context: NotificationContext<RoleClient>,
+    ) -> impl Future<Output = ()> + Send + '_ {
+        self.refresh(ListChanged::Tools)
+    }
+
+    fn on_resource_list_changed(
+        &self,
+        _context: NotificationContext<RoleClient>,
+    ) -> impl Future<Output = ()> + Send + '_ {
+        self.refresh(ListChanged::Resources)
+    }
+
+    fn on_prompt_list_changed(
+        &self,
+        _context: NotificationContext<RoleClient>,
+    ) -> impl Future<Output = ()> + Send + '_ {
+        self.refresh(ListChanged::Prompts)
+    }
+}
+
+/// Sends `notifications/cancelled` for an in-flight request when dropped,
+/// unless disarmed after the response arrived.
+#[cfg(not(target_arch = "wasm32"))]
+struct CancelOnDrop {
+    peer: Option<Peer<RoleClient>>,
+    request_id: RequestId,
+    reason: &'static str,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CancelOnDrop {
+    fn new(peer: Peer<RoleClient>, request_id: RequestId) -> Self {
+        Self {
+            peer: Some(peer),
+            request_id,
+            reason: "cancelled by the user",
+        }
+    }
+
+    fn disarm(&mut self) {
+        self.peer = None;
+    }
+}
+
+/// Counts a tool call as running on its server until dropped.
+#[cfg(not(target_arch = "wasm32"))]
+struct CallInFlight {
+    inner: Arc<McpManagerInner>,
+    server_id: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CallInFlight {
+    fn new(inner: &Arc<McpManagerInner>, server_id: &str) -> Self {
+        *inner
+            .calls_in_flight
+            .lock()
+            .unwrap()
+            .entry(server_id.to_string())
+            .or_default() += 1;
+
+        Self {
+            inner: Arc::clone(inner),
+            server_id: server_id.to_string(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for CallInFlight {
+    fn drop(&mut self) {
+        let mut calls_in_flight = self.inner.calls_in_flight.lock().unwrap();
+        if let Some(count) = calls_in_flight.get_mut(&self.server_id) {
+            *count -= 1;
+            if *count == 0 {
+                calls_in_flight.remove(&self.server_id);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        let Some(peer) = self.peer.take() else {
+            return;
+        };
+
+        let param = CancelledNotificationParam {
+            request_id: self.request_id.clone(),
+            reason: Some(self.reason.to_string()),
+        };
+
+        crate::utils::asynchronous::spawn(async move {
+            if let Err(e) = peer.notify_cancelled(param).await {
+                ::log::warn!("Failed to cancel MCP tool call: {}", e);
+            }
+        });
+    }
+}
+
+/// Converts an item of the content returned by an MCP tool into a typed part.
+///
+/// Binary content (images, audio, blobs) becomes an attachment so it isn't
+/// sent to the model as base64 text.
+#[cfg(not(target_arch = "wasm32"))]
+fn to_tool_result_part(content: &RawContent, index: usize) -> ToolResultPart {
+    match content {
+        RawContent::Text(text) => ToolResultPart::Text(text.text.clone()),
+        RawContent::Image(image) => binary_part(
+            output_name("image", index, &image.mime_type),
+            Some(image.mime_type.clone()),
+            &image.data,
+        ),
+        RawContent::Audio(audio) => binary_part(
+            output_name("audio", index, &audio.raw.mime_type),
+            Some(audio.raw.mime_type.clone()),
+            &audio.raw.data,
+        ),
+        RawContent::Resource(embedded) => match &embedded.resource {
+            ResourceContents::TextResourceContents {
+                uri,
+                mime_type,
+                text,
+                ..
+            } => ToolResultPart::Resource {
+                uri: uri.clone(),
+                mime_type: mime_type.clone(),
+                text: Some(text.clone()),
+            },
+            ResourceContents::BlobResourceContents {
+                uri,
+                mime_type,
+                blob,
+                ..
+            } => binary_part(uri_file_name(uri), mime_type.clone(), blob),
+        },
+        RawContent::ResourceLink(resource) => ToolResultPart::Resource {
+            uri: resource.uri.clone(),
+            mime_type: resource.mime_type.clone(),
+            text: None,
+        },
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn binary_part(name: String, mime_type: Option<String>, base64: &str) -> ToolResultPart {
+    match Attachment::from_base64(name, mime_type, base64) {
+        Ok(attachment) => ToolResultPart::Attachment(attachment),
+        Err(e) => ToolResultPart::Text(format!("[Unreadable tool output: {}]", e)),
+    }
+}
+
+/// Names unnamed binary outputs like `image_1.png`.
+#[cfg(not(target_arch = "wasm32"))]
+fn output_name(kind: &str, index: usize, mime_type: &str) -> String {
+    match mime_type.split_once('/') {
+        Some((_, subtype)) if !subtype.is_empty() => {
+            format!("{}_{}.{}", kind, index + 1, subtype)
+        }
+        _ => format!("{}_{}", kind, index + 1),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn describe_tool_result_part(part: &ToolResultPart) -> String {
+    match part {
+        ToolResultPart::Text(text) => text.clone(),
+        ToolResultPart::Attachment(attachment) => format!(
+            "[{} attached: {}]",
+            attachment.content_type_or_octet_stream(),
+            attachment.name
+        ),
+        ToolResultPart::Resource {
+            uri,
+            text: Some(text),
+            ..
+        } => format!("Resource {}:\n{}", uri, text),
+        ToolResultPart::Resource { uri, .. } => format!("[Resource: {}]", uri),
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "json",
+    feature = "http",
+    feature = "async-rt",
+    not(target_arch = "wasm32")
+))]
+mod tests {
+    use super::*;
+    use crate::protocol::EntityId;
+    use crate::utils::test_server::{TestRequest, TestResponse, TestServer, block_on};
+
+    /// Answers the streamable HTTP MCP handshake and `tools/list`, but only to
+    /// requests carrying the expected credentials.
+    fn mcp_server_handler(request: &TestRequest) -> TestResponse {
+        if request.path == "/token" {
+            let body = String::from_utf8_lossy(&request.body);
+            return if body.contains("client_id=moly") && body.contains("client_secret=secret") {
+                TestResponse::json(200, serde_json::json!({"access_token": "oauth-token"}))
+            } else {
+                TestResponse::json(401, serde_json::json!({"error": "invalid_client"}))
+            };
+        }
+
+        if request.header("x-api-key") != Some("key")
+            || request.header("authorization") != Some("Bearer oauth-token")
+        {
+            return TestResponse::new(401, "Unauthorized");
+        }
+
+        if request.method != "POST" {
+            return TestResponse::new(405, "");
+        }
+
+        let body = request.json();
+        let result = match body["method"].as_str() {
+            Some("initialize") => serde_json::json!({
+                "protocolVersion": body["params"]["protocolVersion"],
+                "capabilities": {"tools": {}, "resources": {}, "prompts": {}},
+                "serverInfo": {"name": "test", "version": "1.0.0"}
+            }),
+            Some("tools/list") => serde_json::json!({
+                "tools": [{
+                    "name": "echo",
+                    "description": "Echoes the input",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {"text": {"type": "string"}},
+                        "required": ["text"]
+                    }
+                }]
+            }),
+            Some("ping") => serde_json::json!({}),
+            Some("resources/list") => serde_json::json!({
+                "resources": [{"uri": "file:///notes.md", "name": "notes", "mimeType": "text/markdown"}]
+            }),
+            Some("resources/read") => serde_json::json!({
+                "contents": [{
+                    "uri": body["params"]["uri"],
+                    "mimeType": "text/markdown",
+                    "text": "# Notes"
+                }]
+            }),
+            Some("prompts/list") => serde_json::json!({
+                "prompts": [{
+                    "name": "review",
+                    "description": "Reviews code",
+                    "arguments": [{"name": "code", "required": true}]
+                }]
+            }),
+            Some("prompts/get") => serde_json::json!({
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": {
+                            "type": "text",
+                            "text": format!("Review: {}", body["params"]["arguments"]["code"].as_str().unwrap_or_default())
+                        }
+                    },
+                    {"role": "assistant", "content": {"type": "text", "text": "Sure."}}
+                ]
+            }),
+            Some("tools/call") if body["params"]["arguments"]["text"] == "slow" => {
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                serde_json::json!({"content": [{"type": "text", "text": "slow"}]})
+            }
+            Some("tools/call") => serde_json::json!({
+                "content": [
+                    {"type": "text", "text": body["params"]["arguments"]["text"]},
+                    {"type": "image", "data": "iVBORw0K", "mimeType": "image/png"},
+                    {"type": "resource_link", "uri": "file:///echo.log", "name": "echo.log"}
+                ],
+                "structuredContent": {"echoed": body["params"]["arguments"]["text"]},
+                "isError": false
+            }),
+            // Notifications don't get a response.
+            _ => return TestResponse::new(202, ""),
+        };
+
+        TestResponse::json(
+            200,
+            serde_json::json!({"jsonrpc": "2.0", "id": body["id"], "result": result}),
+        )
+    }
+
+    #[test]
+    fn test_http_transport_sends_headers_and_oauth_token() {
+        let server = TestServer::start(mcp_server_handler);
+        let manager = McpManagerClient::new();
+
+        let transport = McpHttpTransport::new(format!("{}/mcp", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_oauth(McpOAuthCredentials {
+                token_url: format!("{}/token", server.url()),
+                client_id: "moly".into(),
+                client_secret: "secret".into(),
+                scope: None,
+            });
+
+        block_on(manager.add_server("internal", McpTransport::Http(transport))).unwrap();
+
+        let tools = manager.get_all_namespaced_tools();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "internal__echo");
+        assert!(
+            server
+                .requests()
+                .iter()
+                .filter(|r| r.path == "/mcp")
+                .all(|r| r.header("x-api-key") == Some("key"))
+        );
+        // The token doesn't expire, so it's reused.
+        let token_requests = server
+            .requests()
+            .iter()
+            .filter(|r| r.path == "/token")
+            .count();
+        assert_eq!(token_requests, 1);
+    }
+
+    #[test]
+    fn test_expired_oauth_token_is_requested_again() {
+        let server = TestServer::start(|request| {
+            if request.path == "/token" {
+                return TestResponse::json(
+                    200,
+                    serde_json::json!({"access_token": "oauth-token", "expires_in": 0}),
+                );
+            }
+            mcp_server_handler(request)
+        });
+        let manager = McpManagerClient::new();
+
+        let transport = McpHttpTransport::new(format!("{}/mcp", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_oauth(McpOAuthCredentials {
+                token_url: format!("{}/token", server.url()),
+                client_id: "moly".into(),
+                client_secret: "secret".into(),
+                scope: None,
+            });
+
+        block_on(manager.add_server("internal", McpTransport::Http(transport))).unwrap();
+
+        let requests = server.requests();
+        let token_requests = requests.iter().filter(|r| r.path == "/token").count();
+        let mcp_requests = requests.iter().filter(|r| r.path == "/mcp").count();
+        assert!(mcp_requests > 1);
+        assert_eq!(token_requests, mcp_requests);
+    }
+
+    #[test]
+    fn test_sse_transport_sends_headers_and_oauth_token() {
+        // The stream ends after announcing the endpoint, so the handshake
+        // fails, but only after posting to it.
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/token" => mcp_server_handler(request),
+            "/sse" => TestResponse::sse([(Some("endpoint"), "/message".to_string())]),
+            _ => TestResponse::new(202, ""),
+        });
+        let manager = McpManagerClient::new();
+
+        let transport = McpHttpTransport::new(format!("{}/sse", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_oauth(McpOAuthCredentials {
+                token_url: format!("{}/token", server.url()),
+                client_id: "moly".into(),
+                client_secret: "secret".into(),
+                scope: None,
+            });
+
+        let _ = block_on(manager.add_server("internal", McpTransport::Sse(transport)));
+
+        let requests = server.requests();
+        let sse_requests = requests
+            .iter()
+            .filter(|r| r.path == "/sse" || r.path == "/message")
+            .collect::<Vec<_>>();
+        assert!(sse_requests.iter().any(|r| r.path == "/sse"));
+        assert!(sse_requests.iter().any(|r| r.path == "/message"));
+        assert!(sse_requests.iter().all(|r| {
+            r.header("x-api-key") == Some("key")
+                && r.header("authorization") == Some("Bearer oauth-token")
+        }));
+    }
+
+    #[test]
+    fn test_http_transport_without_credentials_is_rejected() {
+        let server = TestServer::start(mcp_server_handler);
+        let manager = McpManagerClient::new();
+
+        let transport = McpHttpTransport::new(format!("{}/mcp", server.url()));
+        let result = block_on(manager.add_server("internal", McpTransport::Http(transport)));
+
+        assert!(result.is_err());
+        assert!(manager.get_all_namespaced_tools().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_arguments_are_not_sent_to_the_server() {
+        let server = TestServer::start(mcp_server_handler);
+        let manager = McpManagerClient::new();
+        let transport = McpHttpTransport::new(format!("{}/mcp", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_header("Authorization", "Bearer oauth-token");
+
+        let result = block_on(async {
+            manager
+                .add_server("internal", McpTransport::Http(transport))
+                .await
+                .unwrap();
+
+            let arguments = parse_tool_arguments(r#"{"text": 42}"#).unwrap();
+            manager
+                .execute_tool_call("internal__echo", "call-1", arguments)
+                .await
+        });
+
+        assert!(result.is_error);
+        assert!(
+            result
+                .content
+                .contains("$.text: expected string, got number")
+        );
+        assert!(
+            server
+                .requests()
+                .iter()
+                .all(|r| r.body.is_empty() || r.json()["method"] != "tools/call")
+        );
+    }
+
+    #[test]
+    fn test_tool_result_keeps_typed_parts() {
+        let server = TestServer::start(mcp_server_handler);
+        let manager = McpManagerClient::new();
+        let transport = McpHttpTransport::new(format!("{}/mcp", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_header("Authorization", "Bearer oauth-token");
+
+        let result = block_on(async {
+            manager
+                .add_server("internal", McpTransport::Http(transport))
+                .await
+                .unwrap();
+
+            let arguments = parse_tool_arguments(r#"{"text": "hi"}"#).unwrap();
+            manager
+                .execute_tool_call("internal__echo", "call-1", arguments)
+                .await
+        });
+
+        assert!(!result.is_error);
+        assert_eq!(
+            result.structured_content,
+            Some(serde_json::json!({"echoed": "hi"}))
+        );
+        assert_eq!(
+            result.content,
+            "hi\n[image/png attached: image_2.png]\n[Resource: file:///echo.log]"
+        );
+
+        let images = result.attachments().collect::<Vec<_>>();
+        assert_eq!(images.len(), 1);
+        assert!(images[0].is_image());
+        assert_eq!(
+            result.parts[2],
+            ToolResultPart::Resource {
+                uri: "file:///echo.log".into(),
+                mime_type: None,
+                text: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_timed_out_call_is_cancelled_on_the_server() {
+        let server = TestServer::start(mcp_server_handler);
+        let manager = McpManagerClient::new();
+        let transport = McpHttpTransport::new(format!("{}/mcp", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_header("Authorization", "Bearer oauth-token");
+        manager.set_tool_timeout("internal", "echo", Some(Duration::from_millis(200)));
+        let cancelled = || {
+            server
+                .requests()
+                .iter()
+                .any(|r| !r.body.is_empty() && r.json()["method"] == "notifications/cancelled")
+        };
+
+        let (results, elapsed, cancelled_at) = block_on(async {
+            manager
+                .add_server("internal", McpTransport::Http(transport))
+                .await
+                .unwrap();
+
+            let tool_calls = ["slow", "fast"]
+                .into_iter()
+                .map(|text| ToolCall {
+                    id: text.to_string(),
+                    name: "internal__echo".into(),
+                    arguments: parse_tool_arguments(&format!(r#"{{"text": "{}"}}"#, text)).unwrap(),
+                    ..Default::default()
+                })
+                .collect();
+            let started = std::time::Instant::now();
+            let results = manager.execute_tool_calls(tool_calls).await;
+            let elapsed = started.elapsed();
+
+            while !cancelled() && started.elapsed() < Duration::from_secs(5) {
+                crate::utils::asynchronous::sleep(Duration::from_millis(20)).await;
+            }
+            (results, elapsed, started.elapsed())
+        });
+
+        // Both calls went to the same server at once.
+        assert!(elapsed < Duration::from_secs(1));
+        assert_eq!(results[0].tool_call_id, "slow");
+        assert!(results[0].is_error);
+        assert!(results[0].content.contains("timed out"));
+        assert_eq!(results[1].tool_call_id, "fast");
+        assert!(!results[1].is_error);
+        // The server heard of it while still running the slow call.
+        assert!(cancelled());
+        assert!(cancelled_at < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_server_status_is_tracked() {
+        let server = TestServer::start(mcp_server_handler);
+        let manager = McpManagerClient::new();
+
+        let authorized = McpHttpTransport::new(format!("{}/mcp", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_header("Authorization", "Bearer oauth-token");
+        let unauthorized = McpHttpTransport::new(format!("{}/mcp", server.url()));
+
+        block_on(async {
+            manager
+                .add_server("internal", McpTransport::Http(authorized))
+                .await
+                .unwrap();
+            let _ = manager
+                .add_server("rejected", McpTransport::Http(unauthorized))
+                .await;
+        });
+
+        assert_eq!(
+            manager.server_status("internal"),
+            Some(McpServerStatus::Ready { tool_count: 1 })
+        );
+        assert!(matches!(
+            manager.server_status("rejected"),
+            Some(McpServerStatus::Failed { .. })
+        ));
+
+        block_on(manager.remove_server("rejected")).unwrap();
+        assert_eq!(manager.server_statuses().len(), 1);
+    }
+
+    #[test]
+    fn test_server_is_reconnected_after_failed_health_checks() {
+        let pings = Arc::new(AtomicUsize::new(0));
+        let pings_clone = pings.clone();
+        let server = TestServer::start(move |request| {
+            // The first pings fail, as if the connection dropped.
+            if request.method == "POST"
+                && request.json()["method"] == "ping"
+                && pings_clone.fetch_add(1, Ordering::SeqCst) < MAX_MISSED_PINGS as usize
+            {
+                return TestResponse::new(500, "");
+            }
+            mcp_server_handler(request)
+        });
+
+        let manager = McpManagerClient::new();
+        manager.set_health_check_interval(Duration::from_millis(100));
+        let statuses = Arc::new(Mutex::new(Vec::new()));
+        let statuses_clone = statuses.clone();
+        manager.set_status_listener(move |_, status| {
+            statuses_clone.lock().unwrap().push(status.clone());
+        });
+
+        let transport = McpHttpTransport::new(format!("{}/mcp", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_header("Authorization", "Bearer oauth-token");
+
+        block_on(async {
+            manager
+                .add_server("internal", McpTransport::Http(transport))
+                .await
+                .unwrap();
+
+            for _ in 0..50 {
+                crate::utils::asynchronous::sleep(Duration::from_millis(100)).await;
+                if pings.load(Ordering::SeqCst) > MAX_MISSED_PINGS as usize {
+                    break;
+                }
+            }
+        });
+
+        let statuses = statuses.lock().unwrap();
+        assert!(
+            statuses
+                .iter()
+                .any(|s| matches!(s, McpServerStatus::Failed { .. }))
+        );
+        assert_eq!(
+            statuses.last(),
+            Some(&McpServerStatus::Ready { tool_count: 1 })
+        );
+        assert_eq!(manager.get_all_namespaced_tools().len(), 1);
+        assert_eq!(
+            server
+                .requests()
+                .iter()
+                .filter(|r| !r.body.is_empty() && r.json()["method"] == "initialize")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_server_missing_a_single_ping_stays_connected() {
+        let pings = Arc::new(AtomicUsize::new(0));
+        let pings_clone = pings.clone();
+        let server = TestServer::start(move |request| {
+            if request.method == "POST"
+                && request.json()["method"] == "ping"
+                && pings_clone.fetch_add(1, Ordering::SeqCst) == 0
+            {
+                return TestResponse::new(500, "");
+            }
+            mcp_server_handler(request)
+        });
+
+        let manager = McpManagerClient::new();
+        manager.set_health_check_interval(Duration::from_millis(100));
+        let transport = McpHttpTransport::new(format!("{}/mcp", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_header("Authorization", "Bearer oauth-token");
+
+        block_on(async {
+            manager
+                .add_server("internal", McpTransport::Http(transport))
+                .await
+                .unwrap();
+
+            for _ in 0..50 {
+                crate::utils::asynchronous::sleep(Duration::from_millis(100)).await;
+                if pings.load(Ordering::SeqCst) > 2 {
+                    break;
+                }
+            }
+        });
+
+        assert_eq!(
+            manager.server_status("internal"),
+            Some(McpServerStatus::Ready { tool_count: 1 })
+        );
+        assert_eq!(
+            server
+                .requests()
+                .iter()
+                .filter(|r| !r.body.is_empty() && r.json()["method"] == "initialize")
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_tool_discovery_failure_is_reported() {
+        let server = TestServer::start(|request| {
+            if request.method == "POST" && request.json()["method"] == "tools/list" {
+                return TestResponse::new(500, "");
+            }
+            mcp_server_handler(request)
+        });
+
+        let manager = McpManagerClient::new();
+        let transport = McpHttpTransport::new(format!("{}/mcp", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_header("Authorization", "Bearer oauth-token");
+
+        let result = block_on(manager.add_server("internal", McpTransport::Http(transport)));
+
+        assert!(result.is_err());
+        assert!(matches!(
+            manager.server_status("internal"),
+            Some(McpServerStatus::Failed { error }) if error.contains("discover tools")
+        ));
+        assert!(manager.get_all_namespaced_tools().is_empty());
+    }
+
+    #[test]
+    fn test_resources_and_prompts_are_discovered_and_used() {
+        let server = TestServer::start(mcp_server_handler);
+        let manager = McpManagerClient::new();
+        let transport = McpHttpTransport::new(format!("{}/mcp", server.url()))
+            .with_header("X-Api-Key", "key")
+            .with_header("Authorization", "Bearer oauth-token");
+        let bot_id = BotId::new("model", "provider");
+
+        let (attachments, messages) = block_on(async {
+            manager
+                .add_server("docs", McpTransport::Http(transport))
+                .await
+                .unwrap();
+
+            let attachments = manager
+                .read_resource("docs", "file:///notes.md")
+                .await
+                .unwrap();
+
+            let prompt = manager.find_prompt("review").unwrap();
+            let arguments = prompt.parse_arguments("main.rs").unwrap();
+            let messages = manager
+                .get_prompt(&prompt.namespaced_name, arguments, &bot_id)
+                .await
+                .unwrap();
+
+            (attachments, messages)
+        });
+
+        let resources = manager.get_all_resources();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].uri, "file:///notes.md");
+
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].name, "notes.md");
+        assert_eq!(
+            attachments[0].content_type.as_deref(),
+            Some("text/markdown")
+        );
+
+        let prompts = manager.get_all_prompts();
+        assert_eq!(prompts[0].namespaced_name, "docs__review");
+        assert!(prompts[0].arguments[0].required);
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].from, EntityId::User);
+        assert_eq!(messages[0].content.text, "Review: main.rs");
+        assert_eq!(messages[1].from, EntityId::Bot(bot_id));
+    }
+
+    #[test]
+    fn test_local_tools_are_registered_and_called() {
+        use crate::mcp::local_tools::{BuiltinTool, BuiltinToolProvider};
+
+        let manager = McpManagerClient::new();
+        manager.add_local_provider(
+            BuiltinToolProvider::new()
+                .with_tool(BuiltinTool::Calculate)
+                .with_tool(BuiltinTool::ReadFile),
+        );
+
+        // File tools are left out without a workspace.
+        let tools = manager.get_all_namespaced_tools();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "builtin__calculate");
+        assert_eq!(
+            manager.server_status("builtin"),
+            Some(McpServerStatus::Ready { tool_count: 1 })
+        );
+
+        let (result, invalid) = block_on(async {
+            let arguments = parse_tool_arguments(r#"{"expression": "(1 + 2) * 4"}"#).unwrap();
+            let result = manager
+                .execute_tool_call("builtin__calculate", "call-1", arguments)
+                .await;
+
+            let arguments = parse_tool_arguments(r#"{"expression": 12}"#).unwrap();
+            let invalid = manager
+                .execute_tool_call("builtin__calculate", "call-2", arguments)
+                .await;
+
+            (result, invalid)
+        });
+
+        assert!(!result.is_error);
+        assert_eq!(result.content, "12");
+        assert!(invalid.is_error);
+        assert!(invalid.content.contains("$.expression: expected string"));
+
+        // A server can't take the place of the local tools.
+        let transport = McpHttpTransport::new("http://127.0.0.1:1/mcp");
+        assert!(block_on(manager.add_server("builtin", McpTransport::Http(transport))).is_err());
+        assert_eq!(manager.get_all_namespaced_tools().len(), 1);
+
+        block_on(manager.remove_server("builtin")).unwrap();
+        assert!(manager.get_all_namespaced_tools().is_empty());
+    }
 }
@@ -0,0 +1,188 @@
+//! OAuth 2.0 client credentials for MCP servers behind an authorization server.
+//!
+//! The access token is requested before the first request to the server and
+//! again when it's about to expire, so long lived connections keep working.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::lock::Mutex;
+use http::Uri;
+use rmcp::model::ClientJsonRpcMessage;
+use rmcp::transport::common::client_side_sse::BoxedSseResponse;
+use rmcp::transport::sse_client::{SseClient, SseTransportError};
+use rmcp::transport::streamable_http_client::{
+    StreamableHttpClient, StreamableHttpError, StreamableHttpPostResponse,
+};
+use serde_json::Value;
+
+use super::mcp_manager::McpOAuthCredentials;
+
+/// Tokens are renewed this long before they expire, so they don't expire
+/// while a request is on its way.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+struct AccessToken {
+    value: String,
+    /// `None` if the authorization server didn't say.
+    expires_at: Option<Instant>,
+}
+
+impl AccessToken {
+    fn is_fresh(&self) -> bool {
+        self.expires_at
+            .is_none_or(|expires_at| Instant::now() + EXPIRY_MARGIN < expires_at)
+    }
+}
+
+/// Access tokens requested with the client credentials grant, reused until
+/// they expire.
+struct OAuthTokens {
+    client: reqwest::Client,
+    credentials: McpOAuthCredentials,
+    current: Mutex<Option<AccessToken>>,
+}
+
+impl OAuthTokens {
+    /// A valid access token, requested again if the last one expired.
+    async fn get(&self) -> Result<String, String> {
+        let mut current = self.current.lock().await;
+        if let Some(token) = current.as_ref().filter(|token| token.is_fresh()) {
+            return Ok(token.value.clone());
+        }
+
+        let token = request_access_token(&self.client, &self.credentials).await?;
+        let value = token.value.clone();
+        *current = Some(token);
+        Ok(value)
+    }
+}
+
+/// Requests an access token with the OAuth 2.0 client credentials grant.
+async fn request_access_token(
+    client: &reqwest::Client,
+    oauth: &McpOAuthCredentials,
+) -> Result<AccessToken, String> {
+    let mut form = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", oauth.client_id.as_str()),
+        ("client_secret", oauth.client_secret.as_str()),
+    ];
+    if let Some(scope) = &oauth.scope {
+        form.push(("scope", scope.as_str()));
+    }
+
+    let response = client
+        .post(&oauth.token_url)
+        .form(&form)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("OAuth token request failed: {}", e))?;
+
+    let body: Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Invalid OAuth token response: {}", e))?;
+
+    let value = body
+        .get("access_token")
+        .and_then(|t| t.as_str())
+        .ok_or("OAuth token response is missing `access_token`")?
+        .to_string();
+    let expires_at = body
+        .get("expires_in")
+        .and_then(|e| e.as_u64())
+        .map(|seconds| Instant::now() + Duration::from_secs(seconds));
+
+    Ok(AccessToken { value, expires_at })
+}
+
+/// HTTP client for the MCP transports which sends a valid access token with
+/// every request, instead of the one the transport was configured with.
+#[derive(Clone)]
+pub(crate) struct OAuthHttpClient {
+    client: reqwest::Client,
+    tokens: Arc<OAuthTokens>,
+}
+
+impl OAuthHttpClient {
+    /// Wraps the client which sends the requests, also used to request the
+    /// tokens.
+    pub fn new(client: reqwest::Client, credentials: McpOAuthCredentials) -> Self {
+        Self {
+            tokens: Arc::new(OAuthTokens {
+                client: client.clone(),
+                credentials,
+                current: Mutex::new(None),
+            }),
+            client,
+        }
+    }
+
+    async fn token(&self) -> Result<String, std::io::Error> {
+        self.tokens.get().await.map_err(std::io::Error::other)
+    }
+}
+
+impl StreamableHttpClient for OAuthHttpClient {
+    type Error = reqwest::Error;
+
+    async fn post_message(
+        &self,
+        uri: Arc<str>,
+        message: ClientJsonRpcMessage,
+        session_id: Option<Arc<str>>,
+        _auth_header: Option<String>,
+    ) -> Result<StreamableHttpPostResponse, StreamableHttpError<Self::Error>> {
+        let token = self.token().await?;
+        StreamableHttpClient::post_message(&self.client, uri, message, session_id, Some(token))
+            .await
+    }
+
+    async fn delete_session(
+        &self,
+        uri: Arc<str>,
+        session_id: Arc<str>,
+        _auth_header: Option<String>,
+    ) -> Result<(), StreamableHttpError<Self::Error>> {
+        let token = self.token().await?;
+        StreamableHttpClient::delete_session(&self.client, uri, session_id, Some(token)).await
+    }
+
+    async fn get_stream(
+        &self,
+        uri: Arc<str>,
+        session_id: Arc<str>,
+        last_event_id: Option<String>,
+        _auth_header: Option<String>,
+    ) -> Result<BoxedSseResponse, StreamableHttpError<Self::Error>> {
+        let token = self.token().await?;
+        StreamableHttpClient::get_stream(&self.client, uri, session_id, last_event_id, Some(token))
+            .await
+    }
+}
+
+impl SseClient for OAuthHttpClient {
+    type Error = reqwest::Error;
+
+    async fn post_message(
+        &self,
+        uri: Uri,
+        message: ClientJsonRpcMessage,
+        _auth_token: Option<String>,
+    ) -> Result<(), SseTransportError<Self::Error>> {
+        let token = self.token().await?;
+        SseClient::post_message(&self.client, uri, message, Some(token)).await
+    }
+
+    async fn get_stream(
+        &self,
+        uri: Uri,
+        last_event_id: Option<String>,
+        _auth_token: Option<String>,
+    ) -> Result<BoxedSseResponse, SseTransportError<Self::Error>> {
+        let token = self.token().await?;
+        SseClient::get_stream(&self.client, uri, last_event_id, Some(token)).await
+    }
+}
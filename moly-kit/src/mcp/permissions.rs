@@ -0,0 +1,83 @@
+//! Policies deciding which tool calls can run without the user's approval.
+
+use std::collections::HashMap;
+
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
+
+/// What to do when the model calls a tool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "snake_case"))]
+pub enum ToolPolicy {
+    /// Run the tool without asking.
+    Allow,
+    /// Ask the user every time.
+    #[default]
+    Ask,
+    /// Refuse to run the tool without asking. The model is told about it.
+    Deny,
+    /// Ask the first time in each chat, then run it without asking in that
+    /// chat.
+    AllowForChat,
+}
+
+/// Policies configured by server and by tool.
+///
+/// Tools are keyed by their namespaced name, and their policy takes
+/// precedence over the one of their server.
+#[derive(Clone, Debug, Default)]
+pub struct ToolPolicies {
+    tools: HashMap<String, ToolPolicy>,
+    servers: HashMap<String, ToolPolicy>,
+}
+
+impl ToolPolicies {
+    /// The most specific policy configured for the tool, if any.
+    pub fn get(&self, server_id: &str, namespaced_tool_name: &str) -> Option<ToolPolicy> {
+        self.tools
+            .get(namespaced_tool_name)
+            .or_else(|| self.servers.get(server_id))
+            .copied()
+    }
+
+    pub fn set_tool(&mut self, namespaced_tool_name: &str, policy: Option<ToolPolicy>) {
+        set_or_remove(&mut self.tools, namespaced_tool_name, policy);
+    }
+
+    pub fn set_server(&mut self, server_id: &str, policy: Option<ToolPolicy>) {
+        set_or_remove(&mut self.servers, server_id, policy);
+    }
+}
+
+fn set_or_remove(map: &mut HashMap<String, ToolPolicy>, key: &str, policy: Option<ToolPolicy>) {
+    match policy {
+        Some(policy) => map.insert(key.to_string(), policy),
+        None => map.remove(key),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_policy_overrides_server_policy() {
+        let mut policies = ToolPolicies::default();
+        policies.set_server("fs", Some(ToolPolicy::Allow));
+        policies.set_tool("fs__delete_file", Some(ToolPolicy::Deny));
+
+        assert_eq!(policies.get("fs", "fs__read_file"), Some(ToolPolicy::Allow));
+        assert_eq!(
+            policies.get("fs", "fs__delete_file"),
+            Some(ToolPolicy::Deny)
+        );
+        assert_eq!(policies.get("web", "web__fetch"), None);
+
+        policies.set_tool("fs__delete_file", None);
+        assert_eq!(
+            policies.get("fs", "fs__delete_file"),
+            Some(ToolPolicy::Allow)
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Resources and prompts offered by MCP servers, besides their tools.
+//!
+//! Resources can be attached to messages, and prompts are used as
+//! slash-commands expanding into messages.
+
+#[cfg(not(target_arch = "wasm32"))]
+use rmcp::model::{PromptMessage, PromptMessageContent, PromptMessageRole, ResourceContents};
+use serde_json::{Map, Value};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::protocol::{Attachment, BotId, EntityId, Message, MessageContent};
+
+/// A resource offered by an MCP server, which can be attached to messages.
+#[derive(Clone, Debug, PartialEq)]
+pub struct McpResource {
+    pub server_id: String,
+    pub uri: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub mime_type: Option<String>,
+}
+
+/// A prompt template offered by an MCP server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct McpPrompt {
+    pub server_id: String,
+    pub name: String,
+    /// Unique name across servers, like `server__name`, used as the command.
+    pub namespaced_name: String,
+    pub description: Option<String>,
+    pub arguments: Vec<McpPromptArgument>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct McpPromptArgument {
+    pub name: String,
+    pub description: Option<String>,
+    pub required: bool,
+}
+
+impl McpPrompt {
+    /// Usage hint for the command, like `/server__review <code> [language]`.
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.namespaced_name);
+        for argument in &self.arguments {
+            if argument.required {
+                usage.push_str(&format!(" <{}>", argument.name));
+            } else {
+                usage.push_str(&format!(" [{}]", argument.name));
+            }
+        }
+        usage
+    }
+
+    /// Parses the arguments typed after the command.
+    ///
+    /// Arguments are given as `name=value` or as plain values, which are
+    /// assigned to the declared arguments in order. Values with spaces must be
+    /// quoted, like `name="some value"`.
+    pub fn parse_arguments(&self, input: &str) -> Result<Map<String, Value>, String> {
+        let mut arguments = Map::new();
+
+        for token in split_arguments(input) {
+            let named = token
+                .split_once('=')
+                .filter(|(name, _)| self.arguments.iter().any(|a| a.name == *name))
+                .map(|(name, value)| (name.to_string(), value.to_string()));
+
+            let (name, value) = match named {
+                Some(named) => named,
+                None => {
+                    let next = self
+                        .arguments
+                        .iter()
+                        .find(|a| !arguments.contains_key(&a.name));
+                    let Some(argument) = next else {
+                        return Err(format!("Unexpected argument '{}'", token));
+                    };
+                    (argument.name.clone(), token)
+                }
+            };
+
+            arguments.insert(name, Value::String(value));
+        }
+
+        let missing = self
+            .arguments
+            .iter()
+            .filter(|a| a.required && !arguments.contains_key(&a.name))
+            .map(|a| a.name.as_str())
+            .collect::<Vec<_>>();
+
+        if !missing.is_empty() {
+            return Err(format!(
+                "Missing required arguments: {}. Usage: {}",
+                missing.join(", "),
+                self.usage()
+            ));
+        }
+
+        Ok(arguments)
+    }
+}
+
+/// Splits a slash-command like `/server__review lang=rust` into the prompt
+/// name and the rest of the text, if the text is a command.
+pub fn parse_prompt_command(text: &str) -> Option<(&str, &str)> {
+    let command = text.trim().strip_prefix('/')?;
+    let (name, arguments) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((name, arguments.trim()))
+}
+
+/// Splits on whitespace, keeping double quoted parts together (without the
+/// quotes).
+fn split_arguments(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in input.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+/// The last segment of the URI, to name the attachment made from a resource.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn uri_file_name(uri: &str) -> String {
+    uri.rsplit('/')
+        .next()
+        .filter(|n| !n.is_empty())
+        .unwrap_or(uri)
+        .to_string()
+}
+
+/// Converts the contents of a resource read from a server into an attachment.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn resource_attachment(contents: &ResourceContents) -> Result<Attachment, String> {
+    match contents {
+        ResourceContents::TextResourceContents {
+            uri,
+            mime_type,
+            text,
+            ..
+        } => Ok(Attachment::from_bytes(
+            uri_file_name(uri),
+            Some(mime_type.clone().unwrap_or_else(|| "text/plain".into())),
+            text.as_bytes(),
+        )),
+        ResourceContents::BlobResourceContents {
+            uri,
+            mime_type,
+            blob,
+            ..
+        } => Attachment::from_base64(uri_file_name(uri), mime_type.clone(), blob)
+            .map_err(|e| format!("Unreadable resource {}: {}", uri, e)),
+    }
+}
+
+/// Converts the messages of an expanded prompt into chat messages, with the
+/// assistant ones attributed to the given bot.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn prompt_messages(messages: Vec<PromptMessage>, bot_id: &BotId) -> Vec<Message> {
+    messages
+        .into_iter()
+        .map(|message| {
+            let from = match message.role {
+                PromptMessageRole::User => EntityId::User,
+                PromptMessageRole::Assistant => EntityId::Bot(bot_id.clone()),
+            };
+
+            let mut content = MessageContent::default();
+            match message.content {
+                PromptMessageContent::Text { text } => content.text = text,
+                PromptMessageContent::Image { image } => {
+                    let subtype = image.mime_type.rsplit('/').next().unwrap_or("png");
+                    let name = format!("image.{}", subtype);
+                    match Attachment::from_base64(name, Some(image.mime_type.clone()), &image.data)
+                    {
+                        Ok(attachment) => content.attachments.push(attachment),
+                        Err(e) => content.text = format!("[Unreadable image: {}]", e),
+                    }
+                }
+                PromptMessageContent::Resource { resource } => {
+                    match resource_attachment(&resource.resource) {
+                        Ok(attachment) => content.attachments.push(attachment),
+                        Err(e) => content.text = format!("[{}]", e),
+                    }
+                }
+                PromptMessageContent::ResourceLink { link } => {
+                    content.text = format!("[Resource: {}]", link.uri);
+                }
+            }
+
+            Message {
+                from,
+                content,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review_prompt() -> McpPrompt {
+        McpPrompt {
+            server_id: "code".into(),
+            name: "review".into(),
+            namespaced_name: "code__review".into(),
+            description: None,
+            arguments: vec![
+                McpPromptArgument {
+                    name: "code".into(),
+                    description: None,
+                    required: true,
+                },
+                McpPromptArgument {
+                    name: "language".into(),
+                    description: None,
+                    required: false,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse_prompt_command() {
+        assert_eq!(
+            parse_prompt_command("/code__review  lang=rust "),
+            Some(("code__review", "lang=rust"))
+        );
+        assert_eq!(
+            parse_prompt_command("/code__review"),
+            Some(("code__review", ""))
+        );
+        assert_eq!(parse_prompt_command("code__review"), None);
+        assert_eq!(parse_prompt_command("/ review"), None);
+    }
+
+    #[test]
+    fn test_parse_prompt_arguments() {
+        let prompt = review_prompt();
+
+        let arguments = prompt
+            .parse_arguments(r#"language=rust "fn main() {}""#)
+            .unwrap();
+        assert_eq!(arguments["code"], "fn main() {}");
+        assert_eq!(arguments["language"], "rust");
+
+        let arguments = prompt.parse_arguments("main.rs").unwrap();
+        assert_eq!(arguments["code"], "main.rs");
+        assert!(!arguments.contains_key("language"));
+
+        assert_eq!(
+            prompt.parse_arguments("language=rust"),
+            Err("Missing required arguments: code. Usage: /code__review <code> [language]".into())
+        );
+        assert!(prompt.parse_arguments("a b c").is_err());
+    }
+}
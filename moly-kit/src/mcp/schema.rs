@@ -0,0 +1,430 @@
+//! Validation of tool call arguments against the JSON Schema of the tool.
+//!
+//! Only the subset of JSON Schema used by tool definitions in practice is
+//! supported: `type`, `enum`, `const`, `properties`, `required`,
+//! `additionalProperties`, `items`, length and range limits, the `anyOf`,
+//! `oneOf` and `allOf` combinators, and local `$ref`s. Unknown keywords are
+//! ignored, so a schema using them is never stricter than intended.
+
+use serde_json::{Map, Value};
+
+/// A place where the arguments don't match the schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaViolation {
+    /// Location of the offending value, like `$.options.mode` or `$.files[2]`.
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Checks the arguments of a tool call against the tool's input schema,
+/// returning every violation found.
+pub fn validate_arguments(
+    schema: &Map<String, Value>,
+    arguments: &Map<String, Value>,
+) -> Vec<SchemaViolation> {
+    let mut violations = Vec::new();
+    let root = Value::Object(schema.clone());
+    let arguments = Value::Object(arguments.clone());
+    validate(&root, &root, &arguments, "$", &mut violations, 0);
+    violations
+}
+
+/// Bounds `$ref` resolution in case of recursive schemas.
+const MAX_DEPTH: usize = 32;
+
+fn validate(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    path: &str,
+    violations: &mut Vec<SchemaViolation>,
+    depth: usize,
+) {
+    let violation = |message: String| SchemaViolation {
+        path: path.to_string(),
+        message,
+    };
+
+    // `true` and `{}` accept anything, `false` nothing.
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => {
+            return violations.push(violation("no value is allowed here".into()));
+        }
+        Value::Object(schema) => schema,
+        _ => return,
+    };
+
+    if depth > MAX_DEPTH {
+        return;
+    }
+
+    if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+        match resolve_ref(root, reference) {
+            Some(target) => {
+                validate(root, target, value, path, violations, depth + 1);
+            }
+            None => violations.push(violation(format!(
+                "unresolvable schema reference `{}`",
+                reference
+            ))),
+        }
+        return;
+    }
+
+    if let Some(types) = schema.get("type") {
+        let types = match types {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => vec![],
+        };
+
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            return violations.push(violation(format!(
+                "expected {}, got {}",
+                types.join(" or "),
+                type_name(value)
+            )));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !allowed.contains(value) {
+            let allowed = allowed
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            violations.push(violation(format!(
+                "must be one of [{}], got {}",
+                allowed, value
+            )));
+        }
+    }
+
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            violations.push(violation(format!("must be {}, got {}", expected, value)));
+        }
+    }
+
+    match value {
+        Value::String(s) => {
+            let length = s.chars().count();
+            if let Some(min) = schema.get("minLength").and_then(|m| m.as_u64()) {
+                if (length as u64) < min {
+                    violations.push(violation(format!(
+                        "must be at least {} characters long",
+                        min
+                    )));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(|m| m.as_u64()) {
+                if (length as u64) > max {
+                    violations.push(violation(format!(
+                        "must be at most {} characters long",
+                        max
+                    )));
+                }
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or_default();
+            let limit = |keyword: &str| schema.get(keyword).and_then(|l| l.as_f64());
+            if let Some(min) = limit("minimum").filter(|min| n < *min) {
+                violations.push(violation(format!(
+                    "must be greater than or equal to {}",
+                    min
+                )));
+            }
+            if let Some(max) = limit("maximum").filter(|max| n > *max) {
+                violations.push(violation(format!("must be less than or equal to {}", max)));
+            }
+            if let Some(min) = limit("exclusiveMinimum").filter(|min| n <= *min) {
+                violations.push(violation(format!("must be greater than {}", min)));
+            }
+            if let Some(max) = limit("exclusiveMaximum").filter(|max| n >= *max) {
+                violations.push(violation(format!("must be less than {}", max)));
+            }
+        }
+        _ => {}
+    }
+
+    for (keyword, check) in [
+        ("allOf", Combinator::All),
+        ("anyOf", Combinator::Any),
+        ("oneOf", Combinator::One),
+    ] {
+        let Some(options) = schema.get(keyword).and_then(|o| o.as_array()) else {
+            continue;
+        };
+
+        let results = options
+            .iter()
+            .map(|option| {
+                let mut found = Vec::new();
+                validate(root, option, value, path, &mut found, depth + 1);
+                found
+            })
+            .collect::<Vec<_>>();
+        let matching = results.iter().filter(|r| r.is_empty()).count();
+
+        match check {
+            Combinator::All => violations.extend(results.into_iter().flatten()),
+            Combinator::Any if matching == 0 => violations.push(violation(format!(
+                "must match at least one of the allowed schemas ({})",
+                describe_failures(&results)
+            ))),
+            Combinator::One if matching != 1 => violations.push(violation(if matching == 0 {
+                format!(
+                    "must match exactly one of the allowed schemas ({})",
+                    describe_failures(&results)
+                )
+            } else {
+                format!(
+                    "must match exactly one of the allowed schemas, but matches {}",
+                    matching
+                )
+            })),
+            _ => {}
+        }
+    }
+
+    match value {
+        Value::Object(object) => validate_object(root, schema, object, path, violations, depth),
+        Value::Array(items) => validate_array(root, schema, items, path, violations, depth),
+        _ => {}
+    }
+}
+
+enum Combinator {
+    All,
+    Any,
+    One,
+}
+
+fn validate_object(
+    root: &Value,
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+    violations: &mut Vec<SchemaViolation>,
+    depth: usize,
+) {
+    let properties = schema.get("properties").and_then(|p| p.as_object());
+
+    if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+        for name in required.iter().filter_map(|n| n.as_str()) {
+            if !object.contains_key(name) {
+                violations.push(SchemaViolation {
+                    path: property_path(path, name),
+                    message: "missing required property".into(),
+                });
+            }
+        }
+    }
+
+    for (name, value) in object {
+        let property_path = property_path(path, name);
+        match properties.and_then(|p| p.get(name)) {
+            Some(property_schema) => validate(
+                root,
+                property_schema,
+                value,
+                &property_path,
+                violations,
+                depth + 1,
+            ),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => violations.push(SchemaViolation {
+                    path: property_path,
+                    message: "unknown property".into(),
+                }),
+                Some(additional @ Value::Object(_)) => validate(
+                    root,
+                    additional,
+                    value,
+                    &property_path,
+                    violations,
+                    depth + 1,
+                ),
+                _ => {}
+            },
+        }
+    }
+}
+
+fn validate_array(
+    root: &Value,
+    schema: &Map<String, Value>,
+    items: &[Value],
+    path: &str,
+    violations: &mut Vec<SchemaViolation>,
+    depth: usize,
+) {
+    if let Some(min) = schema.get("minItems").and_then(|m| m.as_u64()) {
+        if (items.len() as u64) < min {
+            violations.push(SchemaViolation {
+                path: path.to_string(),
+                message: format!("must have at least {} items", min),
+            });
+        }
+    }
+
+    if let Some(max) = schema.get("maxItems").and_then(|m| m.as_u64()) {
+        if (items.len() as u64) > max {
+            violations.push(SchemaViolation {
+                path: path.to_string(),
+                message: format!("must have at most {} items", max),
+            });
+        }
+    }
+
+    if let Some(item_schema) = schema.get("items") {
+        for (i, item) in items.iter().enumerate() {
+            let item_path = format!("{}[{}]", path, i);
+            validate(root, item_schema, item, &item_path, violations, depth + 1);
+        }
+    }
+}
+
+/// Resolves references within the same schema, like `#/$defs/Mode`.
+fn resolve_ref<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    root.pointer(pointer)
+}
+
+fn has_type(value: &Value, expected: &str) -> bool {
+    match expected {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn property_path(path: &str, name: &str) -> String {
+    format!("{}.{}", path, name)
+}
+
+fn describe_failures(results: &[Vec<SchemaViolation>]) -> String {
+    results
+        .iter()
+        .filter_map(|r| r.first())
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Violations as text, sorted as the order of properties isn't kept.
+    fn check(schema: Value, arguments: Value) -> Vec<String> {
+        let mut violations =
+            validate_arguments(schema.as_object().unwrap(), arguments.as_object().unwrap())
+                .into_iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>();
+        violations.sort();
+        violations
+    }
+
+    fn file_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string", "minLength": 1},
+                "mode": {"$ref": "#/$defs/mode"},
+                "options": {
+                    "type": "object",
+                    "properties": {
+                        "lines": {"type": "array", "items": {"type": "integer", "minimum": 1}}
+                    },
+                    "required": ["lines"],
+                    "additionalProperties": false
+                }
+            },
+            "required": ["path"],
+            "$defs": {
+                "mode": {"enum": ["read", "write"]}
+            }
+        })
+    }
+
+    #[test]
+    fn test_valid_arguments_pass() {
+        let arguments = json!({
+            "path": "notes.txt",
+            "mode": "read",
+            "options": {"lines": [1, 2.0, 30]},
+            "extra": true
+        });
+
+        assert!(check(file_schema(), arguments).is_empty());
+    }
+
+    #[test]
+    fn test_violations_are_reported_with_their_path() {
+        let arguments = json!({
+            "mode": "append",
+            "options": {"lines": [1, "two", 0], "follow": true}
+        });
+
+        assert_eq!(
+            check(file_schema(), arguments),
+            vec![
+                r#"$.mode: must be one of ["read", "write"], got "append""#,
+                "$.options.follow: unknown property",
+                "$.options.lines[1]: expected integer, got string",
+                "$.options.lines[2]: must be greater than or equal to 1",
+                "$.path: missing required property",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_combinators() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "id": {"anyOf": [{"type": "string"}, {"type": "integer"}]},
+                "target": {"oneOf": [{"type": "number"}, {"type": "integer"}]}
+            }
+        });
+
+        assert!(check(schema.clone(), json!({"id": 3, "target": 1.5})).is_empty());
+        assert_eq!(
+            check(schema, json!({"id": true, "target": 2})),
+            vec![
+                "$.id: must match at least one of the allowed schemas ($.id: expected string, got boolean; $.id: expected integer, got boolean)",
+                "$.target: must match exactly one of the allowed schemas, but matches 2",
+            ]
+        );
+    }
+}
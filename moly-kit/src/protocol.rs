@@ -1,7 +1,9 @@
 use makepad_widgets::{Cx, LiveDependency, LiveId, LivePtr, WidgetRef};
 
 // Re-export relevant, protocol related, async types.
-pub use crate::utils::asynchronous::{BoxPlatformSendFuture, BoxPlatformSendStream};
+pub use crate::utils::asynchronous::{
+    BoxPlatformSendFuture, BoxPlatformSendStream, CancellationToken,
+};
 
 #[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
@@ -58,6 +60,7 @@ use std::{
     error::Error,
     fmt,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 mod attachment;
@@ -129,6 +132,8 @@ pub enum RealtimeCommand {
     SendText(String),
     /// Interrupt current AI response
     Interrupt,
+    /// Cancel the response being generated, if any
+    CancelResponse,
     /// Update session configuration
     UpdateSessionConfig {
         voice: String,
@@ -333,18 +338,58 @@ pub struct ToolCall {
     pub permission_status: ToolCallPermissionStatus,
 }
 
-/// Represents the result of a tool call execution
+/// A typed piece of the content returned by a tool
 #[derive(Clone, PartialEq, Debug)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum ToolResultPart {
+    Text(String),
+    /// An image, or any other binary content, returned by the tool
+    Attachment(Attachment),
+    /// A resource of the tool's server, with its text if it was embedded
+    Resource {
+        uri: String,
+        mime_type: Option<String>,
+        text: Option<String>,
+    },
+}
+
+/// Represents the result of a tool call execution
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct ToolResult {
     /// The tool call ID this result corresponds to
     pub tool_call_id: String,
-    /// The result content from the tool execution
+    /// The result content from the tool execution, as text
+    ///
+    /// Used by clients which can't send the typed `parts`.
     pub content: String,
+    /// The typed content of the result, if the tool returned more than text
+    #[cfg_attr(feature = "json", serde(default))]
+    pub parts: Vec<ToolResultPart>,
+    /// Structured output of the tool, matching its output schema
+    #[cfg_attr(feature = "json", serde(default))]
+    pub structured_content: Option<serde_json::Value>,
     /// Whether the tool call was successful
     pub is_error: bool,
 }
 
+impl ToolResult {
+    /// The attachments among the typed parts
+    pub fn attachments(&self) -> impl Iterator<Item = &Attachment> {
+        self.parts.iter().filter_map(|part| match part {
+            ToolResultPart::Attachment(attachment) => Some(attachment),
+            _ => None,
+        })
+    }
+
+    pub fn attachments_mut(&mut self) -> impl Iterator<Item = &mut Attachment> {
+        self.parts.iter_mut().filter_map(|part| match part {
+            ToolResultPart::Attachment(attachment) => Some(attachment),
+            _ => None,
+        })
+    }
+}
+
 /// Standard message content format.
 #[derive(Clone, Debug, PartialEq, Default)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
@@ -397,6 +442,20 @@ pub struct MessageContent {
     /// Optional upgrade to realtime communication
     #[cfg_attr(feature = "json", serde(skip))]
     pub upgrade: Option<Upgrade>,
+
+    /// Token usage reported by the provider while generating this content.
+    ///
+    /// Clients set this (usually on the last chunk) and the chat controller
+    /// moves it into [`MessageMetadata::usage`].
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub usage: Option<TokenUsage>,
+
+    /// The bot that actually generated this content, when it's not the one
+    /// the message was sent to (e.g. a fallback bot).
+    ///
+    /// The chat controller moves it into [`MessageMetadata::answered_by`].
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub answered_by: Option<BotId>,
 }
 
 impl MessageContent {
@@ -411,6 +470,62 @@ impl MessageContent {
             && self.tool_results.is_empty()
             && self.upgrade.is_none()
     }
+
+    /// All the attachments of this content, including the ones returned by tools.
+    pub fn all_attachments(&self) -> impl Iterator<Item = &Attachment> {
+        self.attachments
+            .iter()
+            .chain(self.tool_results.iter().flat_map(|r| r.attachments()))
+    }
+
+    pub fn all_attachments_mut(&mut self) -> impl Iterator<Item = &mut Attachment> {
+        self.attachments.iter_mut().chain(
+            self.tool_results
+                .iter_mut()
+                .flat_map(|r| r.attachments_mut()),
+        )
+    }
+}
+
+/// Token counts reported by a provider for a single response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(default))]
+pub struct TokenUsage {
+    /// Tokens consumed by the input (the whole conversation context).
+    pub prompt_tokens: u64,
+    /// Part of `prompt_tokens` read from the provider's prompt cache, usually
+    /// billed at a lower price.
+    pub cached_prompt_tokens: u64,
+    /// Tokens generated by the model, reasoning included.
+    pub completion_tokens: u64,
+    /// Part of `completion_tokens` spent on reasoning, if reported.
+    pub reasoning_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl std::ops::Add for TokenUsage {
+    type Output = TokenUsage;
+
+    fn add(self, other: TokenUsage) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            cached_prompt_tokens: self.cached_prompt_tokens + other.cached_prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+            reasoning_tokens: self.reasoning_tokens + other.reasoning_tokens,
+        }
+    }
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: TokenUsage) {
+        *self = *self + other;
+    }
 }
 
 /// Metadata automatically tracked by MolyKit for each message.
@@ -446,6 +561,28 @@ pub struct MessageMetadata {
     /// by [`MessageMetadata::default`], it defaults to "now".
     #[cfg_attr(feature = "json", serde(default))]
     pub text_updated_at: DateTime<Utc>,
+
+    /// Whether the user stopped the generation of this message before it was
+    /// complete.
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub stopped_by_user: bool,
+
+    /// Tokens used to generate this message, if the provider reported them.
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub usage: Option<TokenUsage>,
+
+    /// The bot that actually answered, if it's not the one in [`Message::from`].
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub answered_by: Option<BotId>,
 }
 
 impl Default for MessageMetadata {
@@ -457,6 +594,9 @@ impl Default for MessageMetadata {
             created_at: now,
             reasoning_updated_at: now,
             text_updated_at: now,
+            stopped_by_user: false,
+            usage: None,
+            answered_by: None,
         }
     }
 }
@@ -474,6 +614,9 @@ impl MessageMetadata {
             created_at: DateTime::UNIX_EPOCH,
             reasoning_updated_at: DateTime::UNIX_EPOCH,
             text_updated_at: DateTime::UNIX_EPOCH,
+            stopped_by_user: false,
+            usage: None,
+            answered_by: None,
         }
     }
 }
@@ -510,9 +653,40 @@ pub struct Message {
 
     /// The parsed content of this message ready to present.
     pub content: MessageContent,
+
+    /// Alternative versions of the conversation from this message onwards, kept
+    /// when a message is edited or regenerated.
+    ///
+    /// Each branch starts with a sibling of this message and may contain further
+    /// branches itself, forming a tree where the messages list is the active path.
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub branches: Vec<Vec<Message>>,
+
+    /// Position of this message among its siblings, from `0` to `branches.len()`.
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "crate::utils::serde::is_default")
+    )]
+    pub branch_index: usize,
 }
 
 impl Message {
+    /// This message followed by the messages of all its inactive branches,
+    /// recursively.
+    ///
+    /// Useful to go through a whole conversation tree with
+    /// `messages.iter().flat_map(Message::with_branches)`.
+    pub fn with_branches(&self) -> Vec<&Message> {
+        let mut messages = vec![self];
+        for branch in &self.branches {
+            messages.extend(branch.iter().flat_map(Message::with_branches));
+        }
+        messages
+    }
+
     /// Shorthand for constructing an app error message.
     pub fn app_error(error: impl fmt::Display) -> Self {
         Message {
@@ -590,6 +764,8 @@ pub struct ClientError {
     kind: ClientErrorKind,
     message: String,
     source: Option<Arc<dyn Error + Send + Sync + 'static>>,
+    status: Option<u16>,
+    retry_after: Option<Duration>,
 }
 
 impl fmt::Display for ClientError {
@@ -626,6 +802,8 @@ impl ClientError {
             kind,
             message,
             source: None,
+            status: None,
+            retry_after: None,
         }
     }
 
@@ -638,9 +816,23 @@ impl ClientError {
             kind,
             message,
             source: source.map(|s| Arc::new(s) as _),
+            status: None,
+            retry_after: None,
         }
     }
 
+    /// Attaches the HTTP status code of the response that caused this error.
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Attaches how long the server asked to wait before trying again.
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
     /// Error kind accessor.
     pub fn kind(&self) -> ClientErrorKind {
         self.kind
@@ -650,6 +842,27 @@ impl ClientError {
     pub fn message(&self) -> &str {
         self.message.as_str()
     }
+
+    /// HTTP status code accessor, if the error came from an HTTP response.
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    /// Delay requested by the server before retrying, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// Whether the same operation may succeed if tried again later.
+    ///
+    /// This is the case for network errors, rate limits (HTTP 429) and server
+    /// errors (HTTP 5xx).
+    pub fn is_transient(&self) -> bool {
+        match self.status {
+            Some(status) => status == 429 || (500..=599).contains(&status),
+            None => self.kind == ClientErrorKind::Network,
+        }
+    }
 }
 
 /// The outcome of a client operation.
@@ -784,6 +997,40 @@ impl<T> ClientResult<T> {
     }
 }
 
+/// Sampling and length parameters for a generation.
+///
+/// `None` (or an empty `stop` list) leaves the value up to the provider's
+/// default. Clients should ignore the parameters their API doesn't support.
+#[derive(Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(default))]
+pub struct GenerationParams {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub frequency_penalty: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    /// Sequences where the generation should stop.
+    pub stop: Vec<String>,
+    /// Tokens the model may spend thinking before answering, for the APIs
+    /// where extended thinking must be enabled explicitly.
+    pub reasoning_budget: Option<u32>,
+}
+
+/// Per-request options given to [`BotClient::send`].
+#[derive(Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(default))]
+pub struct SendOptions {
+    pub generation: GenerationParams,
+    /// Identifies the request when calling [`BotClient::stop`].
+    ///
+    /// The chat controller sets this to a token owned by the chat, so stopping
+    /// one chat doesn't affect others talking to the same bot.
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub cancellation: Option<CancellationToken>,
+}
+
 /// A standard interface to fetch bots information and send messages to them.
 ///
 /// Warning: Expect this to be cloned to avoid borrow checking issues with
@@ -804,12 +1051,24 @@ pub trait BotClient: Send {
         bot_id: &BotId,
         messages: &[Message],
         tools: &[Tool],
+        options: &SendOptions,
     ) -> BoxPlatformSendStream<'static, ClientResult<MessageContent>>;
 
-    /// Interrupt the bot's current operation.
-    // TODO: There may be many chats with the same bot/model/agent so maybe this
-    // should be implemented by using cancellation tokens.
-    // fn stop(&mut self, bot: BotId);
+    /// Interrupt the work started by the [`BotClient::send`] calls made with
+    /// the given cancellation token.
+    ///
+    /// The caller also drops the stream returned by `send`, which is enough to
+    /// close an HTTP/SSE connection. Override this if the server must be told
+    /// explicitly (e.g. a stop endpoint or a cancel event over a websocket).
+    ///
+    /// The default implementation does nothing.
+    fn stop(
+        &mut self,
+        _bot_id: &BotId,
+        _token: &CancellationToken,
+    ) -> BoxPlatformSendFuture<'static, ClientResult<()>> {
+        Box::pin(futures::future::ready(ClientResult::new_ok(())))
+    }
 
     /// Bots available under this client.
     // NOTE: Could be a stream, but may add complexity rarely needed.
@@ -870,4 +1129,49 @@ mod tests {
         assert_ne!(id1.as_str(), id2.as_str());
         assert_ne!(id1, id2);
     }
+
+    #[test]
+    fn test_with_branches_walks_the_whole_tree() {
+        let message = |text: &str, branches: Vec<Vec<Message>>| Message {
+            content: MessageContent {
+                text: text.into(),
+                ..Default::default()
+            },
+            branches,
+            ..Default::default()
+        };
+
+        let root = message(
+            "a",
+            vec![
+                vec![
+                    message("b", vec![vec![message("c", vec![])]]),
+                    message("d", vec![]),
+                ],
+                vec![message("e", vec![])],
+            ],
+        );
+
+        let texts = root
+            .with_branches()
+            .into_iter()
+            .map(|m| m.content.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["a", "b", "c", "d", "e"]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_branch_fields_are_only_serialized_when_set() {
+        let mut message = Message::default();
+        let json = serde_json::to_value(&message).unwrap();
+        assert!(json.get("branches").is_none());
+        assert!(json.get("branch_index").is_none());
+
+        message.branches = vec![vec![Message::default()]];
+        message.branch_index = 1;
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["branch_index"], 1);
+        assert_eq!(json["branches"].as_array().unwrap().len(), 1);
+    }
 }
@@ -12,3 +12,11 @@ pub mod vec;
 #[cfg(feature = "json")]
 pub(crate) mod serde;
 pub(crate) mod sse;
+
+#[cfg(all(
+    test,
+    feature = "json",
+    feature = "async-rt",
+    not(target_arch = "wasm32")
+))]
+pub(crate) mod test_server;
@@ -115,6 +115,26 @@ pub async fn sleep(duration: std::time::Duration) {
     }
 }
 
+/// Runs blocking code (like file system access) on its own thread, so it
+/// doesn't block the async runtime.
+///
+/// On web, where threads are not available, it's just called.
+pub async fn run_blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        std::thread::spawn(move || {
+            let _ = sender.send(f());
+        });
+        receiver.await.expect("blocking task panicked")
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        f()
+    }
+}
+
 mod abort_on_drop {
     use super::*;
 
@@ -309,3 +329,37 @@ mod thread_token {
 }
 
 pub use thread_token::*;
+
+mod cancellation {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// A clonable flag to ask some ongoing work to be cancelled.
+    ///
+    /// Clones share the same flag and are considered equal, so the token can
+    /// also be used to identify the work it was given to.
+    #[derive(Clone, Debug, Default)]
+    pub struct CancellationToken(Arc<AtomicBool>);
+
+    impl CancellationToken {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn cancel(&self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+
+        pub fn is_cancelled(&self) -> bool {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    impl PartialEq for CancellationToken {
+        fn eq(&self, other: &Self) -> bool {
+            Arc::ptr_eq(&self.0, &other.0)
+        }
+    }
+}
+
+pub use cancellation::*;
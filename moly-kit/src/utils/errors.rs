@@ -1,4 +1,10 @@
-use reqwest::StatusCode;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::{
+    StatusCode,
+    header::{HeaderMap, RETRY_AFTER},
+};
 
 pub fn enrich_http_error(status: StatusCode, original: &str, body: Option<&str>) -> String {
     let clarification = match status {
@@ -34,3 +40,17 @@ pub fn enrich_http_error(status: StatusCode, original: &str, body: Option<&str>)
 
     result
 }
+
+/// Reads the `Retry-After` header, which can be either an amount of seconds or
+/// an HTTP date.
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    let delta = date.with_timezone(&Utc) - Utc::now();
+    Some(delta.to_std().unwrap_or(Duration::ZERO))
+}
@@ -1,11 +1,13 @@
 use reqwest::header::{HeaderValue, USER_AGENT};
 use scraper::Selector;
 
-/// Perform a GET request and return the raw bytes.
-pub(crate) async fn fetch_bytes(url: &str) -> Result<Vec<u8>, ()> {
-    let client = reqwest::Client::new();
+/// Responses bigger than this are not downloaded.
+const MAX_RESPONSE_SIZE: usize = 5 * 1024 * 1024;
 
-    let response = client
+/// Perform a GET request with the given client and return the raw bytes,
+/// failing if there are more than [`MAX_RESPONSE_SIZE`] of them.
+pub(crate) async fn fetch_bytes(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, String> {
+    let mut response = client
         .get(url)
         // Trick the server into thinking we're a browser
         .header(USER_AGENT, HeaderValue::from_static(
@@ -13,21 +15,48 @@ pub(crate) async fn fetch_bytes(url: &str) -> Result<Vec<u8>, ()> {
         ))
         .send()
         .await
-        .map_err(|_| ())?;
+        .map_err(|e| describe_error(&e))?;
 
     if !response.status().is_success() {
-        return Err(());
+        return Err(format!("The server answered with {}", response.status()));
     }
 
-    let text = response.bytes().await.map_err(|_| ())?;
-    Ok(text.to_vec())
+    let too_big = || format!("The response is bigger than {} bytes", MAX_RESPONSE_SIZE);
+
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_RESPONSE_SIZE as u64)
+    {
+        return Err(too_big());
+    }
+
+    // The length may be unknown or wrong, so it's checked while reading.
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| describe_error(&e))? {
+        if bytes.len() + chunk.len() > MAX_RESPONSE_SIZE {
+            return Err(too_big());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+}
+
+/// The error with its causes, as `reqwest` leaves them out of its message.
+fn describe_error(error: &dyn std::error::Error) -> String {
+    let mut text = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        text.push_str(&format!(": {}", error));
+        source = error.source();
+    }
+    text
 }
 
 /// Perform a GET request and parse the response as text.
-pub(crate) async fn fetch_text(url: &str) -> Result<String, ()> {
-    let bytes = fetch_bytes(url).await.map_err(|_| ())?;
-    let text = String::from_utf8(bytes).map_err(|_| ())?;
-    Ok(text)
+async fn fetch_text(client: &reqwest::Client, url: &str) -> Result<String, String> {
+    let bytes = fetch_bytes(client, url).await?;
+    String::from_utf8(bytes).map_err(|_| "The response is not valid text".to_string())
 }
 
 /// Type representing an HTML document.
@@ -48,8 +77,13 @@ impl Html {
 }
 
 /// Perform a GET request and try to parse it as an HTML document.
-pub(crate) async fn fetch_html(url: &str) -> Result<Html, ()> {
-    let text = fetch_text(url).await?;
+pub(crate) async fn fetch_html(url: &str) -> Result<Html, String> {
+    fetch_html_with(&reqwest::Client::new(), url).await
+}
+
+/// Like [`fetch_html`], sending the request with the given client.
+pub(crate) async fn fetch_html_with(client: &reqwest::Client, url: &str) -> Result<Html, String> {
+    let text = fetch_text(client, url).await?;
     Ok(Html(text))
 }
 
@@ -63,3 +97,52 @@ pub(crate) fn extract_title(document: &Html) -> Option<String> {
         .and_then(|element| element.text().next())
         .map(|text| text.trim().to_string())
 }
+
+/// Extract the readable text of the body of a standard HTML document, leaving
+/// out scripts and styles, with one line per block of text.
+pub(crate) fn extract_text(document: &Html) -> String {
+    let document = document.to_scraper();
+    let body_selector = Selector::parse("body").unwrap();
+    let Some(body) = document.select(&body_selector).next() else {
+        return String::new();
+    };
+
+    let mut lines = Vec::new();
+    for node in body.descendants() {
+        let Some(text) = node.value().as_text() else {
+            continue;
+        };
+
+        let hidden = node.ancestors().any(|ancestor| {
+            ancestor.value().as_element().is_some_and(|element| {
+                matches!(element.name(), "script" | "style" | "noscript" | "template")
+            })
+        });
+        if hidden {
+            continue;
+        }
+
+        let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_server::{TestResponse, TestServer, block_on};
+
+    #[test]
+    fn test_big_responses_are_not_downloaded() {
+        let server =
+            TestServer::start(|_| TestResponse::new(200, vec![b'a'; MAX_RESPONSE_SIZE + 1]));
+        let client = reqwest::Client::new();
+
+        let error = block_on(fetch_bytes(&client, &server.url())).unwrap_err();
+        assert!(error.contains("bigger than"), "{}", error);
+    }
+}
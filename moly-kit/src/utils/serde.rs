@@ -16,6 +16,11 @@ where
     Ok(opt.unwrap_or_default())
 }
 
+/// To skip serializing fields left with their default value.
+pub(crate) fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
 /// Deserializes to the default value on any deserialization error.
 ///
 /// This function will catch all deserialization errors and return the default
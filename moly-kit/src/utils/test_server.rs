@@ -0,0 +1,181 @@
+//! Minimal blocking HTTP/1.1 server used by tests to mock remote APIs.
+//!
+//! It runs on a background thread, binds to a random local port and answers
+//! every request with whatever the given handler returns. Each connection is
+//! closed after a single response to keep things simple.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+/// A request received by the [`TestServer`].
+#[derive(Clone, Debug)]
+pub(crate) struct TestRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl TestRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(|v| v.as_str())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).expect("request body is not valid JSON")
+    }
+}
+
+/// A response to be written back by the [`TestServer`].
+#[derive(Clone, Debug)]
+pub(crate) struct TestResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn json(status: u16, value: serde_json::Value) -> Self {
+        Self::new(status, value.to_string()).with_header("Content-Type", "application/json")
+    }
+
+    /// Builds a `text/event-stream` response from `(event, data)` pairs.
+    pub fn sse<'a>(events: impl IntoIterator<Item = (Option<&'a str>, String)>) -> Self {
+        let mut body = String::new();
+        for (event, data) in events {
+            if let Some(event) = event {
+                body.push_str(&format!("event: {}\n", event));
+            }
+            body.push_str(&format!("data: {}\n\n", data));
+        }
+
+        Self::new(200, body).with_header("Content-Type", "text/event-stream")
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// Handle to a running test server.
+///
+/// The server thread is never joined, it just lives until the test process ends.
+pub(crate) struct TestServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<TestRequest>>>,
+}
+
+impl TestServer {
+    pub fn start(handler: impl Fn(&TestRequest) -> TestResponse + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind test server");
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+
+        let requests_clone = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+
+                let handler = handler.clone();
+                let requests = requests_clone.clone();
+                std::thread::spawn(move || {
+                    let _ = serve_connection(stream, handler.as_ref(), &requests);
+                });
+            }
+        });
+
+        Self { addr, requests }
+    }
+
+    /// Base url of the server, without trailing slash.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// All the requests received until now, in arrival order.
+    pub fn requests(&self) -> Vec<TestRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn serve_connection(
+    stream: TcpStream,
+    handler: &(dyn Fn(&TestRequest) -> TestResponse + Send + Sync),
+    requests: &Mutex<Vec<TestRequest>>,
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|l| l.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let request = TestRequest {
+        method,
+        path,
+        headers,
+        body,
+    };
+
+    let response = handler(&request);
+    requests.lock().unwrap().push(request);
+
+    let mut stream = stream;
+    write!(stream, "HTTP/1.1 {} Test\r\n", response.status)?;
+    for (name, value) in &response.headers {
+        write!(stream, "{}: {}\r\n", name, value)?;
+    }
+    write!(
+        stream,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+/// Runs a future to completion on a fresh single threaded Tokio runtime.
+pub(crate) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
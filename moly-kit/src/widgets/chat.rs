@@ -4,12 +4,14 @@ use std::sync::{Arc, Mutex};
 
 use crate::controllers::chat::{
     ChatController, ChatControllerPlugin, ChatControllerPluginRegistrationId, ChatState,
-    ChatStateMutation, ChatTask,
+    ChatStateMutation, ChatTask, denied_tool_result,
 };
-use crate::mcp::mcp_manager::display_name_from_namespaced;
+use crate::mcp::mcp_manager::parse_namespaced_tool_name;
+use crate::mcp::parse_prompt_command;
 use crate::utils::makepad::events::EventExt;
 use crate::utils::vec::VecMutation;
 use crate::widgets::moly_modal::MolyModalWidgetExt;
+use crate::widgets::prompt_input::McpMenuItem;
 use crate::*;
 
 live_design!(
@@ -40,6 +42,46 @@ live_design!(
     }
 );
 
+/// Emitted when the user asks to remember a decision about a tool.
+///
+/// The policy is already applied to the chat's tool manager, this allows the
+/// app to persist it.
+#[derive(Clone, Debug, DefaultNone)]
+pub enum ToolPolicyAction {
+    Remembered {
+        server_id: String,
+        tool_name: String,
+        policy: ToolPolicy,
+    },
+    None,
+}
+
+/// Sets the policy of the given tools (by namespaced name) on the tool manager
+/// and notifies about it.
+fn remember_tool_policy(
+    cx: &mut Cx,
+    controller: &ChatController,
+    tool_names: &[String],
+    policy: ToolPolicy,
+) {
+    let Some(tool_manager) = controller.tool_manager() else {
+        return;
+    };
+
+    for name in tool_names {
+        let Ok((server_id, tool_name)) = parse_namespaced_tool_name(name) else {
+            continue;
+        };
+
+        tool_manager.set_tool_policy(&server_id, &tool_name, Some(policy));
+        cx.action(ToolPolicyAction::Remembered {
+            server_id,
+            tool_name,
+            policy,
+        });
+    }
+}
+
 /// A batteries-included chat to to implement chatbots.
 #[derive(Live, LiveHook, Widget)]
 pub struct Chat {
@@ -55,6 +97,10 @@ pub struct Chat {
     #[rust]
     bot_id: Option<BotId>,
 
+    /// Options given to the bot client on every send (e.g. temperature).
+    #[rust]
+    send_options: SendOptions,
+
     /// Toggles response streaming on or off. Default is on.
     // TODO: Implement this.
     #[live(true)]
@@ -67,6 +113,7 @@ pub struct Chat {
 impl Widget for Chat {
     fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
         self.ui_runner().handle(cx, event, scope, self);
+        self.update_mcp_menu_enabled(event);
         self.deref.handle_event(cx, event, scope);
 
         self.handle_messages(cx, event);
@@ -96,6 +143,8 @@ impl Chat {
             self.handle_submit(cx);
         }
 
+        self.handle_mcp_menu(cx, event);
+
         if self.prompt_input_ref().read().call_pressed(event.actions()) {
             self.handle_call(cx);
         }
@@ -110,7 +159,7 @@ impl Chat {
                 .unwrap()
                 .lock()
                 .unwrap()
-                .dispatch_task(ChatTask::Send(bot_id));
+                .dispatch_task(ChatTask::Send(bot_id, self.send_options.clone()));
         }
     }
 
@@ -236,9 +285,6 @@ impl Chat {
                     lock.dispatch_mutation(mutation);
                 }
                 MessagesAction::EditRegenerate(index) => {
-                    let mut messages =
-                        chat_controller.lock().unwrap().state().messages[0..=index].to_vec();
-
                     let text = self
                         .messages_ref()
                         .read()
@@ -249,37 +295,99 @@ impl Chat {
                         .write()
                         .set_message_editor_visibility(index, false);
 
-                    messages[index].update_content(|content| {
-                        content.text = text;
-                    });
+                    let mut lock = chat_controller.lock().unwrap();
 
-                    chat_controller
-                        .lock()
-                        .unwrap()
-                        .dispatch_mutation(VecMutation::Set(messages));
+                    // The original message and everything after it are kept
+                    // as a branch the user can switch back to.
+                    let original = &lock.state().messages[index];
+                    let mut message = Message {
+                        from: original.from.clone(),
+                        content: original.content.clone(),
+                        ..Default::default()
+                    };
+                    message.content.text = text;
+
+                    let mutation = lock.state().fork_messages(index, vec![message]);
+                    lock.dispatch_mutation(mutation);
 
                     if let Some(bot_id) = self.bot_id.clone() {
-                        chat_controller
-                            .lock()
-                            .unwrap()
-                            .dispatch_task(ChatTask::Send(bot_id));
+                        lock.dispatch_task(ChatTask::Send(bot_id, self.send_options.clone()));
                     }
                 }
-                MessagesAction::ToolApprove(index) => {
+                MessagesAction::Regenerate(index) => {
+                    let Some(bot_id) = self.bot_id.clone() else {
+                        continue;
+                    };
+
+                    let mut lock = chat_controller.lock().unwrap();
+                    if lock.state().is_streaming {
+                        continue;
+                    }
+
+                    // Regenerating a user message means generating the response
+                    // that follows it.
+                    let fork_index = match lock.state().messages[index].from {
+                        EntityId::Bot(_) => index,
+                        _ => index + 1,
+                    };
+
+                    lock.dispatch_task(ChatTask::Regenerate(
+                        fork_index,
+                        bot_id,
+                        self.send_options.clone(),
+                    ));
+                }
+                MessagesAction::SwitchBranch(index, branch) => {
+                    let mut lock = chat_controller.lock().unwrap();
+                    if lock.state().is_streaming {
+                        continue;
+                    }
+
+                    if let Some(mutation) = lock.state().switch_branch(index, branch) {
+                        lock.dispatch_mutation(mutation);
+                    }
+                }
+                MessagesAction::ToolApprove {
+                    index,
+                    for_chat,
+                    remember,
+                } => {
                     let mut lock = chat_controller.lock().unwrap();
 
                     let mut updated_message = lock.state().messages[index].clone();
 
+                    // Calls denied by their policy stay denied.
                     for tool_call in &mut updated_message.content.tool_calls {
-                        tool_call.permission_status = ToolCallPermissionStatus::Approved;
+                        if tool_call.permission_status == ToolCallPermissionStatus::Pending {
+                            tool_call.permission_status = ToolCallPermissionStatus::Approved;
+                        }
                     }
 
                     lock.dispatch_mutation(VecMutation::Update(index, updated_message));
 
                     let tools = lock.state().messages[index].content.tool_calls.clone();
+                    let approved = tools
+                        .iter()
+                        .filter(|tc| tc.permission_status == ToolCallPermissionStatus::Approved)
+                        .map(|tc| tc.name.clone())
+                        .collect::<Vec<_>>();
+
+                    if for_chat {
+                        lock.allow_tools_for_chat(approved.clone());
+                    }
+
+                    if remember {
+                        let policy = if for_chat {
+                            ToolPolicy::AllowForChat
+                        } else {
+                            ToolPolicy::Allow
+                        };
+                        remember_tool_policy(cx, &lock, &approved, policy);
+                    }
+
                     lock.dispatch_task(ChatTask::Execute(tools, self.bot_id.clone()));
                 }
-                MessagesAction::ToolDeny(index) => {
+                MessagesAction::ToolDeny { index, remember } => {
                     let mut lock = chat_controller.lock().unwrap();
 
                     let mut updated_message = lock.state().messages[index].clone();
@@ -292,23 +400,22 @@ impl Chat {
 
                     lock.dispatch_mutation(VecMutation::Update(index, updated_message));
 
+                    let tool_calls = &lock.state().messages[index].content.tool_calls;
+
+                    if remember {
+                        let names = tool_calls
+                            .iter()
+                            .map(|tc| tc.name.clone())
+                            .collect::<Vec<_>>();
+                        remember_tool_policy(cx, &lock, &names, ToolPolicy::Deny);
+                    }
+
                     // Create synthetic tool results indicating denial to maintain conversation flow
-                    let tool_results: Vec<ToolResult> = lock.state().messages[index]
-                        .content
-                        .tool_calls
-                        .iter()
-                        .map(|tc| {
-                            let display_name = display_name_from_namespaced(&tc.name);
-                            ToolResult {
-                                tool_call_id: tc.id.clone(),
-                                content: format!(
-                                    "Tool execution was denied by the user. Tool '{}' was not executed.",
-                                    display_name
-                                ),
-                                is_error: true,
-                            }
-                        })
-                        .collect();
+                    let tool_results: Vec<ToolResult> =
+                        tool_calls.iter().map(denied_tool_result).collect();
+
+                    let tool_calls = tool_calls.clone();
+                    lock.notify_tool_calls_denied(&tool_calls, self.bot_id.clone());
 
                     // Add tool result message with denial results
                     lock.dispatch_mutation(VecMutation::Push(Message {
@@ -326,6 +433,55 @@ impl Chat {
         }
     }
 
+    /// Enables the `/` menu of the prompt input while there are MCP servers,
+    /// fills it and handles the chosen entry.
+    fn tool_manager(&self) -> Option<McpManagerClient> {
+        self.chat_controller
+            .as_ref()
+            .and_then(|c| c.lock().unwrap().tool_manager().cloned())
+    }
+
+    /// Lets `/` open the MCP menu only while there are MCP servers.
+    ///
+    /// Checked when text is typed, before the prompt handles it.
+    fn update_mcp_menu_enabled(&mut self, event: &Event) {
+        if !matches!(event, Event::TextInput(_)) {
+            return;
+        }
+
+        let mcp_enabled = self.tool_manager().is_some_and(|tm| tm.has_servers());
+        self.prompt_input_ref()
+            .write()
+            .set_mcp_menu_enabled(mcp_enabled);
+    }
+
+    fn handle_mcp_menu(&mut self, cx: &mut Cx, event: &Event) {
+        let actions = event.actions();
+        let mut prompt = self.prompt_input_ref();
+
+        if prompt.read().should_build_items(actions) {
+            let (prompts, resources) = self
+                .tool_manager()
+                .map(|tm| (tm.get_all_prompts(), tm.get_all_resources()))
+                .unwrap_or_default();
+            prompt.write().set_mcp_menu(cx, prompts, resources);
+        }
+
+        let selected = prompt.read().mcp_menu_item_selected(actions);
+        match selected {
+            Some(McpMenuItem::Prompt(mcp_prompt)) => {
+                prompt.set_text(cx, &format!("/{} ", mcp_prompt.namespaced_name));
+                prompt.read().text_input_ref().set_key_focus(cx);
+            }
+            Some(McpMenuItem::Resource(resource)) => {
+                if let Some(tool_manager) = self.tool_manager() {
+                    prompt.write().attach_mcp_resource(tool_manager, resource);
+                }
+            }
+            None => {}
+        }
+    }
+
     fn handle_submit(&mut self, cx: &mut Cx) {
         let mut prompt = self.prompt_input_ref();
         let chat_controller = self.chat_controller.clone().unwrap();
@@ -341,6 +497,44 @@ impl Chat {
                 .attachments
                 .clone();
 
+            // Slash-commands naming an MCP prompt expand into its messages.
+            let mcp_prompt = parse_prompt_command(&text).and_then(|(name, input)| {
+                let tool_manager = chat_controller.lock().unwrap().tool_manager().cloned()?;
+                let mcp_prompt = tool_manager.find_prompt(name)?;
+                Some((mcp_prompt.parse_arguments(input), mcp_prompt))
+            });
+
+            if let Some((arguments, mcp_prompt)) = mcp_prompt {
+                let mut lock = chat_controller.lock().unwrap();
+                match arguments {
+                    Ok(arguments) => {
+                        if !attachments.is_empty() {
+                            lock.dispatch_mutation(VecMutation::Push(Message {
+                                from: EntityId::User,
+                                content: MessageContent {
+                                    attachments,
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            }));
+                        }
+
+                        prompt.write().reset(cx);
+                        lock.dispatch_task(ChatTask::UsePrompt(
+                            mcp_prompt.namespaced_name,
+                            arguments,
+                            bot_id,
+                            self.send_options.clone(),
+                        ));
+                    }
+                    // The command is kept in the input to be fixed.
+                    Err(e) => {
+                        lock.dispatch_mutation(VecMutation::Push(Message::app_error(e)));
+                    }
+                }
+                return;
+            }
+
             if !text.is_empty() || !attachments.is_empty() {
                 chat_controller
                     .lock()
@@ -360,7 +554,7 @@ impl Chat {
             chat_controller
                 .lock()
                 .unwrap()
-                .dispatch_task(ChatTask::Send(bot_id));
+                .dispatch_task(ChatTask::Send(bot_id, self.send_options.clone()));
         } else if prompt.read().has_stop_task() {
             chat_controller
                 .lock()
@@ -378,7 +572,7 @@ impl Chat {
                 .unwrap()
                 .lock()
                 .unwrap()
-                .dispatch_task(ChatTask::Send(bot_id));
+                .dispatch_task(ChatTask::Send(bot_id, self.send_options.clone()));
         }
     }
 
@@ -406,6 +600,14 @@ impl Chat {
         self.bot_id.as_ref()
     }
 
+    pub fn set_send_options(&mut self, send_options: SendOptions) {
+        self.send_options = send_options;
+    }
+
+    pub fn send_options(&self) -> &SendOptions {
+        &self.send_options
+    }
+
     pub fn set_chat_controller(
         &mut self,
         _cx: &mut Cx,
@@ -57,6 +57,11 @@ live_design! {
                 svg_file: dep("crate://self/resources/edit.svg")
             }
         }
+        regenerate = <ActionButton> {
+            draw_icon: {
+                svg_file: dep("crate://self/resources/regenerate.svg")
+            }
+        }
         delete = <ActionButton> {
             draw_icon: {
                 svg_file: dep("crate://self/resources/delete.svg")
@@ -64,6 +69,38 @@ live_design! {
         }
     }
 
+    BranchButton = <Button> {
+        width: Fit, height: Fit
+        padding: {left: 4, right: 4}
+        draw_text: {
+            text_style: <THEME_FONT_BOLD>{font_size: 9},
+            color: #667085
+            color_hover: #000
+            color_focus: #667085
+        }
+        draw_bg: {
+            fn pixel(self) -> vec4 {
+                return #0000
+            }
+        }
+    }
+
+    // Navigation between the versions of a message, shown as "< 2/3 >".
+    BranchSwitcher = <View> {
+        width: Fit,
+        align: {y: 0.5},
+        margin: {right: 10},
+        previous_branch = <BranchButton> { text: "<" }
+        position = <Label> {
+            text: "1/1"
+            draw_text: {
+                text_style: {font_size: 9},
+                color: #667085
+            }
+        }
+        next_branch = <BranchButton> { text: ">" }
+    }
+
     EditActionButton = <Button> {
         padding: {left: 10, right: 10, top: 4, bottom: 4},
         draw_text: {
@@ -126,6 +163,7 @@ live_design! {
         actions_section = <View> {
             margin: {left: 32, top: 2, bottom: 5},
             height: 22,
+            branch_switcher = <BranchSwitcher> { visible: false }
             actions = <Actions> { visible: false }
             edit_actions = <EditActions> { visible: false }
         }
@@ -227,10 +265,23 @@ live_design! {
             text: "Approve",
             draw_bg: {color: #4CAF50, color_hover: #45a049}
         }
+        approve_for_chat = <ToolApprovalButton> {
+            text: "Allow for this chat",
+            draw_bg: {color: #43A047, color_hover: #388E3C}
+        }
         deny = <ToolApprovalButton> {
             text: "Deny",
             draw_bg: {color: #f44336, color_hover: #d32f2f}
         }
+        remember = <CheckBox> {
+            text: "Remember this decision"
+            draw_text: {
+                fn get_color(self) -> vec4 {
+                    return #222;
+                }
+                text_style: {font_size: 10}
+            }
+        }
     }
 
     // Line for tool permission requests (from assistant asking to use a tool)
@@ -100,11 +100,29 @@ pub enum MessagesAction {
     /// history should be regenerated from here.
     EditRegenerate(usize),
 
+    /// The response at (or following) the given index should be generated again.
+    Regenerate(usize),
+
+    /// The message at the given index should be replaced by its sibling at the
+    /// given branch position.
+    SwitchBranch(usize, usize),
+
     /// The tool request at the given index should be approved and executed.
-    ToolApprove(usize),
+    ///
+    /// With `for_chat`, the tools may run without asking for the rest of the
+    /// chat. With `remember`, the decision becomes the policy of the tools.
+    ToolApprove {
+        index: usize,
+        for_chat: bool,
+        remember: bool,
+    },
 
-    /// The tool request at the given index should be denied.
-    ToolDeny(usize),
+    /// The tool request at the given index should be denied, and with
+    /// `remember`, always denied from now on.
+    ToolDeny {
+        index: usize,
+        remember: bool,
+    },
 
     None,
 }
@@ -310,8 +328,10 @@ impl Messages {
                     item
                 }
                 EntityId::Tool => {
-                    // Render tool execution results
-                    let item = if message.metadata.is_writing() {
+                    // Render tool execution results, as they come in.
+                    let executing =
+                        message.metadata.is_writing() && message.content.tool_results.is_empty();
+                    let item = if executing {
                         // Show loading animation for tool execution
                         let item = list.item(cx, index, live_id!(LoadingLine));
                         item.message_loading(ids!(content_section.loading))
@@ -325,11 +345,11 @@ impl Messages {
                         Some(Picture::Grapheme("T".into()));
                     item.label(ids!(name)).set_text(cx, "Tool");
 
-                    if !message.metadata.is_writing() {
+                    if !executing {
                         item.slot(ids!(content))
                             .current()
                             .as_standard_message_content()
-                            .set_content(cx, &message.content);
+                            .set_content_with_metadata(cx, &message.content, &message.metadata);
                     }
 
                     self.apply_actions_and_editor_visibility(cx, &item, index);
@@ -507,6 +527,14 @@ impl Messages {
                 }
             };
 
+            let branch_position = chat_controller.state().branch_position(index);
+            item.view(ids!(branch_switcher))
+                .set_visible(cx, branch_position.is_some());
+            if let Some((position, total)) = branch_position {
+                item.label(ids!(branch_switcher.position))
+                    .set_text(cx, &format!("{}/{}", position + 1, total));
+            }
+
             item.draw_all(cx, &mut Scope::empty());
 
             if let Some(second_last_message_index) = second_last_message_index
@@ -569,6 +597,13 @@ impl Messages {
         }
     }
 
+    /// Jump to the message at the given index instantly.
+    pub fn instant_scroll_to(&mut self, cx: &mut Cx, index: usize) {
+        self.portal_list(ids!(list))
+            .set_first_id_and_scroll(index, 0.0);
+        self.redraw(cx);
+    }
+
     /// Smoothly scroll to the end of the list.
     ///
     /// Warning: Do not continuously fire this method. Use [`Self::instant_scroll_to_bottom`]
@@ -668,6 +703,28 @@ impl Messages {
                 self.redraw(cx);
             }
 
+            if item.button(ids!(regenerate)).clicked(actions) {
+                cx.widget_action(
+                    self.widget_uid(),
+                    &scope.path,
+                    MessagesAction::Regenerate(index),
+                );
+            }
+
+            if item
+                .button(ids!(branch_switcher.previous_branch))
+                .clicked(actions)
+            {
+                self.switch_branch_by(cx, scope, index, -1);
+            }
+
+            if item
+                .button(ids!(branch_switcher.next_branch))
+                .clicked(actions)
+            {
+                self.switch_branch_by(cx, scope, index, 1);
+            }
+
             if item.button(ids!(edit_actions.cancel)).clicked(actions) {
                 self.set_message_editor_visibility(index, false);
                 self.redraw(cx);
@@ -694,11 +751,21 @@ impl Messages {
                 );
             }
 
-            if item.button(ids!(tool_actions.approve)).clicked(actions) {
+            let remember = item.check_box(ids!(tool_actions.remember)).active(cx);
+            let approve = item.button(ids!(tool_actions.approve)).clicked(actions);
+            let approve_for_chat = item
+                .button(ids!(tool_actions.approve_for_chat))
+                .clicked(actions);
+
+            if approve || approve_for_chat {
                 cx.widget_action(
                     self.widget_uid(),
                     &scope.path,
-                    MessagesAction::ToolApprove(index),
+                    MessagesAction::ToolApprove {
+                        index,
+                        for_chat: approve_for_chat,
+                        remember,
+                    },
                 );
             }
 
@@ -706,7 +773,7 @@ impl Messages {
                 cx.widget_action(
                     self.widget_uid(),
                     &scope.path,
-                    MessagesAction::ToolDeny(index),
+                    MessagesAction::ToolDeny { index, remember },
                 );
             }
 
@@ -727,6 +794,31 @@ impl Messages {
         }
     }
 
+    /// Emits [`MessagesAction::SwitchBranch`] to move to a sibling of the
+    /// message, if there is one in that direction.
+    fn switch_branch_by(&mut self, cx: &mut Cx, scope: &mut Scope, index: usize, delta: isize) {
+        let Some((position, total)) = self
+            .chat_controller
+            .as_ref()
+            .and_then(|c| c.lock().unwrap().state().branch_position(index))
+        else {
+            return;
+        };
+
+        let Some(branch) = position
+            .checked_add_signed(delta)
+            .filter(|branch| *branch < total)
+        else {
+            return;
+        };
+
+        cx.widget_action(
+            self.widget_uid(),
+            &scope.path,
+            MessagesAction::SwitchBranch(index, branch),
+        );
+    }
+
     fn apply_actions_and_editor_visibility(
         &mut self,
         cx: &mut Cx,
@@ -4,8 +4,9 @@ use std::cell::{Ref, RefMut};
 #[allow(unused)]
 use crate::{
     Attachment,
+    mcp::{McpManagerClient, McpPrompt, McpResource},
     protocol::{BotCapabilities, BotCapability},
-    utils::makepad::events::EventExt,
+    utils::{asynchronous::spawn, makepad::events::EventExt},
     widgets::attachment_list::{AttachmentListRef, AttachmentListWidgetExt},
 };
 
@@ -100,11 +101,43 @@ live_design! {
         submit = <SubmitButton> {}
     }
 
+    McpMenuItem = <View> {
+        width: Fill, height: Fit
+        flow: Down, spacing: 2
+        padding: {top: 6, bottom: 6, left: 8, right: 8}
+        title = <Label> {
+            draw_text: {
+                color: #000
+                text_style: {font_size: 10}
+            }
+        }
+        description = <Label> {
+            width: Fill
+            draw_text: {
+                wrap: Word
+                color: #667085
+                text_style: {font_size: 9}
+            }
+        }
+    }
+
+    McpMenuSection = <Label> {
+        padding: {top: 4, bottom: 4, left: 8}
+        draw_text: {
+            color: #98A2B3
+            text_style: {font_size: 9}
+        }
+    }
+
     pub PromptInput = {{PromptInput}} <CommandTextInput> {
         send_icon: dep("crate://self/resources/send.svg"),
         stop_icon: dep("crate://self/resources/stop.svg"),
 
         height: Fit { max: 350 }
+
+        mcp_item_template: <McpMenuItem> {}
+        mcp_section_template: <McpMenuSection> {}
+
         persistent = {
             height: Fit
             padding: {top: 10, bottom: 10, left: 10, right: 10}
@@ -198,6 +231,15 @@ pub enum Interactivity {
     Disabled,
 }
 
+/// An entry of the menu opened by typing `/`.
+#[derive(Clone, Debug)]
+pub enum McpMenuItem {
+    /// Inserted as a slash-command to fill its arguments.
+    Prompt(McpPrompt),
+    /// Read and attached to the message.
+    Resource(McpResource),
+}
+
 /// A prepared text input for conversation with bots.
 ///
 /// This is mostly a dummy widget. Prefer using and adapting [crate::widgets::chat::Chat] instead.
@@ -225,6 +267,16 @@ pub struct PromptInput {
     /// Capabilities of the currently selected bot
     #[rust]
     pub bot_capabilities: Option<BotCapabilities>,
+
+    #[live]
+    mcp_item_template: Option<LivePtr>,
+
+    #[live]
+    mcp_section_template: Option<LivePtr>,
+
+    /// Entries currently in the `/` menu, by the uid of their widget.
+    #[rust]
+    mcp_menu: Vec<(WidgetUid, McpMenuItem)>,
 }
 
 impl LiveHook for PromptInput {
@@ -252,11 +304,7 @@ impl Widget for PromptInput {
             Attachment::pick_multiple(move |result| match result {
                 Ok(attachments) => {
                     ui.defer_with_redraw(move |me, _, _| {
-                        let mut list = me.attachment_list_ref();
-                        list.write().attachments.extend(attachments);
-                        list.write().on_tap(move |list, index| {
-                            list.attachments.remove(index);
-                        });
+                        me.add_attachments(attachments);
                     });
                 }
                 Err(_) => {}
@@ -377,6 +425,143 @@ impl PromptInput {
         self.attachment_list(ids!(attachments))
     }
 
+    /// Adds attachments to the message being written. They can be removed by
+    /// tapping them.
+    pub fn add_attachments(&mut self, attachments: Vec<Attachment>) {
+        let mut list = self.attachment_list_ref();
+        list.write().attachments.extend(attachments);
+        list.write().on_tap(move |list, index| {
+            list.attachments.remove(index);
+        });
+    }
+
+    /// Lets typing `/` open the menu of MCP prompts and resources. Disabled by
+    /// default, as it's useless without MCP servers.
+    pub fn set_mcp_menu_enabled(&mut self, enabled: bool) {
+        self.deref.trigger = enabled.then(|| "/".to_string());
+    }
+
+    /// Fills the `/` menu with the MCP prompts and resources matching what was
+    /// typed after the `/`.
+    pub fn set_mcp_menu(
+        &mut self,
+        cx: &mut Cx,
+        prompts: Vec<McpPrompt>,
+        resources: Vec<McpResource>,
+    ) {
+        let terms = self
+            .deref
+            .search_text()
+            .split_whitespace()
+            .map(|t| t.to_lowercase())
+            .collect::<Vec<_>>();
+        let matches = |texts: &[&str]| {
+            terms
+                .iter()
+                .all(|t| texts.iter().any(|text| text.to_lowercase().contains(t)))
+        };
+
+        self.deref.clear_items();
+        self.mcp_menu.clear();
+
+        let prompts = prompts
+            .into_iter()
+            .filter(|p| {
+                matches(&[
+                    p.namespaced_name.as_str(),
+                    p.description.as_deref().unwrap_or_default(),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        let resources = resources
+            .into_iter()
+            .filter(|r| matches(&[r.name.as_str(), r.uri.as_str()]))
+            .collect::<Vec<_>>();
+
+        if prompts.is_empty() && resources.is_empty() {
+            self.add_mcp_menu_section(cx, "No MCP prompts or resources found");
+            return;
+        }
+
+        if !prompts.is_empty() {
+            self.add_mcp_menu_section(cx, "Prompts");
+        }
+
+        for prompt in prompts {
+            let description = prompt.description.clone().unwrap_or_default();
+            self.add_mcp_menu_item(
+                cx,
+                &prompt.usage(),
+                &description,
+                McpMenuItem::Prompt(prompt),
+            );
+        }
+
+        if !resources.is_empty() {
+            self.add_mcp_menu_section(cx, "Resources");
+        }
+
+        for resource in resources {
+            let description = resource
+                .description
+                .clone()
+                .unwrap_or_else(|| resource.uri.clone());
+            self.add_mcp_menu_item(
+                cx,
+                &resource.name,
+                &description,
+                McpMenuItem::Resource(resource),
+            );
+        }
+    }
+
+    fn add_mcp_menu_section(&mut self, cx: &mut Cx, text: &str) {
+        let label = WidgetRef::new_from_ptr(cx, self.mcp_section_template);
+        label.set_text(cx, text);
+        self.deref.add_unselectable_item(label);
+    }
+
+    fn add_mcp_menu_item(
+        &mut self,
+        cx: &mut Cx,
+        title: &str,
+        description: &str,
+        item: McpMenuItem,
+    ) {
+        let widget = WidgetRef::new_from_ptr(cx, self.mcp_item_template);
+        widget.label(ids!(title)).set_text(cx, title);
+        widget.label(ids!(description)).set_text(cx, description);
+        self.mcp_menu.push((widget.widget_uid(), item));
+        self.deref.add_item(widget);
+    }
+
+    /// The entry of the `/` menu chosen by the user, if any.
+    pub fn mcp_menu_item_selected(&self, actions: &Actions) -> Option<McpMenuItem> {
+        let selected = self.deref.item_selected(actions)?;
+        self.mcp_menu
+            .iter()
+            .find(|(uid, _)| *uid == selected.widget_uid())
+            .map(|(_, item)| item.clone())
+    }
+
+    /// Reads an MCP resource in the background and attaches it to the
+    /// message being written.
+    pub fn attach_mcp_resource(&mut self, tool_manager: McpManagerClient, resource: McpResource) {
+        let ui = self.ui_runner();
+        spawn(async move {
+            let result = tool_manager
+                .read_resource(&resource.server_id, &resource.uri)
+                .await
+                .map_err(|e| e.to_string());
+
+            ui.defer_with_redraw(move |me, _, _| match result {
+                Ok(attachments) => me.add_attachments(attachments),
+                Err(e) => ::log::error!("Failed to read MCP resource '{}': {}", resource.uri, e),
+            });
+        });
+    }
+
     /// Set the capabilities of the currently selected bot
     pub fn set_bot_capabilities(&mut self, cx: &mut Cx, capabilities: Option<BotCapabilities>) {
         self.bot_capabilities = capabilities;
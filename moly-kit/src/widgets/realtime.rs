@@ -1,9 +1,11 @@
-use crate::controllers::chat::ChatController;
+use crate::controllers::chat::{ChatController, ToolApproval, ToolExecutionRecord};
+use crate::mcp::ToolPolicy;
 use crate::widgets::{
     avatar::AvatarWidgetRefExt, slot::SlotWidgetRefExt,
     standard_message_content::StandardMessageContentWidgetRefExt,
 };
 use crate::{protocol::*, utils::makepad::events::EventExt};
+use chrono::Utc;
 use makepad_widgets::permission::Permission;
 use makepad_widgets::permission::PermissionStatus;
 use makepad_widgets::{makepad_platform::AudioDeviceType, *};
@@ -805,7 +807,7 @@ impl WidgetMatchEvent for Realtime {
             .button(ids!(message_section.content_section.tool_actions.deny))
             .clicked(actions)
         {
-            self.deny_tool_call(cx);
+            self.deny_tool_call(cx, ToolApproval::Manual);
         }
 
         let speaker_dropdown = self.drop_down(ids!(speaker_selector.device_selector));
@@ -1204,34 +1206,42 @@ impl Realtime {
                     call_id,
                     arguments,
                 } => {
-                    // Check if dangerous mode is enabled to auto-approve function calls
-                    let dangerous_mode_enabled = self
+                    // The tool policies may approve or deny the call without asking.
+                    let (policy, approval) = self
                         .chat_controller
                         .as_ref()
-                        .map(|ctx| {
-                            ctx.lock()
-                                .unwrap()
-                                .tool_manager()
-                                .map(|tm| tm.get_dangerous_mode_enabled())
-                                .unwrap_or(false)
+                        .and_then(|ctx| {
+                            ctx.lock().unwrap().tool_manager().map(|tm| {
+                                let approval = match tm.configured_tool_policy(&name) {
+                                    Some(_) => ToolApproval::Policy,
+                                    None => ToolApproval::DangerousMode,
+                                };
+                                (tm.tool_policy(&name), approval)
+                            })
                         })
-                        .unwrap_or(false);
+                        .unwrap_or((ToolPolicy::default(), ToolApproval::Manual));
 
-                    if dangerous_mode_enabled {
-                        // Auto-approve function calls in dangerous mode
-                        use crate::mcp::mcp_manager::display_name_from_namespaced;
-                        let display_name = display_name_from_namespaced(&name);
-                        self.label(ids!(status_label))
-                            .set_text(cx, &format!("🔧 Auto-executing tool: {}", display_name));
+                    match policy {
+                        ToolPolicy::Allow => {
+                            use crate::mcp::mcp_manager::display_name_from_namespaced;
+                            let display_name = display_name_from_namespaced(&name);
+                            self.label(ids!(status_label))
+                                .set_text(cx, &format!("🔧 Auto-executing tool: {}", display_name));
 
-                        // Execute the function call directly
-                        self.handle_function_call(cx, name, call_id, arguments);
-                    } else {
-                        // Show permission request as usual
-                        self.label(ids!(status_label))
-                            .set_text(cx, &format!("🔧 Tool permission requested: {}", name));
+                            // Execute the function call directly
+                            self.handle_function_call(cx, name, call_id, arguments, approval);
+                        }
+                        ToolPolicy::Deny => {
+                            self.pending_tool_call = Some((name, call_id, arguments));
+                            self.deny_tool_call(cx, approval);
+                        }
+                        ToolPolicy::Ask | ToolPolicy::AllowForChat => {
+                            // Show permission request as usual
+                            self.label(ids!(status_label))
+                                .set_text(cx, &format!("🔧 Tool permission requested: {}", name));
 
-                        self.show_tool_permission_request(cx, name, call_id, arguments);
+                            self.show_tool_permission_request(cx, name, call_id, arguments);
+                        }
                     }
                 }
                 RealtimeEvent::Error(error) => {
@@ -1316,12 +1326,45 @@ impl Realtime {
         self.view.redraw(cx);
     }
 
+    /// The bot of the conversation, if known.
+    fn bot_id(&self) -> Option<BotId> {
+        match &self.bot_entity_id {
+            Some(EntityId::Bot(bot_id)) => Some(bot_id.clone()),
+            _ => None,
+        }
+    }
+
+    /// Tells the plugins of the chat controller about a tool call handled in
+    /// the conversation, so it's audited like the ones of the chat.
+    fn notify_tool_executed(
+        chat_controller: &Mutex<ChatController>,
+        bot_id: Option<BotId>,
+        tool_call: ToolCall,
+        approval: ToolApproval,
+        started_at: chrono::DateTime<Utc>,
+        result: ToolResult,
+    ) {
+        let record = ToolExecutionRecord {
+            bot_id,
+            tool_call,
+            approval,
+            started_at,
+            duration: (Utc::now() - started_at).to_std().unwrap_or_default(),
+            result,
+        };
+        chat_controller
+            .lock()
+            .unwrap()
+            .notify_tool_executed(&record);
+    }
+
     fn handle_function_call(
         &mut self,
         _cx: &mut Cx,
         name: String,
         call_id: String,
         arguments: String,
+        approval: ToolApproval,
     ) {
         let Some(chat_controller) = self.chat_controller.as_ref().cloned() else {
             ::log::error!("No chat controller available for function call");
@@ -1358,32 +1401,44 @@ impl Realtime {
         };
 
         let channel = self.realtime_channel.clone();
+        let bot_id = self.bot_id();
 
         let future = async move {
+            let started_at = Utc::now();
+
             // Parse the arguments JSON
-            let arguments_map = match crate::mcp::mcp_manager::parse_tool_arguments(&arguments) {
-                Ok(args) => args,
+            let parsed_arguments = crate::mcp::mcp_manager::parse_tool_arguments(&arguments);
+            let result = match &parsed_arguments {
+                Ok(arguments_map) => {
+                    tool_manager
+                        .execute_tool_call(&name, &call_id, arguments_map.clone())
+                        .await
+                }
                 Err(e) => {
                     ::log::error!("Failed to parse function call arguments: {}", e);
-                    if let Some(channel) = &channel {
-                        let error_result = serde_json::json!({
-                            "error": e
-                        })
-                        .to_string();
-                        let _ = channel.command_sender.unbounded_send(
-                            crate::protocol::RealtimeCommand::SendFunctionCallResult {
-                                call_id,
-                                output: error_result,
-                            },
-                        );
+                    ToolResult {
+                        tool_call_id: call_id.clone(),
+                        content: e.clone(),
+                        is_error: true,
+                        ..Default::default()
                     }
-                    return;
                 }
             };
 
-            let result = tool_manager
-                .execute_tool_call(&name, &call_id, arguments_map)
-                .await;
+            let tool_call = ToolCall {
+                id: call_id.clone(),
+                name,
+                arguments: parsed_arguments.unwrap_or_default(),
+                permission_status: ToolCallPermissionStatus::Approved,
+            };
+            Self::notify_tool_executed(
+                &chat_controller,
+                bot_id,
+                tool_call,
+                approval,
+                started_at,
+                result.clone(),
+            );
 
             if let Some(channel) = &channel {
                 let output = if result.is_error {
@@ -1416,7 +1471,7 @@ impl Realtime {
                 .set_text(cx, &format!("🔧 Executing tool: {}", display_name));
 
             // Execute the tool
-            self.handle_function_call(cx, name, call_id, arguments);
+            self.handle_function_call(cx, name, call_id, arguments, ToolApproval::Manual);
 
             // Resume recording if conversation is active
             if self.conversation_active {
@@ -1427,11 +1482,30 @@ impl Realtime {
         }
     }
 
-    fn deny_tool_call(&mut self, cx: &mut Cx) {
-        if let Some((name, call_id, _arguments)) = self.pending_tool_call.take() {
+    fn deny_tool_call(&mut self, cx: &mut Cx, approval: ToolApproval) {
+        if let Some((name, call_id, arguments)) = self.pending_tool_call.take() {
             // Hide permission UI
             self.view(ids!(tool_permission_line)).set_visible(cx, false);
 
+            if let Some(chat_controller) = &self.chat_controller {
+                let tool_call = ToolCall {
+                    id: call_id.clone(),
+                    name: name.clone(),
+                    arguments: crate::mcp::mcp_manager::parse_tool_arguments(&arguments)
+                        .unwrap_or_default(),
+                    permission_status: ToolCallPermissionStatus::Denied,
+                };
+                let result = crate::controllers::chat::denied_tool_result(&tool_call);
+                Self::notify_tool_executed(
+                    chat_controller,
+                    self.bot_id(),
+                    tool_call,
+                    approval,
+                    Utc::now(),
+                    result,
+                );
+            }
+
             // Send denial response
             if let Some(channel) = &self.realtime_channel {
                 let denial_result = serde_json::json!({
@@ -61,13 +61,15 @@ impl StandardMessageContent {
     ) {
         /// String to add as suffix to the message text when its being typed.
         const TYPING_INDICATOR: &str = "●";
+        /// Note added at the end of messages the user stopped.
+        const STOPPED_NOTE: &str = "\n\n*Stopped by user*";
 
         let citation_list = self.citation_list(ids!(citations));
         citation_list.borrow_mut().unwrap().urls = content.citations.clone();
         citation_list.borrow_mut().unwrap().visible = !content.citations.is_empty();
 
         let mut attachments = self.attachment_list(ids!(attachments));
-        attachments.write().attachments = content.attachments.clone();
+        attachments.write().attachments = content.all_attachments().cloned().collect();
 
         let ui = self.ui_runner();
         attachments.write().on_tap(move |list, index| {
@@ -93,11 +95,18 @@ impl StandardMessageContent {
         if metadata.is_writing() {
             let text_with_typing = format!("{} {}", content.text, TYPING_INDICATOR);
             markdown.set_text(cx, &text_with_typing);
-        } else if !content.tool_calls.is_empty() {
-            let tool_calls_text = Self::generate_tool_calls_text(content);
-            markdown.set_text(cx, &tool_calls_text);
         } else {
-            markdown.set_text(cx, &content.text);
+            let mut text = if !content.tool_calls.is_empty() {
+                Self::generate_tool_calls_text(content)
+            } else {
+                content.text.clone()
+            };
+
+            if metadata.stopped_by_user {
+                text.push_str(STOPPED_NOTE);
+            }
+
+            markdown.set_text(cx, &text);
         }
     }
 
@@ -0,0 +1,70 @@
+//! Shares or looks for devices in the local network, to try discovery with
+//! two processes, in the same host or not.
+//!
+//! ```sh
+//! cargo run -p moly-sync --example discovery -- share "Desktop"
+//! cargo run -p moly-sync --example discovery -- browse
+//! ```
+
+use moly_sync::{start_sync_server, PeerBrowser, SyncNode, SyncState};
+use std::net::Ipv4Addr;
+
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("share") => share(args.get(1).map(String::as_str).unwrap_or("Moly")),
+        Some("browse") => browse(),
+        _ => {
+            eprintln!("Usage: discovery share <device name> | discovery browse");
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn share(device_name: &str) -> anyhow::Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let node = SyncNode::new(SyncState::new(device_name)?);
+        let server = start_sync_server(node, Ipv4Addr::UNSPECIFIED.into(), None).await?;
+        let code = server.share("{}".to_string())?;
+
+        println!(
+            "Sharing as {:?} at port {}",
+            device_name,
+            server.addr.port()
+        );
+        println!("Pairing code: {}", code);
+        println!("Press Ctrl+C to stop");
+
+        tokio::signal::ctrl_c().await?;
+        server.stop_sharing();
+        server.stop();
+        Ok(())
+    })
+}
+
+fn browse() -> anyhow::Result<()> {
+    let own_device_id = SyncState::new("browser")?.device_id().to_string();
+    let _browser = PeerBrowser::start(&own_device_id, |peers| {
+        println!("Nearby devices:");
+        for peer in peers {
+            println!(
+                "  {} ({})",
+                peer.device_name,
+                peer.server_addr().unwrap_or_default()
+            );
+        }
+    })?;
+
+    println!("Looking for devices, press Ctrl+C to stop");
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(tokio::signal::ctrl_c())?;
+    Ok(())
+}
@@ -22,6 +22,32 @@ pub struct EncryptedData {
     pub data: String,
 }
 
+/// Generate a random, base64-encoded secret to be used as the PIN of
+/// [`encrypt_json`] and [`decrypt_json`] when there is no user provided one
+pub fn generate_secret() -> Result<String> {
+    let mut secret = [0u8; 32];
+    getrandom::getrandom(&mut secret)
+        .map_err(|e| anyhow::anyhow!("Failed to generate random secret: {}", e))?;
+    Ok(BASE64.encode(secret))
+}
+
+/// Encrypted data format, for data encrypted with a key agreed on beforehand
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SealedData {
+    /// Base64-encoded nonce used for encryption
+    pub nonce: String,
+    /// Base64-encoded encrypted data
+    pub data: String,
+}
+
+/// Generate a random, hex-encoded identifier of the given amount of bytes
+pub(crate) fn generate_id(bytes: usize) -> Result<String> {
+    let mut id = vec![0u8; bytes];
+    getrandom::getrandom(&mut id)
+        .map_err(|e| anyhow::anyhow!("Failed to generate random id: {}", e))?;
+    Ok(id.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
 /// Derive an AES-256 key from a PIN and salt using PBKDF2
 fn derive_key(pin: &str, salt: &[u8]) -> [u8; 32] {
     let mut key = [0u8; 32];
@@ -33,6 +59,11 @@ fn derive_key(pin: &str, salt: &[u8]) -> [u8; 32] {
 ///
 /// Returns base64-encoded JSON containing salt, nonce, and encrypted data
 pub fn encrypt_json(json_data: &str, pin: &str) -> Result<String> {
+    encrypt_bytes(json_data.as_bytes(), pin)
+}
+
+/// Encrypt arbitrary bytes the same way as [`encrypt_json`]
+pub fn encrypt_bytes(data: &[u8], pin: &str) -> Result<String> {
     // Generate random salt and nonce
     let mut salt = [0u8; SALT_SIZE];
     let mut nonce_bytes = [0u8; NONCE_SIZE];
@@ -50,9 +81,9 @@ pub fn encrypt_json(json_data: &str, pin: &str) -> Result<String> {
     // Create nonce
     let nonce = Nonce::from_slice(&nonce_bytes);
 
-    // Encrypt the JSON data
+    // Encrypt the data
     let ciphertext = cipher
-        .encrypt(nonce, json_data.as_bytes())
+        .encrypt(nonce, data)
         .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
 
     // Create encrypted data structure
@@ -66,11 +97,76 @@ pub fn encrypt_json(json_data: &str, pin: &str) -> Result<String> {
     serde_json::to_string(&encrypted_data).context("Failed to serialize encrypted data")
 }
 
+/// Encrypt bytes using AES-256-GCM with a 32 bytes key, like the one agreed
+/// on for a [`crate::SyncSession`]
+///
+/// Unlike [`encrypt_bytes`], no key is derived, so it's cheap enough to be
+/// used for every request
+pub fn seal_bytes(data: &[u8], key: &[u8]) -> Result<String> {
+    if key.len() != 32 {
+        anyhow::bail!("Invalid key size: expected 32, got {}", key.len());
+    }
+
+    let mut nonce_bytes = [0u8; NONCE_SIZE];
+    getrandom::getrandom(&mut nonce_bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to generate random nonce: {}", e))?;
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce_bytes), data)
+        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+
+    let sealed_data = SealedData {
+        nonce: BASE64.encode(nonce_bytes),
+        data: BASE64.encode(ciphertext),
+    };
+
+    serde_json::to_string(&sealed_data).context("Failed to serialize sealed data")
+}
+
+/// Decrypt bytes encrypted with [`seal_bytes`]
+pub fn open_bytes(sealed_json: &str, key: &[u8]) -> Result<Vec<u8>> {
+    if key.len() != 32 {
+        anyhow::bail!("Invalid key size: expected 32, got {}", key.len());
+    }
+
+    let sealed_data: SealedData =
+        serde_json::from_str(sealed_json).context("Failed to parse sealed data JSON")?;
+
+    let nonce_bytes = BASE64
+        .decode(&sealed_data.nonce)
+        .context("Failed to decode nonce from base64")?;
+    let ciphertext = BASE64
+        .decode(&sealed_data.data)
+        .context("Failed to decode ciphertext from base64")?;
+
+    if nonce_bytes.len() != NONCE_SIZE {
+        anyhow::bail!(
+            "Invalid nonce size: expected {}, got {}",
+            NONCE_SIZE,
+            nonce_bytes.len()
+        );
+    }
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher
+        .decrypt(Nonce::from_slice(&nonce_bytes), ciphertext.as_ref())
+        .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))
+}
+
 /// Decrypt JSON data using AES-256-GCM with a PIN-derived key
 ///
 /// Takes base64-encoded JSON containing salt, nonce, and encrypted data
 /// Returns the original JSON string
 pub fn decrypt_json(encrypted_json: &str, pin: &str) -> Result<String> {
+    let plaintext = decrypt_bytes(encrypted_json, pin)?;
+
+    // Convert back to string
+    String::from_utf8(plaintext).context("Decrypted data is not valid UTF-8")
+}
+
+/// Decrypt bytes encrypted with [`encrypt_bytes`]
+pub fn decrypt_bytes(encrypted_json: &str, pin: &str) -> Result<Vec<u8>> {
     // Parse the encrypted data structure
     let encrypted_data: EncryptedData =
         serde_json::from_str(encrypted_json).context("Failed to parse encrypted data JSON")?;
@@ -111,12 +207,9 @@ pub fn decrypt_json(encrypted_json: &str, pin: &str) -> Result<String> {
     let nonce = Nonce::from_slice(&nonce_bytes);
 
     // Decrypt the data
-    let plaintext = cipher
+    cipher
         .decrypt(nonce, ciphertext.as_ref())
-        .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))?;
-
-    // Convert back to string
-    String::from_utf8(plaintext).context("Decrypted data is not valid UTF-8")
+        .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))
 }
 
 #[cfg(test)]
@@ -146,6 +239,16 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let key = [7u8; 32];
+        let sealed = seal_bytes(b"chunk", &key).unwrap();
+
+        assert_eq!(open_bytes(&sealed, &key).unwrap(), b"chunk");
+        assert!(open_bytes(&sealed, &[8u8; 32]).is_err());
+        assert!(seal_bytes(b"chunk", b"short").is_err());
+    }
+
     #[test]
     fn test_different_encryptions_produce_different_results() {
         let data = r#"{"test": "data"}"#;
@@ -0,0 +1,251 @@
+//! Discovery of the devices sharing in the local network, through mDNS/DNS-SD.
+//!
+//! While sharing, the server advertises a `_moly-sync._tcp` service, named
+//! after the device id and with the device name in its TXT record. Importing
+//! devices browse for it, so the user picks a device by name instead of typing
+//! its address.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+
+/// DNS-SD service type advertised by the sharing devices.
+pub const SERVICE_TYPE: &str = "_moly-sync._tcp.local.";
+
+const DEVICE_ID_PROPERTY: &str = "id";
+const DEVICE_NAME_PROPERTY: &str = "name";
+
+/// A device found sharing in the local network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveredPeer {
+    pub device_id: String,
+    pub device_name: String,
+    pub addresses: Vec<IpAddr>,
+    pub port: u16,
+}
+
+impl DiscoveredPeer {
+    /// Address of the sync server of the peer, preferring IPv4 ones, which
+    /// don't depend on the interface they were found at.
+    pub fn server_addr(&self) -> Option<String> {
+        let ip = self
+            .addresses
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .or_else(|| self.addresses.first())?;
+
+        Some(format!("http://{}", SocketAddr::new(*ip, self.port)))
+    }
+}
+
+/// Advertises the sync server of this device until dropped.
+pub struct Advertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl std::fmt::Debug for Advertisement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Advertisement")
+            .field("fullname", &self.fullname)
+            .finish()
+    }
+}
+
+impl Advertisement {
+    /// Starts advertising the server listening at the address, in all the
+    /// interfaces if it listens in all of them.
+    pub fn start(device_id: &str, device_name: &str, addr: SocketAddr) -> Result<Self> {
+        let daemon = ServiceDaemon::new()?;
+        let host_name = format!("{}.local.", device_id);
+        let properties = [
+            (DEVICE_ID_PROPERTY, device_id),
+            (DEVICE_NAME_PROPERTY, device_name),
+        ];
+
+        let service = if addr.ip().is_unspecified() {
+            ServiceInfo::new(
+                SERVICE_TYPE,
+                device_id,
+                &host_name,
+                "",
+                addr.port(),
+                &properties[..],
+            )?
+            .enable_addr_auto()
+        } else {
+            ServiceInfo::new(
+                SERVICE_TYPE,
+                device_id,
+                &host_name,
+                addr.ip(),
+                addr.port(),
+                &properties[..],
+            )?
+        };
+
+        let fullname = service.get_fullname().to_string();
+        daemon.register(service)?;
+        ::log::info!("Advertising the sync server as {}", fullname);
+
+        Ok(Self { daemon, fullname })
+    }
+}
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        if let Err(e) = self.daemon.unregister(&self.fullname) {
+            ::log::warn!("Failed to stop advertising the sync server: {}", e);
+        }
+        // Shutting down right away would not give the daemon time to tell the
+        // peers the service is gone.
+        let daemon = self.daemon.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            let _ = daemon.shutdown();
+        });
+    }
+}
+
+/// Keeps track of the devices sharing in the local network until dropped.
+pub struct PeerBrowser {
+    daemon: ServiceDaemon,
+    peers: Arc<Mutex<HashMap<String, DiscoveredPeer>>>,
+}
+
+impl PeerBrowser {
+    /// Starts browsing, leaving out this device, and calls `on_change` with
+    /// the devices found every time they change.
+    pub fn start(
+        own_device_id: &str,
+        on_change: impl Fn(Vec<DiscoveredPeer>) + Send + 'static,
+    ) -> Result<Self> {
+        let daemon = ServiceDaemon::new()?;
+        let receiver = daemon.browse(SERVICE_TYPE)?;
+        let peers: Arc<Mutex<HashMap<String, DiscoveredPeer>>> = Default::default();
+
+        let own_device_id = own_device_id.to_string();
+        let found = peers.clone();
+        std::thread::spawn(move || {
+            // Ends when the daemon is shut down.
+            while let Ok(event) = receiver.recv() {
+                let changed = match event {
+                    ServiceEvent::ServiceResolved(service) => {
+                        let Some(device_id) = service.get_property_val_str(DEVICE_ID_PROPERTY)
+                        else {
+                            continue;
+                        };
+                        if device_id == own_device_id {
+                            continue;
+                        }
+
+                        let peer = DiscoveredPeer {
+                            device_id: device_id.to_string(),
+                            device_name: service
+                                .get_property_val_str(DEVICE_NAME_PROPERTY)
+                                .unwrap_or(device_id)
+                                .to_string(),
+                            addresses: service
+                                .get_addresses()
+                                .iter()
+                                .map(|ip| ip.to_ip_addr())
+                                .collect(),
+                            port: service.get_port(),
+                        };
+
+                        let mut found = found.lock().unwrap();
+                        let previous =
+                            found.insert(service.get_fullname().to_string(), peer.clone());
+                        previous.as_ref() != Some(&peer)
+                    }
+                    ServiceEvent::ServiceRemoved(_, fullname) => {
+                        found.lock().unwrap().remove(&fullname).is_some()
+                    }
+                    _ => false,
+                };
+
+                if changed {
+                    on_change(sorted_peers(&found.lock().unwrap()));
+                }
+            }
+        });
+
+        Ok(Self { daemon, peers })
+    }
+
+    /// The devices found so far, sorted by name.
+    pub fn peers(&self) -> Vec<DiscoveredPeer> {
+        sorted_peers(&self.peers.lock().unwrap())
+    }
+}
+
+impl Drop for PeerBrowser {
+    fn drop(&mut self) {
+        let _ = self.daemon.shutdown();
+    }
+}
+
+fn sorted_peers(peers: &HashMap<String, DiscoveredPeer>) -> Vec<DiscoveredPeer> {
+    let mut peers = peers.values().cloned().collect::<Vec<_>>();
+    peers.sort_by(|a, b| {
+        a.device_name
+            .cmp(&b.device_name)
+            .then_with(|| a.device_id.cmp(&b.device_id))
+    });
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::time::{Duration, Instant};
+
+    fn wait_for(browser: &PeerBrowser, condition: impl Fn(&[DiscoveredPeer]) -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if condition(&browser.peers()) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        false
+    }
+
+    #[test]
+    fn test_server_addr_prefers_ipv4() {
+        let peer = DiscoveredPeer {
+            device_id: "a".into(),
+            device_name: "laptop".into(),
+            addresses: vec!["fe80::1".parse().unwrap(), "192.168.1.4".parse().unwrap()],
+            port: 8080,
+        };
+        assert_eq!(peer.server_addr().unwrap(), "http://192.168.1.4:8080");
+
+        let peer = DiscoveredPeer {
+            addresses: vec!["fe80::1".parse().unwrap()],
+            ..peer
+        };
+        assert_eq!(peer.server_addr().unwrap(), "http://[fe80::1]:8080");
+    }
+
+    #[test]
+    fn test_advertised_devices_are_discovered() {
+        let addr = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 47321);
+        let desktop = Advertisement::start("d35k70b", "Desktop", addr).unwrap();
+        let own = Advertisement::start("1a8709b", "Laptop", addr).unwrap();
+
+        let browser = PeerBrowser::start("1a8709b", |_| {}).unwrap();
+        assert!(wait_for(&browser, |peers| peers
+            .iter()
+            .any(|p| p.device_name == "Desktop" && p.port == 47321)));
+        assert!(browser.peers().iter().all(|p| p.device_id != "1a8709b"));
+
+        drop(desktop);
+        assert!(wait_for(&browser, |peers| peers.is_empty()));
+        drop(own);
+    }
+}
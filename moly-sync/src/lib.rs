@@ -1,9 +1,21 @@
 mod client;
 mod crypto;
 #[cfg(not(target_arch = "wasm32"))]
+mod discovery;
+mod replica;
+#[cfg(not(target_arch = "wasm32"))]
 mod server;
+mod session;
+mod sync;
+mod transfer;
 
 pub use client::*;
 pub use crypto::*;
 #[cfg(not(target_arch = "wasm32"))]
+pub use discovery::*;
+pub use replica::*;
+#[cfg(not(target_arch = "wasm32"))]
 pub use server::*;
+pub use session::*;
+pub use sync::*;
+pub use transfer::*;
@@ -0,0 +1,468 @@
+//! Versioned copies of the records kept in sync between paired devices.
+//!
+//! Every record carries the [`Version`] of its last change. Versions come from
+//! a hybrid logical clock: the wall time in milliseconds, never going backwards
+//! and always moved past the versions received from peers. The most recent
+//! change wins a conflict, and ties are broken by device id, so both devices
+//! always resolve it the same way.
+//!
+//! Removed records are kept as tombstones so the removal itself is synced.
+
+use std::collections::{HashMap, HashSet};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// The kinds of data kept in sync.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordKind {
+    Provider,
+    McpServer,
+    PromptTemplate,
+    /// The details of a chat, without its messages.
+    Chat,
+    ChatMessage,
+}
+
+/// When and where a record was last changed.
+///
+/// Versions are ordered by timestamp first and device id second.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Version {
+    /// Hybrid logical clock value, in milliseconds.
+    pub timestamp: u64,
+    pub device_id: String,
+}
+
+/// The changes of a replica after some cursor, see [`Replica::changes_since`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Changes {
+    pub records: Vec<Record>,
+    /// Once it has the records, the peer is up to date until this cursor.
+    pub cursor: u64,
+}
+
+/// A change to a record, as exchanged between devices.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub kind: RecordKind,
+    pub id: String,
+    pub version: Version,
+    /// The content of the record, or `None` if it was removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<Value>,
+}
+
+impl Record {
+    pub fn is_deleted(&self) -> bool {
+        self.payload.is_none()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct StoredRecord {
+    kind: RecordKind,
+    id: String,
+    version: Version,
+    deleted: bool,
+    /// Hash of the payload, to notice local changes without keeping a copy of
+    /// the payload on disk.
+    digest: String,
+    /// Position of the last change in this replica, used as delta cursor.
+    seq: u64,
+    /// The peer this version was received from, so it's not sent back to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    /// Only known after the record is observed or received in this session.
+    #[serde(skip)]
+    payload: Option<Value>,
+}
+
+impl StoredRecord {
+    fn to_record(&self) -> Record {
+        Record {
+            kind: self.kind,
+            id: self.id.clone(),
+            version: self.version.clone(),
+            payload: if self.deleted {
+                None
+            } else {
+                self.payload.clone()
+            },
+        }
+    }
+}
+
+/// The versions of all the records known by a device.
+///
+/// Payloads are not serialized with the replica, the app is the owner of the
+/// actual data and must [`Replica::observe`] it before exchanging changes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replica {
+    device_id: String,
+    clock: u64,
+    seq: u64,
+    records: Vec<StoredRecord>,
+}
+
+impl Replica {
+    pub fn new(device_id: impl Into<String>) -> Self {
+        Self {
+            device_id: device_id.into(),
+            clock: 0,
+            seq: 0,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    /// Position of the latest change, to later ask for the changes after it.
+    pub fn cursor(&self) -> u64 {
+        self.seq
+    }
+
+    /// The current state of a record, if it was ever seen.
+    pub fn get(&self, kind: RecordKind, id: &str) -> Option<Record> {
+        self.find(kind, id).map(|i| self.records[i].to_record())
+    }
+
+    /// Compares the current local records of a kind with the known ones,
+    /// giving a new version to the ones that changed and removing the ones
+    /// that are not present anymore.
+    ///
+    /// Returns the amount of records that changed.
+    pub fn observe(
+        &mut self,
+        kind: RecordKind,
+        items: impl IntoIterator<Item = (String, Value)>,
+    ) -> usize {
+        self.observe_where(kind, items, |_| true)
+    }
+
+    /// Like [`Replica::observe`], but only for the records of the kind whose
+    /// id is accepted by the filter, so the rest are neither compared nor
+    /// removed. The items are expected to pass the filter too.
+    pub fn observe_where(
+        &mut self,
+        kind: RecordKind,
+        items: impl IntoIterator<Item = (String, Value)>,
+        filter: impl Fn(&str) -> bool,
+    ) -> usize {
+        let mut known = self
+            .records
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.kind == kind && filter(&r.id))
+            .map(|(i, r)| (r.id.clone(), i))
+            .collect::<HashMap<_, _>>();
+        let mut seen = HashSet::new();
+        let mut changed = 0;
+
+        for (id, payload) in items {
+            let digest = digest(&payload);
+
+            match known.get(&id).copied() {
+                Some(i) if !self.records[i].deleted && self.records[i].digest == digest => {
+                    self.records[i].payload = Some(payload);
+                }
+                existing => {
+                    let version = self.next_version();
+                    let seq = self.next_seq();
+                    let record = StoredRecord {
+                        kind,
+                        id: id.clone(),
+                        version,
+                        deleted: false,
+                        digest,
+                        seq,
+                        source: None,
+                        payload: Some(payload),
+                    };
+
+                    match existing {
+                        Some(i) => self.records[i] = record,
+                        None => {
+                            known.insert(id.clone(), self.records.len());
+                            self.records.push(record);
+                        }
+                    }
+                    changed += 1;
+                }
+            }
+
+            seen.insert(id);
+        }
+
+        let removed = known
+            .iter()
+            .filter(|(id, i)| !self.records[**i].deleted && !seen.contains(*id))
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+
+        for i in removed {
+            let version = self.next_version();
+            let seq = self.next_seq();
+            let record = &mut self.records[i];
+            record.version = version;
+            record.seq = seq;
+            record.deleted = true;
+            record.digest.clear();
+            record.source = None;
+            record.payload = None;
+            changed += 1;
+        }
+
+        changed
+    }
+
+    /// The changes made after the cursor, leaving out the ones received from
+    /// the peer itself.
+    ///
+    /// Records changed but not observed in this session are not included, and
+    /// the returned cursor stops before them so they are sent once observed.
+    pub fn changes_since(&self, cursor: u64, peer_id: &str) -> Changes {
+        let changed = self
+            .records
+            .iter()
+            .filter(|r| r.seq > cursor)
+            .filter(|r| r.source.as_deref() != Some(peer_id));
+
+        let mut records = Vec::new();
+        let mut unobserved_seq = None::<u64>;
+        for record in changed {
+            if record.deleted || record.payload.is_some() {
+                records.push(record.to_record());
+            } else {
+                unobserved_seq = Some(unobserved_seq.map_or(record.seq, |s| s.min(record.seq)));
+            }
+        }
+
+        Changes {
+            records,
+            cursor: unobserved_seq.map_or(self.seq, |seq| seq - 1),
+        }
+    }
+
+    /// Merges the changes received from a peer, keeping the newest version of
+    /// each record.
+    ///
+    /// Returns the changes that won and must be applied to the local data.
+    pub fn apply(&mut self, records: Vec<Record>, peer_id: &str) -> Vec<Record> {
+        let mut applied = Vec::new();
+
+        for record in records {
+            self.clock = self.clock.max(record.version.timestamp);
+
+            let existing = self.find(record.kind, &record.id);
+            if let Some(i) = existing {
+                if self.records[i].version >= record.version {
+                    continue;
+                }
+            }
+
+            let seq = self.next_seq();
+            let stored = StoredRecord {
+                kind: record.kind,
+                id: record.id.clone(),
+                version: record.version.clone(),
+                deleted: record.is_deleted(),
+                digest: record.payload.as_ref().map(digest).unwrap_or_default(),
+                seq,
+                source: Some(peer_id.to_string()),
+                payload: record.payload.clone(),
+            };
+
+            match existing {
+                Some(i) => self.records[i] = stored,
+                None => self.records.push(stored),
+            }
+            applied.push(record);
+        }
+
+        applied
+    }
+
+    fn find(&self, kind: RecordKind, id: &str) -> Option<usize> {
+        self.records
+            .iter()
+            .position(|r| r.kind == kind && r.id == id)
+    }
+
+    fn next_version(&mut self) -> Version {
+        self.clock = (self.clock + 1).max(now_millis());
+        Version {
+            timestamp: self.clock,
+            device_id: self.device_id.clone(),
+        }
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+}
+
+fn digest(payload: &Value) -> String {
+    let hash = Sha256::digest(payload.to_string().as_bytes());
+    BASE64.encode(hash)
+}
+
+fn now_millis() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn provider(id: &str, url: &str) -> (String, Value) {
+        (id.to_string(), json!({"id": id, "url": url}))
+    }
+
+    #[test]
+    fn test_observe_versions_only_changes() {
+        let mut replica = Replica::new("a");
+
+        let changed = replica.observe(
+            RecordKind::Provider,
+            [provider("openai", "https://api.openai.com/v1")],
+        );
+        assert_eq!(changed, 1);
+        let first = replica.get(RecordKind::Provider, "openai").unwrap();
+
+        let changed = replica.observe(
+            RecordKind::Provider,
+            [provider("openai", "https://api.openai.com/v1")],
+        );
+        assert_eq!(changed, 0);
+        assert_eq!(replica.get(RecordKind::Provider, "openai").unwrap(), first);
+
+        let changed = replica.observe(RecordKind::Provider, []);
+        assert_eq!(changed, 1);
+        let removed = replica.get(RecordKind::Provider, "openai").unwrap();
+        assert!(removed.is_deleted());
+        assert!(removed.version > first.version);
+
+        // Other kinds are not affected.
+        assert_eq!(replica.observe(RecordKind::Chat, []), 0);
+    }
+
+    #[test]
+    fn test_observe_where_leaves_other_records() {
+        let mut replica = Replica::new("a");
+        replica.observe(
+            RecordKind::ChatMessage,
+            [
+                ("1/a".to_string(), json!("a")),
+                ("2/b".to_string(), json!("b")),
+            ],
+        );
+
+        let changed = replica.observe_where(
+            RecordKind::ChatMessage,
+            [("1/c".to_string(), json!("c"))],
+            |id| id.starts_with("1/"),
+        );
+        assert_eq!(changed, 2);
+        let deleted = |id| {
+            replica
+                .get(RecordKind::ChatMessage, id)
+                .unwrap()
+                .is_deleted()
+        };
+        assert!(deleted("1/a"));
+        assert!(!deleted("1/c"));
+        assert!(!deleted("2/b"));
+    }
+
+    #[test]
+    fn test_changes_since_cursor() {
+        let mut replica = Replica::new("a");
+        replica.observe(RecordKind::Provider, [provider("openai", "a")]);
+        let cursor = replica.cursor();
+
+        replica.observe(
+            RecordKind::Provider,
+            [provider("openai", "a"), provider("gemini", "b")],
+        );
+
+        let changes = replica.changes_since(cursor, "b");
+        assert_eq!(changes.records.len(), 1);
+        assert_eq!(changes.records[0].id, "gemini");
+        assert_eq!(changes.cursor, replica.cursor());
+        assert_eq!(replica.changes_since(replica.cursor(), "b").records, vec![]);
+    }
+
+    #[test]
+    fn test_cursor_stops_before_unobserved_records() {
+        let mut replica = Replica::new("a");
+        replica.observe(RecordKind::Provider, [provider("openai", "a")]);
+        replica.observe(RecordKind::PromptTemplate, [provider("greeting", "b")]);
+
+        // Payloads are not stored, so they are unknown after a restart.
+        let json = serde_json::to_string(&replica).unwrap();
+        let mut replica: Replica = serde_json::from_str(&json).unwrap();
+        replica.observe(RecordKind::PromptTemplate, [provider("greeting", "b")]);
+
+        let changes = replica.changes_since(0, "b");
+        assert_eq!(changes.records.len(), 1);
+        assert_eq!(changes.records[0].id, "greeting");
+        assert_eq!(changes.cursor, 0);
+
+        replica.observe(RecordKind::Provider, [provider("openai", "a")]);
+        let changes = replica.changes_since(0, "b");
+        assert_eq!(changes.records.len(), 2);
+        assert_eq!(changes.cursor, replica.cursor());
+    }
+
+    #[test]
+    fn test_conflicts_resolve_the_same_on_both_sides() {
+        let mut a = Replica::new("a");
+        let mut b = Replica::new("b");
+        a.observe(RecordKind::PromptTemplate, [provider("greeting", "from a")]);
+        b.observe(RecordKind::PromptTemplate, [provider("greeting", "from b")]);
+
+        let from_a = a.changes_since(0, "b").records;
+        let from_b = b.changes_since(0, "a").records;
+        a.apply(from_b, "b");
+        b.apply(from_a, "a");
+
+        assert_eq!(
+            a.get(RecordKind::PromptTemplate, "greeting"),
+            b.get(RecordKind::PromptTemplate, "greeting")
+        );
+
+        // Received changes are not sent back to where they came from.
+        let winner = a.get(RecordKind::PromptTemplate, "greeting").unwrap();
+        let loser_side = if winner.version.device_id == "a" {
+            &b
+        } else {
+            &a
+        };
+        let winner_id = winner.version.device_id.as_str();
+        assert_eq!(loser_side.changes_since(0, winner_id).records, vec![]);
+    }
+
+    #[test]
+    fn test_stale_changes_are_ignored() {
+        let mut a = Replica::new("a");
+        let mut b = Replica::new("b");
+        a.observe(RecordKind::Provider, [provider("openai", "old")]);
+        let old = a.changes_since(0, "b").records;
+
+        b.apply(old.clone(), "a");
+        b.observe(RecordKind::Provider, [provider("openai", "new")]);
+        let new = b.get(RecordKind::Provider, "openai").unwrap();
+
+        assert_eq!(b.apply(old, "a"), vec![]);
+        assert_eq!(b.get(RecordKind::Provider, "openai").unwrap(), new);
+        assert!(new.version > a.get(RecordKind::Provider, "openai").unwrap().version);
+    }
+}
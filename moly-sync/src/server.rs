@@ -1,62 +1,353 @@
 use anyhow::Result;
-use axum::extract::Query;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use moly_kit::utils::asynchronous::spawn;
-use rand::Rng;
-use std::collections::HashMap;
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::oneshot;
 
-use crate::crypto::encrypt_json;
+use crate::crypto::{generate_id, open_bytes, seal_bytes};
+use crate::discovery::Advertisement;
+use crate::session::{
+    generate_pairing_code, normalize_pairing_code, SessionCall, SessionRequest, SessionStart,
+    SessionStarted, CLIENT_IDENTITY, SERVER_IDENTITY,
+};
+use crate::sync::{answer_exchange, Envelope, PairRequest, PairResponse, Pairing, SyncNode};
+use crate::transfer::{ChunkResponse, FileSource, TransferManifest, TRANSFER_CHUNK_SIZE};
 
-/// Server handle that can be used to stop the server
+/// Sessions that can be started with a pairing code, right or wrong, before
+/// the code stops working
+pub const MAX_SESSION_ATTEMPTS: usize = 5;
+
+/// Time a pairing code can be used for, if no session was opened with it
+pub const PAIRING_CODE_LIFETIME: Duration = Duration::from_secs(10 * 60);
+
+/// Time after which a session without requests expires
+pub const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Handle of a server started with [`start_sync_server`]
 #[derive(Debug)]
-pub struct ServerHandle {
-    pub addr: std::net::SocketAddr,
-    pub pin: String,
+pub struct SyncServerHandle {
+    pub addr: SocketAddr,
+    node: SyncNode,
+    sharing: Arc<Mutex<Option<Sharing>>>,
+    /// Makes this device visible to nearby ones while sharing
+    advertisement: Mutex<Option<Advertisement>>,
     shutdown_tx: oneshot::Sender<()>,
 }
 
-impl ServerHandle {
+/// Data served in the sessions opened with the pairing code, while sharing
+#[derive(Debug)]
+struct Sharing {
+    code: String,
+    json_file: String,
+    chats: Option<Arc<SharedChats>>,
+    secrets: SharedSecrets,
+    created_at: Instant,
+    attempts: usize,
+    /// Set once a session proves it knows the code, which can't be used again
+    used: bool,
+    sessions: Vec<Session>,
+}
+
+struct Session {
+    id: String,
+    key: Vec<u8>,
+    last_used: Instant,
+}
+
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("id", &self.id)
+            .field("last_used", &self.last_used)
+            .finish()
+    }
+}
+
+/// Secrets given to the sessions, see [`SyncServerHandle::share_secrets`]
+#[derive(Clone, Default)]
+struct SharedSecrets(BTreeMap<String, String>);
+
+impl std::fmt::Debug for SharedSecrets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedSecrets")
+            .field("names", &self.0.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Chats offered for transfer, see [`SyncServerHandle::share_chats`]
+struct SharedChats {
+    manifest: TransferManifest,
+    source: Arc<dyn FileSource>,
+}
+
+impl std::fmt::Debug for SharedChats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedChats")
+            .field("manifest", &self.manifest)
+            .finish()
+    }
+}
+
+impl Sharing {
+    /// Runs the server side of the key agreement, counting it as an attempt
+    fn start_session(
+        &mut self,
+        start: SessionStart,
+    ) -> std::result::Result<SessionStarted, axum::http::StatusCode> {
+        use axum::http::StatusCode;
+
+        self.expire_sessions();
+
+        if self.used || self.created_at.elapsed() > PAIRING_CODE_LIFETIME {
+            return Err(StatusCode::GONE);
+        }
+
+        if self.attempts >= MAX_SESSION_ATTEMPTS {
+            ::log::warn!("Too many sessions started with the same pairing code");
+            return Err(StatusCode::TOO_MANY_REQUESTS);
+        }
+        self.attempts += 1;
+
+        let message = BASE64
+            .decode(&start.message)
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+        let (spake, server_message) = Spake2::<Ed25519Group>::start_b(
+            &Password::new(self.code.as_bytes()),
+            &Identity::new(CLIENT_IDENTITY),
+            &Identity::new(SERVER_IDENTITY),
+        );
+        let key = spake
+            .finish(&message)
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+        let session_id = generate_id(16).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        self.sessions.push(Session {
+            id: session_id.clone(),
+            key,
+            last_used: Instant::now(),
+        });
+
+        Ok(SessionStarted {
+            session_id,
+            message: BASE64.encode(server_message),
+        })
+    }
+
+    /// Decrypts the call with the key of its session
+    ///
+    /// A session that fails to decrypt its first call used a wrong code, so
+    /// it's discarded. One that succeeds makes the code used, discarding the
+    /// other sessions started with it.
+    fn open_call(
+        &mut self,
+        request: &SessionRequest,
+    ) -> std::result::Result<(Vec<u8>, SessionCall), axum::http::StatusCode> {
+        use axum::http::StatusCode;
+
+        self.expire_sessions();
+
+        let Some(index) = self
+            .sessions
+            .iter()
+            .position(|s| s.id == request.session_id)
+        else {
+            return Err(StatusCode::UNAUTHORIZED);
+        };
+
+        let Ok(data) = open_bytes(&request.data, &self.sessions[index].key) else {
+            ::log::warn!("Discarded a session with a wrong pairing code");
+            self.sessions.remove(index);
+            return Err(StatusCode::UNAUTHORIZED);
+        };
+
+        let call: SessionCall =
+            serde_json::from_slice(&data).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+        if !self.used {
+            self.used = true;
+            self.sessions.retain(|s| s.id == request.session_id);
+        }
+
+        let session = self
+            .sessions
+            .iter_mut()
+            .find(|s| s.id == request.session_id)
+            .unwrap();
+        session.last_used = Instant::now();
+        let key = session.key.clone();
+
+        if let SessionCall::Close = call {
+            self.sessions.retain(|s| s.id != request.session_id);
+        }
+
+        Ok((key, call))
+    }
+
+    fn expire_sessions(&mut self) {
+        self.sessions
+            .retain(|s| s.last_used.elapsed() < SESSION_IDLE_TIMEOUT);
+    }
+}
+
+impl SyncServerHandle {
+    /// Start serving the JSON file and accepting pairings, with a new pairing
+    /// code which is returned
+    ///
+    /// Any code shared before stops working.
+    pub fn share(&self, json_file: String) -> Result<String> {
+        let code = generate_pairing_code()?;
+
+        *self.sharing.lock().unwrap() = Some(Sharing {
+            code: normalize_pairing_code(&code),
+            json_file,
+            chats: None,
+            secrets: SharedSecrets::default(),
+            created_at: Instant::now(),
+            attempts: 0,
+            used: false,
+            sessions: Vec::new(),
+        });
+        self.advertise();
+        Ok(code)
+    }
+
+    /// Also offer the chats in the manifest for transfer, in the same
+    /// sessions, while sharing
+    ///
+    /// Only the files listed in the manifest are served from the source
+    pub fn share_chats(&self, manifest: TransferManifest, source: Arc<dyn FileSource>) {
+        if let Some(sharing) = self.sharing.lock().unwrap().as_mut() {
+            sharing.chats = Some(Arc::new(SharedChats { manifest, source }));
+        }
+    }
+
+    /// Stop offering the chats, the rest of the data is still shared
+    pub fn stop_sharing_chats(&self) {
+        if let Some(sharing) = self.sharing.lock().unwrap().as_mut() {
+            sharing.chats = None;
+        }
+    }
+
+    /// Also give the secrets, like API keys, to the sessions, while sharing
+    ///
+    /// They are only sent encrypted with the key of the session asking for
+    /// them, never in the shared JSON file.
+    pub fn share_secrets(&self, secrets: BTreeMap<String, String>) {
+        if let Some(sharing) = self.sharing.lock().unwrap().as_mut() {
+            sharing.secrets = SharedSecrets(secrets);
+        }
+    }
+
+    /// Stop giving the secrets, the rest of the data is still shared
+    pub fn stop_sharing_secrets(&self) {
+        if let Some(sharing) = self.sharing.lock().unwrap().as_mut() {
+            sharing.secrets = SharedSecrets::default();
+        }
+    }
+
+    /// Stop serving the JSON file and accepting pairings, ending the open
+    /// sessions. Already paired devices can still sync
+    pub fn stop_sharing(&self) {
+        self.sharing.lock().unwrap().take();
+        self.advertisement.lock().unwrap().take();
+    }
+
+    pub fn is_sharing(&self) -> bool {
+        self.sharing.lock().unwrap().is_some()
+    }
+
     /// Stop the server gracefully
     pub fn stop(self) {
         let _ = self.shutdown_tx.send(());
     }
+
+    /// Start advertising the server in the local network, if not already.
+    ///
+    /// Discovery is a convenience, the address can still be typed if it fails.
+    fn advertise(&self) {
+        let mut advertisement = self.advertisement.lock().unwrap();
+        if advertisement.is_some() {
+            return;
+        }
+
+        let (device_id, device_name) = {
+            let state = self.node.lock();
+            (state.device_id().to_string(), state.device_name.clone())
+        };
+
+        match Advertisement::start(&device_id, &device_name, self.addr) {
+            Ok(started) => *advertisement = Some(started),
+            Err(e) => ::log::warn!("Failed to advertise the sync server: {}", e),
+        }
+    }
 }
 
-/// Start a simple HTTP server that serves encrypted JSON file and return a handle to stop it
-pub async fn start_server(json_file: String, port: Option<u16>) -> Result<ServerHandle> {
-    use axum::{routing::get, Router};
-    use tower_http::cors::CorsLayer;
+/// Start a server to keep paired devices in sync with the given node,
+/// listening at the given address, or all the interfaces if unspecified
+///
+/// It can also share a JSON file, and accept new pairings, with a pairing
+/// code, see [`SyncServerHandle::share`]
+pub async fn start_sync_server(
+    node: SyncNode,
+    ip: IpAddr,
+    port: Option<u16>,
+) -> Result<SyncServerHandle> {
+    use axum::{http::StatusCode, routing::post, Json, Router};
 
     let port = port.unwrap_or(0); // 0 = any available port
-    let bind_addr = format!("0.0.0.0:{}", port);
-    let listener = tokio::net::TcpListener::bind(&bind_addr).await?;
+    let listener = tokio::net::TcpListener::bind(SocketAddr::new(ip, port)).await?;
     let addr = listener.local_addr()?;
 
-    // Generate a random 4 digit PIN
-    let pin = format!("{:04}", rand::rng().random_range(0..=9999));
-
-    // Pre-encrypt the JSON data with the PIN
-    let encrypted_json = encrypt_json(&json_file, &pin)
-        .map_err(|e| anyhow::anyhow!("Failed to encrypt preferences data: {}", e))?;
+    let sharing: Arc<Mutex<Option<Sharing>>> = Arc::new(Mutex::new(None));
 
     let app = Router::new()
         .route(
-            "/preferences.json",
-            get({
-                let token = pin.clone();
-                let encrypted_data = encrypted_json.clone();
-                move |Query(query): Query<HashMap<String, String>>| async move {
-                    if query.get("token") == Some(&token) {
-                        Ok(encrypted_data)
-                    } else {
-                        ::log::warn!("Invalid token provided for preferences access");
-                        Err(axum::http::StatusCode::UNAUTHORIZED)
-                    }
+            "/sync/session",
+            post({
+                let sharing = sharing.clone();
+                move |Json(start): Json<SessionStart>| async move {
+                    let mut sharing = sharing.lock().unwrap();
+                    let sharing = sharing.as_mut().ok_or(StatusCode::GONE)?;
+                    sharing.start_session(start).map(Json)
+                }
+            }),
+        )
+        .route(
+            "/sync/session/call",
+            post({
+                let sharing = sharing.clone();
+                let node = node.clone();
+                move |Json(request): Json<SessionRequest>| async move {
+                    answer_call(&sharing, &node, request).await
+                }
+            }),
+        )
+        .route(
+            "/sync/exchange",
+            post({
+                let node = node.clone();
+                move |Json(envelope): Json<Envelope>| async move {
+                    let response = answer_exchange(&node, envelope).map_err(|e| {
+                        ::log::warn!("Rejected sync exchange: {}", e);
+                        StatusCode::UNAUTHORIZED
+                    })?;
+
+                    node.persist().await.map_err(|e| {
+                        ::log::error!("Failed to store the synced changes: {}", e);
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    })?;
+
+                    Ok::<_, StatusCode>(Json(response))
                 }
             }),
         )
-        .route("/health", get(|| async { "OK" }))
-        .layer(CorsLayer::permissive());
+        .route("/health", axum::routing::get(|| async { "OK" }));
 
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
 
@@ -66,13 +357,115 @@ pub async fn start_server(json_file: String, port: Option<u16>) -> Result<Server
         });
 
         if let Err(e) = server.await {
-            log::error!("Server error: {}", e);
+            log::error!("Sync server error: {}", e);
         }
     });
 
-    Ok(ServerHandle {
+    Ok(SyncServerHandle {
         addr,
+        node,
+        sharing,
+        advertisement: Mutex::new(None),
         shutdown_tx,
-        pin,
+    })
+}
+
+/// Answers a call made in a session, encrypted with its key
+async fn answer_call(
+    sharing: &Mutex<Option<Sharing>>,
+    node: &SyncNode,
+    request: SessionRequest,
+) -> std::result::Result<String, axum::http::StatusCode> {
+    use axum::http::StatusCode;
+
+    let (key, call, json_file, chats, secrets) = {
+        let mut sharing = sharing.lock().unwrap();
+        let sharing = sharing.as_mut().ok_or(StatusCode::UNAUTHORIZED)?;
+        let (key, call) = sharing.open_call(&request)?;
+        let json_file = matches!(call, SessionCall::Preferences).then(|| sharing.json_file.clone());
+        let secrets = matches!(call, SessionCall::Secrets).then(|| sharing.secrets.clone());
+        (key, call, json_file, sharing.chats.clone(), secrets)
+    };
+
+    let answer = match call {
+        SessionCall::Preferences => serde_json::to_vec(&json_file),
+        SessionCall::Pair(request) => serde_json::to_vec(&pair(node, request).map_err(|e| {
+            ::log::error!("Failed to pair: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?),
+        SessionCall::TransferManifest => {
+            let chats = chats.ok_or(StatusCode::NOT_FOUND)?;
+            serde_json::to_vec(&chats.manifest)
+        }
+        SessionCall::TransferChunk { path, index } => {
+            let chats = chats.ok_or(StatusCode::NOT_FOUND)?;
+            serde_json::to_vec(&read_chunk(&chats, &path, index).await?)
+        }
+        SessionCall::Secrets => serde_json::to_vec(&secrets.unwrap_or_default().0),
+        SessionCall::Close => serde_json::to_vec(&()),
+    };
+
+    answer
+        .map_err(anyhow::Error::from)
+        .and_then(|answer| seal_bytes(&answer, &key))
+        .map_err(|e| {
+            ::log::error!("Failed to encrypt the session answer: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Reads a chunk of a file offered for transfer
+async fn read_chunk(
+    chats: &SharedChats,
+    path: &str,
+    index: u64,
+) -> std::result::Result<ChunkResponse, axum::http::StatusCode> {
+    use axum::http::StatusCode;
+
+    if !chats.manifest.contains(path) {
+        ::log::warn!("Requested file not offered for transfer: {}", path);
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let chunk_size = TRANSFER_CHUNK_SIZE as u64;
+    let Some(offset) = index.checked_mul(chunk_size) else {
+        ::log::warn!("Requested chunk {} of {} is out of range", index, path);
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    let chunk = chats
+        .source
+        .read_chunk(path, offset, TRANSFER_CHUNK_SIZE)
+        .await
+        .map_err(|e| {
+            ::log::error!("Failed to read {} for transfer: {}", path, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(ChunkResponse {
+        chunks: chunk.file_size.div_ceil(chunk_size).max(1),
+        data: BASE64.encode(chunk.content),
+    })
+}
+
+/// Keeps the pairing with the requesting device and answers with the shared
+/// secret
+fn pair(node: &SyncNode, request: PairRequest) -> Result<PairResponse> {
+    let secret = crate::crypto::generate_secret()?;
+
+    let mut state = node.lock();
+    state.add_pairing(Pairing {
+        device_id: request.device_id,
+        device_name: request.device_name,
+        address: None,
+        secret: secret.clone(),
+        sent_cursor: 0,
+        received_cursor: 0,
+    });
+
+    Ok(PairResponse {
+        device_id: state.device_id().to_string(),
+        device_name: state.device_name.clone(),
+        secret,
     })
 }
@@ -0,0 +1,278 @@
+//! Sessions with a device sharing its data, opened with the pairing code it
+//! shows.
+//!
+//! The code itself is never sent. Both sides run SPAKE2 with it to agree on a
+//! session key, which encrypts every later request and response. Someone
+//! observing the traffic learns nothing to guess the code offline, and each
+//! session started lets an active attacker test a single guess, so the server
+//! limits how many sessions a code can start.
+//!
+//! Codes are single use: once a session proves it knows the key, no other
+//! session can be started with the same code. Sessions expire after some time
+//! without requests.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+
+use crate::client::server_url;
+use crate::crypto::{open_bytes, seal_bytes};
+use crate::sync::PairRequest;
+
+/// Amount of symbols in a pairing code, without the separators.
+pub const PAIRING_CODE_LENGTH: usize = 12;
+
+/// Symbols of the pairing codes, leaving out the ones easy to confuse, like
+/// `0` and `O` or `1` and `I`. There are 32 of them, 5 bits each.
+const PAIRING_CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+pub(crate) const CLIENT_IDENTITY: &[u8] = b"moly-sync client";
+pub(crate) const SERVER_IDENTITY: &[u8] = b"moly-sync server";
+
+/// Generate a random pairing code, like `7KQ2-M9XD-4HTR`
+pub fn generate_pairing_code() -> Result<String> {
+    let mut bytes = [0u8; PAIRING_CODE_LENGTH];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to generate pairing code: {}", e))?;
+
+    let symbols = bytes
+        .iter()
+        .map(|b| PAIRING_CODE_ALPHABET[*b as usize % PAIRING_CODE_ALPHABET.len()] as char)
+        .collect::<Vec<_>>();
+
+    Ok(symbols
+        .chunks(4)
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-"))
+}
+
+/// The code as used for the key agreement, so it can be typed in lowercase and
+/// with or without separators
+pub fn normalize_pairing_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Sent to `/sync/session` to start a session.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SessionStart {
+    /// Base64-encoded SPAKE2 message of the client.
+    pub message: String,
+}
+
+/// Answer to [`SessionStart`].
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SessionStarted {
+    pub session_id: String,
+    /// Base64-encoded SPAKE2 message of the server.
+    pub message: String,
+}
+
+/// Sent to `/sync/session/call`, with a [`SessionCall`] encrypted with the
+/// session key.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SessionRequest {
+    pub session_id: String,
+    pub data: String,
+}
+
+/// What can be asked for in a session.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub(crate) enum SessionCall {
+    Preferences,
+    Pair(PairRequest),
+    TransferManifest,
+    TransferChunk { path: String, index: u64 },
+    Secrets,
+    Close,
+}
+
+/// A session opened with [`SyncSession::connect`].
+#[derive(Clone)]
+pub struct SyncSession {
+    server_addr: String,
+    session_id: String,
+    key: Vec<u8>,
+    client: reqwest::Client,
+}
+
+impl std::fmt::Debug for SyncSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncSession")
+            .field("server_addr", &self.server_addr)
+            .field("session_id", &self.session_id)
+            .finish()
+    }
+}
+
+impl SyncSession {
+    /// Opens a session with the device serving at the address, using the
+    /// pairing code it shows
+    ///
+    /// A wrong code is only noticed by the first request made in the session.
+    pub async fn connect(server_addr: &str, code: &str) -> Result<Self> {
+        let code = normalize_pairing_code(code);
+        let (spake, message) = Spake2::<Ed25519Group>::start_a(
+            &Password::new(code.as_bytes()),
+            &Identity::new(CLIENT_IDENTITY),
+            &Identity::new(SERVER_IDENTITY),
+        );
+
+        let client = reqwest::Client::new();
+        let response = client
+            .post(server_url(server_addr, "/sync/session"))
+            .json(&SessionStart {
+                message: BASE64.encode(message),
+            })
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => {}
+            reqwest::StatusCode::GONE => {
+                anyhow::bail!("The pairing code expired or was already used, share again")
+            }
+            reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                anyhow::bail!("Too many attempts with this pairing code, share again")
+            }
+            status => anyhow::bail!("Failed to start a session: {}", status),
+        }
+
+        let started: SessionStarted = response.json().await?;
+        let message = BASE64
+            .decode(&started.message)
+            .context("Failed to decode the server message")?;
+        let key = spake
+            .finish(&message)
+            .map_err(|e| anyhow::anyhow!("Failed to agree on a session key: {:?}", e))?;
+
+        Ok(Self {
+            server_addr: server_url(server_addr, ""),
+            session_id: started.session_id,
+            key,
+            client,
+        })
+    }
+
+    /// Address of the server, with scheme
+    pub fn server_addr(&self) -> &str {
+        &self.server_addr
+    }
+
+    /// Fetches the preferences JSON shared by the server
+    pub async fn fetch_preferences(&self) -> Result<String> {
+        self.call(&SessionCall::Preferences).await
+    }
+
+    /// Fetches the secrets shared by the server, like API keys, by name
+    ///
+    /// They travel encrypted with the session key, so only this session can
+    /// read them. Empty if the server doesn't share them.
+    pub async fn fetch_secrets(&self) -> Result<BTreeMap<String, String>> {
+        self.call(&SessionCall::Secrets).await
+    }
+
+    /// Ends the session, which can't be used anymore
+    pub async fn close(&self) -> Result<()> {
+        self.call(&SessionCall::Close).await
+    }
+
+    pub(crate) async fn call<T: DeserializeOwned>(&self, call: &SessionCall) -> Result<T> {
+        let request = SessionRequest {
+            session_id: self.session_id.clone(),
+            data: seal_bytes(&serde_json::to_vec(call)?, &self.key)?,
+        };
+
+        let response = self
+            .client
+            .post(server_url(&self.server_addr, "/sync/session/call"))
+            .json(&request)
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => {}
+            reqwest::StatusCode::UNAUTHORIZED => anyhow::bail!(
+                "The session was rejected. The pairing code may be wrong, or the session expired."
+            ),
+            status => anyhow::bail!("Request rejected by the server: {}", status),
+        }
+
+        let data = open_bytes(&response.text().await?, &self.key)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pairing_code_format() {
+        let code = generate_pairing_code().unwrap();
+        assert_eq!(code.len(), PAIRING_CODE_LENGTH + 2);
+        assert_eq!(code.matches('-').count(), 2);
+        assert_ne!(code, generate_pairing_code().unwrap());
+
+        assert_eq!(normalize_pairing_code("7kq2-m9xd 4htr"), "7KQ2M9XD4HTR");
+        assert_eq!(
+            normalize_pairing_code(&code),
+            normalize_pairing_code(&code.to_lowercase())
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_codes_are_single_use_and_attempts_are_limited() {
+        use crate::server::{start_sync_server, MAX_SESSION_ATTEMPTS};
+        use crate::sync::{SyncNode, SyncState};
+        use std::net::Ipv4Addr;
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let node = SyncNode::new(SyncState::new("desktop").unwrap());
+            let server = start_sync_server(node, Ipv4Addr::LOCALHOST.into(), None)
+                .await
+                .unwrap();
+            let addr = format!("127.0.0.1:{}", server.addr.port());
+
+            // A wrong code is only noticed by the server when the session is used.
+            let code = server.share(r#"{"a":1}"#.to_string()).unwrap();
+            let guess = SyncSession::connect(&addr, "AAAA-AAAA-AAAA").await.unwrap();
+            assert!(guess.fetch_preferences().await.is_err());
+
+            let session = SyncSession::connect(&addr, &code.to_lowercase())
+                .await
+                .unwrap();
+            assert_eq!(session.fetch_preferences().await.unwrap(), r#"{"a":1}"#);
+
+            // Secrets are only given if shared.
+            assert!(session.fetch_secrets().await.unwrap().is_empty());
+            let secrets = BTreeMap::from([("api_key".to_string(), "sk-1".to_string())]);
+            server.share_secrets(secrets.clone());
+            assert_eq!(session.fetch_secrets().await.unwrap(), secrets);
+
+            // The code was used, no more sessions can be started with it.
+            assert!(SyncSession::connect(&addr, &code).await.is_err());
+            session.close().await.unwrap();
+            assert!(session.fetch_preferences().await.is_err());
+
+            // Guessing stops after a few attempts, even with the right code.
+            let code = server.share("{}".to_string()).unwrap();
+            for _ in 0..MAX_SESSION_ATTEMPTS {
+                let guess = SyncSession::connect(&addr, "AAAA-AAAA-AAAA").await.unwrap();
+                assert!(guess.fetch_preferences().await.is_err());
+            }
+            assert!(SyncSession::connect(&addr, &code).await.is_err());
+
+            server.stop();
+        });
+    }
+}
@@ -0,0 +1,489 @@
+//! Continuous two-way sync between paired devices.
+//!
+//! Devices are paired once, in a session opened with the pairing code shown by
+//! the serving device, which gives both of them a shared secret to encrypt
+//! every later exchange. Then the
+//! paired client periodically sends the changes made since its last exchange,
+//! and receives the ones the server had, so only deltas are transferred.
+
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::Result;
+use moly_kit::utils::asynchronous::{BoxPlatformSendFuture, PlatformSendFuture};
+use serde::{Deserialize, Serialize};
+
+use crate::client::server_url;
+use crate::crypto::{decrypt_json, encrypt_json, generate_id};
+use crate::replica::{Record, Replica};
+use crate::session::{SessionCall, SyncSession};
+
+/// A device this one is kept in sync with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pairing {
+    pub device_id: String,
+    pub device_name: String,
+    /// Where the peer can be reached, only known by the side that started the
+    /// pairing, which is the one starting the exchanges.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Key used to encrypt the exchanges with this peer.
+    pub secret: String,
+    /// Local changes up to this cursor were already sent to the peer.
+    #[serde(default)]
+    pub sent_cursor: u64,
+    /// Changes of the peer up to this cursor were already received.
+    #[serde(default)]
+    pub received_cursor: u64,
+}
+
+/// Everything a device needs to keep in sync with its peers, meant to be
+/// persisted between sessions.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SyncState {
+    pub device_name: String,
+    pub replica: Replica,
+    #[serde(default)]
+    pub pairings: Vec<Pairing>,
+    /// Port the sync server last listened at, to be reused so the paired
+    /// clients can still reach it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen_port: Option<u16>,
+    /// Address the sync server listens at, all the interfaces if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen_address: Option<IpAddr>,
+    /// Changes received from peers and not yet applied to the local data.
+    ///
+    /// Stored with the rest of the state, since the peers don't send them
+    /// again once received.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pending: Vec<Record>,
+}
+
+impl SyncState {
+    /// Creates the state of a new device, with a random id.
+    pub fn new(device_name: impl Into<String>) -> Result<Self> {
+        let device_id = generate_id(16)?;
+
+        Ok(Self {
+            device_name: device_name.into(),
+            replica: Replica::new(device_id),
+            pairings: Vec::new(),
+            listen_port: None,
+            listen_address: None,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn device_id(&self) -> &str {
+        self.replica.device_id()
+    }
+
+    pub fn pairing(&self, device_id: &str) -> Option<&Pairing> {
+        self.pairings.iter().find(|p| p.device_id == device_id)
+    }
+
+    /// Adds the pairing, replacing the previous one with the same device.
+    pub fn add_pairing(&mut self, pairing: Pairing) {
+        self.remove_pairing(&pairing.device_id);
+        self.pairings.push(pairing);
+    }
+
+    pub fn remove_pairing(&mut self, device_id: &str) {
+        self.pairings.retain(|p| p.device_id != device_id);
+    }
+
+    /// Takes the changes received from peers since the last call, which must
+    /// be applied to the local data before observing it again.
+    pub fn take_pending(&mut self) -> Vec<Record> {
+        std::mem::take(&mut self.pending)
+    }
+
+    pub(crate) fn receive(&mut self, records: Vec<Record>, peer_id: &str) -> usize {
+        let applied = self.replica.apply(records, peer_id);
+        let count = applied.len();
+        self.pending.extend(applied);
+        count
+    }
+
+    fn pairing_mut(&mut self, device_id: &str) -> Option<&mut Pairing> {
+        self.pairings.iter_mut().find(|p| p.device_id == device_id)
+    }
+}
+
+type Persist = Arc<dyn Fn(SyncState) -> BoxPlatformSendFuture<'static, Result<()>> + Send + Sync>;
+
+/// Shared handle to the [`SyncState`] of this device, used by both the server
+/// and the client side of the exchanges.
+#[derive(Clone)]
+pub struct SyncNode {
+    state: Arc<Mutex<SyncState>>,
+    persist: Option<Persist>,
+}
+
+impl std::fmt::Debug for SyncNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyncNode")
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl SyncNode {
+    /// A node whose state is only kept in memory.
+    pub fn new(state: SyncState) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            persist: None,
+        }
+    }
+
+    /// A node which stores its state with `persist` after every exchange,
+    /// before the changes are confirmed to the peer.
+    pub fn with_persistence<F>(
+        state: SyncState,
+        persist: impl Fn(SyncState) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: PlatformSendFuture<Output = Result<()>> + 'static,
+    {
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            persist: Some(Arc::new(move |state| Box::pin(persist(state)))),
+        }
+    }
+
+    /// Locks the state. Hold it while applying the pending changes and
+    /// observing the local data, so no exchange happens in between.
+    pub fn lock(&self) -> MutexGuard<'_, SyncState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Stores the current state, if the node was created with
+    /// [`SyncNode::with_persistence`].
+    pub async fn persist(&self) -> Result<()> {
+        match &self.persist {
+            Some(persist) => {
+                let state = self.lock().clone();
+                persist(state).await
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Sent in a [`SyncSession`] to pair with the server.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PairRequest {
+    pub device_id: String,
+    pub device_name: String,
+}
+
+/// Answer to [`PairRequest`].
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PairResponse {
+    pub device_id: String,
+    pub device_name: String,
+    pub secret: String,
+}
+
+/// Wraps the exchanges, so the receiver knows which secret to decrypt with.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Envelope {
+    pub device_id: String,
+    pub data: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ExchangeRequest {
+    /// The server changes after this cursor are requested.
+    pub since: u64,
+    pub changes: Vec<Record>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ExchangeResponse {
+    /// Cursor of the server after the exchange.
+    pub cursor: u64,
+    pub changes: Vec<Record>,
+}
+
+impl Envelope {
+    pub(crate) fn seal<T: Serialize>(device_id: &str, value: &T, secret: &str) -> Result<Self> {
+        Ok(Self {
+            device_id: device_id.to_string(),
+            data: encrypt_json(&serde_json::to_string(value)?, secret)?,
+        })
+    }
+
+    pub(crate) fn open<T: for<'de> Deserialize<'de>>(&self, secret: &str) -> Result<T> {
+        let json = decrypt_json(&self.data, secret)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+/// Pairs this device with the one serving the session, and keeps the pairing
+/// in the state.
+pub async fn pair_with(node: &SyncNode, session: &SyncSession) -> Result<Pairing> {
+    let request = {
+        let state = node.lock();
+        PairRequest {
+            device_id: state.device_id().to_string(),
+            device_name: state.device_name.clone(),
+        }
+    };
+
+    let response: PairResponse = session.call(&SessionCall::Pair(request)).await?;
+
+    let pairing = Pairing {
+        device_id: response.device_id,
+        device_name: response.device_name,
+        address: Some(session.server_addr().to_string()),
+        secret: response.secret,
+        sent_cursor: 0,
+        received_cursor: 0,
+    };
+
+    node.lock().add_pairing(pairing.clone());
+    Ok(pairing)
+}
+
+/// Exchanges the changes with a peer paired with [`pair_with`].
+///
+/// Returns the amount of changes received, which can be taken from the state
+/// with [`SyncState::take_pending`].
+pub async fn sync_with(node: &SyncNode, peer_device_id: &str) -> Result<usize> {
+    let (device_id, address, secret, request, sent_until) = {
+        let state = node.lock();
+        let pairing = state
+            .pairing(peer_device_id)
+            .ok_or_else(|| anyhow::anyhow!("Not paired with device {}", peer_device_id))?;
+        let address = pairing
+            .address
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("The address of {} is unknown", peer_device_id))?;
+
+        let changes = state
+            .replica
+            .changes_since(pairing.sent_cursor, peer_device_id);
+        let request = ExchangeRequest {
+            since: pairing.received_cursor,
+            changes: changes.records,
+        };
+
+        (
+            state.device_id().to_string(),
+            address,
+            pairing.secret.clone(),
+            request,
+            changes.cursor,
+        )
+    };
+
+    let envelope = Envelope::seal(&device_id, &request, &secret)?;
+
+    let response = reqwest::Client::new()
+        .post(server_url(&address, "/sync/exchange"))
+        .json(&envelope)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("Sync rejected by the server: {}", response.status());
+    }
+
+    let envelope: Envelope = response.json().await?;
+    let response: ExchangeResponse = envelope.open(&secret)?;
+
+    let received = {
+        let mut state = node.lock();
+        let received = state.receive(response.changes, peer_device_id);
+        if let Some(pairing) = state.pairing_mut(peer_device_id) {
+            pairing.sent_cursor = sent_until;
+            pairing.received_cursor = response.cursor;
+        }
+        received
+    };
+
+    node.persist().await?;
+    Ok(received)
+}
+
+/// Answers an exchange started by a paired client with [`sync_with`].
+///
+/// Fails if the client is unknown or the request can't be decrypted. The
+/// state must be persisted before the answer is sent, since the client won't
+/// send its changes again.
+pub(crate) fn answer_exchange(node: &SyncNode, envelope: Envelope) -> Result<Envelope> {
+    let secret = node
+        .lock()
+        .pairing(&envelope.device_id)
+        .map(|p| p.secret.clone())
+        .ok_or_else(|| anyhow::anyhow!("Unknown device {}", envelope.device_id))?;
+
+    let request: ExchangeRequest = envelope.open(&secret)?;
+
+    let (device_id, response) = {
+        let mut state = node.lock();
+        state.receive(request.changes, &envelope.device_id);
+        let changes = state
+            .replica
+            .changes_since(request.since, &envelope.device_id);
+        let response = ExchangeResponse {
+            cursor: changes.cursor,
+            changes: changes.records,
+        };
+        (state.device_id().to_string(), response)
+    };
+
+    Envelope::seal(&device_id, &response, &secret)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::replica::RecordKind;
+    use crate::server::start_sync_server;
+    use serde_json::json;
+    use std::net::Ipv4Addr;
+
+    fn template(name: &str, content: &str) -> (String, serde_json::Value) {
+        (name.to_string(), json!({"name": name, "content": content}))
+    }
+
+    fn node(name: &str) -> SyncNode {
+        SyncNode::new(SyncState::new(name).unwrap())
+    }
+
+    #[test]
+    fn test_two_devices_stay_in_sync() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let desktop = node("desktop");
+            let laptop = node("laptop");
+
+            desktop.lock().replica.observe(
+                RecordKind::PromptTemplate,
+                [template("review", "Review this code")],
+            );
+            laptop.lock().replica.observe(
+                RecordKind::PromptTemplate,
+                [template("translate", "Translate to English")],
+            );
+
+            let server = start_sync_server(desktop.clone(), Ipv4Addr::LOCALHOST.into(), None)
+                .await
+                .unwrap();
+            let code = server.share("{}".to_string()).unwrap();
+            let addr = format!("127.0.0.1:{}", server.addr.port());
+
+            let guess = SyncSession::connect(&addr, "AAAA-AAAA-AAAA").await.unwrap();
+            assert!(pair_with(&laptop, &guess).await.is_err());
+            let session = SyncSession::connect(&addr, &code).await.unwrap();
+            let pairing = pair_with(&laptop, &session).await.unwrap();
+            assert_eq!(pairing.device_name, "desktop");
+            assert_eq!(
+                desktop
+                    .lock()
+                    .pairing(laptop.lock().device_id())
+                    .unwrap()
+                    .device_name,
+                "laptop"
+            );
+
+            // Pairing stays valid after sharing stops.
+            server.stop_sharing();
+
+            let desktop_id = desktop.lock().device_id().to_string();
+            assert_eq!(sync_with(&laptop, &desktop_id).await.unwrap(), 1);
+            assert_eq!(desktop.lock().take_pending().len(), 1);
+            assert_eq!(laptop.lock().take_pending()[0].id, "review");
+
+            // Nothing new, nothing exchanged.
+            assert_eq!(sync_with(&laptop, &desktop_id).await.unwrap(), 0);
+            assert!(desktop.lock().take_pending().is_empty());
+
+            // A removal on the server reaches the client.
+            desktop.lock().replica.observe(
+                RecordKind::PromptTemplate,
+                [template("translate", "Translate to English")],
+            );
+            assert_eq!(sync_with(&laptop, &desktop_id).await.unwrap(), 1);
+            let pending = laptop.lock().take_pending();
+            assert_eq!(pending[0].id, "review");
+            assert!(pending[0].is_deleted());
+
+            server.stop();
+        });
+    }
+
+    #[test]
+    fn test_received_changes_are_persisted_before_answering() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let stored = Arc::new(Mutex::new(None::<String>));
+            let desktop = SyncNode::with_persistence(SyncState::new("desktop").unwrap(), {
+                let stored = stored.clone();
+                move |state| {
+                    let stored = stored.clone();
+                    async move {
+                        *stored.lock().unwrap() = Some(serde_json::to_string(&state)?);
+                        Ok(())
+                    }
+                }
+            });
+            let laptop = node("laptop");
+            laptop.lock().replica.observe(
+                RecordKind::PromptTemplate,
+                [template("translate", "Translate to English")],
+            );
+
+            let server = start_sync_server(desktop.clone(), Ipv4Addr::LOCALHOST.into(), None)
+                .await
+                .unwrap();
+            let code = server.share("{}".to_string()).unwrap();
+            let addr = format!("127.0.0.1:{}", server.addr.port());
+            let session = SyncSession::connect(&addr, &code).await.unwrap();
+            pair_with(&laptop, &session).await.unwrap();
+
+            let desktop_id = desktop.lock().device_id().to_string();
+            sync_with(&laptop, &desktop_id).await.unwrap();
+
+            // The stored state still has the changes, even if they were never
+            // applied before the app stopped.
+            let json = stored.lock().unwrap().clone().unwrap();
+            let mut restored: SyncState = serde_json::from_str(&json).unwrap();
+            let pending = restored.take_pending();
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0].id, "translate");
+
+            server.stop();
+        });
+    }
+
+    #[test]
+    fn test_unknown_devices_are_rejected() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let desktop = node("desktop");
+            let stranger = node("stranger");
+
+            let server = start_sync_server(desktop.clone(), Ipv4Addr::LOCALHOST.into(), None)
+                .await
+                .unwrap();
+            stranger.lock().add_pairing(Pairing {
+                device_id: desktop.lock().device_id().to_string(),
+                device_name: "desktop".into(),
+                address: Some(format!("127.0.0.1:{}", server.addr.port())),
+                secret: "guessed".into(),
+                sent_cursor: 0,
+                received_cursor: 0,
+            });
+
+            let desktop_id = desktop.lock().device_id().to_string();
+            assert!(sync_with(&stranger, &desktop_id).await.is_err());
+
+            server.stop();
+        });
+    }
+}
@@ -0,0 +1,388 @@
+//! Opt-in transfer of the chats, and the attachments they reference, to another
+//! device.
+//!
+//! Files are sent in chunks, each one encrypted with the session key like the
+//! rest of the shared data. An interrupted download resumes from the last chunk
+//! received, and a restarted transfer skips the files completed before, as
+//! recorded in its [`TransferCheckpoint`].
+
+use std::collections::VecDeque;
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use moly_kit::utils::asynchronous::BoxPlatformSendFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::session::{SessionCall, SyncSession};
+
+/// Maximum size of the chunks files are sent in.
+pub const TRANSFER_CHUNK_SIZE: usize = 256 * 1024;
+
+/// The chats offered by the sharing device.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransferManifest {
+    pub chats: Vec<TransferChat>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransferChat {
+    pub id: String,
+    /// Path of the chat file, relative to the app data.
+    pub path: String,
+    /// Paths of the attachments persisted for the chat.
+    #[serde(default)]
+    pub attachments: Vec<String>,
+}
+
+impl TransferManifest {
+    /// If the file is part of the transfer, so it can be served.
+    pub fn contains(&self, path: &str) -> bool {
+        self.chats
+            .iter()
+            .any(|c| c.path == path || c.attachments.iter().any(|a| a == path))
+    }
+}
+
+/// Part of a file read from a [`FileSource`].
+pub struct FileChunk {
+    pub content: Vec<u8>,
+    /// Size of the whole file.
+    pub file_size: u64,
+}
+
+/// Gives the content of the files listed in the shared [`TransferManifest`].
+pub trait FileSource: Send + Sync {
+    /// Reads up to `len` bytes of the file, starting at `offset`.
+    fn read_chunk(
+        &self,
+        path: &str,
+        offset: u64,
+        len: usize,
+    ) -> BoxPlatformSendFuture<'static, Result<FileChunk>>;
+}
+
+/// Files already received, meant to be persisted to resume the transfer.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransferCheckpoint {
+    pub completed: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TransferProgress {
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_received: u64,
+}
+
+/// A downloaded file, to be written at the same path it has in the sender.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceivedFile {
+    pub path: String,
+    pub content: Vec<u8>,
+    /// Set for chat files, which come after their attachments.
+    pub chat_id: Option<String>,
+}
+
+/// Answer to [`SessionCall::TransferChunk`].
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ChunkResponse {
+    /// Amount of chunks of the whole file.
+    pub chunks: u64,
+    /// Base64-encoded content of the chunk.
+    pub data: String,
+}
+
+struct PendingFile {
+    path: String,
+    chat_id: Option<String>,
+}
+
+struct PartialFile {
+    content: Vec<u8>,
+    next_chunk: u64,
+    chunks: u64,
+}
+
+/// Downloads the chats shared by another device, one file at a time.
+pub struct ChatTransfer {
+    session: SyncSession,
+    pending: VecDeque<PendingFile>,
+    partial: Option<PartialFile>,
+    checkpoint: TransferCheckpoint,
+    progress: TransferProgress,
+}
+
+impl ChatTransfer {
+    /// Fetches the chats shared in the session, leaving out the ones with an id
+    /// already known, and the files completed in a previous attempt.
+    pub async fn start(
+        session: SyncSession,
+        known_chat_ids: &[String],
+        checkpoint: TransferCheckpoint,
+    ) -> Result<Self> {
+        let manifest: TransferManifest = session.call(&SessionCall::TransferManifest).await?;
+
+        let pending = manifest
+            .chats
+            .into_iter()
+            .filter(|chat| !known_chat_ids.contains(&chat.id))
+            .flat_map(|chat| {
+                let attachments = chat.attachments.into_iter().map(|path| PendingFile {
+                    path,
+                    chat_id: None,
+                });
+                let chat = PendingFile {
+                    path: chat.path,
+                    chat_id: Some(chat.id),
+                };
+                attachments.chain(std::iter::once(chat))
+            })
+            .filter(|file| {
+                let safe = is_relative_path(&file.path);
+                if !safe {
+                    log::warn!("Ignoring shared file with unsafe path: {}", file.path);
+                }
+                safe
+            })
+            .filter(|file| !checkpoint.completed.contains(&file.path))
+            .collect::<VecDeque<_>>();
+
+        let progress = TransferProgress {
+            files_done: 0,
+            files_total: pending.len(),
+            bytes_received: 0,
+        };
+
+        Ok(Self {
+            session,
+            pending,
+            partial: None,
+            checkpoint,
+            progress,
+        })
+    }
+
+    pub fn progress(&self) -> TransferProgress {
+        self.progress
+    }
+
+    pub fn checkpoint(&self) -> &TransferCheckpoint {
+        &self.checkpoint
+    }
+
+    /// Downloads the next file, or returns `None` if there are no more.
+    ///
+    /// If it fails, calling it again resumes the download from the last
+    /// chunk received.
+    pub async fn next_file(
+        &mut self,
+        mut on_progress: impl FnMut(TransferProgress),
+    ) -> Result<Option<ReceivedFile>> {
+        let Some(file) = self.pending.front() else {
+            return Ok(None);
+        };
+        let path = file.path.clone();
+
+        loop {
+            let partial = self.partial.get_or_insert_with(|| PartialFile {
+                content: Vec::new(),
+                next_chunk: 0,
+                chunks: 1,
+            });
+
+            if partial.next_chunk >= partial.chunks {
+                break;
+            }
+
+            let response = fetch_chunk(&self.session, &path, partial.next_chunk).await?;
+            let chunk = BASE64
+                .decode(&response.data)
+                .context("Failed to decode the chunk")?;
+
+            partial.chunks = response.chunks;
+            partial.next_chunk += 1;
+            partial.content.extend_from_slice(&chunk);
+
+            self.progress.bytes_received += chunk.len() as u64;
+            on_progress(self.progress);
+        }
+
+        let file = self.pending.pop_front().unwrap();
+        let partial = self.partial.take().unwrap();
+
+        Ok(Some(ReceivedFile {
+            path: file.path,
+            content: partial.content,
+            chat_id: file.chat_id,
+        }))
+    }
+
+    /// Records the file as completed, once it's written.
+    pub fn complete(&mut self, path: &str) {
+        self.checkpoint.completed.push(path.to_string());
+        self.progress.files_done += 1;
+    }
+}
+
+/// If the path stays inside the directory it's relative to, so it's safe to
+/// write a received file to it.
+fn is_relative_path(path: &str) -> bool {
+    let path = std::path::Path::new(path);
+    !path.as_os_str().is_empty()
+        && path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+}
+
+async fn fetch_chunk(session: &SyncSession, path: &str, index: u64) -> Result<ChunkResponse> {
+    let call = SessionCall::TransferChunk {
+        path: path.to_string(),
+        index,
+    };
+    session
+        .call(&call)
+        .await
+        .with_context(|| format!("Failed to fetch {}", path))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::server::start_sync_server;
+    use crate::sync::{SyncNode, SyncState};
+    use std::collections::HashMap;
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Files in memory, failing the read number `fail_at`, if given.
+    struct MemorySource {
+        files: HashMap<String, Vec<u8>>,
+        reads: AtomicUsize,
+        fail_at: Option<usize>,
+    }
+
+    impl FileSource for MemorySource {
+        fn read_chunk(
+            &self,
+            path: &str,
+            offset: u64,
+            len: usize,
+        ) -> BoxPlatformSendFuture<'static, Result<FileChunk>> {
+            let read = self.reads.fetch_add(1, Ordering::SeqCst);
+            let file = self.files.get(path).cloned();
+            let fail = self.fail_at == Some(read);
+            Box::pin(async move {
+                if fail {
+                    anyhow::bail!("disk error");
+                }
+                let file = file.ok_or_else(|| anyhow::anyhow!("not found"))?;
+                let start = (offset as usize).min(file.len());
+                let end = (start + len).min(file.len());
+                Ok(FileChunk {
+                    content: file[start..end].to_vec(),
+                    file_size: file.len() as u64,
+                })
+            })
+        }
+    }
+
+    fn manifest() -> TransferManifest {
+        TransferManifest {
+            chats: vec![
+                TransferChat {
+                    id: "1".into(),
+                    path: "chats/1.chat.json".into(),
+                    attachments: vec!["attachments/photo.png".into()],
+                },
+                TransferChat {
+                    id: "2".into(),
+                    path: "chats/2.chat.json".into(),
+                    attachments: vec![],
+                },
+            ],
+        }
+    }
+
+    fn source(fail_at: Option<usize>) -> Arc<MemorySource> {
+        let photo = (0..TRANSFER_CHUNK_SIZE + 10)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        Arc::new(MemorySource {
+            files: HashMap::from([
+                ("chats/1.chat.json".to_string(), br#"{"id":1}"#.to_vec()),
+                ("chats/2.chat.json".to_string(), br#"{"id":2}"#.to_vec()),
+                ("attachments/photo.png".to_string(), photo),
+                ("secret.txt".to_string(), b"secret".to_vec()),
+            ]),
+            reads: AtomicUsize::new(0),
+            fail_at,
+        })
+    }
+
+    #[test]
+    fn test_only_relative_paths_are_received() {
+        assert!(is_relative_path("chats/1.chat.json"));
+        assert!(!is_relative_path("../preferences.json"));
+        assert!(!is_relative_path("chats/../../secret"));
+        assert!(!is_relative_path("/etc/passwd"));
+        assert!(!is_relative_path(""));
+    }
+
+    #[test]
+    fn test_transfer_resumes_and_skips_known_chats() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let node = SyncNode::new(SyncState::new("desktop").unwrap());
+            let server = start_sync_server(node, Ipv4Addr::LOCALHOST.into(), None)
+                .await
+                .unwrap();
+            let code = server.share("{}".to_string()).unwrap();
+            // The second chunk of the photo fails to be read the first time.
+            let source = source(Some(1));
+            server.share_chats(manifest(), source.clone());
+            let addr = format!("127.0.0.1:{}", server.addr.port());
+            let session = SyncSession::connect(&addr, &code).await.unwrap();
+
+            let mut transfer =
+                ChatTransfer::start(session.clone(), &["2".to_string()], Default::default())
+                    .await
+                    .unwrap();
+            assert_eq!(transfer.progress().files_total, 2);
+
+            assert!(transfer.next_file(|_| {}).await.is_err());
+            let photo = transfer.next_file(|_| {}).await.unwrap().unwrap();
+            assert_eq!(photo.path, "attachments/photo.png");
+            assert_eq!(photo.content, source.files["attachments/photo.png"]);
+            assert_eq!(photo.chat_id, None);
+            transfer.complete(&photo.path);
+
+            // The first chunk was not downloaded again.
+            assert_eq!(
+                transfer.progress().bytes_received,
+                photo.content.len() as u64
+            );
+
+            let checkpoint = transfer.checkpoint().clone();
+            let mut transfer = ChatTransfer::start(session.clone(), &["2".to_string()], checkpoint)
+                .await
+                .unwrap();
+            assert_eq!(transfer.progress().files_total, 1);
+
+            let chat = transfer.next_file(|_| {}).await.unwrap().unwrap();
+            assert_eq!(chat.chat_id.as_deref(), Some("1"));
+            transfer.complete(&chat.path);
+            assert_eq!(transfer.next_file(|_| {}).await.unwrap(), None);
+
+            // Files out of the manifest are never served.
+            assert!(fetch_chunk(&session, "secret.txt", 0).await.is_err());
+
+            // Neither are chunks out of range.
+            let reads = source.reads.load(Ordering::SeqCst);
+            assert!(fetch_chunk(&session, &chat.path, u64::MAX).await.is_err());
+            assert_eq!(source.reads.load(Ordering::SeqCst), reads);
+
+            server.stop();
+        });
+    }
+}
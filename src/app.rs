@@ -5,17 +5,20 @@ use crate::data::downloads::download::DownloadFileAction;
 use crate::data::moly_client::MolyClientAction;
 use crate::data::store::*;
 use crate::landing::model_files_item::ModelFileItemAction;
+use crate::mcp::mcp_input_prompt::{McpInputPromptAction, McpInputPromptWidgetRefExt};
 use crate::shared::actions::{ChatAction, DownloadAction};
 use crate::shared::download_notification_popup::{
     DownloadNotificationPopupAction, DownloadNotificationPopupRef,
     DownloadNotificationPopupWidgetRefExt, DownloadResult,
 };
+use crate::shared::modal::ModalWidgetRefExt;
 use crate::shared::moly_server_popup::MolyServerPopupAction;
 use crate::shared::popup_notification::PopupNotificationWidgetRefExt;
 use moly_protocol::data::{File, FileID};
 
 use makepad_widgets::*;
 use markdown::MarkdownAction;
+use moly_kit::ToolPolicyAction;
 
 live_design! {
     use link::theme::*;
@@ -29,12 +32,14 @@ live_design! {
     use crate::shared::download_notification_popup::DownloadNotificationPopup;
     use crate::shared::moly_server_popup::MolyServerPopup;
     use crate::shared::desktop_buttons::MolyDesktopButton;
+    use crate::shared::modal::Modal;
 
     use crate::landing::model_card::ModelCardViewAllModal;
     use crate::chat::chat_screen::ChatScreen;
     use crate::settings::moly_server_screen::MolyServerScreen;
     use crate::settings::providers_screen::ProvidersScreen;
     use crate::mcp::mcp_screen::McpScreen;
+    use crate::mcp::mcp_input_prompt::McpInputPrompt;
 
     ICON_CHAT = dep("crate://self/resources/icons/chat.svg")
     ICON_LOCAL = dep("crate://self/resources/icons/local.svg")
@@ -201,6 +206,12 @@ live_design! {
                         popup_moly_server = <MolyServerPopup> {}
                     }
                 }
+
+                mcp_input_modal = <Modal> {
+                    content: {
+                        mcp_input_prompt = <McpInputPrompt> {}
+                    }
+                }
             }
         }
     }
@@ -429,6 +440,26 @@ impl MatchEvent for App {
                 self.ui.popup_notification(ids!(moly_server_popup)).open(cx);
             }
 
+            if let McpAction::InputsRequired(inputs) = action.cast() {
+                self.ui
+                    .mcp_input_prompt(ids!(mcp_input_prompt))
+                    .add_inputs(cx, inputs);
+                self.ui.modal(ids!(mcp_input_modal)).open(cx);
+            }
+
+            if let McpInputPromptAction::Finished = action.cast() {
+                self.ui.modal(ids!(mcp_input_modal)).close(cx);
+            }
+
+            if let ToolPolicyAction::Remembered {
+                server_id,
+                tool_name,
+                policy,
+            } = action.cast()
+            {
+                store.set_mcp_tool_policy(&server_id, &tool_name, policy);
+            }
+
             if let MolyServerPopupAction::CloseButtonClicked = action.cast() {
                 self.ui
                     .popup_notification(ids!(moly_server_popup))
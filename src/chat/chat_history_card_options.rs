@@ -1,5 +1,7 @@
 use super::chat_history_card::ChatHistoryCardAction;
 use crate::data::chats::chat::ChatID;
+use crate::data::chats::export::ExportFormat;
+use crate::data::store::Store;
 use makepad_widgets::*;
 
 live_design! {
@@ -12,6 +14,34 @@ live_design! {
 
     ICON_DELETE = dep("crate://self/resources/icons/delete.svg")
     ICON_EDIT = dep("crate://self/resources/icons/edit.svg")
+    ICON_EXPORT = dep("crate://self/resources/icons/download.svg")
+
+    ExportButton = <MolyButton> {
+        width: Fill
+        height: Fit
+        padding: { top: 12, right: 12, bottom: 12, left: 12}
+        align: {x: 0.0, y: 0.5}
+
+        draw_bg: {
+            border_size: 0,
+            border_radius: 0
+        }
+
+        icon_walk: {width: 12, height: 12}
+        draw_icon: {
+            svg_file: (ICON_EXPORT),
+            fn get_color(self) -> vec4 {
+                return #000;
+            }
+        }
+
+        draw_text: {
+            text_style: <REGULAR_FONT>{font_size: 9},
+            fn get_color(self) -> vec4 {
+                return #000;
+            }
+        }
+    }
 
     pub ChatHistoryCardOptions = {{ChatHistoryCardOptions}} {
         width: Fit
@@ -58,6 +88,10 @@ live_design! {
                 text: "Edit Chat Name"
             }
 
+            export_markdown = <ExportButton> { text: "Export as Markdown" }
+            export_html = <ExportButton> { text: "Export as HTML" }
+            export_json = <ExportButton> { text: "Export as JSON" }
+            export_all = <ExportButton> { text: "Export All Chats" }
 
             delete_chat = <MolyButton> {
                 width: Fill
@@ -126,7 +160,7 @@ impl ChatHistoryCardOptionsRef {
 }
 
 impl WidgetMatchEvent for ChatHistoryCardOptions {
-    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, _scope: &mut Scope) {
+    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
         if self.button(ids!(delete_chat)).clicked(actions) {
             cx.action(ChatHistoryCardAction::MenuClosed(self.chat_id));
 
@@ -140,5 +174,35 @@ impl WidgetMatchEvent for ChatHistoryCardOptions {
 
             cx.action(ChatHistoryCardAction::ActivateTitleEdition(self.chat_id));
         }
+
+        let export = [
+            (ids!(export_markdown), ExportFormat::Markdown),
+            (ids!(export_html), ExportFormat::Html),
+            (ids!(export_json), ExportFormat::Json),
+        ]
+        .into_iter()
+        .find(|(id, _)| self.button(id).clicked(actions));
+
+        if let Some((_, format)) = export {
+            cx.action(ChatHistoryCardAction::MenuClosed(self.chat_id));
+
+            let store = scope.data.get::<Store>().unwrap();
+            store.chats.export_chats(&[self.chat_id], format);
+        }
+
+        // All the chats are exported to the interchange format, which is the
+        // one that can be imported back.
+        if self.button(ids!(export_all)).clicked(actions) {
+            cx.action(ChatHistoryCardAction::MenuClosed(self.chat_id));
+
+            let store = scope.data.get::<Store>().unwrap();
+            let chat_ids = store
+                .chats
+                .saved_chats
+                .iter()
+                .map(|c| c.borrow().id)
+                .collect::<Vec<_>>();
+            store.chats.export_chats(&chat_ids, ExportFormat::Json);
+        }
     }
 }
@@ -1,5 +1,8 @@
 use makepad_widgets::*;
 
+use crate::data::chats::Chats;
+use crate::data::chats::search::ChatSearchResult;
+use crate::data::store::Store;
 use crate::shared::actions::ChatAction;
 
 live_design! {
@@ -9,9 +12,12 @@ live_design! {
 
     use crate::shared::styles::*;
     use crate::shared::widgets::*;
+    use crate::shared::resource_imports::*;
     use crate::chat::chat_history::ChatHistory;
 
     ICON_NEW_CHAT = dep("crate://self/resources/icons/new_chat.svg")
+    ICON_SEARCH = dep("crate://self/resources/icons/search.svg")
+    ICON_IMPORT = dep("crate://self/resources/icons/folder.svg")
 
     HeadingLabel = <Label> {
         margin: {left: 4, bottom: 4},
@@ -30,12 +36,106 @@ live_design! {
         }
     }
 
+    SearchInput = <RoundedView> {
+        width: Fill, height: Fit
+        margin: {left: 10, right: 10, bottom: 10}
+        padding: {left: 8, right: 4}
+        align: {y: 0.5}
+        spacing: 4
+        show_bg: true
+        draw_bg: {
+            color: #fff
+            border_radius: 5.0
+            border_size: 1.0
+            border_color: #D0D5DD
+        }
+
+        <Icon> {
+            draw_icon: {
+                svg_file: (ICON_SEARCH),
+                fn get_color(self) -> vec4 {
+                    return #667085;
+                }
+            }
+            icon_walk: {width: 13, height: 13}
+        }
+
+        input = <MolyTextInput> {
+            width: Fill, height: Fit
+            empty_text: "Search chats"
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 10},
+            }
+        }
+
+        clear_search_button = <MolyButton> {
+            visible: false,
+            draw_icon: {
+                svg_file: (ICON_CLOSE),
+                fn get_color(self) -> vec4 {
+                    return #8;
+                }
+            }
+            icon_walk: {width: 8, height: 8}
+        }
+    }
+
+    SearchResult = <RoundedView> {
+        width: Fill, height: Fit
+        flow: Down
+        padding: 8
+        spacing: 4
+        cursor: Hand
+        show_bg: true
+        draw_bg: {
+            color: #0000
+            border_radius: 5
+        }
+
+        title = <Label> {
+            width: Fill
+            draw_text: {
+                text_style: <BOLD_FONT>{font_size: 9},
+                color: #101828
+            }
+        }
+
+        snippet = <Html> {
+            width: Fill, height: Fit
+            font_size: 9
+            font_color: #475467
+        }
+    }
+
     pub ChatHistoryPanel = {{ChatHistoryPanel}} <MolyTogglePanel> {
         // Workaround: Instantiate a view replacing the whole `open_content` content,
         // because `CachedView` is currently rendering up-side-down on web.
         open_content = <View> {
-            <ChatHistory> {
+            <View> {
+                width: Fill, height: Fill
+                flow: Down
                 margin: {top: 80}
+
+                search = <SearchInput> {}
+
+                chat_history = <ChatHistory> {}
+
+                search_results = <View> {
+                    width: Fill, height: Fill
+                    visible: false
+                    flow: Down
+                    padding: { left: 10, right: 10 }
+
+                    no_results = <NoAgentsWarning> {
+                        visible: false
+                        text: "No messages found"
+                    }
+
+                    list = <PortalList> {
+                        drag_scrolling: false
+                        SearchResult = <SearchResult> {}
+                    }
+                }
             }
             right_border = <View> {
                 width: 1.6, height: Fill
@@ -51,6 +151,17 @@ live_design! {
             margin: { left: -10 },
             default = {
                 after = {
+                    import_chats_button = <MolyButton> {
+                        width: Fit,
+                        height: Fit,
+                        icon_walk: {margin: { top: -1 }, width: 18, height: 18},
+                        draw_icon: {
+                            svg_file: (ICON_IMPORT),
+                            fn get_color(self) -> vec4 {
+                                return #475467;
+                            }
+                        }
+                    }
                     new_chat_button = <MolyButton> {
                         width: Fit,
                         height: Fit,
@@ -72,15 +183,58 @@ live_design! {
 pub struct ChatHistoryPanel {
     #[deref]
     deref: TogglePanel,
+
+    #[rust]
+    search_results: Vec<ChatSearchResult>,
 }
 
 impl Widget for ChatHistoryPanel {
     fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
         self.deref.handle_event(cx, event, scope);
+
+        if let Event::Actions(actions) = event {
+            if self.button(ids!(import_chats_button)).clicked(actions) {
+                Chats::import_chats_from_files();
+            }
+
+            self.handle_search(cx, actions, scope);
+        }
     }
 
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
-        self.deref.draw_walk(cx, scope, walk)
+        let store = scope.data.get::<Store>().unwrap();
+
+        // Results of deleted chats are skipped.
+        let results = self
+            .search_results
+            .iter()
+            .filter_map(|r| {
+                let chat = store.chats.get_chat_by_id(r.chat_id)?;
+                Some((chat.borrow().get_title().to_string(), r))
+            })
+            .collect::<Vec<_>>();
+
+        self.view(ids!(search_results.no_results))
+            .set_visible(cx, results.is_empty());
+
+        while let Some(view_item) = self.deref.draw_walk(cx, scope, walk).step() {
+            if let Some(mut list) = view_item.as_portal_list().borrow_mut() {
+                list.set_item_range(cx, 0, results.len());
+                while let Some(item_id) = list.next_visible_item(cx) {
+                    let Some((title, result)) = results.get(item_id) else {
+                        continue;
+                    };
+
+                    let item = list.item(cx, item_id, live_id!(SearchResult));
+                    item.label(ids!(title)).set_text(cx, title);
+                    item.html(ids!(snippet))
+                        .set_text(cx, &highlighted_html(&result.snippet, &result.highlights));
+                    item.draw_all(cx, scope);
+                }
+            }
+        }
+
+        DrawStep::done()
     }
 }
 
@@ -92,3 +246,81 @@ impl WidgetMatchEvent for ChatHistoryPanel {
         }
     }
 }
+
+impl ChatHistoryPanel {
+    fn handle_search(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
+        let input = self.text_input(ids!(search.input));
+        let clear_button = self.button(ids!(search.clear_search_button));
+
+        if clear_button.clicked(actions) {
+            input.set_text(cx, "");
+            input.set_key_focus(cx);
+            self.show_search_results(cx, scope, "");
+        }
+
+        if let Some(query) = input.changed(actions) {
+            self.show_search_results(cx, scope, &query);
+        }
+
+        let clicked = self
+            .portal_list(ids!(search_results.list))
+            .items_with_actions(actions)
+            .into_iter()
+            .find(|(_, item)| item.as_view().finger_down(actions).is_some());
+
+        if let Some((item_id, _)) = clicked {
+            let Some(result) = self.search_results.get(item_id) else {
+                return;
+            };
+
+            let store = scope.data.get_mut::<Store>().unwrap();
+            store.chats.set_current_chat(Some(result.chat_id));
+
+            cx.action(ChatAction::ChatSelected(result.chat_id));
+            cx.action(ChatAction::MessageSelected(
+                result.chat_id,
+                result.message_index,
+            ));
+        }
+    }
+
+    /// Replaces the chat history with the messages matching the query, or goes
+    /// back to it if the query is empty.
+    fn show_search_results(&mut self, cx: &mut Cx, scope: &mut Scope, query: &str) {
+        let searching = !query.trim().is_empty();
+
+        self.search_results = if searching {
+            let store = scope.data.get::<Store>().unwrap();
+            store.chats.search(query)
+        } else {
+            vec![]
+        };
+
+        self.button(ids!(search.clear_search_button))
+            .set_visible(cx, searching);
+        self.view(ids!(chat_history)).set_visible(cx, !searching);
+        self.view(ids!(search_results)).set_visible(cx, searching);
+        self.redraw(cx);
+    }
+}
+
+/// Renders the snippet as HTML with the matched ranges in bold.
+fn highlighted_html(snippet: &str, highlights: &[std::ops::Range<usize>]) -> String {
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+
+    let mut html = String::new();
+    let mut last = 0;
+    for range in highlights {
+        html.push_str(&escape(&snippet[last..range.start]));
+        html.push_str("<b>");
+        html.push_str(&escape(&snippet[range.clone()]));
+        html.push_str("</b>");
+        last = range.end;
+    }
+    html.push_str(&escape(&snippet[last..]));
+    html
+}
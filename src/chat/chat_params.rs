@@ -1,7 +1,12 @@
 use makepad_widgets::*;
 
 use crate::{
-    data::{chats::chat::ChatID, store::Store},
+    data::{
+        chats::chat::{ChatID, InferenceParam},
+        prompt_templates::{PromptTemplate, parse_variable_values},
+        store::Store,
+    },
+    shared::actions::ChatAction,
     shared::tooltip::TooltipWidgetExt,
 };
 
@@ -33,6 +38,34 @@ live_design! {
         }
     }
 
+    ChatParamsTextInput = <MolyTextInput> {
+        width: Fill,
+        height: Fit,
+        draw_bg: {
+            border_radius: 0
+            color: #0000
+            border_size: 0
+        }
+        draw_text: {
+            text_style: <REGULAR_FONT>{font_size: 10},
+        }
+    }
+
+    ChatParamsButton = <MolyButton> {
+        width: Fit, height: 30
+        padding: {left: 10, right: 10}
+        draw_bg: {
+            color: #fff
+            border_size: 1.0
+            border_color_1: #D9D9D9
+            border_radius: 5.0
+        }
+        draw_text: {
+            text_style: <REGULAR_FONT>{font_size: 9},
+            color: #344054
+        }
+    }
+
     pub ChatParams = {{ChatParams}} <MolyTogglePanel> {
         width: 110,
         open_content = {
@@ -88,6 +121,75 @@ live_design! {
                             }
                         }
                     }
+
+                    <View> {
+                        width: Fill, height: Fit
+                        spacing: 8
+                        align: {y: 0.5}
+
+                        <ChatParamsTextInputWrapper> {
+                            height: 30,
+                            scrolled_content = {
+                                template_name = <ChatParamsTextInput> {
+                                    empty_text: "Template name"
+                                }
+                            }
+                        }
+
+                        save_template = <ChatParamsButton> { text: "Save as template" }
+                    }
+
+                    template_hint = <Label> {
+                        width: Fill
+                        draw_text: {
+                            text_style: <REGULAR_FONT>{font_size: 9},
+                            color: #667085
+                            wrap: Word
+                        }
+                    }
+                }
+
+                templates = <View> {
+                    visible: false
+                    flow: Down
+                    height: Fit
+                    width: Fill
+                    spacing: 12
+                    padding: {left: 4}
+
+                    templates_label = <Label> {
+                        draw_text: {
+                            text_style: <BOLD_FONT>{font_size: 10},
+                            color: #000
+                        }
+                        text: "Prompt Templates"
+                        hover_actions_enabled: true
+                    }
+
+                    template_selector = <DropDownFlat> {
+                        width: Fill
+                        draw_text: {
+                            text_style: <REGULAR_FONT>{font_size: 10},
+                        }
+                    }
+
+                    template_values_wrapper = <ChatParamsTextInputWrapper> {
+                        visible: false
+                        height: 65,
+                        scrolled_content = {
+                            template_values = <ChatParamsTextInput> {
+                                empty_text: "variable: value"
+                            }
+                        }
+                    }
+
+                    <View> {
+                        width: Fill, height: Fit
+                        spacing: 8
+
+                        start_from_template = <ChatParamsButton> { text: "New chat" }
+                        delete_template = <ChatParamsButton> { text: "Delete" }
+                    }
                 }
 
                 <Label> {
@@ -147,6 +249,13 @@ live_design! {
                         step: 1.0
                     }
 
+                    thinking_budget = <MolySlider> {
+                        text: "Thinking Budget"
+                        min: 0.0
+                        max: 32000.0
+                        step: 1.0
+                    }
+
                     <View> {
                         flow: Down
                         height: Fit
@@ -227,6 +336,9 @@ live_design! {
     }
 }
 
+/// Seconds without changes to the params before saving them.
+const SAVE_DELAY: f64 = 0.5;
+
 const TOOLTIP_OFFSET: DVec2 = DVec2 {
     x: -320.0,
     y: -30.0,
@@ -243,12 +355,29 @@ pub struct ChatParams {
 
     #[rust]
     current_chat_id: Option<ChatID>,
+
+    /// Name of the template chosen in the selector.
+    #[rust]
+    selected_template: Option<String>,
+
+    /// Chat whose params changed, saved once they stop changing (e.g. when
+    /// a slider is released).
+    #[rust]
+    pending_save: Option<ChatID>,
+
+    #[rust]
+    save_timer: Timer,
 }
 
 impl Widget for ChatParams {
     fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
         self.deref.handle_event(cx, event, scope);
         self.widget_match_event(cx, event, scope);
+
+        if self.save_timer.is_event(event).is_some() {
+            self.save_timer = Timer::default();
+            self.save_pending_params(scope.data.get::<Store>().unwrap());
+        }
     }
 
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
@@ -263,6 +392,7 @@ impl Widget for ChatParams {
             let temperature = self.slider(ids!(temperature));
             let top_p = self.slider(ids!(top_p));
             let max_tokens = self.slider(ids!(max_tokens));
+            let thinking_budget = self.slider(ids!(thinking_budget));
             let frequency_penalty = self.slider(ids!(frequency_penalty));
             let presence_penalty = self.slider(ids!(presence_penalty));
             let stop = self.text_input(ids!(stop));
@@ -273,6 +403,7 @@ impl Widget for ChatParams {
             temperature.set_value(cx, ip.temperature.into());
             top_p.set_value(cx, ip.top_p.into());
             max_tokens.set_value(cx, ip.max_tokens.into());
+            thinking_budget.set_value(cx, ip.thinking_budget.into());
             frequency_penalty.set_value(cx, ip.frequency_penalty.into());
             presence_penalty.set_value(cx, ip.presence_penalty.into());
             stop.set_text(cx, &ip.stop);
@@ -280,6 +411,35 @@ impl Widget for ChatParams {
             let system_prompt_value = chat.system_prompt.clone().unwrap_or_default();
             system_prompt.set_text(cx, &system_prompt_value);
 
+            let template_name = self.text_input(ids!(template_name)).text();
+            let hint = match (
+                system_prompt_value.is_empty(),
+                template_name.trim().is_empty(),
+            ) {
+                (true, _) => "Write a system prompt to save it as a template.",
+                (false, true) => "Name the template to save it.",
+                (false, false) => "",
+            };
+            self.button(ids!(save_template))
+                .set_enabled(cx, hint.is_empty());
+            let hint_label = self.label(ids!(template_hint));
+            hint_label.set_visible(cx, !hint.is_empty());
+            hint_label.set_text(cx, hint);
+
+            let templates = &store.preferences.prompt_templates;
+            self.view(ids!(templates))
+                .set_visible(cx, !templates.is_empty());
+
+            let names = templates.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+            let selected = self
+                .selected_template
+                .as_ref()
+                .and_then(|name| names.iter().position(|n| n == name))
+                .unwrap_or(0);
+            let selector = self.drop_down(ids!(template_selector));
+            selector.set_labels(cx, names);
+            selector.set_selected_item(cx, selected);
+
             // Currently, `active` and `set_active` interact with the animator of
             // the widget to do what they do. To avoid some visual issues, we should not
             // trigger the animator unnecessarily. This is a workaround.
@@ -300,6 +460,15 @@ impl WidgetMatchEvent for ChatParams {
 
         let store = scope.data.get_mut::<Store>().unwrap();
 
+        self.handle_template_actions(cx, actions, store);
+
+        // Keeps the save button and its hint in sync with what was typed.
+        let name_changed = self.text_input(ids!(template_name)).changed(actions);
+        let prompt_changed = self.text_input(ids!(system_prompt)).changed(actions);
+        if name_changed.is_some() || prompt_changed.is_some() {
+            self.redraw(cx);
+        }
+
         if let Some(chat) = store.chats.get_current_chat() {
             let mut chat = chat.borrow_mut();
 
@@ -308,26 +477,37 @@ impl WidgetMatchEvent for ChatParams {
                 self.redraw(cx);
             }
 
+            // Changes to another chat are not waited for.
+            if self.pending_save.is_some_and(|id| id != chat.id) {
+                cx.stop_timer(self.save_timer);
+                self.save_pending_params(store);
+            }
+
+            let previous_params = chat.inferences_params.clone();
             let ip = &mut chat.inferences_params;
 
             if let Some(value) = self.slider(ids!(temperature)).slided(&actions) {
-                ip.temperature = value as f32;
+                ip.set(InferenceParam::Temperature, value);
             }
 
             if let Some(value) = self.slider(ids!(top_p)).slided(&actions) {
-                ip.top_p = value as f32;
+                ip.set(InferenceParam::TopP, value);
             }
 
             if let Some(value) = self.slider(ids!(max_tokens)).slided(&actions) {
-                ip.max_tokens = value as u32;
+                ip.set(InferenceParam::MaxTokens, value);
+            }
+
+            if let Some(value) = self.slider(ids!(thinking_budget)).slided(&actions) {
+                ip.thinking_budget = value as u32;
             }
 
             if let Some(value) = self.slider(ids!(frequency_penalty)).slided(&actions) {
-                ip.frequency_penalty = value as f32;
+                ip.set(InferenceParam::FrequencyPenalty, value);
             }
 
             if let Some(value) = self.slider(ids!(presence_penalty)).slided(&actions) {
-                ip.presence_penalty = value as f32;
+                ip.set(InferenceParam::PresencePenalty, value);
             }
 
             if let Some(value) = self.text_input(ids!(stop)).changed(&actions) {
@@ -338,6 +518,12 @@ impl WidgetMatchEvent for ChatParams {
                 ip.stream = value;
             }
 
+            if chat.inferences_params != previous_params {
+                self.pending_save = Some(chat.id);
+                cx.stop_timer(self.save_timer);
+                self.save_timer = cx.start_timeout(SAVE_DELAY);
+            }
+
             if let Some(value) = self.text_input(ids!(system_prompt)).changed(&actions) {
                 if value.is_empty() {
                     chat.system_prompt = None;
@@ -350,6 +536,104 @@ impl WidgetMatchEvent for ChatParams {
 }
 
 impl ChatParams {
+    fn save_pending_params(&mut self, store: &Store) {
+        let Some(chat_id) = self.pending_save.take() else {
+            return;
+        };
+
+        if let Some(chat) = store.chats.get_chat_by_id(chat_id) {
+            chat.borrow().save_and_forget();
+        }
+    }
+
+    fn handle_template_actions(&mut self, cx: &mut Cx, actions: &Actions, store: &mut Store) {
+        if self.button(ids!(save_template)).clicked(actions) {
+            let name_input = self.text_input(ids!(template_name));
+            let name = name_input.text().trim().to_string();
+            let template = store.chats.get_current_chat().and_then(|chat| {
+                let chat = chat.borrow();
+                let prompt = chat.system_prompt.clone().filter(|p| !p.is_empty())?;
+                Some(PromptTemplate {
+                    name: name.clone(),
+                    prompt,
+                    default_bot: chat.associated_bot.clone(),
+                    inference_params: Some(chat.inferences_params.clone()),
+                })
+            });
+
+            if let Some(template) = template.filter(|_| !name.is_empty()) {
+                store.preferences.save_prompt_template(template);
+                self.selected_template = Some(name);
+                name_input.set_text(cx, "");
+                self.redraw(cx);
+            }
+        }
+
+        // The first template is shown selected until another one is chosen.
+        if self.selected_template.is_none() {
+            if let Some(first) = store.preferences.prompt_templates.first() {
+                self.selected_template = Some(first.name.clone());
+                self.prefill_template_values(cx, store);
+            }
+        }
+
+        if let Some(index) = self.drop_down(ids!(template_selector)).selected(actions) {
+            self.selected_template = store
+                .preferences
+                .prompt_templates
+                .get(index)
+                .map(|t| t.name.clone());
+            self.prefill_template_values(cx, store);
+        }
+
+        let selected = self
+            .selected_template
+            .as_ref()
+            .and_then(|name| store.preferences.get_prompt_template(name))
+            .or_else(|| store.preferences.prompt_templates.first())
+            .cloned();
+
+        let Some(template) = selected else {
+            return;
+        };
+
+        if self.button(ids!(start_from_template)).clicked(actions) {
+            let values = parse_variable_values(&self.text_input(ids!(template_values)).text());
+            let chat_id = store.chats.create_chat_from_template(&template, &values);
+            cx.action(ChatAction::ChatSelected(chat_id));
+            self.redraw(cx);
+        }
+
+        if self.button(ids!(delete_template)).clicked(actions) {
+            store.preferences.remove_prompt_template(&template.name);
+            self.selected_template = None;
+            self.prefill_template_values(cx, store);
+            self.redraw(cx);
+        }
+    }
+
+    /// Shows a `name: ` line to fill for each variable of the selected template.
+    fn prefill_template_values(&mut self, cx: &mut Cx, store: &Store) {
+        let variables = self
+            .selected_template
+            .as_ref()
+            .and_then(|name| store.preferences.get_prompt_template(name))
+            .or_else(|| store.preferences.prompt_templates.first())
+            .map(|t| t.variables())
+            .unwrap_or_default();
+
+        let text = variables
+            .iter()
+            .map(|v| format!("{}: ", v))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        self.text_input(ids!(template_values)).set_text(cx, &text);
+        self.view(ids!(template_values_wrapper))
+            .set_visible(cx, !variables.is_empty());
+        self.redraw(cx);
+    }
+
     fn handle_tooltip_actions(&mut self, cx: &mut Cx, actions: &Actions) {
         if !self.is_open(cx) {
             return;
@@ -376,6 +660,13 @@ impl ChatParams {
             cx, actions
         );
 
+        self.handle_tooltip_actions_for_label(
+            ids!(templates_label),
+            "Saved system prompts to start new chats from. Placeholders like {{topic}} in the prompt are filled with the values given below, one `name: value` per line.".to_string(),
+            TOOLTIP_OFFSET,
+            cx, actions
+        );
+
         self.handle_tooltip_actions_for_label(
             ids!(stream_label),
             "Streaming is the sending of words as they are created by the AI language model one at a time, so you can show them as they are being generated.".to_string(),
@@ -390,6 +681,13 @@ impl ChatParams {
             cx, actions
         );
 
+        self.handle_tooltip_actions_for_slider(
+            ids!(thinking_budget),
+            "The thinking budget sets how many tokens models with extended thinking, like Claude, may spend reasoning before they answer. It is added to the max tokens. Zero disables extended thinking, and sampling parameters like temperature are not sent while it is enabled.".to_string(),
+            TOOLTIP_OFFSET,
+            cx, actions
+        );
+
         self.handle_tooltip_actions_for_label(
             ids!(stop_label),
             "Stop sequences are used to make the model stop generating tokens at a desired point, such as the end of a sentence or a list. The model response will not contain the stop sequence and you can pass up to four stop sequences.".to_string(),
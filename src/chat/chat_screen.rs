@@ -10,6 +10,10 @@ use crate::settings::providers::ConnectionSettingsAction;
 use crate::shared::actions::ChatAction;
 use crate::shared::bot_context::BotContext;
 
+/// MolyServer keeps generating after the connection is closed, so it's asked
+/// explicitly to stop through this endpoint (relative to the provider url).
+const MOLY_SERVER_STOP_ENDPOINT: &str = "chat/completions/stop";
+
 live_design! {
     use link::theme::*;
     use link::shaders::*;
@@ -153,8 +157,11 @@ impl ChatScreen {
                                 let _ = client.set_key(&key);
                             }
                             client.set_tools_enabled(provider.tools_enabled);
+                            if provider.provider_type == ProviderType::MolyServer {
+                                client.set_stop_endpoint(Some(MOLY_SERVER_STOP_ENDPOINT.into()));
+                            }
 
-                            let mut client = MapClient::from(client);
+                            let mut client = MapClient::from(RetryClient::from(client));
                             if let Some(icon) = store.get_provider_icon(&provider.name) {
                                 client.set_map_bots(move |mut bots| {
                                     for bot in bots.iter_mut() {
@@ -193,6 +200,30 @@ impl ChatScreen {
                             multi_client.add_client(Box::new(client));
                         }
                     }
+                    ProviderType::Anthropic => {
+                        if provider.enabled && provider.api_key.is_some() {
+                            let mut client = AnthropicClient::new(provider.url.clone());
+                            if let Some(key) = provider.api_key.as_ref() {
+                                let _ = client.set_key(&key);
+                            }
+                            client.set_tools_enabled(provider.tools_enabled);
+                            if provider.provider_type == ProviderType::MolyServer {
+                                client.set_stop_endpoint(Some(MOLY_SERVER_STOP_ENDPOINT.into()));
+                            }
+
+                            let mut client = MapClient::from(RetryClient::from(client));
+                            if let Some(icon) = store.get_provider_icon(&provider.name) {
+                                client.set_map_bots(move |mut bots| {
+                                    for bot in bots.iter_mut() {
+                                        bot.avatar = Picture::Dependency(icon.clone());
+                                    }
+                                    bots
+                                });
+                            }
+
+                            multi_client.add_client(Box::new(client));
+                        }
+                    }
                     ProviderType::MoFa => {
                         // For MoFa we don't require an API key
                         if provider.enabled {
@@ -236,6 +267,10 @@ impl ChatScreen {
                 }
             }
 
+            for bot_id in store.preferences.bot_equivalence_groups.keys() {
+                multi_client.set_fallbacks(bot_id.clone(), store.get_bot_fallbacks(bot_id));
+            }
+
             multi_client
         };
 
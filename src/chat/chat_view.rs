@@ -1,7 +1,7 @@
 use makepad_widgets::*;
 use moly_kit::controllers::chat::{
     ChatController, ChatControllerPlugin, ChatControllerPluginRegistrationId, ChatState,
-    ChatStateMutation,
+    ChatStateMutation, ToolExecutionRecord,
 };
 use moly_kit::utils::asynchronous::spawn;
 use moly_kit::utils::vec::{VecEffect, VecMutation};
@@ -10,8 +10,9 @@ use moly_kit::*;
 use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
-use crate::data::chats::chat::ChatID;
-use crate::data::store::{ProviderSyncingStatus, Store};
+use crate::data::chats::chat::{ChatID, ChatInferenceParams};
+use crate::data::store::{McpAction, ProviderSyncingStatus, Store};
+use crate::data::tool_audit::ToolAuditEntry;
 use crate::shared::bot_context::BotContext;
 use crate::shared::utils::attachments::{
     delete_attachment, generate_persistence_key, set_persistence_key_and_reader,
@@ -149,6 +150,10 @@ pub struct ChatView {
 
     #[rust]
     message_updated_while_inactive: bool,
+
+    /// Params the send options of the chat widget were built from.
+    #[rust]
+    inference_params: Option<ChatInferenceParams>,
 }
 
 impl LiveHook for ChatView {
@@ -193,6 +198,7 @@ impl Widget for ChatView {
         self.view.handle_event(cx, event, scope);
 
         self.handle_current_bot(cx, scope);
+        self.handle_send_options(scope);
         self.handle_unread_messages(scope);
     }
 
@@ -307,6 +313,24 @@ impl ChatView {
         }
     }
 
+    /// Keeps the chat widget sending with the inference params of this chat.
+    fn handle_send_options(&mut self, scope: &mut Scope) {
+        let store = scope.data.get_mut::<Store>().unwrap();
+        let Some(chat) = store.chats.get_chat_by_id(self.chat_id) else {
+            return;
+        };
+
+        let chat = chat.borrow();
+        if self.inference_params.as_ref() == Some(&chat.inferences_params) {
+            return;
+        }
+
+        self.chat(ids!(chat))
+            .write()
+            .set_send_options(chat.inferences_params.to_send_options());
+        self.inference_params = Some(chat.inferences_params.clone());
+    }
+
     fn handle_unread_messages(&mut self, scope: &mut Scope) {
         let store = scope.data.get_mut::<Store>().unwrap();
         if self.message_updated_while_inactive {
@@ -350,6 +374,7 @@ impl ChatViewRef {
     pub fn set_chat_id(&mut self, chat_id: ChatID) {
         if let Some(mut inner) = self.borrow_mut() {
             inner.chat_id = chat_id;
+            inner.inference_params = None;
             inner
                 .model_selector(ids!(model_selector))
                 .set_chat_id(chat_id);
@@ -381,6 +406,18 @@ impl ChatControllerPlugin for Glue {
     fn on_state_ready(&mut self, state: &ChatState, _mutatins: &[ChatStateMutation]) {
         self.sweep_attachments(state);
     }
+
+    fn on_tool_executed(&mut self, record: &ToolExecutionRecord) {
+        let record = record.clone();
+
+        self.ui.defer(move |chat_view, cx, scope| {
+            let store = scope.data.get_mut::<Store>().unwrap();
+            store
+                .tool_audit
+                .append(ToolAuditEntry::from_record(chat_view.chat_id, &record));
+            cx.action(McpAction::ToolCallAudited);
+        });
+    }
 }
 
 impl Glue {
@@ -420,6 +457,7 @@ impl Glue {
 
             // Write to disk.
             store_chat.borrow_mut().save_and_forget();
+            store.chats.mark_messages_changed();
 
             // Keep track of whether the message was updated while the chat view was inactive
             if !chat_view.focused {
@@ -439,7 +477,7 @@ impl Glue {
                     // verify an actual insert happened.
 
                     for message in messages {
-                        for attachment in &message.content.attachments {
+                        for attachment in message.content.all_attachments() {
                             if !attachment.has_persistence_key()
                                 && !self
                                     .persisting_attachments
@@ -454,7 +492,7 @@ impl Glue {
                 }
                 VecEffect::Remove(_start, _end, removed) => {
                     for messages in removed {
-                        for attachment in &messages.content.attachments {
+                        for attachment in messages.content.all_attachments() {
                             if attachment.has_persistence_key() {
                                 self.marked_attachments.insert(attachment.clone());
                             }
@@ -475,7 +513,7 @@ impl Glue {
         }
 
         for message in &state.messages {
-            for attachment in &message.content.attachments {
+            for attachment in message.content.all_attachments() {
                 self.marked_attachments.remove(attachment);
             }
         }
@@ -549,11 +587,11 @@ impl Glue {
                     let mut mutations: Vec<ChatStateMutation> = Vec::new();
 
                     for (index, message) in lock.state().messages.iter().enumerate() {
-                        if message.content.attachments.iter().any(|att| att == &attachment) {
+                        if message.content.all_attachments().any(|att| att == &attachment) {
                             found = true;
                             let mut updated_message = message.clone();
 
-                            for att in &mut updated_message.content.attachments {
+                            for att in updated_message.content.all_attachments_mut() {
                                 if att == &attachment {
                                     set_persistence_key_and_reader(att, key.clone());
                                 }
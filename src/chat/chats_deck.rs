@@ -133,6 +133,16 @@ impl WidgetMatchEvent for ChatsDeck {
                         self.create_or_update_chat_view(cx, &chat.borrow());
                     }
                 }
+                ChatAction::MessageSelected(chat_id, index) => {
+                    if let Some(chat_view) = self.chat_view_refs.get_mut(&chat_id) {
+                        chat_view
+                            .chat(ids!(chat))
+                            .read()
+                            .messages_ref()
+                            .write()
+                            .instant_scroll_to(cx, index);
+                    }
+                }
                 _ => {}
             }
 
@@ -74,6 +74,26 @@ pub fn fetch_models_for_provider(provider: &Provider) {
                 None,
             );
         }
+        ProviderType::Anthropic => {
+            fetch_models_with_client(
+                provider_id.clone(),
+                move || {
+                    let mut client = moly_kit::clients::AnthropicClient::new(url);
+                    if let Some(key) = api_key {
+                        let _ = client.set_key(&key);
+                    }
+                    Box::new(client)
+                },
+                move |bot| ProviderBot {
+                    id: bot.id.clone(),
+                    name: bot.name.clone(),
+                    description: format!("Model from {}", provider_id),
+                    provider_id: provider_id.clone(),
+                    enabled: true,
+                },
+                None,
+            );
+        }
         ProviderType::DeepInquire => {
             fetch_models_with_client(
                 provider_id.clone(),
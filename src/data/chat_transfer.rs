@@ -0,0 +1,269 @@
+//! Opt-in transfer of the saved chats, and their attachments, to another device
+//! through `moly-sync`.
+//!
+//! Received files are written at the same paths they have in the sending
+//! device, as long as they are chat files or attachments which don't exist
+//! yet. The files completed so far are recorded, so an interrupted transfer
+//! continues where it stopped the next time it's started.
+
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::channel::oneshot;
+use moly_kit::utils::asynchronous::sleep;
+use moly_sync::{
+    ChatTransfer, ReceivedFile, SyncSession, TransferChat, TransferCheckpoint, TransferManifest,
+    TransferProgress,
+};
+use serde::Deserialize;
+
+use super::chats::Chats;
+use super::chats::chat::{Chat, ChatID};
+use crate::app::app_runner;
+use crate::shared::utils::filesystem;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{Arc, Mutex};
+
+#[cfg(not(target_arch = "wasm32"))]
+use moly_kit::utils::asynchronous::BoxPlatformSendFuture;
+#[cfg(not(target_arch = "wasm32"))]
+use moly_sync::{FileChunk, FileSource};
+
+const CHECKPOINT_PATH: &str = "sync/chat_transfer.json";
+
+/// Consecutive failures to download a file before giving up. The next
+/// transfer resumes from the last file completed.
+const MAX_ATTEMPTS: usize = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// The saved chats with messages, offered to the other device.
+pub fn transfer_manifest(chats: &Chats) -> TransferManifest {
+    let chats = chats
+        .saved_chats
+        .iter()
+        .map(|chat| chat.borrow())
+        .filter(|chat| !chat.messages.is_empty())
+        .map(|chat| TransferChat {
+            id: chat.id.to_string(),
+            path: chat.file_path().to_string_lossy().replace('\\', "/"),
+            attachments: chat.attachment_paths(),
+        })
+        .collect();
+
+    TransferManifest { chats }
+}
+
+/// Serves the files of the shared chats from the app data.
+///
+/// Chunks of the same file are requested one after the other, so the last file
+/// read is kept around instead of reading it again for every chunk.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+pub struct ChatFileSource {
+    last_read: Arc<Mutex<Option<(String, Arc<Vec<u8>>)>>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileSource for ChatFileSource {
+    fn read_chunk(
+        &self,
+        path: &str,
+        offset: u64,
+        len: usize,
+    ) -> BoxPlatformSendFuture<'static, Result<FileChunk>> {
+        let path = path.to_string();
+        let last_read = self.last_read.clone();
+
+        Box::pin(async move {
+            let cached = last_read
+                .lock()
+                .unwrap()
+                .as_ref()
+                .filter(|(last_path, _)| *last_path == path)
+                .map(|(_, content)| content.clone());
+
+            let content = match cached {
+                Some(content) => content,
+                None => {
+                    let content = Arc::new(filesystem::global().read(Path::new(&path)).await?);
+                    *last_read.lock().unwrap() = Some((path, content.clone()));
+                    content
+                }
+            };
+
+            let start = (offset as usize).min(content.len());
+            let end = start.saturating_add(len).min(content.len());
+
+            Ok(FileChunk {
+                content: content[start..end].to_vec(),
+                file_size: content.len() as u64,
+            })
+        })
+    }
+}
+
+/// Downloads the chats shared in the session, leaving out the ones with an id
+/// already known, and adds them to the store as they arrive.
+///
+/// Returns the amount of chats received.
+pub async fn receive_chats(
+    session: SyncSession,
+    known_chat_ids: Vec<String>,
+    mut on_progress: impl FnMut(TransferProgress),
+) -> Result<usize> {
+    let checkpoint = filesystem::global()
+        .read_json::<TransferCheckpoint>(Path::new(CHECKPOINT_PATH))
+        .await
+        .unwrap_or_default();
+
+    let mut transfer = ChatTransfer::start(session, &known_chat_ids, checkpoint).await?;
+    on_progress(transfer.progress());
+
+    let mut received = 0;
+    let mut failures = 0;
+
+    loop {
+        let file = match transfer.next_file(&mut on_progress).await {
+            Ok(Some(file)) => file,
+            Ok(None) => break,
+            Err(e) => {
+                failures += 1;
+                if failures >= MAX_ATTEMPTS {
+                    return Err(e);
+                }
+
+                log::warn!("Chat transfer interrupted, retrying: {}", e);
+                sleep(RETRY_DELAY).await;
+                continue;
+            }
+        };
+        failures = 0;
+
+        let path = PathBuf::from(&file.path);
+        let writable = can_write_received_file(&file).await;
+        if writable {
+            filesystem::global()
+                .queue_write(path.clone(), file.content)
+                .await?;
+        } else {
+            log::warn!("Ignoring transferred file {}", file.path);
+        }
+
+        if writable && file.chat_id.is_some() {
+            match Chat::load(&path).await {
+                Ok(chat) => {
+                    received += 1;
+                    app_runner().defer(move |app, cx, _| {
+                        if let Some(store) = app.store.as_mut() {
+                            store.chats.add_transferred_chat(chat);
+                            app.ui.redraw(cx);
+                        }
+                    });
+                }
+                Err(e) => log::error!("Failed to load transferred chat {:?}: {}", path, e),
+            }
+        }
+
+        transfer.complete(&file.path);
+        on_progress(transfer.progress());
+        filesystem::global()
+            .queue_write_json(PathBuf::from(CHECKPOINT_PATH), transfer.checkpoint())
+            .await?;
+    }
+
+    if let Err(e) = filesystem::global()
+        .remove(Path::new(CHECKPOINT_PATH))
+        .await
+    {
+        log::debug!("No chat transfer checkpoint to remove: {}", e);
+    }
+
+    Ok(received)
+}
+
+/// Only the content needed to check a received chat file.
+#[derive(Deserialize)]
+struct ReceivedChatId {
+    id: ChatID,
+}
+
+/// If the received file can be written: a new chat, named after its id, or
+/// an attachment which doesn't exist yet.
+async fn can_write_received_file(file: &ReceivedFile) -> bool {
+    if !is_expected_path(&file.path, file.chat_id.as_deref()) {
+        return false;
+    }
+
+    match &file.chat_id {
+        Some(chat_id) => {
+            let Ok(chat_id) = chat_id.parse::<ChatID>() else {
+                return false;
+            };
+            let content_id = serde_json::from_slice::<ReceivedChatId>(&file.content)
+                .map(|chat| chat.id)
+                .ok();
+
+            content_id == Some(chat_id) && !chat_exists(chat_id).await
+        }
+        None => !filesystem::global()
+            .exists(Path::new(&file.path))
+            .await
+            .unwrap_or(true),
+    }
+}
+
+/// If the path is `chats/<chat_id>.chat.json` for chats, or
+/// `attachments/<file>` otherwise.
+fn is_expected_path(path: &str, chat_id: Option<&str>) -> bool {
+    let mut components = Path::new(path).components();
+    let (Some(Component::Normal(dir)), Some(Component::Normal(name)), None) =
+        (components.next(), components.next(), components.next())
+    else {
+        return false;
+    };
+
+    match chat_id {
+        Some(chat_id) => {
+            chat_id.parse::<ChatID>().is_ok()
+                && dir == "chats"
+                && name.to_str() == Some(format!("{}.chat.json", chat_id).as_str())
+        }
+        None => dir == "attachments",
+    }
+}
+
+/// If the store has a chat with the id, which may have been added after the
+/// transfer started.
+async fn chat_exists(chat_id: ChatID) -> bool {
+    let (tx, rx) = oneshot::channel();
+    app_runner().defer(move |app, _, _| {
+        let exists = app
+            .store
+            .as_ref()
+            .is_some_and(|store| store.chats.get_chat_by_id(chat_id).is_some());
+        let _ = tx.send(exists);
+    });
+    // Assumes it exists if the app is gone, so nothing is written.
+    rx.await.unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_chats_and_attachments_are_written() {
+        assert!(is_expected_path("chats/42.chat.json", Some("42")));
+        assert!(is_expected_path("attachments/0190.png", None));
+
+        assert!(!is_expected_path("chats/42.chat.json", Some("43")));
+        assert!(!is_expected_path("chats/42.chat.json", None));
+        assert!(!is_expected_path("chats/../42.chat.json", Some("42")));
+        assert!(!is_expected_path("chats/x.chat.json", Some("x")));
+        assert!(!is_expected_path("preferences/preferences.json", None));
+        assert!(!is_expected_path("attachments/nested/file.png", None));
+        assert!(!is_expected_path("attachments", None));
+    }
+}
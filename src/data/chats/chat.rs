@@ -1,11 +1,16 @@
+use super::search;
 use crate::shared::utils::{
     attachments::{delete_attachment, persistence_reader},
     filesystem,
 };
 use anyhow::{Result, anyhow};
-use moly_kit::{BotId, Message, utils::asynchronous::spawn};
+use moly_kit::{
+    BotId, GenerationParams, Message, SendOptions, ToolResultPart, utils::asynchronous::spawn,
+};
 use moly_protocol::data::FileID;
 use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
 
 pub type ChatID = u128;
@@ -28,12 +33,128 @@ struct ChatData {
     title_state: TitleState,
     #[serde(default)]
     accessed_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    inferences_params: ChatInferenceParams,
 
     // Legacy field, it can be removed in the future.
     last_used_file_id: Option<FileID>,
 }
 
-#[derive(Debug, Clone)]
+/// The part of a chat kept in sync with paired devices, leaving out local
+/// state like when it was last accessed.
+///
+/// Messages are synced on their own, see [`SyncedMessage`].
+#[derive(Serialize, Deserialize)]
+pub struct SyncedChat {
+    pub id: ChatID,
+    associated_bot: Option<BotId>,
+    system_prompt: Option<String>,
+    title: String,
+    #[serde(default)]
+    title_state: TitleState,
+    #[serde(default)]
+    inferences_params: ChatInferenceParams,
+}
+
+/// A message of a chat, as kept in sync with paired devices.
+///
+/// Each message is synced on its own, so replies added to the same chat on
+/// two devices at the same time are both kept. Concurrent edits of the same
+/// message are still resolved by the most recent one.
+///
+/// Attachments are left out, their files stay on the device they were added
+/// on.
+#[derive(Serialize, Deserialize)]
+pub struct SyncedMessage {
+    pub message: Message,
+}
+
+/// Id of the synced record of a message, from the chat and the key given by
+/// [`message_sync_keys`].
+pub fn synced_message_id(chat_id: ChatID, key: &str) -> String {
+    format!("{}/{}", chat_id, key)
+}
+
+/// The chat and the message key of a synced message id.
+pub fn parse_synced_message_id(id: &str) -> Option<(ChatID, &str)> {
+    let (chat_id, key) = id.split_once('/')?;
+    Some((chat_id.parse().ok()?, key))
+}
+
+/// Keys identifying the messages across devices, which sort like the messages.
+///
+/// Messages have no id, so they are identified by their creation time,
+/// numbered when several share it (like the ones saved before it was kept).
+pub fn message_sync_keys(messages: &[Message]) -> Vec<String> {
+    let mut repeated = HashMap::new();
+    messages
+        .iter()
+        .map(|message| {
+            let created_at = message.metadata.created_at;
+            let count = repeated.entry(created_at).or_insert(0);
+            let key = format!(
+                "{}#{:06}",
+                created_at.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
+                count
+            );
+            *count += 1;
+            key
+        })
+        .collect()
+}
+
+/// Moves the attachments of `from`, including the ones returned by tools, to
+/// the same place in `to`.
+fn move_attachments(from: &mut Message, to: &mut Message) {
+    to.content.attachments = std::mem::take(&mut from.content.attachments);
+    for result in &mut to.content.tool_results {
+        let Some(local) = from
+            .content
+            .tool_results
+            .iter_mut()
+            .find(|local| local.tool_call_id == result.tool_call_id)
+        else {
+            continue;
+        };
+
+        let attachments = local
+            .parts
+            .drain(..)
+            .filter(|part| matches!(part, ToolResultPart::Attachment(_)));
+        result.parts.extend(attachments);
+    }
+}
+
+/// A copy of the message without attachments, including the ones returned by
+/// tools and the ones in its branches.
+fn without_attachments(message: &Message) -> Message {
+    let mut message = message.clone();
+    message.content.attachments.clear();
+    for result in &mut message.content.tool_results {
+        result
+            .parts
+            .retain(|part| !matches!(part, ToolResultPart::Attachment(_)));
+    }
+    for branch in &mut message.branches {
+        for m in branch.iter_mut() {
+            *m = without_attachments(m);
+        }
+    }
+    message
+}
+
+/// A generation param which can be set for each chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum InferenceParam {
+    Temperature,
+    TopP,
+    MaxTokens,
+    FrequencyPenalty,
+    PresencePenalty,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ChatInferenceParams {
     pub frequency_penalty: f32,
     pub max_tokens: u32,
@@ -42,6 +163,12 @@ pub struct ChatInferenceParams {
     pub top_p: f32,
     pub stream: bool,
     pub stop: String,
+    /// Tokens the model may spend thinking, where it must be enabled
+    /// explicitly. Zero leaves it disabled.
+    pub thinking_budget: u32,
+    /// Params set by the user, which are sent even if they have their default
+    /// value.
+    pub explicit_params: BTreeSet<InferenceParam>,
 }
 
 impl Default for ChatInferenceParams {
@@ -54,6 +181,70 @@ impl Default for ChatInferenceParams {
             top_p: 1.0,
             stream: true,
             stop: "".into(),
+            thinking_budget: 0,
+            explicit_params: BTreeSet::new(),
+        }
+    }
+}
+
+impl ChatInferenceParams {
+    /// Sets the value of a param chosen by the user.
+    pub fn set(&mut self, param: InferenceParam, value: f64) {
+        match param {
+            InferenceParam::Temperature => self.temperature = value as f32,
+            InferenceParam::TopP => self.top_p = value as f32,
+            InferenceParam::MaxTokens => self.max_tokens = value as u32,
+            InferenceParam::FrequencyPenalty => self.frequency_penalty = value as f32,
+            InferenceParam::PresencePenalty => self.presence_penalty = value as f32,
+        }
+        self.explicit_params.insert(param);
+    }
+
+    /// Builds the options given to the bot client when sending messages.
+    ///
+    /// Only the values set by the user or changed from their defaults are
+    /// sent, so the provider defaults apply otherwise (some models reject any
+    /// non-default temperature).
+    pub fn to_send_options(&self) -> SendOptions {
+        let defaults = Self::default();
+        let pick = |param, value, default| {
+            (value != default || self.explicit_params.contains(&param)).then_some(value)
+        };
+
+        // Stop sequences are edited as a single comma separated text.
+        let stop = self
+            .stop
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect();
+
+        SendOptions {
+            generation: GenerationParams {
+                temperature: pick(
+                    InferenceParam::Temperature,
+                    self.temperature,
+                    defaults.temperature,
+                ),
+                top_p: pick(InferenceParam::TopP, self.top_p, defaults.top_p),
+                max_tokens: (self.max_tokens != defaults.max_tokens
+                    || self.explicit_params.contains(&InferenceParam::MaxTokens))
+                .then_some(self.max_tokens),
+                frequency_penalty: pick(
+                    InferenceParam::FrequencyPenalty,
+                    self.frequency_penalty,
+                    defaults.frequency_penalty,
+                ),
+                presence_penalty: pick(
+                    InferenceParam::PresencePenalty,
+                    self.presence_penalty,
+                    defaults.presence_penalty,
+                ),
+                stop,
+                reasoning_budget: (self.thinking_budget > 0).then_some(self.thinking_budget),
+            },
+            ..Default::default()
         }
     }
 }
@@ -77,6 +268,9 @@ pub struct Chat {
     title: String,
     title_state: TitleState,
     chats_dir: PathBuf,
+    /// Increased every time the chat is saved, to notice its changes without
+    /// going through its messages.
+    revision: Cell<u64>,
 }
 
 impl Chat {
@@ -95,6 +289,7 @@ impl Chat {
             system_prompt: None,
             accessed_at: chrono::Utc::now(),
             has_unread_messages: false,
+            revision: Cell::new(0),
         }
     }
 
@@ -121,10 +316,11 @@ impl Chat {
                     title: data.title,
                     title_state: data.title_state,
                     chats_dir: dir.to_path_buf(),
-                    inferences_params: ChatInferenceParams::default(),
+                    inferences_params: data.inferences_params,
                     system_prompt: data.system_prompt,
                     accessed_at: data.accessed_at,
                     has_unread_messages: false,
+                    revision: Cell::new(0),
                 };
 
                 Ok(chat)
@@ -134,7 +330,7 @@ impl Chat {
     }
 
     pub async fn save(&self) {
-        let path = self.chats_dir.join(self.file_name());
+        let path = self.file_path();
         let data = ChatData {
             id: self.id,
             associated_bot: self.associated_bot.clone(),
@@ -143,6 +339,7 @@ impl Chat {
             title: self.title.clone(),
             title_state: self.title_state,
             accessed_at: self.accessed_at,
+            inferences_params: self.inferences_params.clone(),
 
             // Legacy field, it can be removed in the future.
             last_used_file_id: None,
@@ -152,9 +349,94 @@ impl Chat {
             .queue_write_json(path, &data)
             .await
             .unwrap();
+
+        // Partial responses are saved on every chunk, the index is updated once
+        // they are complete.
+        if self.messages.iter().any(|m| m.metadata.is_writing()) {
+            return;
+        }
+
+        let updated = search::global()
+            .lock()
+            .unwrap()
+            .update_chat(self.id, &self.messages);
+        if updated {
+            search::save_later();
+        }
+    }
+
+    /// The synced part of the chat, without its messages.
+    pub fn to_synced(&self) -> SyncedChat {
+        SyncedChat {
+            id: self.id,
+            associated_bot: self.associated_bot.clone(),
+            system_prompt: self.system_prompt.clone(),
+            title: self.title.clone(),
+            title_state: self.title_state,
+            inferences_params: self.inferences_params.clone(),
+        }
+    }
+
+    /// Replaces the details of the chat with the ones received from a paired
+    /// device. Messages are kept.
+    pub fn apply_synced(&mut self, synced: SyncedChat) {
+        self.id = synced.id;
+        self.associated_bot = synced.associated_bot;
+        self.system_prompt = synced.system_prompt;
+        self.title = synced.title;
+        self.title_state = synced.title_state;
+        self.inferences_params = synced.inferences_params;
+    }
+
+    /// The messages to sync, with the ids of their records.
+    pub fn to_synced_messages(&self) -> Vec<(String, SyncedMessage)> {
+        message_sync_keys(&self.messages)
+            .into_iter()
+            .zip(&self.messages)
+            .map(|(key, message)| {
+                let synced = SyncedMessage {
+                    message: without_attachments(message),
+                };
+                (synced_message_id(self.id, &key), synced)
+            })
+            .collect()
+    }
+
+    /// Adds, replaces or removes (if `None`) the message with the key, as
+    /// received from a paired device.
+    ///
+    /// The attachments of a replaced message are kept, since they are not
+    /// synced.
+    pub fn apply_synced_message(&mut self, key: &str, synced: Option<SyncedMessage>) {
+        let keys = message_sync_keys(&self.messages);
+        let existing = keys.iter().position(|k| k == key);
+
+        match (existing, synced) {
+            (Some(index), Some(SyncedMessage { mut message })) => {
+                move_attachments(&mut self.messages[index], &mut message);
+                self.messages[index] = message;
+            }
+            (Some(index), None) => {
+                self.messages.remove(index);
+            }
+            (None, Some(SyncedMessage { message })) => {
+                let index = keys
+                    .iter()
+                    .position(|k| k.as_str() > key)
+                    .unwrap_or(keys.len());
+                self.messages.insert(index, message);
+            }
+            (None, None) => {}
+        }
+    }
+
+    /// Changes every time the chat is saved.
+    pub fn revision(&self) -> u64 {
+        self.revision.get()
     }
 
     pub fn save_and_forget(&self) {
+        self.revision.set(self.revision.get() + 1);
         let self_clone = self.clone();
         spawn(async move {
             self_clone.save().await;
@@ -162,9 +444,15 @@ impl Chat {
     }
 
     pub fn remove_saved_file_and_forget(&self) {
-        let path = self.chats_dir.join(self.file_name());
+        let path = self.file_path();
+        let chat_id = self.id;
         spawn(async move {
             filesystem::global().remove(&path).await.unwrap();
+
+            let removed = search::global().lock().unwrap().remove_chat(chat_id);
+            if removed {
+                search::save_later();
+            }
         });
 
         for m in &self.messages {
@@ -190,6 +478,22 @@ impl Chat {
         format!("{}.chat.json", self.id)
     }
 
+    /// Path of the saved chat, relative to the app data.
+    pub fn file_path(&self) -> PathBuf {
+        self.chats_dir.join(self.file_name())
+    }
+
+    /// Paths of the attachments persisted for the messages of the chat,
+    /// including the ones returned by tools.
+    pub fn attachment_paths(&self) -> Vec<String> {
+        self.messages
+            .iter()
+            .flat_map(|m| m.content.all_attachments())
+            .filter_map(|a| a.get_persistence_key())
+            .map(|key| key.to_string())
+            .collect()
+    }
+
     pub fn get_title(&self) -> &str {
         &self.title
     }
@@ -234,3 +538,192 @@ impl Chat {
         self.accessed_at = chrono::Utc::now();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use moly_kit::protocol::{Attachment, EntityId, MessageContent, ToolResult};
+
+    fn message(text: &str, created_at_secs: i64) -> Message {
+        let mut message = Message {
+            from: EntityId::User,
+            content: MessageContent {
+                text: text.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        message.metadata.created_at = Utc.timestamp_opt(created_at_secs, 0).unwrap();
+        message
+    }
+
+    fn chat(messages: Vec<Message>) -> Chat {
+        let mut chat = Chat::new(PathBuf::new());
+        chat.id = 1;
+        chat.messages = messages;
+        chat
+    }
+
+    /// Applies the messages of `from` missing or different in `to`, like an
+    /// exchange between devices would.
+    fn sync_messages(from: &Chat, to: &mut Chat) {
+        let known = to
+            .to_synced_messages()
+            .into_iter()
+            .map(|(id, synced)| (id, synced.message.content.text))
+            .collect::<Vec<_>>();
+
+        for (id, synced) in from.to_synced_messages() {
+            if !known.contains(&(id.clone(), synced.message.content.text.clone())) {
+                let (_, key) = parse_synced_message_id(&id).unwrap();
+                to.apply_synced_message(key, Some(synced));
+            }
+        }
+    }
+
+    fn texts(chat: &Chat) -> Vec<&str> {
+        chat.messages
+            .iter()
+            .map(|m| m.content.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_send_options_leave_out_untouched_defaults() {
+        let params = ChatInferenceParams {
+            top_p: 0.5,
+            stop: "END, ,STOP".into(),
+            ..Default::default()
+        };
+
+        let generation = params.to_send_options().generation;
+        assert_eq!(generation.temperature, None);
+        assert_eq!(generation.top_p, Some(0.5));
+        assert_eq!(generation.max_tokens, None);
+        assert_eq!(generation.stop, vec!["END", "STOP"]);
+        assert_eq!(generation.reasoning_budget, None);
+    }
+
+    #[test]
+    fn test_send_options_include_defaults_set_by_the_user() {
+        let mut params = ChatInferenceParams::default();
+        params.set(InferenceParam::Temperature, 1.0);
+        params.set(InferenceParam::MaxTokens, 2048.0);
+
+        let generation = params.to_send_options().generation;
+        assert_eq!(generation.temperature, Some(1.0));
+        assert_eq!(generation.max_tokens, Some(2048));
+        assert_eq!(generation.top_p, None);
+    }
+
+    #[test]
+    fn test_concurrent_replies_are_kept() {
+        let history = vec![message("hello", 10), message("hi!", 20)];
+        let mut desktop = chat(history.clone());
+        let mut laptop = chat(history);
+
+        desktop.messages.push(message("from desktop", 30));
+        laptop.messages.push(message("from laptop", 40));
+
+        let desktop_before = desktop.clone();
+        sync_messages(&laptop, &mut desktop);
+        sync_messages(&desktop_before, &mut laptop);
+
+        let expected = vec!["hello", "hi!", "from desktop", "from laptop"];
+        assert_eq!(texts(&desktop), expected);
+        assert_eq!(texts(&laptop), expected);
+    }
+
+    #[test]
+    fn test_removed_messages_keep_the_chat() {
+        let mut chat = chat(vec![message("hello", 10)]);
+        let (id, _) = chat.to_synced_messages().remove(0);
+        let (chat_id, key) = parse_synced_message_id(&id).unwrap();
+        assert_eq!(chat_id, chat.id);
+
+        chat.apply_synced_message(key, None);
+        assert!(chat.messages.is_empty());
+    }
+
+    #[test]
+    fn test_messages_with_the_same_time_have_different_keys() {
+        let keys = message_sync_keys(&[message("a", 0), message("b", 0), message("c", 5)]);
+        assert_eq!(keys.len(), 3);
+        assert_ne!(keys[0], keys[1]);
+        assert!(keys[0] < keys[1] && keys[1] < keys[2]);
+    }
+
+    #[test]
+    fn test_attachments_are_not_synced() {
+        let mut with_attachment = message("see this", 10);
+        with_attachment
+            .content
+            .attachments
+            .push(Attachment::from_bytes("notes.txt".into(), None, b"notes"));
+        let mut local = chat(vec![with_attachment]);
+
+        let (id, mut synced) = local.to_synced_messages().remove(0);
+        assert!(synced.message.content.attachments.is_empty());
+
+        // An edit from a peer keeps the local attachments.
+        synced.message.content.text = "see this file".into();
+        let (_, key) = parse_synced_message_id(&id).unwrap();
+        local.apply_synced_message(key, Some(synced));
+        assert_eq!(local.messages[0].content.text, "see this file");
+        assert_eq!(local.messages[0].content.attachments.len(), 1);
+    }
+
+    #[test]
+    fn test_tool_result_attachments_are_not_synced() {
+        let image = Attachment::from_bytes("chart.png".into(), Some("image/png".into()), b"png");
+        let mut with_image = message("", 10);
+        with_image.content.tool_results.push(ToolResult {
+            tool_call_id: "call_1".into(),
+            content: "Chart drawn".into(),
+            parts: vec![
+                ToolResultPart::Text("Chart drawn".into()),
+                ToolResultPart::Attachment(image),
+            ],
+            ..Default::default()
+        });
+        let mut local = chat(vec![with_image]);
+
+        let (id, mut synced) = local.to_synced_messages().remove(0);
+        let result = &synced.message.content.tool_results[0];
+        assert_eq!(result.parts.len(), 1);
+        assert_eq!(result.attachments().count(), 0);
+
+        // An edit from a peer keeps the images returned locally.
+        synced.message.content.tool_results[0].content = "Chart redrawn".into();
+        let (_, key) = parse_synced_message_id(&id).unwrap();
+        local.apply_synced_message(key, Some(synced));
+        let result = &local.messages[0].content.tool_results[0];
+        assert_eq!(result.content, "Chart redrawn");
+        assert_eq!(result.attachments().count(), 1);
+    }
+
+    #[test]
+    fn test_attachment_paths_include_tool_results() {
+        let mut file = Attachment::from_bytes("notes.txt".into(), None, b"notes");
+        file.set_persistence_key("attachments/notes.txt".into());
+        let mut image =
+            Attachment::from_bytes("chart.png".into(), Some("image/png".into()), b"png");
+        image.set_persistence_key("attachments/chart.png".into());
+
+        let mut request = message("see this", 10);
+        request.content.attachments.push(file);
+        let mut response = message("", 20);
+        response.content.tool_results.push(ToolResult {
+            tool_call_id: "call_1".into(),
+            parts: vec![ToolResultPart::Attachment(image)],
+            ..Default::default()
+        });
+
+        let chat = chat(vec![request, response]);
+        assert_eq!(
+            chat.attachment_paths(),
+            vec!["attachments/notes.txt", "attachments/chart.png"]
+        );
+    }
+}
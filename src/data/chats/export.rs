@@ -0,0 +1,499 @@
+//! Exporters to share chats outside of Moly.
+//!
+//! Besides the human readable formats, chats can be exported to a versioned
+//! JSON interchange format which can be imported back with [`super::import`].
+
+use chrono::{DateTime, Utc};
+use moly_kit::utils::asynchronous::BoxPlatformSendFuture;
+use moly_kit::{
+    Attachment, BotId, EntityId, Message, TokenUsage, ToolCall, ToolResult, ToolResultPart,
+};
+use serde::{Deserialize, Serialize};
+
+use super::chat::{Chat, ChatID};
+
+/// Identifies a JSON file as a Moly chats archive.
+pub const ARCHIVE_FORMAT: &str = "moly-chats";
+
+/// Version of the interchange format written by this build.
+///
+/// Must be increased on breaking changes to [`ChatArchive`].
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChatArchive {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub chats: Vec<ArchivedChat>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchivedChat {
+    /// Id of the chat where it was exported from, to not import it twice.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<ChatID>,
+    pub title: String,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub bot: Option<BotId>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    pub messages: Vec<ArchivedMessage>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchivedMessage {
+    /// One of `user`, `assistant`, `system`, `tool` or `app`.
+    pub role: String,
+    /// The bot which wrote the message, for the `assistant` role.
+    #[serde(default)]
+    pub bot: Option<BotId>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reasoning: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ArchivedAttachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_results: Vec<ArchivedToolResult>,
+    /// Provider specific data needed to continue the conversation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    /// Other versions of the conversation from this message onwards.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<Vec<ArchivedMessage>>,
+    /// Position of this message among its siblings.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub branch_index: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchivedToolResult {
+    pub tool_call_id: String,
+    #[serde(default)]
+    pub content: String,
+    /// Typed parts of the result, except attachments which are embedded in
+    /// `attachments`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<ToolResultPart>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<ArchivedAttachment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<serde_json::Value>,
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchivedAttachment {
+    pub name: String,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    /// Base64 encoded content, missing if it couldn't be read on export.
+    #[serde(default)]
+    pub data: Option<String>,
+}
+
+/// Serializes the chats in the given format.
+///
+/// Attachments are read to be embedded in HTML and JSON exports. The ones that
+/// can't be read are still listed by name.
+pub async fn export_chats(chats: &[Chat], format: ExportFormat) -> Vec<u8> {
+    match format {
+        ExportFormat::Markdown => to_markdown(chats).into_bytes(),
+        ExportFormat::Html => to_html(chats).await.into_bytes(),
+        ExportFormat::Json => {
+            let archive = to_archive(chats).await;
+            serde_json::to_vec_pretty(&archive).expect("archive is always serializable")
+        }
+    }
+}
+
+/// Suggested file name for exporting the given chats.
+pub fn export_file_name(chats: &[Chat], format: ExportFormat) -> String {
+    let stem = match chats {
+        [chat] => chat
+            .get_title()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect::<String>()
+            .split('-')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("-"),
+        _ => format!("moly-chats-{}", Utc::now().format("%Y-%m-%d")),
+    };
+
+    let stem = if stem.is_empty() { "chat".into() } else { stem };
+    format!("{}.{}", stem, format.extension())
+}
+
+pub async fn to_archive(chats: &[Chat]) -> ChatArchive {
+    let mut archived = Vec::with_capacity(chats.len());
+
+    for chat in chats {
+        let messages = archive_messages(&chat.messages).await;
+
+        archived.push(ArchivedChat {
+            id: Some(chat.id),
+            title: chat.get_title().into(),
+            created_at: created_at(chat),
+            bot: chat.associated_bot.clone(),
+            system_prompt: chat.system_prompt.clone(),
+            messages,
+        });
+    }
+
+    ChatArchive {
+        format: ARCHIVE_FORMAT.into(),
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+        chats: archived,
+    }
+}
+
+/// Boxed because branches make it recursive.
+fn archive_messages(messages: &[Message]) -> BoxPlatformSendFuture<'_, Vec<ArchivedMessage>> {
+    Box::pin(async move {
+        let mut archived = Vec::with_capacity(messages.len());
+        for message in messages {
+            let (role, bot) = role(&message.from);
+            let mut attachments = Vec::new();
+            for attachment in &message.content.attachments {
+                attachments.push(archive_attachment(attachment).await);
+            }
+
+            let mut tool_results = Vec::with_capacity(message.content.tool_results.len());
+            for result in &message.content.tool_results {
+                tool_results.push(archive_tool_result(result).await);
+            }
+
+            let mut branches = Vec::with_capacity(message.branches.len());
+            for branch in &message.branches {
+                branches.push(archive_messages(branch).await);
+            }
+
+            archived.push(ArchivedMessage {
+                role: role.into(),
+                bot,
+                created_at: Some(message.metadata.created_at),
+                text: message.content.text.clone(),
+                reasoning: message.content.reasoning.clone(),
+                attachments,
+                citations: message.content.citations.clone(),
+                tool_calls: message.content.tool_calls.clone(),
+                tool_results,
+                data: message.content.data.clone(),
+                usage: message.metadata.usage,
+                branches,
+                branch_index: message.branch_index,
+            });
+        }
+        archived
+    })
+}
+
+async fn archive_tool_result(result: &ToolResult) -> ArchivedToolResult {
+    let mut attachments = Vec::new();
+    for attachment in result.attachments() {
+        attachments.push(archive_attachment(attachment).await);
+    }
+
+    ArchivedToolResult {
+        tool_call_id: result.tool_call_id.clone(),
+        content: result.content.clone(),
+        parts: result
+            .parts
+            .iter()
+            .filter(|part| !matches!(part, ToolResultPart::Attachment(_)))
+            .cloned()
+            .collect(),
+        attachments,
+        structured_content: result.structured_content.clone(),
+        is_error: result.is_error,
+    }
+}
+
+async fn archive_attachment(attachment: &Attachment) -> ArchivedAttachment {
+    ArchivedAttachment {
+        name: attachment.name.clone(),
+        content_type: attachment.content_type.clone(),
+        data: read_base64(attachment).await,
+    }
+}
+
+fn to_markdown(chats: &[Chat]) -> String {
+    let mut md = String::new();
+
+    for (i, chat) in chats.iter().enumerate() {
+        if i > 0 {
+            md.push_str("\n---\n\n");
+        }
+
+        md.push_str(&format!("# {}\n\n", chat.get_title()));
+
+        if let Some(prompt) = chat.system_prompt.as_deref().filter(|p| !p.is_empty()) {
+            md.push_str(&format!("> **System prompt:** {}\n\n", prompt));
+        }
+
+        for entry in entries(&chat.messages) {
+            let message = match entry {
+                Entry::Message(message) => message,
+                Entry::BranchStart => {
+                    md.push_str("<details>\n<summary>Other version</summary>\n\n");
+                    continue;
+                }
+                Entry::BranchEnd => {
+                    md.push_str("</details>\n\n");
+                    continue;
+                }
+            };
+
+            md.push_str(&format!("## {}\n\n", author(message)));
+
+            // Collapsed by renderers supporting HTML in Markdown, like GitHub.
+            if !message.content.reasoning.is_empty() {
+                md.push_str("<details>\n<summary>Reasoning</summary>\n\n");
+                md.push_str(message.content.reasoning.trim());
+                md.push_str("\n\n</details>\n\n");
+            }
+
+            if !message.content.text.is_empty() {
+                md.push_str(message.content.text.trim());
+                md.push_str("\n\n");
+            }
+
+            for attachment in &message.content.attachments {
+                md.push_str(&format!("📎 `{}`\n\n", attachment.name));
+            }
+        }
+    }
+
+    md
+}
+
+async fn to_html(chats: &[Chat]) -> String {
+    let title = match chats {
+        [chat] => escape_html(chat.get_title()),
+        _ => "Moly chats".into(),
+    };
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n",
+        title, HTML_STYLE
+    );
+
+    for chat in chats {
+        html.push_str(&format!(
+            "<section class=\"chat\">\n<h1>{}</h1>\n",
+            escape_html(chat.get_title())
+        ));
+
+        if let Some(prompt) = chat.system_prompt.as_deref().filter(|p| !p.is_empty()) {
+            html.push_str(&format!(
+                "<p class=\"system\"><strong>System prompt:</strong> {}</p>\n",
+                escape_html(prompt)
+            ));
+        }
+
+        for entry in entries(&chat.messages) {
+            let message = match entry {
+                Entry::Message(message) => message,
+                Entry::BranchStart => {
+                    html.push_str("<details class=\"branch\"><summary>Other version</summary>\n");
+                    continue;
+                }
+                Entry::BranchEnd => {
+                    html.push_str("</details>\n");
+                    continue;
+                }
+            };
+
+            let class = match message.from {
+                EntityId::User => "user",
+                _ => "bot",
+            };
+
+            html.push_str(&format!(
+                "<article class=\"message {}\">\n<h2>{}</h2>\n",
+                class,
+                escape_html(&author(message))
+            ));
+
+            if !message.content.reasoning.is_empty() {
+                html.push_str(&format!(
+                    "<details><summary>Reasoning</summary><div class=\"text\">{}</div></details>\n",
+                    escape_html(message.content.reasoning.trim())
+                ));
+            }
+
+            if !message.content.text.is_empty() {
+                html.push_str(&format!(
+                    "<div class=\"text\">{}</div>\n",
+                    escape_html(message.content.text.trim())
+                ));
+            }
+
+            for attachment in &message.content.attachments {
+                html.push_str(&attachment_html(attachment).await);
+            }
+
+            html.push_str("</article>\n");
+        }
+
+        html.push_str("</section>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Embeds the attachment as a data URL so the document is self-contained.
+async fn attachment_html(attachment: &Attachment) -> String {
+    let name = escape_html(&attachment.name);
+    let Some(data) = read_base64(attachment).await else {
+        return format!("<p class=\"attachment\">📎 {} (not available)</p>\n", name);
+    };
+
+    let url = format!(
+        "data:{};base64,{}",
+        attachment.content_type_or_octet_stream(),
+        data
+    );
+
+    if attachment.is_image() {
+        format!(
+            "<figure class=\"attachment\"><img src=\"{}\" alt=\"{}\"><figcaption>{}</figcaption></figure>\n",
+            url, name, name
+        )
+    } else {
+        format!(
+            "<p class=\"attachment\">📎 <a href=\"{}\" download=\"{}\">{}</a></p>\n",
+            url, name, name
+        )
+    }
+}
+
+const HTML_STYLE: &str = "body{font-family:sans-serif;max-width:800px;margin:0 auto;padding:20px;color:#101828}\
+.chat{margin-bottom:40px}\
+.message{padding:12px 16px;margin:12px 0;border-radius:8px;background:#f9fafb}\
+.message.user{background:#eef4ff}\
+.message h2{font-size:13px;margin:0 0 8px;color:#475467}\
+.text{white-space:pre-wrap}\
+details{margin-bottom:8px;color:#667085}\
+.attachment img{max-width:100%}\
+.system{color:#667085}\
+.branch{margin:12px 0;padding-left:12px;border-left:3px solid #d0d5dd}";
+
+async fn read_base64(attachment: &Attachment) -> Option<String> {
+    if !attachment.is_available() {
+        return None;
+    }
+
+    match attachment.read_base64().await {
+        Ok(data) => Some(data),
+        Err(e) => {
+            log::warn!(
+                "Failed to read attachment {} for exporting: {}",
+                attachment.name,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// App messages (like inline errors) are not part of the conversation.
+fn is_exported(message: &Message) -> bool {
+    !matches!(message.from, EntityId::App)
+}
+
+/// A message to export or the bounds of an inactive branch.
+enum Entry<'a> {
+    Message(&'a Message),
+    BranchStart,
+    BranchEnd,
+}
+
+/// Flattens the conversation tree, placing the inactive versions of the
+/// conversation right before the message they are an alternative to.
+fn entries(messages: &[Message]) -> Vec<Entry<'_>> {
+    let mut flattened = Vec::new();
+
+    for message in messages {
+        for branch in &message.branches {
+            flattened.push(Entry::BranchStart);
+            flattened.extend(entries(branch));
+            flattened.push(Entry::BranchEnd);
+        }
+
+        if is_exported(message) {
+            flattened.push(Entry::Message(message));
+        }
+    }
+
+    flattened
+}
+
+fn author(message: &Message) -> String {
+    match &message.from {
+        EntityId::User => "User".into(),
+        EntityId::System => "System".into(),
+        EntityId::Bot(bot_id) => bot_id.id().into(),
+        EntityId::Tool => "Tool".into(),
+        EntityId::App => "Moly".into(),
+    }
+}
+
+fn role(entity: &EntityId) -> (&'static str, Option<BotId>) {
+    match entity {
+        EntityId::User => ("user", None),
+        EntityId::System => ("system", None),
+        EntityId::Bot(bot_id) => ("assistant", Some(bot_id.clone())),
+        EntityId::Tool => ("tool", None),
+        EntityId::App => ("app", None),
+    }
+}
+
+/// Chat ids are their creation time, in milliseconds.
+fn created_at(chat: &Chat) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(chat.id as i64)
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
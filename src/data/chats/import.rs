@@ -0,0 +1,441 @@
+//! Importers for chats exported by Moly or by other apps.
+//!
+//! Supported formats are the interchange JSON written by [`super::export`] and
+//! the `conversations.json` file from the ChatGPT data export.
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow, bail};
+use chrono::{DateTime, Utc};
+use moly_kit::{
+    Attachment, BotId, EntityId, Message, MessageContent, MessageMetadata, ToolResult,
+    ToolResultPart,
+};
+use serde::Deserialize;
+
+use super::chat::ChatID;
+use super::export::{
+    ARCHIVE_FORMAT, ARCHIVE_VERSION, ArchivedAttachment, ArchivedMessage, ArchivedToolResult,
+    ChatArchive,
+};
+
+/// Provider used for the bots of chats imported from ChatGPT.
+const CHATGPT_PROVIDER: &str = "chatgpt";
+
+/// A chat read from an export file, not yet saved in Moly.
+#[derive(Clone, Debug)]
+pub struct ImportedChat {
+    /// Id the chat had in the Moly instance it was exported from, if any.
+    pub id: Option<ChatID>,
+    pub title: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub associated_bot: Option<BotId>,
+    pub system_prompt: Option<String>,
+    pub messages: Vec<Message>,
+}
+
+/// Parses the chats of a Moly archive or a ChatGPT `conversations.json`.
+pub fn parse_chats(content: &[u8]) -> Result<Vec<ImportedChat>> {
+    let value: serde_json::Value = serde_json::from_slice(content)?;
+
+    if value.get("format").and_then(|f| f.as_str()) == Some(ARCHIVE_FORMAT) {
+        let archive: ChatArchive = serde_json::from_value(value)?;
+        return from_archive(archive);
+    }
+
+    if value.is_array() {
+        let conversations: Vec<Conversation> = serde_json::from_value(value)?;
+        return Ok(conversations.into_iter().map(from_chatgpt).collect());
+    }
+
+    Err(anyhow!("Unrecognized chats file format"))
+}
+
+fn from_archive(archive: ChatArchive) -> Result<Vec<ImportedChat>> {
+    if archive.version > ARCHIVE_VERSION {
+        bail!(
+            "Chats archive version {} is newer than the supported version {}",
+            archive.version,
+            ARCHIVE_VERSION
+        );
+    }
+
+    let chats = archive
+        .chats
+        .into_iter()
+        .map(|chat| ImportedChat {
+            id: chat.id,
+            title: chat.title,
+            created_at: chat.created_at,
+            associated_bot: chat.bot,
+            system_prompt: chat.system_prompt,
+            messages: chat
+                .messages
+                .into_iter()
+                .map(from_archived_message)
+                .collect(),
+        })
+        .collect();
+
+    Ok(chats)
+}
+
+fn from_archived_message(message: ArchivedMessage) -> Message {
+    let from = match (message.role.as_str(), message.bot) {
+        ("user", _) => EntityId::User,
+        ("system", _) => EntityId::System,
+        ("tool", _) => EntityId::Tool,
+        ("assistant", Some(bot_id)) => EntityId::Bot(bot_id),
+        ("assistant", None) => EntityId::Bot(BotId::new("unknown", "unknown")),
+        _ => EntityId::App,
+    };
+
+    let attachments = message
+        .attachments
+        .into_iter()
+        .filter_map(from_archived_attachment)
+        .collect();
+
+    let mut metadata = metadata_at(message.created_at);
+    metadata.usage = message.usage;
+
+    Message {
+        from,
+        metadata,
+        content: MessageContent {
+            text: message.text,
+            reasoning: message.reasoning,
+            attachments,
+            citations: message.citations,
+            tool_calls: message.tool_calls,
+            tool_results: message
+                .tool_results
+                .into_iter()
+                .map(from_archived_tool_result)
+                .collect(),
+            data: message.data,
+            ..Default::default()
+        },
+        branches: message
+            .branches
+            .into_iter()
+            .map(|branch| branch.into_iter().map(from_archived_message).collect())
+            .collect(),
+        branch_index: message.branch_index,
+        ..Default::default()
+    }
+}
+
+fn from_archived_tool_result(result: ArchivedToolResult) -> ToolResult {
+    let mut parts = result.parts;
+    parts.extend(
+        result
+            .attachments
+            .into_iter()
+            .filter_map(from_archived_attachment)
+            .map(ToolResultPart::Attachment),
+    );
+
+    ToolResult {
+        tool_call_id: result.tool_call_id,
+        content: result.content,
+        parts,
+        structured_content: result.structured_content,
+        is_error: result.is_error,
+    }
+}
+
+fn from_archived_attachment(attachment: ArchivedAttachment) -> Option<Attachment> {
+    let data = attachment.data?;
+    Attachment::from_base64(attachment.name.clone(), attachment.content_type, &data)
+        .inspect_err(|e| log::warn!("Skipping attachment {} on import: {}", attachment.name, e))
+        .ok()
+}
+
+#[derive(Deserialize)]
+struct Conversation {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    mapping: HashMap<String, Node>,
+    #[serde(default)]
+    current_node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    #[serde(default)]
+    message: Option<ChatGptMessage>,
+    #[serde(default)]
+    parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMessage {
+    author: Author,
+    #[serde(default)]
+    create_time: Option<f64>,
+    content: Content,
+    #[serde(default)]
+    metadata: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    role: String,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    #[serde(default)]
+    parts: Vec<serde_json::Value>,
+}
+
+/// Builds the chat from the branch the conversation ended on, as the export
+/// keeps every edited or regenerated version of the messages.
+fn from_chatgpt(conversation: Conversation) -> ImportedChat {
+    let mut path = Vec::new();
+    let mut current = conversation.current_node.clone();
+    // Bounded by the amount of nodes in case of malformed cycles.
+    for _ in 0..conversation.mapping.len() {
+        let Some(node) = current.and_then(|id| conversation.mapping.get(&id)) else {
+            break;
+        };
+        if let Some(message) = &node.message {
+            path.push(message);
+        }
+        current = node.parent.clone();
+    }
+    path.reverse();
+
+    let mut associated_bot = None;
+    let mut system_prompt = None;
+    let mut messages = Vec::new();
+
+    for message in path {
+        // Only text parts are kept, images are references to files not
+        // included in `conversations.json`.
+        let text = message
+            .content
+            .parts
+            .iter()
+            .filter_map(|p| p.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let from = match message.author.role.as_str() {
+            "user" => EntityId::User,
+            "assistant" => {
+                let model = message
+                    .metadata
+                    .get("model_slug")
+                    .and_then(|m| m.as_str())
+                    .unwrap_or(CHATGPT_PROVIDER);
+                let bot_id = BotId::new(model, CHATGPT_PROVIDER);
+                associated_bot = Some(bot_id.clone());
+                EntityId::Bot(bot_id)
+            }
+            "system" => {
+                system_prompt.get_or_insert(text);
+                continue;
+            }
+            "tool" => EntityId::Tool,
+            _ => continue,
+        };
+
+        messages.push(Message {
+            from,
+            metadata: metadata_at(message.create_time.and_then(timestamp)),
+            content: MessageContent {
+                text,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+
+    ImportedChat {
+        id: None,
+        title: conversation
+            .title
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| "Imported Chat".into()),
+        created_at: conversation.create_time.and_then(timestamp),
+        associated_bot,
+        system_prompt,
+        messages,
+    }
+}
+
+fn metadata_at(created_at: Option<DateTime<Utc>>) -> MessageMetadata {
+    let created_at = created_at.unwrap_or(DateTime::UNIX_EPOCH);
+    MessageMetadata {
+        created_at,
+        reasoning_updated_at: created_at,
+        text_updated_at: created_at,
+        ..MessageMetadata::epoch()
+    }
+}
+
+/// ChatGPT times are unix timestamps in (fractional) seconds.
+fn timestamp(seconds: f64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis((seconds * 1000.0) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::chats::chat::Chat;
+    use crate::data::chats::export::to_archive;
+    use moly_kit::{TokenUsage, ToolCall};
+
+    #[test]
+    fn test_archive_round_trip() {
+        let mut chat = Chat::new("chats".into());
+        chat.set_title("Greetings".into());
+        chat.messages = vec![
+            Message {
+                from: EntityId::User,
+                content: MessageContent {
+                    text: "Hi".into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Message {
+                from: EntityId::Bot(BotId::new("model", "provider")),
+                content: MessageContent {
+                    text: "Hello".into(),
+                    reasoning: "Be polite".into(),
+                    ..Default::default()
+                },
+                branches: vec![vec![Message {
+                    from: EntityId::Bot(BotId::new("model", "provider")),
+                    content: MessageContent {
+                        text: "Good morning".into(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }]],
+                branch_index: 1,
+                ..Default::default()
+            },
+            Message {
+                from: EntityId::Bot(BotId::new("model", "provider")),
+                content: MessageContent {
+                    tool_calls: vec![ToolCall {
+                        id: "call_1".into(),
+                        name: "search".into(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                metadata: MessageMetadata {
+                    usage: Some(TokenUsage {
+                        prompt_tokens: 12,
+                        completion_tokens: 3,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Message {
+                from: EntityId::Tool,
+                content: MessageContent {
+                    tool_results: vec![ToolResult {
+                        tool_call_id: "call_1".into(),
+                        content: "No results".into(),
+                        parts: vec![ToolResultPart::Text("No results".into())],
+                        is_error: true,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ];
+
+        let archive = futures::executor::block_on(to_archive(&[chat.clone()]));
+        let json = serde_json::to_vec(&archive).unwrap();
+        let imported = parse_chats(&json).unwrap();
+
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].id, Some(chat.id));
+        assert_eq!(imported[0].title, "Greetings");
+        assert_eq!(imported[0].messages.len(), 4);
+        assert_eq!(imported[0].messages[1].from, chat.messages[1].from);
+        assert_eq!(imported[0].messages[1].content.reasoning, "Be polite");
+        assert_eq!(imported[0].messages[1].branch_index, 1);
+        assert_eq!(
+            imported[0].messages[1].branches[0][0].content.text,
+            "Good morning"
+        );
+        assert_eq!(
+            imported[0].messages[2].content.tool_calls,
+            chat.messages[2].content.tool_calls
+        );
+        assert_eq!(
+            imported[0].messages[2].metadata.usage,
+            chat.messages[2].metadata.usage
+        );
+        assert_eq!(
+            imported[0].messages[3].content.tool_results,
+            chat.messages[3].content.tool_results
+        );
+    }
+
+    #[test]
+    fn test_chatgpt_import_follows_current_branch() {
+        let json = serde_json::json!([{
+            "title": "Trip ideas",
+            "create_time": 1700000000.5,
+            "current_node": "c",
+            "mapping": {
+                "root": {"message": null, "parent": null},
+                "a": {
+                    "parent": "root",
+                    "message": {
+                        "author": {"role": "user"},
+                        "content": {"content_type": "text", "parts": ["Where to go?"]}
+                    }
+                },
+                "b": {
+                    "parent": "a",
+                    "message": {
+                        "author": {"role": "assistant"},
+                        "content": {"content_type": "text", "parts": ["Old answer"]}
+                    }
+                },
+                "c": {
+                    "parent": "a",
+                    "message": {
+                        "author": {"role": "assistant"},
+                        "content": {"content_type": "text", "parts": ["Lisbon"]},
+                        "metadata": {"model_slug": "gpt-4o"}
+                    }
+                }
+            }
+        }]);
+
+        let imported = parse_chats(json.to_string().as_bytes()).unwrap();
+
+        assert_eq!(imported[0].title, "Trip ideas");
+        let texts = imported[0]
+            .messages
+            .iter()
+            .map(|m| m.content.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec!["Where to go?", "Lisbon"]);
+        assert_eq!(
+            imported[0].associated_bot,
+            Some(BotId::new("gpt-4o", CHATGPT_PROVIDER))
+        );
+    }
+}
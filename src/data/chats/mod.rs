@@ -1,21 +1,33 @@
 pub mod chat;
+pub mod export;
+pub mod import;
+pub mod search;
 
-use chat::{Chat, ChatID};
+use chat::{Chat, ChatID, SyncedChat, SyncedMessage};
 use futures::StreamExt;
-use moly_kit::BotId;
+use moly_kit::{BotId, Message};
 use moly_protocol::data::*;
 use std::collections::HashMap;
 use std::{cell::RefCell, path::PathBuf};
 
-use crate::shared::utils::filesystem;
+use crate::app::app_runner;
+use crate::shared::utils::attachments::{
+    generate_persistence_key, set_persistence_key_and_reader, write_attachment_to_key,
+};
+use crate::shared::utils::{file_dialog, filesystem};
+use moly_kit::{Attachment, utils::asynchronous::spawn};
 
 use super::moly_client::MolyClient;
 use super::preferences::Preferences;
+use super::prompt_templates::PromptTemplate;
 use super::providers::{
     Provider, ProviderBot, ProviderConnectionStatus, ProviderFetchModelsResult, ProviderID,
     ProviderType, fetch_models_for_provider,
 };
 use super::store::{ProviderSyncing, ProviderSyncingStatus};
+use export::ExportFormat;
+use import::ImportedChat;
+use search::{ChatSearchIndex, ChatSearchResult};
 
 pub struct Chats {
     pub moly_client: MolyClient,
@@ -30,6 +42,10 @@ pub struct Chats {
     current_chat_id: Option<ChatID>,
     chats_dir: PathBuf,
 
+    /// Bumped every time messages are added, changed or removed, so derived
+    /// data like the usage totals is only recomputed when needed.
+    messages_revision: u64,
+
     /// Placeholder remote model used when a remote model is not available
     /// This is used to avoid recreating it on each call and make borrowing simpler.
     unknown_bot: ProviderBot,
@@ -45,6 +61,7 @@ impl Chats {
             available_bots: HashMap::new(),
             providers: HashMap::new(),
             unknown_bot: ProviderBot::unknown(),
+            messages_revision: 0,
         }
     }
 
@@ -84,9 +101,46 @@ impl Chats {
             .collect::<Vec<_>>()
             .await;
 
+        let mut index = ChatSearchIndex::load().await;
+        let changed = {
+            let saved = chats
+                .saved_chats
+                .iter()
+                .map(|c| c.borrow())
+                .collect::<Vec<_>>();
+            index.sync(saved.iter().map(|c| &**c))
+        };
+        if changed {
+            index.save().await;
+        }
+        *search::global().lock().unwrap() = index;
+
         chats
     }
 
+    /// Finds the messages of the saved chats matching the query, newest chats
+    /// first.
+    pub fn search(&self, query: &str) -> Vec<ChatSearchResult> {
+        let matches = search::global().lock().unwrap().query(query);
+
+        matches
+            .into_iter()
+            .filter_map(|(chat_id, message_index, field)| {
+                let chat = self.get_chat_by_id(chat_id)?.borrow();
+                let message = chat.messages.get(message_index)?;
+                let (snippet, highlights) = search::snippet(message, field, query)?;
+
+                Some(ChatSearchResult {
+                    chat_id,
+                    message_index,
+                    field,
+                    snippet,
+                    highlights,
+                })
+            })
+            .collect()
+    }
+
     pub fn get_last_selected_chat_id(&self) -> Option<ChatID> {
         self.saved_chats
             .iter()
@@ -127,6 +181,16 @@ impl Chats {
             chat.borrow_mut().delete_message(message_id);
             chat.borrow().save_and_forget();
         }
+        self.mark_messages_changed();
+    }
+
+    /// Must be called after changing the messages of a chat directly.
+    pub fn mark_messages_changed(&mut self) {
+        self.messages_revision += 1;
+    }
+
+    pub fn messages_revision(&self) -> u64 {
+        self.messages_revision
     }
 
     pub fn create_empty_chat(&mut self, bot_id: Option<BotId>) -> ChatID {
@@ -150,6 +214,27 @@ impl Chats {
         id
     }
 
+    /// Starts a new chat with the template's prompt, filled with the given
+    /// variable values, and its default bot and parameters if any.
+    pub fn create_chat_from_template(
+        &mut self,
+        template: &PromptTemplate,
+        values: &HashMap<String, String>,
+    ) -> ChatID {
+        let chat_id = self.create_empty_chat(template.default_bot.clone());
+
+        if let Some(chat) = self.get_chat_by_id(chat_id) {
+            let mut chat = chat.borrow_mut();
+            chat.system_prompt = Some(template.render(values));
+            if let Some(params) = &template.inference_params {
+                chat.inferences_params = params.clone();
+            }
+            chat.save_and_forget();
+        }
+
+        chat_id
+    }
+
     pub fn remove_chat(&mut self, chat_id: ChatID) {
         if self.current_chat_id == Some(chat_id) {
             self.set_current_chat(self.get_last_selected_chat_id());
@@ -163,6 +248,168 @@ impl Chats {
 
         let chat = self.saved_chats.remove(pos);
         chat.borrow().remove_saved_file_and_forget();
+        self.mark_messages_changed();
+    }
+
+    /// Exports the given chats to a single file, letting the user choose where
+    /// to save it.
+    pub fn export_chats(&self, chat_ids: &[ChatID], format: ExportFormat) {
+        let chats = chat_ids
+            .iter()
+            .filter_map(|id| self.get_chat_by_id(*id))
+            .map(|c| c.borrow().clone())
+            .collect::<Vec<_>>();
+
+        if chats.is_empty() {
+            return;
+        }
+
+        spawn(async move {
+            let content = export::export_chats(&chats, format).await;
+            let file_name = export::export_file_name(&chats, format);
+
+            // The save dialog must be opened from the UI thread.
+            app_runner().defer(move |_, _, _| {
+                file_dialog::save_file(&file_name, &content);
+            });
+        });
+    }
+
+    /// Lets the user pick exported chat files and adds their chats.
+    ///
+    /// Must be called from the UI thread.
+    pub fn import_chats_from_files() {
+        for path in file_dialog::pick_files("Chats", &["json"]) {
+            spawn(async move {
+                let imported = match std::fs::read(&path) {
+                    Ok(content) => import::parse_chats(&content),
+                    Err(e) => Err(e.into()),
+                };
+
+                let mut imported = match imported {
+                    Ok(imported) => imported,
+                    Err(e) => {
+                        log::error!("Failed to import chats from {}: {}", path.display(), e);
+                        return;
+                    }
+                };
+
+                for chat in &mut imported {
+                    persist_imported_attachments(&mut chat.messages).await;
+                }
+
+                app_runner().defer(move |app, cx, _| {
+                    let store = app.store.as_mut().unwrap();
+                    store.chats.add_imported_chats(imported);
+                    app.ui.redraw(cx);
+                });
+            });
+        }
+    }
+
+    /// Saves the imported chats as new chats.
+    ///
+    /// Chats exported by Moly keep their id, so the ones which already exist
+    /// are skipped instead of duplicated.
+    pub fn add_imported_chats(&mut self, imported: Vec<ImportedChat>) -> Vec<ChatID> {
+        let mut ids = Vec::with_capacity(imported.len());
+
+        for imported in imported {
+            let exists = |id: ChatID| self.get_chat_by_id(id).is_some() || ids.contains(&id);
+            if imported.id.is_some_and(exists) {
+                log::info!(
+                    "Skipping import of chat {:?}, it already exists",
+                    imported.id
+                );
+                continue;
+            }
+
+            let mut chat = Chat::new(self.chats_dir.clone());
+
+            // Ids are creation timestamps, which collide on bulk imports.
+            if let Some(id) = imported.id {
+                chat.id = id;
+            } else if let Some(created_at) = imported.created_at {
+                chat.id = created_at.timestamp_millis() as ChatID;
+            }
+            while self.get_chat_by_id(chat.id).is_some() || ids.contains(&chat.id) {
+                chat.id += 1;
+            }
+
+            chat.set_title(imported.title);
+            chat.associated_bot = imported.associated_bot;
+            chat.system_prompt = imported.system_prompt;
+            chat.messages = imported.messages;
+            chat.save_and_forget();
+
+            ids.push(chat.id);
+            self.saved_chats.push(RefCell::new(chat));
+        }
+
+        self.mark_messages_changed();
+        ids
+    }
+
+    /// Adds or updates a chat received from a paired device.
+    pub fn set_synced_chat(&mut self, synced: SyncedChat) {
+        match self.get_chat_by_id(synced.id) {
+            Some(chat) => {
+                let mut chat = chat.borrow_mut();
+                chat.apply_synced(synced);
+                chat.save_and_forget();
+            }
+            None => {
+                let mut chat = Chat::new(self.chats_dir.clone());
+                chat.apply_synced(synced);
+                chat.save_and_forget();
+                self.saved_chats.push(RefCell::new(chat));
+            }
+        }
+        self.mark_messages_changed();
+    }
+
+    /// Adds, updates or removes (if `None`) messages received from a paired
+    /// device, by chat id and message key. Messages of unknown chats are
+    /// ignored.
+    pub fn set_synced_messages(&mut self, messages: Vec<(ChatID, String, Option<SyncedMessage>)>) {
+        let mut changed_chats = Vec::new();
+
+        for (chat_id, key, synced) in messages {
+            let Some(chat) = self.get_chat_by_id(chat_id) else {
+                continue;
+            };
+            chat.borrow_mut().apply_synced_message(&key, synced);
+            if !changed_chats.contains(&chat_id) {
+                changed_chats.push(chat_id);
+            }
+        }
+
+        if changed_chats.is_empty() {
+            return;
+        }
+
+        for chat_id in changed_chats {
+            if let Some(chat) = self.get_chat_by_id(chat_id) {
+                chat.borrow().save_and_forget();
+            }
+        }
+        self.mark_messages_changed();
+    }
+
+    /// Adds a chat received from another device, unless a chat with the same
+    /// id already exists.
+    ///
+    /// Returns if the chat was added.
+    pub fn add_transferred_chat(&mut self, chat: Chat) -> bool {
+        if self.get_chat_by_id(chat.id).is_some() {
+            return false;
+        }
+
+        // Saved again so it's indexed for search.
+        chat.save_and_forget();
+        self.saved_chats.push(RefCell::new(chat));
+        self.mark_messages_changed();
+        true
     }
 
     /// Registers a provider to listen to and the provider info.
@@ -497,3 +744,32 @@ impl Chats {
             .map(|m| m.id.clone())
     }
 }
+
+/// Writes the in-memory attachments of imported messages to disk, like the ones
+/// sent from the chat.
+async fn persist_imported_attachments(messages: &mut [Message]) {
+    let mut attachments = Vec::new();
+    collect_attachments_mut(messages, &mut attachments);
+
+    for attachment in attachments {
+        let key = generate_persistence_key(attachment);
+        match write_attachment_to_key(attachment, &key).await {
+            Ok(()) => set_persistence_key_and_reader(attachment, key),
+            Err(e) => log::error!(
+                "Failed to persist imported attachment {}: {}",
+                attachment.name,
+                e
+            ),
+        }
+    }
+}
+
+/// Attachments of the messages, including the ones in inactive branches.
+fn collect_attachments_mut<'a>(messages: &'a mut [Message], out: &mut Vec<&'a mut Attachment>) {
+    for message in messages {
+        out.extend(message.content.all_attachments_mut());
+        for branch in &mut message.branches {
+            collect_attachments_mut(branch, out);
+        }
+    }
+}
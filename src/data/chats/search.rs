@@ -0,0 +1,417 @@
+//! Full-text search over the saved chats.
+//!
+//! The index maps each term to the messages containing it and is persisted next
+//! to the chats directory. It's updated one chat at a time from [`Chat::save`],
+//! skipping chats whose content didn't change since they were last indexed, and
+//! written to disk once the saves settle down.
+//!
+//! Messages of inactive branches are indexed under the message they branch
+//! from, which is where the conversation shows the other versions.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+use moly_kit::Message;
+use moly_kit::utils::asynchronous::{sleep, spawn};
+use serde::{Deserialize, Serialize};
+
+use super::chat::{Chat, ChatID};
+use crate::shared::utils::filesystem;
+
+const INDEX_PATH: &str = "chat_search_index.json";
+
+/// Time without changes to wait before writing the shared index to disk.
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Amount of characters kept around the first match in a snippet.
+const SNIPPET_CONTEXT_BEFORE: usize = 30;
+const SNIPPET_CONTEXT_AFTER: usize = 90;
+
+/// The part of a message where a term was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SearchField {
+    Text,
+    Reasoning,
+    Attachment,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct Posting {
+    chat_id: ChatID,
+    message_index: usize,
+    field: SearchField,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct IndexedChat {
+    fingerprint: u64,
+    terms: Vec<String>,
+}
+
+/// A message matching a search query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatSearchResult {
+    pub chat_id: ChatID,
+    pub message_index: usize,
+    pub field: SearchField,
+    /// Fragment of the matched field around the first match.
+    pub snippet: String,
+    /// Byte ranges of `snippet` matching the query.
+    pub highlights: Vec<Range<usize>>,
+}
+
+/// Inverted index over the text, reasoning and attachment names of the chat
+/// messages.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChatSearchIndex {
+    terms: BTreeMap<String, Vec<Posting>>,
+    chats: HashMap<ChatID, IndexedChat>,
+}
+
+impl ChatSearchIndex {
+    /// Reads the persisted index, or starts an empty one if there is none.
+    pub async fn load() -> Self {
+        filesystem::global()
+            .read_json(Path::new(INDEX_PATH))
+            .await
+            .unwrap_or_else(|e| {
+                log::info!("Chat search index not loaded, it will be rebuilt: {}", e);
+                Self::default()
+            })
+    }
+
+    pub async fn save(&self) {
+        if let Err(e) = filesystem::global()
+            .queue_write_json(PathBuf::from(INDEX_PATH), self)
+            .await
+        {
+            log::error!("Failed to save the chat search index: {}", e);
+        }
+    }
+
+    /// Indexes the messages of the chat, replacing what was indexed for it before.
+    ///
+    /// Returns `false` if the chat didn't change since it was last indexed.
+    pub fn update_chat(&mut self, chat_id: ChatID, messages: &[Message]) -> bool {
+        let fingerprint = fingerprint(messages);
+        if self
+            .chats
+            .get(&chat_id)
+            .is_some_and(|c| c.fingerprint == fingerprint)
+        {
+            return false;
+        }
+
+        self.remove_chat(chat_id);
+
+        let mut terms = HashSet::new();
+        for (message_index, message) in messages.iter().enumerate() {
+            for (field, text) in branches_fields(message) {
+                let posting = Posting {
+                    chat_id,
+                    message_index,
+                    field,
+                };
+
+                let message_terms = tokenize(text).map(|(_, term)| term).collect::<HashSet<_>>();
+
+                for term in message_terms {
+                    self.terms.entry(term.clone()).or_default().push(posting);
+                    terms.insert(term);
+                }
+            }
+        }
+
+        self.chats.insert(
+            chat_id,
+            IndexedChat {
+                fingerprint,
+                terms: terms.into_iter().collect(),
+            },
+        );
+
+        true
+    }
+
+    /// Returns `false` if the chat was not indexed.
+    pub fn remove_chat(&mut self, chat_id: ChatID) -> bool {
+        let Some(indexed) = self.chats.remove(&chat_id) else {
+            return false;
+        };
+
+        for term in indexed.terms {
+            if let Some(postings) = self.terms.get_mut(&term) {
+                postings.retain(|p| p.chat_id != chat_id);
+                if postings.is_empty() {
+                    self.terms.remove(&term);
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Indexes the given chats and drops any other, returning `true` if
+    /// anything changed.
+    pub fn sync<'a>(&mut self, chats: impl IntoIterator<Item = &'a Chat>) -> bool {
+        let mut changed = false;
+        let mut present = HashSet::new();
+
+        for chat in chats {
+            present.insert(chat.id);
+            changed |= self.update_chat(chat.id, &chat.messages);
+        }
+
+        let stale = self
+            .chats
+            .keys()
+            .filter(|id| !present.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for chat_id in stale {
+            changed |= self.remove_chat(chat_id);
+        }
+
+        changed
+    }
+
+    /// Finds the messages containing all the words of the query, newest chats
+    /// first.
+    ///
+    /// Words match terms starting with them, so results show up while typing.
+    /// The field of a match is where its first query word was found.
+    pub fn query(&self, query: &str) -> Vec<(ChatID, usize, SearchField)> {
+        let words = tokenize(query).map(|(_, w)| w).collect::<Vec<_>>();
+        let Some((first, rest)) = words.split_first() else {
+            return vec![];
+        };
+
+        let mut matches: BTreeMap<(ChatID, usize), SearchField> = BTreeMap::new();
+        for posting in self.postings_with_prefix(first) {
+            matches
+                .entry((posting.chat_id, posting.message_index))
+                .or_insert(posting.field);
+        }
+
+        for word in rest {
+            let found = self
+                .postings_with_prefix(word)
+                .map(|p| (p.chat_id, p.message_index))
+                .collect::<HashSet<_>>();
+            matches.retain(|key, _| found.contains(key));
+        }
+
+        let mut results = matches
+            .into_iter()
+            .map(|((chat_id, message_index), field)| (chat_id, message_index, field))
+            .collect::<Vec<_>>();
+
+        results.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        results
+    }
+
+    fn postings_with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a Posting> {
+        self.terms
+            .range(prefix.to_string()..)
+            .take_while(move |(term, _)| term.starts_with(prefix))
+            .flat_map(|(_, postings)| postings)
+    }
+}
+
+/// Shared index kept up to date as chats are saved.
+pub fn global() -> Arc<Mutex<ChatSearchIndex>> {
+    static INDEX: LazyLock<Arc<Mutex<ChatSearchIndex>>> = LazyLock::new(Default::default);
+    INDEX.clone()
+}
+
+/// Writes the shared index to disk after [`SAVE_DEBOUNCE`], unless it changes
+/// again in the meantime, so many chat saves in a row write it only once.
+pub fn save_later() {
+    static LAST_CHANGE: AtomicU64 = AtomicU64::new(0);
+    let change = LAST_CHANGE.fetch_add(1, Ordering::SeqCst) + 1;
+
+    spawn(async move {
+        sleep(SAVE_DEBOUNCE).await;
+        if LAST_CHANGE.load(Ordering::SeqCst) != change {
+            return;
+        }
+
+        // Serialized under the lock instead of cloning the whole index.
+        let content = serde_json::to_vec(&*global().lock().unwrap());
+        let result = match content {
+            Ok(content) => {
+                filesystem::global()
+                    .queue_write(PathBuf::from(INDEX_PATH), content)
+                    .await
+            }
+            Err(e) => Err(e.into()),
+        };
+
+        if let Err(e) = result {
+            log::error!("Failed to save the chat search index: {}", e);
+        }
+    });
+}
+
+/// Builds the snippet shown for a match in the given field of a message.
+pub fn snippet(
+    message: &Message,
+    field: SearchField,
+    query: &str,
+) -> Option<(String, Vec<Range<usize>>)> {
+    let words = tokenize(query).map(|(_, w)| w).collect::<Vec<_>>();
+    let (text, matched) = branches_fields(message)
+        .into_iter()
+        .filter(|(f, _)| *f == field)
+        .map(|(_, text)| {
+            let matched = tokenize(text)
+                .filter(|(_, term)| words.iter().any(|w| term.starts_with(w.as_str())))
+                .map(|(range, _)| range)
+                .collect::<Vec<_>>();
+            (text, matched)
+        })
+        .find(|(_, matched)| !matched.is_empty())?;
+
+    let first = &matched[0];
+    let start = text[..first.start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT_BEFORE.saturating_sub(1))
+        .map_or(0, |(i, _)| i);
+    let end = text[first.start..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT_AFTER)
+        .map_or(text.len(), |(i, _)| first.start + i);
+
+    let prefix = if start > 0 { "…" } else { "" };
+    let suffix = if end < text.len() { "…" } else { "" };
+    let snippet = format!("{}{}{}", prefix, &text[start..end], suffix).replace('\n', " ");
+
+    let offset = prefix.len();
+    let highlights = matched
+        .into_iter()
+        .filter(|r| r.start >= start && r.end <= end)
+        .map(|r| (r.start - start + offset)..(r.end - start + offset))
+        .collect();
+
+    Some((snippet, highlights))
+}
+
+fn message_fields(message: &Message) -> Vec<(SearchField, &str)> {
+    let mut fields = vec![
+        (SearchField::Text, message.content.text.as_str()),
+        (SearchField::Reasoning, message.content.reasoning.as_str()),
+    ];
+
+    fields.extend(
+        message
+            .content
+            .attachments
+            .iter()
+            .map(|a| (SearchField::Attachment, a.name.as_str())),
+    );
+
+    fields
+}
+
+/// Fields of the message and its inactive branches.
+fn branches_fields(message: &Message) -> Vec<(SearchField, &str)> {
+    message
+        .with_branches()
+        .into_iter()
+        .flat_map(message_fields)
+        .collect()
+}
+
+fn fingerprint(messages: &[Message]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for message in messages {
+        for (field, text) in branches_fields(message) {
+            field.hash(&mut hasher);
+            text.hash(&mut hasher);
+        }
+    }
+    messages.len().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Splits the text into lowercase alphanumeric terms, with their byte range in
+/// the original text.
+fn tokenize(text: &str) -> impl Iterator<Item = (Range<usize>, String)> + '_ {
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        while chars.next_if(|(_, c)| !c.is_alphanumeric()).is_some() {}
+        let (start, _) = *chars.peek()?;
+        let mut end = start;
+        while let Some((i, c)) = chars.next_if(|(_, c)| c.is_alphanumeric()) {
+            end = i + c.len_utf8();
+        }
+        Some((start..end, text[start..end].to_lowercase()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moly_kit::MessageContent;
+
+    fn message(text: &str) -> Message {
+        Message {
+            content: MessageContent {
+                text: text.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_index_query_and_update() {
+        let mut index = ChatSearchIndex::default();
+        index.update_chat(1, &[message("Hello there"), message("Rust borrow checker")]);
+        index.update_chat(2, &[message("Borrowing books")]);
+
+        assert_eq!(
+            index.query("borrow"),
+            vec![(2, 0, SearchField::Text), (1, 1, SearchField::Text)]
+        );
+        assert_eq!(index.query("rust BORR"), vec![(1, 1, SearchField::Text)]);
+        assert!(!index.update_chat(2, &[message("Borrowing books")]));
+
+        index.update_chat(2, &[message("Reading books")]);
+        assert_eq!(index.query("borrow"), vec![(1, 1, SearchField::Text)]);
+
+        index.remove_chat(1);
+        assert!(index.query("borrow").is_empty());
+        assert!(!index.terms.contains_key("rust"));
+    }
+
+    #[test]
+    fn test_inactive_branches_are_found_at_their_fork() {
+        let mut fork = message("Tell me a joke");
+        fork.branches = vec![vec![message("Tell me a riddle"), message("Sphinx")]];
+
+        let mut index = ChatSearchIndex::default();
+        index.update_chat(1, &[message("Hi"), fork.clone()]);
+
+        assert_eq!(index.query("sphinx"), vec![(1, 1, SearchField::Text)]);
+        let (snippet, _) = snippet(&fork, SearchField::Text, "riddle").unwrap();
+        assert_eq!(snippet, "Tell me a riddle");
+    }
+
+    #[test]
+    fn test_snippet_highlights_matches() {
+        let message = message("The quick brown fox");
+        let (snippet, highlights) = snippet(&message, SearchField::Text, "QUICK fox").unwrap();
+
+        assert_eq!(snippet, "The quick brown fox");
+        assert_eq!(highlights, vec![4..9, 16..19]);
+    }
+}
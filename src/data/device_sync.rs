@@ -0,0 +1,222 @@
+//! Keeps the providers, MCP servers, prompt templates and chats in sync with
+//! the devices paired through `moly-sync`.
+//!
+//! The state of the sync, including the pairings, is stored apart from the
+//! preferences so it's never shared with other devices.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+
+use moly_kit::utils::asynchronous::spawn;
+use moly_sync::{Pairing, SyncNode, SyncState};
+
+#[cfg(not(target_arch = "wasm32"))]
+use moly_sync::{DiscoveredPeer, PeerBrowser, SyncServerHandle};
+
+use crate::shared::utils::filesystem;
+
+const SYNC_DIR: &str = "sync";
+const SYNC_STATE_FILENAME: &str = "state.json";
+
+pub struct DeviceSync {
+    node: SyncNode,
+    #[cfg(not(target_arch = "wasm32"))]
+    server: Option<SyncServerHandle>,
+    /// Looks for devices sharing in the local network, while importing.
+    #[cfg(not(target_arch = "wasm32"))]
+    browser: Option<PeerBrowser>,
+    /// Why the paired devices can't connect to this one, shown to the user.
+    server_error: Option<String>,
+}
+
+impl DeviceSync {
+    /// Reads the stored state, or creates the one of a new device.
+    pub async fn load() -> Self {
+        let state = match filesystem::global()
+            .read_json::<SyncState>(&sync_state_path())
+            .await
+        {
+            Ok(state) => state,
+            Err(_) => {
+                log::info!("No sync state found, a new one will be created.");
+                SyncState::new(default_device_name()).expect("failed to create the sync state")
+            }
+        };
+
+        Self {
+            // Stored before answering the exchanges, since the peers don't
+            // send the changes again once answered.
+            node: SyncNode::with_persistence(state, |state| async move {
+                filesystem::global()
+                    .queue_write_json(sync_state_path(), &state)
+                    .await
+            }),
+            #[cfg(not(target_arch = "wasm32"))]
+            server: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            browser: None,
+            server_error: None,
+        }
+    }
+
+    pub fn node(&self) -> &SyncNode {
+        &self.node
+    }
+
+    pub fn pairings(&self) -> Vec<Pairing> {
+        self.node.lock().pairings.clone()
+    }
+
+    /// If there is anything to keep in sync, either because there are paired
+    /// devices or because new ones can pair now.
+    pub fn is_active(&self) -> bool {
+        !self.node.lock().pairings.is_empty() || self.is_sharing()
+    }
+
+    /// If some paired device connects to this one, which must keep serving.
+    pub fn is_serving_pairings(&self) -> bool {
+        self.node
+            .lock()
+            .pairings
+            .iter()
+            .any(|p| p.address.is_none())
+    }
+
+    pub fn forget_pairings(&mut self) {
+        self.node.lock().pairings.clear();
+        self.server_error = None;
+        self.save();
+
+        if !self.is_sharing() {
+            self.stop_server();
+        }
+    }
+
+    pub fn save(&self) {
+        let state = self.node.lock().clone();
+        spawn(async move {
+            if let Err(e) = filesystem::global()
+                .queue_write_json(sync_state_path(), &state)
+                .await
+            {
+                log::error!("Failed to write the sync state: {:?}", e);
+            }
+        });
+    }
+
+    /// Address the sync server listens at, all the interfaces by default.
+    pub fn listen_address(&self) -> IpAddr {
+        self.node
+            .lock()
+            .listen_address
+            .unwrap_or(Ipv4Addr::UNSPECIFIED.into())
+    }
+
+    /// Takes effect the next time the server is started.
+    pub fn set_listen_address(&mut self, address: Option<IpAddr>) {
+        self.node.lock().listen_address = address;
+        self.save();
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn server(&self) -> Option<&SyncServerHandle> {
+        self.server.as_ref()
+    }
+
+    /// Keeps the started server, and its port to reuse it next time.
+    ///
+    /// If the port changed, the devices paired before can't reach this one
+    /// anymore, so the user is told to pair them again.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_server(&mut self, server: SyncServerHandle) {
+        let port = server.addr.port();
+        let previous_port = self.node.lock().listen_port.replace(port);
+        self.save();
+
+        self.server_error = match previous_port {
+            Some(previous_port) if previous_port != port && self.is_serving_pairings() => {
+                Some(format!(
+                    "Port {} is no longer available, so the paired devices can't connect. \
+                     Pair them again.",
+                    previous_port
+                ))
+            }
+            _ => None,
+        };
+
+        if let Some(previous) = self.server.replace(server) {
+            previous.stop();
+        }
+    }
+
+    /// Why the paired devices can't connect to this one, if they can't.
+    pub fn server_error(&self) -> Option<&str> {
+        self.server_error.as_deref()
+    }
+
+    /// Takes note that the server for the paired devices couldn't start.
+    pub fn set_server_error(&mut self, error: String) {
+        self.server_error = Some(error);
+    }
+
+    /// Stops accepting new pairings. The server keeps running if paired
+    /// devices connect to it.
+    pub fn stop_sharing(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(server) = &self.server {
+            server.stop_sharing();
+        }
+
+        if !self.is_serving_pairings() {
+            self.stop_server();
+        }
+    }
+
+    /// Looks for other devices sharing in the local network, calling
+    /// `on_change` with the ones found every time they change, until
+    /// [`Self::stop_browsing`] is called.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn browse_nearby(&mut self, on_change: impl Fn(Vec<DiscoveredPeer>) + Send + 'static) {
+        let device_id = self.node.lock().device_id().to_string();
+        match PeerBrowser::start(&device_id, on_change) {
+            Ok(browser) => self.browser = Some(browser),
+            Err(e) => log::warn!("Failed to look for nearby devices: {}", e),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn stop_browsing(&mut self) {
+        self.browser = None;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn is_sharing(&self) -> bool {
+        self.server.as_ref().is_some_and(|s| s.is_sharing())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn is_sharing(&self) -> bool {
+        false
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn stop_server(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.stop();
+            log::info!("Sync server stopped");
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn stop_server(&mut self) {}
+}
+
+fn default_device_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .unwrap_or_else(|_| "Moly".to_string())
+}
+
+fn sync_state_path() -> PathBuf {
+    Path::new(SYNC_DIR).join(SYNC_STATE_FILENAME)
+}
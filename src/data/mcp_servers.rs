@@ -1,4 +1,6 @@
 use indexmap::IndexMap;
+use moly_kit::mcp::local_tools::BUILTIN_TOOLS_ID;
+use moly_kit::mcp::{BuiltinTool, BuiltinToolProvider, ToolPolicy};
 use serde::{Deserialize, Serialize};
 
 /// Represents an input configuration for MCP servers
@@ -30,6 +32,8 @@ pub struct McpServer {
     pub transport_type: Option<String>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     pub headers: IndexMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oauth: Option<OAuthConfig>,
 
     // Optional extras
     #[serde(
@@ -39,6 +43,32 @@ pub struct McpServer {
     pub enabled: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub working_directory: Option<String>,
+    /// Time limit in seconds for the calls to the tools of this server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Time limits in seconds for specific tools, by tool name
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tool_timeouts: IndexMap<String, u64>,
+    /// Whether the tools of this server need approval to run, unless
+    /// overridden in `tool_policies`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_policy: Option<ToolPolicy>,
+    /// Approval policies for specific tools, by tool name
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tool_policies: IndexMap<String, ToolPolicy>,
+}
+
+/// OAuth client credentials for HTTP/SSE servers requiring an access token
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OAuthConfig {
+    pub token_url: String,
+    pub client_id: String,
+    /// Only kept here until it's moved to the secret store, under
+    /// [`oauth_secret_name`]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub client_secret: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
 }
 
 fn default_enabled() -> bool {
@@ -67,8 +97,13 @@ impl McpServer {
             url: None,
             transport_type: None,
             headers: IndexMap::new(),
+            oauth: None,
             enabled: true,
             working_directory: None,
+            timeout: None,
+            tool_timeouts: IndexMap::new(),
+            tool_policy: None,
+            tool_policies: IndexMap::new(),
         }
     }
 
@@ -81,8 +116,13 @@ impl McpServer {
             url: Some(url),
             transport_type: Some("http".to_string()),
             headers: IndexMap::new(),
+            oauth: None,
             enabled: true,
             working_directory: None,
+            timeout: None,
+            tool_timeouts: IndexMap::new(),
+            tool_policy: None,
+            tool_policies: IndexMap::new(),
         }
     }
 
@@ -95,8 +135,13 @@ impl McpServer {
             url: Some(url),
             transport_type: Some("sse".to_string()),
             headers: IndexMap::new(),
+            oauth: None,
             enabled: true,
             working_directory: None,
+            timeout: None,
+            tool_timeouts: IndexMap::new(),
+            tool_policy: None,
+            tool_policies: IndexMap::new(),
         }
     }
 
@@ -144,9 +189,106 @@ impl McpServer {
         self.headers = headers;
         self
     }
+
+    /// Set OAuth client credentials for HTTP/SSE transport
+    pub fn with_oauth(mut self, oauth: OAuthConfig) -> Self {
+        self.oauth = Some(oauth);
+        self
+    }
 }
 
 impl McpServer {
+    /// Ids of the `${input:id}` placeholders used in `command`, `args`, `env`,
+    /// `url` and `headers`, in order of appearance and without duplicates
+    pub fn input_placeholders(&self) -> Vec<String> {
+        let mut ids: Vec<String> = Vec::new();
+        for text in self.resolvable_fields() {
+            for (_, id) in input_placeholders(text) {
+                if !ids.iter().any(|i| i == id) {
+                    ids.push(id.to_string());
+                }
+            }
+        }
+        ids
+    }
+
+    /// Copy of this server with the input placeholders replaced by their values
+    ///
+    /// Fails with the ids of the inputs without a value.
+    pub fn resolve_inputs(
+        &self,
+        value_of: impl Fn(&str) -> Option<String>,
+    ) -> Result<McpServer, Vec<String>> {
+        let mut resolved = self.clone();
+        let mut missing = Vec::new();
+
+        let fields = resolved
+            .command
+            .iter_mut()
+            .chain(resolved.args.iter_mut())
+            .chain(resolved.env.values_mut())
+            .chain(resolved.url.iter_mut())
+            .chain(resolved.headers.values_mut());
+
+        for field in fields {
+            *field = replace_input_placeholders(field, &value_of, &mut missing);
+        }
+
+        if missing.is_empty() {
+            Ok(resolved)
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Copy of this server as shared with paired devices, without the
+    /// settings that only apply to this device: the tool policies, and whether
+    /// the command of a stdio server may run.
+    pub fn without_local_settings(&self) -> McpServer {
+        McpServer {
+            enabled: self.command.is_none() && self.enabled,
+            tool_policy: None,
+            tool_policies: IndexMap::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Takes a server received from a paired device, keeping the local
+    /// settings of the server it replaces, if any.
+    ///
+    /// A command received from another device is never run until the user
+    /// enables its server here, so a stdio server stays enabled only if it
+    /// runs the same command as before.
+    pub fn with_local_settings(self, local: Option<&McpServer>) -> McpServer {
+        let same_command = local.is_some_and(|local| {
+            local.command == self.command
+                && local.args == self.args
+                && local.env == self.env
+                && local.working_directory == self.working_directory
+        });
+
+        McpServer {
+            enabled: match &self.command {
+                Some(_) => same_command && local.is_some_and(|local| local.enabled),
+                None => self.enabled,
+            },
+            tool_policy: local.and_then(|local| local.tool_policy),
+            tool_policies: local
+                .map(|local| local.tool_policies.clone())
+                .unwrap_or_default(),
+            ..self
+        }
+    }
+
+    fn resolvable_fields(&self) -> impl Iterator<Item = &String> {
+        self.command
+            .iter()
+            .chain(self.args.iter())
+            .chain(self.env.values())
+            .chain(self.url.iter())
+            .chain(self.headers.values())
+    }
+
     /// Convert this server configuration to a transport for the MCP manager
     #[cfg(not(target_arch = "wasm32"))]
     pub fn to_transport(&self) -> Option<moly_kit::mcp::mcp_manager::McpTransport> {
@@ -167,10 +309,22 @@ impl McpServer {
 
             Some(moly_kit::mcp::mcp_manager::McpTransport::Stdio(command))
         } else if let Some(url) = &self.url {
+            let mut http = moly_kit::mcp::mcp_manager::McpHttpTransport::new(url.clone())
+                .with_headers(self.headers.clone());
+
+            if let Some(oauth) = &self.oauth {
+                http = http.with_oauth(moly_kit::mcp::mcp_manager::McpOAuthCredentials {
+                    token_url: oauth.token_url.clone(),
+                    client_id: oauth.client_id.clone(),
+                    client_secret: oauth.client_secret.clone(),
+                    scope: oauth.scope.clone(),
+                });
+            }
+
             // Network transport - determine if HTTP or SSE
             match self.transport_type.as_deref() {
-                Some("sse") => Some(moly_kit::mcp::mcp_manager::McpTransport::Sse(url.clone())),
-                _ => Some(moly_kit::mcp::mcp_manager::McpTransport::Http(url.clone())),
+                Some("sse") => Some(moly_kit::mcp::mcp_manager::McpTransport::Sse(http)),
+                _ => Some(moly_kit::mcp::mcp_manager::McpTransport::Http(http)),
             }
         } else {
             None
@@ -183,6 +337,109 @@ impl McpServer {
     }
 }
 
+/// Name under which the value of an input is kept in the secret store
+pub fn input_secret_name(input_id: &str) -> String {
+    format!("mcp_input:{}", input_id)
+}
+
+/// Name under which the OAuth client secret of a server is kept in the secret
+/// store
+pub fn oauth_secret_name(server_id: &str) -> String {
+    format!("mcp_oauth:{}", server_id)
+}
+
+/// Finds the `${input:id}` placeholders in the text, with their byte range and id
+fn input_placeholders(text: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    const PREFIX: &str = "${input:";
+
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find(PREFIX).map(|i| offset + i) {
+        let id_start = start + PREFIX.len();
+        let Some(end) = text[id_start..].find('}').map(|i| id_start + i) else {
+            break;
+        };
+
+        found.push((start..end + 1, text[id_start..end].trim()));
+        offset = end + 1;
+    }
+    found
+}
+
+fn replace_input_placeholders(
+    text: &str,
+    value_of: &impl Fn(&str) -> Option<String>,
+    missing: &mut Vec<String>,
+) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for (range, id) in input_placeholders(text) {
+        match value_of(id) {
+            Some(value) => {
+                replaced.push_str(&text[last..range.start]);
+                replaced.push_str(&value);
+                last = range.end;
+            }
+            None if !missing.iter().any(|m| m == id) => missing.push(id.to_string()),
+            None => {}
+        }
+    }
+
+    replaced.push_str(&text[last..]);
+    replaced
+}
+
+/// Tools run by Moly itself, without an external server
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BuiltinToolsConfig {
+    /// Names of the enabled tools, like `read_file`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enabled: Vec<String>,
+    /// Directory the file tools are restricted to. They are not available
+    /// without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+}
+
+impl BuiltinToolsConfig {
+    pub fn is_empty(&self) -> bool {
+        self.enabled.is_empty() && self.workspace.is_none()
+    }
+
+    pub fn is_enabled(&self, tool: BuiltinTool) -> bool {
+        self.enabled.iter().any(|name| name == tool.name())
+    }
+
+    pub fn set_enabled(&mut self, tool: BuiltinTool, enabled: bool) {
+        self.enabled.retain(|name| name != tool.name());
+        if enabled {
+            self.enabled.push(tool.name().to_string());
+        }
+    }
+
+    /// The provider of the enabled tools, if any is enabled
+    pub fn to_provider(&self) -> Option<BuiltinToolProvider> {
+        let tools = BuiltinTool::ALL
+            .into_iter()
+            .filter(|tool| self.is_enabled(*tool))
+            .collect::<Vec<_>>();
+
+        if tools.is_empty() {
+            return None;
+        }
+
+        let mut provider = BuiltinToolProvider::new();
+        if let Some(workspace) = self.workspace.as_ref().filter(|w| !w.trim().is_empty()) {
+            provider = provider.with_workspace(workspace.trim());
+        }
+        for tool in tools {
+            provider = provider.with_tool(tool);
+        }
+        Some(provider)
+    }
+}
+
 /// Represents the complete MCP servers configuration (follows MCP standard format)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpServersConfig {
@@ -193,6 +450,11 @@ pub struct McpServersConfig {
     pub enabled: bool,
     #[serde(default = "default_dangerous_mode_enabled")]
     pub dangerous_mode_enabled: bool,
+    /// How many tool calls may run at the same time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_tool_calls: Option<usize>,
+    #[serde(default, skip_serializing_if = "BuiltinToolsConfig::is_empty")]
+    pub builtin_tools: BuiltinToolsConfig,
 }
 
 impl Default for McpServersConfig {
@@ -202,6 +464,8 @@ impl Default for McpServersConfig {
             inputs: Vec::new(),
             enabled: true,
             dangerous_mode_enabled: false,
+            max_concurrent_tool_calls: None,
+            builtin_tools: BuiltinToolsConfig::default(),
         }
     }
 }
@@ -235,12 +499,47 @@ impl McpServersConfig {
         self.inputs.iter().find(|input| input.id == id)
     }
 
+    /// The declared input with the given id, or a plain text one if a server
+    /// uses it without declaring it in `inputs`
+    pub fn get_input_or_default(&self, id: &str) -> InputConfig {
+        self.get_input(id).cloned().unwrap_or_else(|| InputConfig {
+            id: id.to_string(),
+            input_type: "promptString".to_string(),
+            description: id.to_string(),
+            password: false,
+        })
+    }
+
+    /// Removes the OAuth client secrets written in the configuration, to be
+    /// kept in the secret store instead
+    ///
+    /// Returns them with the id of their server.
+    pub fn take_oauth_secrets(&mut self) -> Vec<(String, String)> {
+        self.servers
+            .iter_mut()
+            .filter_map(|(id, server)| {
+                let oauth = server.oauth.as_mut()?;
+                let secret = std::mem::take(&mut oauth.client_secret);
+                (!secret.is_empty()).then(|| (id.clone(), secret))
+            })
+            .collect()
+    }
+
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
         serde_json::to_string_pretty(self)
     }
 
+    /// Parses the config, failing if a server takes the id of the built-in
+    /// tools.
     pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(json)
+        let config: Self = serde_json::from_str(json)?;
+        if config.servers.contains_key(BUILTIN_TOOLS_ID) {
+            return Err(serde::de::Error::custom(format!(
+                "the server id '{}' is reserved for the built-in tools",
+                BUILTIN_TOOLS_ID
+            )));
+        }
+        Ok(config)
     }
 
     pub fn create_sample() -> Self {
@@ -343,6 +642,100 @@ mod tests {
         assert!(json.contains("\"type\": \"sse\""));
     }
 
+    #[test]
+    fn test_synced_servers_keep_local_settings() {
+        let mut local = McpServer::stdio("npx".to_string(), vec!["server".to_string()]);
+        local
+            .tool_policies
+            .insert("read".to_string(), ToolPolicy::Allow);
+
+        let mut remote = local.clone();
+        remote
+            .tool_policies
+            .insert("delete".to_string(), ToolPolicy::Allow);
+        let synced = remote.without_local_settings();
+        assert!(synced.tool_policies.is_empty());
+
+        let unchanged = synced.clone().with_local_settings(Some(&local));
+        assert!(unchanged.enabled);
+        assert_eq!(unchanged.tool_policies, local.tool_policies);
+
+        let mut changed = synced.clone();
+        changed.args.push("--evil".to_string());
+        assert!(!changed.with_local_settings(Some(&local)).enabled);
+
+        let new = synced.with_local_settings(None);
+        assert!(!new.enabled);
+        assert!(new.tool_policies.is_empty());
+
+        let http = McpServer::http("http://localhost:8931".to_string());
+        assert!(
+            http.without_local_settings()
+                .with_local_settings(None)
+                .enabled
+        );
+    }
+
+    #[test]
+    fn test_tool_policies_round_trip() {
+        let mut server = McpServer::http("http://localhost:8931".to_string());
+        server.tool_policy = Some(ToolPolicy::Ask);
+        server
+            .tool_policies
+            .insert("delete_file".to_string(), ToolPolicy::Deny);
+        server
+            .tool_policies
+            .insert("read_file".to_string(), ToolPolicy::AllowForChat);
+
+        let json = serde_json::to_string(&server).unwrap();
+        assert!(json.contains(r#""tool_policy":"ask""#));
+        assert!(json.contains(r#""delete_file":"deny""#));
+        assert!(json.contains(r#""read_file":"allow_for_chat""#));
+
+        let deserialized: McpServer = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.tool_policy, Some(ToolPolicy::Ask));
+        assert_eq!(deserialized.tool_policies, server.tool_policies);
+    }
+
+    #[test]
+    fn test_builtin_tools_config() {
+        let mut config = McpServersConfig::from_json(r#"{"servers": {}}"#).unwrap();
+        assert!(config.builtin_tools.to_provider().is_none());
+
+        config
+            .builtin_tools
+            .set_enabled(BuiltinTool::Calculate, true);
+        config
+            .builtin_tools
+            .set_enabled(BuiltinTool::ReadFile, true);
+        config
+            .builtin_tools
+            .set_enabled(BuiltinTool::Calculate, false);
+
+        let json = config.to_json().unwrap();
+        let deserialized = McpServersConfig::from_json(&json).unwrap();
+        assert_eq!(deserialized.builtin_tools.enabled, vec!["read_file"]);
+
+        // File tools need a workspace.
+        let provider = deserialized.builtin_tools.to_provider().unwrap();
+        assert!(provider.available_tools().is_empty());
+
+        config.builtin_tools.workspace = Some("/home/user/project".to_string());
+        let provider = config.builtin_tools.to_provider().unwrap();
+        assert_eq!(provider.available_tools(), vec![BuiltinTool::ReadFile]);
+    }
+
+    #[test]
+    fn test_builtin_tools_id_is_reserved() {
+        let json = r#"{"servers": {"builtin": {"url": "http://localhost:8931"}}}"#;
+        let error = McpServersConfig::from_json(json).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("reserved for the built-in tools")
+        );
+    }
+
     #[test]
     fn test_stdio_server_creation() {
         let server = McpServer::stdio("node".to_string(), vec!["server.js".to_string()])
@@ -413,4 +806,97 @@ mod tests {
         assert_eq!(imagesorcery_server.command.as_ref().unwrap(), "uvx");
         assert_eq!(imagesorcery_server.args, vec!["imagesorcery-mcp"]);
     }
+
+    #[test]
+    fn test_network_transport_keeps_headers_and_oauth() {
+        use moly_kit::mcp::mcp_manager::McpTransport;
+
+        let json = r#"{
+            "url": "https://mcp.internal/sse",
+            "type": "sse",
+            "headers": {"Authorization": "Bearer token"},
+            "oauth": {
+                "token_url": "https://auth.internal/token",
+                "client_id": "moly",
+                "client_secret": "secret"
+            }
+        }"#;
+        let server: McpServer = serde_json::from_str(json).unwrap();
+
+        let Some(McpTransport::Sse(http)) = server.to_transport() else {
+            panic!("expected an SSE transport");
+        };
+        assert_eq!(http.url, "https://mcp.internal/sse");
+        assert_eq!(
+            http.headers,
+            vec![("Authorization".to_string(), "Bearer token".to_string())]
+        );
+        assert_eq!(http.oauth.unwrap().client_id, "moly");
+    }
+
+    #[test]
+    fn test_oauth_secrets_are_taken_out_of_the_config() {
+        let json = r#"{
+            "servers": {
+                "internal": {
+                    "url": "https://mcp.internal/mcp",
+                    "oauth": {
+                        "token_url": "https://auth.internal/token",
+                        "client_id": "moly",
+                        "client_secret": "secret"
+                    }
+                },
+                "local": {"command": "uvx", "args": ["server"]}
+            }
+        }"#;
+        let mut config = McpServersConfig::from_json(json).unwrap();
+
+        assert_eq!(
+            config.take_oauth_secrets(),
+            vec![("internal".to_string(), "secret".to_string())]
+        );
+        assert!(config.take_oauth_secrets().is_empty());
+
+        let json = config.to_json().unwrap();
+        assert!(!json.contains("client_secret"));
+        assert!(json.contains(r#""client_id": "moly""#));
+    }
+
+    #[test]
+    fn test_resolve_input_placeholders() {
+        let mut headers = IndexMap::new();
+        headers.insert(
+            "Authorization".to_string(),
+            "Bearer ${input:token}".to_string(),
+        );
+        let server = McpServer::http("https://${input:host}/mcp".to_string()).with_headers(headers);
+
+        assert_eq!(server.input_placeholders(), vec!["host", "token"]);
+        assert_eq!(
+            server.resolve_inputs(|_| None).unwrap_err(),
+            vec!["host", "token"]
+        );
+
+        let resolved = server
+            .resolve_inputs(|id| Some(format!("<{}>", id)))
+            .unwrap();
+        assert_eq!(resolved.url.unwrap(), "https://<host>/mcp");
+        assert_eq!(resolved.headers["Authorization"], "Bearer <token>");
+    }
+
+    #[test]
+    fn test_resolve_input_placeholders_in_command() {
+        let server = McpServer::stdio(
+            "${input:runtime}".to_string(),
+            vec!["--token=${input:token}".to_string()],
+        );
+
+        assert_eq!(server.input_placeholders(), vec!["runtime", "token"]);
+
+        let resolved = server
+            .resolve_inputs(|id| Some(format!("<{}>", id)))
+            .unwrap();
+        assert_eq!(resolved.command.unwrap(), "<runtime>");
+        assert_eq!(resolved.args, vec!["--token=<token>"]);
+    }
 }
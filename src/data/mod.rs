@@ -1,11 +1,17 @@
 pub mod bot_fetcher;
 pub mod capture;
+pub mod chat_transfer;
 pub mod chats;
+pub mod device_sync;
 pub mod downloads;
 pub mod mcp_servers;
 pub mod moly_client;
 pub mod preferences;
+pub mod prompt_templates;
 pub mod providers;
 pub mod search;
+pub mod secrets;
 pub mod store;
 pub mod supported_providers;
+pub mod tool_audit;
+pub mod usage;
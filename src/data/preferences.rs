@@ -1,11 +1,13 @@
-use moly_kit::{BotId, utils::asynchronous::spawn};
+use moly_kit::{BotId, ToolPolicy, utils::asynchronous::spawn};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use crate::data::providers::ProviderID;
 use crate::shared::utils::filesystem;
 
-use super::mcp_servers::McpServersConfig;
+use super::mcp_servers::{BuiltinToolsConfig, McpServer, McpServersConfig};
+use super::prompt_templates::PromptTemplate;
 use super::providers::{Provider, ProviderType};
 
 const PREFERENCES_DIR: &str = "preferences";
@@ -20,6 +22,13 @@ pub struct Preferences {
     pub providers_preferences: Vec<ProviderPreferences>,
     #[serde(default)]
     pub mcp_servers_config: McpServersConfig,
+    /// User defined groups of equivalent bots (e.g. the same model on different
+    /// providers), used as fallbacks of each other. Maps a bot to its group name.
+    #[serde(default)]
+    pub bot_equivalence_groups: HashMap<BotId, String>,
+    /// Library of reusable system prompts, unique by name.
+    #[serde(default)]
+    pub prompt_templates: Vec<PromptTemplate>,
 }
 
 impl Default for Preferences {
@@ -29,6 +38,8 @@ impl Default for Preferences {
             downloaded_files_dir: default_model_downloads_dir().to_path_buf(),
             providers_preferences: vec![],
             mcp_servers_config: McpServersConfig::new(),
+            bot_equivalence_groups: HashMap::new(),
+            prompt_templates: vec![],
         }
     }
 }
@@ -41,6 +52,9 @@ impl Preferences {
             Ok(mut preferences) => {
                 // Migrate providers without IDs
                 preferences.migrate_provider_ids();
+                if preferences.migrate_anthropic_providers() {
+                    preferences.save();
+                }
                 preferences
             }
             Err(_e) => {
@@ -73,7 +87,15 @@ impl Preferences {
         self.save();
     }
 
-    pub fn insert_or_update_provider(&mut self, provider: &Provider) {
+    /// Keeps the provider, with its API key in the secret store, or in plain
+    /// text while the store is locked so it's moved there once unlocked.
+    pub fn insert_or_update_provider(&mut self, provider: &Provider, secrets_locked: bool) {
+        self.apply_provider(provider, secrets_locked);
+        self.save();
+    }
+
+    fn apply_provider(&mut self, provider: &Provider, secrets_locked: bool) {
+        let (api_key, api_key_secret) = stored_api_key(provider, secrets_locked);
         if let Some(existing_provider) = self
             .providers_preferences
             .iter_mut()
@@ -81,7 +103,8 @@ impl Preferences {
         {
             existing_provider.id = provider.id.clone();
             existing_provider.url = provider.url.clone();
-            existing_provider.api_key = provider.api_key.clone();
+            existing_provider.api_key = api_key;
+            existing_provider.api_key_secret = api_key_secret;
             existing_provider.enabled = provider.enabled;
             existing_provider.system_prompt = provider.system_prompt.clone();
             existing_provider.tools_enabled = provider.tools_enabled;
@@ -90,7 +113,8 @@ impl Preferences {
                 id: provider.id.clone(),
                 name: provider.name.clone(),
                 url: provider.url.clone(),
-                api_key: provider.api_key.clone(),
+                api_key,
+                api_key_secret,
                 enabled: provider.enabled,
                 provider_type: provider.provider_type.clone(),
                 models: provider
@@ -103,7 +127,6 @@ impl Preferences {
                 tools_enabled: provider.tools_enabled,
             });
         }
-        self.save();
     }
 
     pub fn remove_provider(&mut self, provider_id: &ProviderID) {
@@ -133,10 +156,46 @@ impl Preferences {
         self.save();
     }
 
+    /// Sets the equivalence group of a bot, or removes it from any group if the
+    /// name is empty.
+    pub fn set_bot_equivalence_group(&mut self, bot_id: &BotId, group: &str) {
+        let group = group.trim();
+        if group.is_empty() {
+            self.bot_equivalence_groups.remove(bot_id);
+        } else {
+            self.bot_equivalence_groups
+                .insert(bot_id.clone(), group.to_string());
+        }
+        self.save();
+    }
+
+    /// Adds the template to the library, replacing the one with the same name.
+    pub fn save_prompt_template(&mut self, template: PromptTemplate) {
+        match self
+            .prompt_templates
+            .iter_mut()
+            .find(|t| t.name == template.name)
+        {
+            Some(existing) => *existing = template,
+            None => self.prompt_templates.push(template),
+        }
+        self.save();
+    }
+
+    pub fn remove_prompt_template(&mut self, name: &str) {
+        self.prompt_templates.retain(|t| t.name != name);
+        self.save();
+    }
+
+    pub fn get_prompt_template(&self, name: &str) -> Option<&PromptTemplate> {
+        self.prompt_templates.iter().find(|t| t.name == name)
+    }
+
     /// Import preferences from a JSON string
     ///
     /// If merge is true, the provider preferences will be extended with the new ones,
-    /// otherwise, the existing preferences will be replaced.
+    /// replacing the existing ones with the same id, otherwise, the existing
+    /// preferences will be replaced.
     ///
     /// If include_mcp_servers is true, the MCP servers will be included in the import, replacing the existing ones.
     pub fn import_from_json(
@@ -147,10 +206,24 @@ impl Preferences {
     ) -> Result<(), serde_json::Error> {
         let preferences = serde_json::from_str::<Preferences>(json)?;
         if merge {
-            self.providers_preferences
-                .extend(preferences.providers_preferences.clone());
+            merge_provider_preferences(
+                &mut self.providers_preferences,
+                preferences.providers_preferences.clone(),
+            );
+            self.bot_equivalence_groups
+                .extend(preferences.bot_equivalence_groups);
+            // Imported templates win over local ones with the same name.
+            self.prompt_templates.retain(|t| {
+                !preferences
+                    .prompt_templates
+                    .iter()
+                    .any(|p| p.name == t.name)
+            });
+            self.prompt_templates.extend(preferences.prompt_templates);
         } else {
             self.providers_preferences = preferences.providers_preferences.clone();
+            self.bot_equivalence_groups = preferences.bot_equivalence_groups;
+            self.prompt_templates = preferences.prompt_templates;
         }
 
         if include_mcp_servers {
@@ -161,8 +234,38 @@ impl Preferences {
         Ok(())
     }
 
+    /// Adds or replaces, by id, a provider received from a paired device.
+    pub fn set_synced_provider(&mut self, provider: ProviderPreferences) {
+        merge_provider_preferences(&mut self.providers_preferences, vec![provider]);
+        self.save();
+    }
+
+    /// Adds, replaces or removes an MCP server received from a paired device,
+    /// see [`McpServer::with_local_settings`].
+    pub fn set_synced_mcp_server(&mut self, server_id: &str, server: Option<McpServer>) {
+        match server {
+            Some(server) => {
+                let local = self.mcp_servers_config.servers.get(server_id);
+                let server = server.with_local_settings(local);
+                self.mcp_servers_config
+                    .servers
+                    .insert(server_id.to_string(), server);
+            }
+            None => {
+                self.mcp_servers_config.servers.shift_remove(server_id);
+            }
+        }
+        self.save();
+    }
+
+    /// The preferences as shared with other devices, without API keys, which
+    /// are given apart.
     pub fn as_json(&self) -> String {
-        serde_json::to_string(self).unwrap()
+        let mut preferences = self.clone();
+        for provider in &mut preferences.providers_preferences {
+            *provider = provider.without_api_key();
+        }
+        serde_json::to_string(&preferences).unwrap()
     }
 
     pub fn get_mcp_servers_config_json(&self) -> String {
@@ -171,11 +274,9 @@ impl Preferences {
             .unwrap_or_else(|_| "{}".to_string())
     }
 
-    pub fn update_mcp_servers_from_json(&mut self, json: &str) -> Result<(), serde_json::Error> {
-        let config = McpServersConfig::from_json(json)?;
+    pub fn set_mcp_servers_config(&mut self, config: McpServersConfig) {
         self.mcp_servers_config = config;
         self.save();
-        Ok(())
     }
 
     pub fn set_mcp_servers_enabled(&mut self, enabled: bool) {
@@ -197,6 +298,30 @@ impl Preferences {
         self.mcp_servers_config.dangerous_mode_enabled
     }
 
+    pub fn set_mcp_builtin_tools(&mut self, builtin_tools: BuiltinToolsConfig) {
+        self.mcp_servers_config.builtin_tools = builtin_tools;
+        self.save();
+    }
+
+    pub fn set_mcp_tool_policy(&mut self, server_id: &str, tool_name: &str, policy: ToolPolicy) {
+        let Some(server) = self.mcp_servers_config.servers.get_mut(server_id) else {
+            return;
+        };
+
+        server.tool_policies.insert(tool_name.to_string(), policy);
+        self.save();
+    }
+
+    pub fn remove_mcp_tool_policy(&mut self, server_id: &str, tool_name: &str) {
+        let Some(server) = self.mcp_servers_config.servers.get_mut(server_id) else {
+            return;
+        };
+
+        if server.tool_policies.shift_remove(tool_name).is_some() {
+            self.save();
+        }
+    }
+
     /// Migrate providers without IDs by generating them from URLs
     fn migrate_provider_ids(&mut self) {
         let mut needs_save = false;
@@ -210,6 +335,71 @@ impl Preferences {
             self.save();
         }
     }
+
+    /// Moves the providers added as OpenAI compatible but pointing to the
+    /// Anthropic API to the native Anthropic client.
+    ///
+    /// Returns if any provider was migrated.
+    fn migrate_anthropic_providers(&mut self) -> bool {
+        let mut migrated = false;
+        for provider in &mut self.providers_preferences {
+            if provider.provider_type == ProviderType::OpenAI && is_anthropic_api(&provider.url) {
+                provider.provider_type = ProviderType::Anthropic;
+                provider.url = provider.url.trim_end_matches('/').to_string();
+                migrated = true;
+            }
+        }
+        migrated
+    }
+}
+
+fn is_anthropic_api(url: &str) -> bool {
+    url::Url::parse(url).is_ok_and(|url| {
+        url.host_str()
+            .is_some_and(|host| host == "anthropic.com" || host.ends_with(".anthropic.com"))
+    })
+}
+
+/// Adds the imported providers, replacing the existing ones with the same id.
+///
+/// Imported providers without an API key keep the local one, as providers from
+/// paired devices never carry it (and the secrets may still be locked here).
+fn merge_provider_preferences(
+    existing: &mut Vec<ProviderPreferences>,
+    imported: Vec<ProviderPreferences>,
+) {
+    for mut provider in imported {
+        provider.ensure_id();
+        match existing.iter_mut().find(|p| p.id == provider.id) {
+            Some(existing) => {
+                if provider.api_key.is_none() {
+                    provider.api_key = existing.api_key.take();
+                }
+                if provider.api_key_secret.is_none() {
+                    provider.api_key_secret = existing.api_key_secret.take();
+                }
+                *existing = provider;
+            }
+            None => existing.push(provider),
+        }
+    }
+}
+
+/// Name under which the API key of a provider is kept in the secret store
+pub fn api_key_secret_name(provider_id: &str) -> String {
+    format!("provider_api_key:{}", provider_id)
+}
+
+/// The plain text API key and the name of the secret to keep for the provider.
+///
+/// Secrets set while the store is locked only live in memory, so the key stays
+/// in plain text until it's unlocked.
+fn stored_api_key(provider: &Provider, secrets_locked: bool) -> (Option<String>, Option<String>) {
+    match &provider.api_key {
+        Some(api_key) if secrets_locked => (Some(api_key.clone()), None),
+        Some(_) => (None, Some(api_key_secret_name(&provider.id))),
+        None => (None, None),
+    }
 }
 
 fn preferences_path() -> PathBuf {
@@ -223,7 +413,13 @@ pub struct ProviderPreferences {
     pub id: ProviderID,
     pub name: String,
     pub url: String,
+    /// API key in plain text, as stored by older versions. It's moved to the
+    /// secret store as soon as it's unlocked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub api_key: Option<String>,
+    /// Name of the API key in the secret store, if the provider has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_secret: Option<String>,
     pub enabled: bool,
     pub provider_type: ProviderType,
     // (model_name, enabled)
@@ -250,6 +446,16 @@ impl ProviderPreferences {
         }
     }
 
+    /// The provider without its API key, nor the reference to it, which only
+    /// makes sense in this device.
+    pub fn without_api_key(&self) -> Self {
+        Self {
+            api_key: None,
+            api_key_secret: None,
+            ..self.clone()
+        }
+    }
+
     /// Generate a stable ID from URL and name for migration
     pub fn generate_id_from_url_and_name(
         url: &str,
@@ -271,6 +477,7 @@ impl ProviderPreferences {
             "https://openrouter.ai/api/v1" => "openrouter".to_string(),
             "http://localhost:8765/api/v1" => "molyserver".to_string(),
             "https://api.deepseek.com/v1" => "deepseek".to_string(),
+            "https://api.anthropic.com/v1" => "anthropic".to_string(),
             _ => {
                 // For custom providers, create ID from name
                 let base = name
@@ -290,3 +497,133 @@ impl ProviderPreferences {
 fn default_model_downloads_dir() -> &'static Path {
     Path::new("model_downloads")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(id: &str, api_key: &str) -> ProviderPreferences {
+        ProviderPreferences {
+            id: id.into(),
+            name: id.into(),
+            url: format!("https://{}.example.com/v1", id),
+            api_key: Some(api_key.into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merged_providers_are_not_duplicated() {
+        let mut existing = vec![provider("openai", "old"), provider("gemini", "mine")];
+        merge_provider_preferences(
+            &mut existing,
+            vec![provider("openai", "new"), provider("deepseek", "theirs")],
+        );
+
+        let keys = existing
+            .iter()
+            .map(|p| (p.id.as_str(), p.api_key.as_deref().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                ("openai", "new"),
+                ("gemini", "mine"),
+                ("deepseek", "theirs")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merged_providers_keep_local_api_keys() {
+        let mut existing = vec![
+            provider("openai", "sk-legacy"),
+            ProviderPreferences {
+                api_key: None,
+                api_key_secret: Some(api_key_secret_name("gemini")),
+                ..provider("gemini", "")
+            },
+        ];
+        let synced = existing
+            .iter()
+            .map(|p| ProviderPreferences {
+                name: "Renamed".into(),
+                ..p.without_api_key()
+            })
+            .collect();
+        merge_provider_preferences(&mut existing, synced);
+
+        assert!(existing.iter().all(|p| p.name == "Renamed"));
+        assert_eq!(existing[0].api_key.as_deref(), Some("sk-legacy"));
+        assert_eq!(
+            existing[1].api_key_secret,
+            Some(api_key_secret_name("gemini"))
+        );
+    }
+
+    #[test]
+    fn test_api_keys_stay_in_plain_text_while_secrets_are_locked() {
+        let openai = Provider {
+            id: "openai".into(),
+            url: "https://api.openai.com/v1".into(),
+            api_key: Some("sk-new".into()),
+            ..Default::default()
+        };
+
+        let mut preferences = Preferences::default();
+        preferences.apply_provider(&openai, true);
+        let stored = &preferences.providers_preferences[0];
+        assert_eq!(stored.api_key.as_deref(), Some("sk-new"));
+        assert!(stored.api_key_secret.is_none());
+
+        preferences.apply_provider(&openai, false);
+        let stored = &preferences.providers_preferences[0];
+        assert!(stored.api_key.is_none());
+        assert_eq!(stored.api_key_secret, Some(api_key_secret_name("openai")));
+    }
+
+    #[test]
+    fn test_openai_compatible_anthropic_providers_are_migrated() {
+        let mut preferences = Preferences::default();
+        preferences.providers_preferences = vec![
+            ProviderPreferences {
+                url: "https://api.anthropic.com/v1/".into(),
+                provider_type: ProviderType::OpenAI,
+                ..provider("my-claude", "sk-ant")
+            },
+            ProviderPreferences {
+                url: "https://openrouter.ai/api/v1/anthropic".into(),
+                provider_type: ProviderType::OpenAI,
+                ..provider("router", "sk-or")
+            },
+        ];
+
+        assert!(preferences.migrate_anthropic_providers());
+        let migrated = &preferences.providers_preferences[0];
+        assert_eq!(migrated.provider_type, ProviderType::Anthropic);
+        assert_eq!(migrated.url, "https://api.anthropic.com/v1");
+        assert_eq!(
+            preferences.providers_preferences[1].provider_type,
+            ProviderType::OpenAI
+        );
+
+        assert!(!preferences.migrate_anthropic_providers());
+    }
+
+    #[test]
+    fn test_api_keys_are_not_shared() {
+        let mut preferences = Preferences::default();
+        preferences.providers_preferences = vec![ProviderPreferences {
+            api_key_secret: Some(api_key_secret_name("openai")),
+            ..provider("openai", "sk-legacy")
+        }];
+
+        let json = preferences.as_json();
+        assert!(!json.contains("sk-legacy"));
+        assert!(!json.contains("provider_api_key"));
+
+        let shared = serde_json::from_str::<Preferences>(&json).unwrap();
+        assert_eq!(shared.providers_preferences[0].id, "openai");
+        assert!(shared.providers_preferences[0].api_key.is_none());
+    }
+}
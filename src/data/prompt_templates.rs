@@ -0,0 +1,119 @@
+//! Reusable system prompts (personas) to start chats from.
+
+use std::collections::HashMap;
+
+use moly_kit::BotId;
+use serde::{Deserialize, Serialize};
+
+use super::chats::chat::ChatInferenceParams;
+
+/// A named system prompt, with `{{variable}}` placeholders filled in when a
+/// chat is started from it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    /// Unique name of the template in the library.
+    pub name: String,
+    pub prompt: String,
+    /// Bot selected for the chats started from this template.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_bot: Option<BotId>,
+    /// Parameters for the chats started from this template, instead of the
+    /// default ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inference_params: Option<ChatInferenceParams>,
+}
+
+impl PromptTemplate {
+    /// Names of the placeholders in the prompt, in order of appearance and
+    /// without duplicates.
+    pub fn variables(&self) -> Vec<String> {
+        let mut variables: Vec<String> = Vec::new();
+        for (_, name) in placeholders(&self.prompt) {
+            if !variables.iter().any(|v| v == name) {
+                variables.push(name.to_string());
+            }
+        }
+        variables
+    }
+
+    /// Fills the placeholders with the given values.
+    ///
+    /// Placeholders without a value are left as they are, so they are visible
+    /// in the resulting prompt.
+    pub fn render(&self, values: &HashMap<String, String>) -> String {
+        let mut rendered = String::with_capacity(self.prompt.len());
+        let mut last = 0;
+
+        for (range, name) in placeholders(&self.prompt) {
+            if let Some(value) = values.get(name) {
+                rendered.push_str(&self.prompt[last..range.start]);
+                rendered.push_str(value);
+                last = range.end;
+            }
+        }
+
+        rendered.push_str(&self.prompt[last..]);
+        rendered
+    }
+}
+
+/// Finds the `{{name}}` placeholders in the text, with their byte range and
+/// trimmed name.
+fn placeholders(text: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+
+    while let Some(start) = text[offset..].find("{{").map(|i| offset + i) {
+        let Some(end) = text[start + 2..].find("}}").map(|i| start + 2 + i) else {
+            break;
+        };
+
+        let name = text[start + 2..end].trim();
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+
+        if valid {
+            found.push((start..end + 2, name));
+            offset = end + 2;
+        } else {
+            offset = start + 2;
+        }
+    }
+
+    found
+}
+
+/// Parses `name: value` lines, as typed by the user to fill the variables.
+pub fn parse_variable_values(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .filter(|(name, value)| !name.is_empty() && !value.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variables_and_render() {
+        let template = PromptTemplate {
+            name: "Translator".into(),
+            prompt: "Translate from {{ source }} to {{target}}. Answer in {{target}}, {{}} stays."
+                .into(),
+            default_bot: None,
+            inference_params: None,
+        };
+
+        assert_eq!(template.variables(), vec!["source", "target"]);
+
+        let values = parse_variable_values("target: French\nsource:\n");
+        assert_eq!(
+            template.render(&values),
+            "Translate from {{ source }} to French. Answer in French, {{}} stays."
+        );
+    }
+}
@@ -163,6 +163,7 @@ pub enum ProviderType {
     OpenAI,
     OpenAIImage,
     OpenAIRealtime,
+    Anthropic,
     MoFa,
     DeepInquire,
     MolyServer,
@@ -174,6 +175,7 @@ impl ProviderType {
             ProviderType::OpenAI => "OpenAI",
             ProviderType::OpenAIImage => "OpenAI (Image Generation)",
             ProviderType::OpenAIRealtime => "OpenAI (Realtime)",
+            ProviderType::Anthropic => "Anthropic",
             ProviderType::MoFa => "MoFa",
             ProviderType::DeepInquire => "DeepInquire",
             ProviderType::MolyServer => "MolyServer",
@@ -0,0 +1,237 @@
+//! Encrypted local storage for values that must never end up in plain text
+//! files like `preferences.json`, such as provider API keys and the answers to
+//! MCP `${input:...}` prompts.
+//!
+//! Secrets are kept together in an encrypted vault file. Its key is kept in the
+//! OS keyring where there is one (Keychain, Windows Credential Manager or the
+//! Secret Service on Linux). Otherwise, the vault is unlocked with a passphrase
+//! given by the user, and secrets can't be read until then. The key is never
+//! written next to the vault.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use moly_kit::utils::asynchronous::spawn;
+
+use crate::shared::utils::filesystem;
+
+const SECRETS_DIR: &str = "secrets";
+const SECRETS_FILENAME: &str = "secrets.json";
+
+#[cfg(not(target_arch = "wasm32"))]
+const KEYRING_SERVICE: &str = "moly";
+#[cfg(not(target_arch = "wasm32"))]
+const KEYRING_USER: &str = "secrets-vault-key";
+
+/// Where the key of the vault comes from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SecretProtection {
+    /// Kept in the OS keyring.
+    Keyring,
+    /// A passphrase given by the user.
+    #[default]
+    Passphrase,
+}
+
+#[derive(Clone, Default)]
+pub struct SecretStore {
+    protection: SecretProtection,
+    /// Not known while locked.
+    key: Option<String>,
+    /// The vault as read while locked, to be opened with the passphrase.
+    locked_vault: Option<String>,
+    secrets: BTreeMap<String, String>,
+}
+
+impl std::fmt::Debug for SecretStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretStore")
+            .field("protection", &self.protection)
+            .field("locked", &self.is_locked())
+            .field("names", &self.secrets.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl SecretStore {
+    /// Reads the stored secrets, with the key from the OS keyring, which is
+    /// created there if there is none.
+    ///
+    /// Without a keyring, the store stays locked until [`Self::unlock`] is
+    /// called. The same happens if the secrets can't be decrypted with the key
+    /// from the keyring (e.g. they were written with a passphrase, or the key
+    /// was lost), so they are never overwritten.
+    pub async fn load() -> Self {
+        let vault = filesystem::global().read_string(&secrets_path()).await.ok();
+
+        let key = keyring_key();
+        let protection = match key {
+            Some(_) => SecretProtection::Keyring,
+            None => SecretProtection::Passphrase,
+        };
+
+        let mut store = Self {
+            protection,
+            key: None,
+            locked_vault: vault,
+            secrets: BTreeMap::new(),
+        };
+
+        if let Some(key) = key {
+            if let Err(e) = store.open(key) {
+                log::warn!("Secrets can't be opened with the OS keyring key: {}", e);
+                store.protection = SecretProtection::Passphrase;
+            }
+        }
+
+        store
+    }
+
+    pub fn protection(&self) -> SecretProtection {
+        self.protection
+    }
+
+    /// If the passphrase is needed to read the stored secrets. Secrets set in
+    /// the meantime are only kept in memory until then.
+    pub fn is_locked(&self) -> bool {
+        self.key.is_none()
+    }
+
+    /// If there are secrets waiting for the passphrase, or it's chosen now.
+    pub fn has_locked_vault(&self) -> bool {
+        self.locked_vault.is_some()
+    }
+
+    /// Unlocks the stored secrets with the passphrase, which is chosen now if
+    /// there are none yet.
+    ///
+    /// If the store is already unlocked, the passphrase protects it from now on
+    /// instead of the previous one.
+    pub fn unlock(&mut self, passphrase: &str) -> Result<()> {
+        if passphrase.is_empty() {
+            anyhow::bail!("The passphrase can't be empty");
+        }
+
+        match self.protection {
+            SecretProtection::Keyring => {
+                anyhow::bail!("Secrets are already protected by the OS keyring")
+            }
+            SecretProtection::Passphrase if self.is_locked() => {
+                self.open(passphrase.to_string())
+                    .map_err(|_| anyhow::anyhow!("Wrong passphrase"))?;
+                // Also keeps the secrets set while locked.
+                self.save();
+            }
+            SecretProtection::Passphrase => {
+                self.key = Some(passphrase.to_string());
+                self.save();
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.secrets.get(name).map(|s| s.as_str())
+    }
+
+    pub fn set(&mut self, name: &str, value: String) {
+        if self.get(name) == Some(value.as_str()) {
+            return;
+        }
+
+        self.secrets.insert(name.to_string(), value);
+        self.save();
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        if self.secrets.remove(name).is_some() {
+            self.save();
+        }
+    }
+
+    /// Decrypts the vault read while locked, if any, with the key.
+    fn open(&mut self, key: String) -> Result<()> {
+        if let Some(vault) = &self.locked_vault {
+            let json = moly_sync::decrypt_json(vault, &key)?;
+            let stored: BTreeMap<String, String> = serde_json::from_str(&json)?;
+
+            // The ones set while locked are newer.
+            let unsaved = std::mem::replace(&mut self.secrets, stored);
+            self.secrets.extend(unsaved);
+        }
+
+        self.locked_vault = None;
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn save(&self) {
+        // Without a key, secrets only live for the current session.
+        let Some(key) = self.key.clone() else {
+            return;
+        };
+
+        let secrets = self.secrets.clone();
+        spawn(async move {
+            if let Err(e) = write_vault(key, secrets).await {
+                log::error!("Failed to write secrets file: {:?}", e);
+            }
+        });
+    }
+}
+
+/// The key of the vault kept in the OS keyring, created if there is none yet.
+///
+/// `None` if there is no keyring available, including the in-memory mock
+/// `keyring` falls back to on unsupported targets (e.g. Android), which would
+/// forget the key when the app closes.
+#[cfg(not(target_arch = "wasm32"))]
+fn keyring_key() -> Option<String> {
+    let persistence = keyring::default::default_credential_builder().persistence();
+    if !matches!(
+        persistence,
+        keyring::credential::CredentialPersistence::UntilDelete
+    ) {
+        log::info!("No persistent OS keyring available, secrets need a passphrase");
+        return None;
+    }
+
+    let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+        .inspect_err(|e| log::info!("No OS keyring available: {}", e))
+        .ok()?;
+
+    match entry.get_password() {
+        Ok(key) => Some(key),
+        Err(keyring::Error::NoEntry) => {
+            let key = moly_sync::generate_secret().ok()?;
+            entry
+                .set_password(&key)
+                .inspect_err(|e| log::info!("Failed to keep the key in the OS keyring: {}", e))
+                .ok()?;
+            Some(key)
+        }
+        Err(e) => {
+            log::info!("No OS keyring available, secrets need a passphrase: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn keyring_key() -> Option<String> {
+    None
+}
+
+async fn write_vault(key: String, secrets: BTreeMap<String, String>) -> Result<()> {
+    let json = serde_json::to_string(&secrets)?;
+    let encrypted = moly_sync::encrypt_json(&json, &key)?;
+    filesystem::global()
+        .queue_write_string(secrets_path(), encrypted)
+        .await
+}
+
+fn secrets_path() -> PathBuf {
+    Path::new(SECRETS_DIR).join(SECRETS_FILENAME)
+}
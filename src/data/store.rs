@@ -3,18 +3,31 @@ use crate::data::providers::ProviderID;
 use crate::shared::actions::ChatAction;
 use crate::shared::bot_context::BotContext;
 
-use super::chats::chat::ChatID;
+use super::chats::chat::{ChatID, SyncedChat, SyncedMessage, parse_synced_message_id};
+use super::device_sync::DeviceSync;
 use super::downloads::download::DownloadFileAction;
-use super::mcp_servers::McpServersConfig;
+use super::mcp_servers::{
+    BuiltinToolsConfig, InputConfig, McpServer, McpServersConfig, input_secret_name,
+    oauth_secret_name,
+};
 use super::moly_client::MolyClient;
-use super::preferences::Preferences;
+use super::preferences::{Preferences, ProviderPreferences, api_key_secret_name};
+use super::prompt_templates::PromptTemplate;
 use super::providers::{ProviderFetchModelsResult, ProviderType};
 use super::search::SortCriteria;
+use super::secrets::SecretStore;
 use super::supported_providers;
+use super::tool_audit::ToolAuditLog;
+use super::usage::{PriceTable, UsageTotals};
 use super::{chats::Chats, downloads::Downloads, search::Search};
 use chrono::{DateTime, Utc};
 use makepad_widgets::{Action, ActionDefaultRef, DefaultNone};
-use moly_kit::utils::asynchronous::spawn;
+use moly_kit::utils::asynchronous::{sleep, spawn};
+use moly_sync::{Record, RecordKind, SyncState};
+use serde::{Serialize, de::DeserializeOwned};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
 
 use super::providers::{Provider, ProviderConnectionStatus};
 use moly_kit::mcp::mcp_manager::McpManagerClient;
@@ -26,6 +39,9 @@ use moly_kit::*;
 #[allow(dead_code)]
 const DEFAULT_MOFA_ADDRESS: &str = "http://localhost:8000";
 
+/// How often changes are exchanged with the paired devices.
+const DEVICE_SYNC_INTERVAL: Duration = Duration::from_secs(15);
+
 #[derive(Clone, DefaultNone, Debug)]
 pub enum StoreAction {
     Search(String),
@@ -34,6 +50,19 @@ pub enum StoreAction {
     None,
 }
 
+#[derive(Clone, DefaultNone, Debug)]
+pub enum McpAction {
+    /// Some MCP servers were not loaded because these inputs have no value yet.
+    InputsRequired(Vec<InputConfig>),
+    /// The connection status of an MCP server changed.
+    ServerStatusChanged,
+    /// A tool call was added to the audit log.
+    ToolCallAudited,
+    /// A tool policy was remembered or forgotten.
+    ToolPoliciesChanged,
+    None,
+}
+
 #[derive(Clone, Debug)]
 pub struct FileWithDownloadInfo {
     pub file: File,
@@ -73,19 +102,38 @@ pub struct Store {
     pub downloads: Downloads,
     pub chats: Chats,
     pub preferences: Preferences,
+    pub secrets: SecretStore,
+    pub tool_audit: ToolAuditLog,
+    pub device_sync: DeviceSync,
     pub bot_context: Option<BotContext>,
     moly_client: MolyClient,
     pub provider_syncing_status: ProviderSyncingStatus,
 
     pub provider_icons: Vec<LiveDependency>,
+    pub price_table: PriceTable,
+    /// Usage totals by provider, keyed by what they were computed from.
+    usage_cache: RefCell<Option<(UsageCacheKey, HashMap<ProviderID, UsageTotals>)>>,
+
+    /// MCP inputs the user was already asked for in this session, which are
+    /// not asked for again on reloads until given a value.
+    asked_mcp_inputs: RefCell<HashSet<String>>,
+    /// Revision of each chat when its messages were last observed for the
+    /// paired devices.
+    synced_chat_revisions: HashMap<ChatID, u64>,
 }
 
 const MOLY_SERVER_VERSION_EXTENSION: &str = "/api/v1";
 
+/// Messages revision, providers count and bots count.
+type UsageCacheKey = (u64, usize, usize);
+
 impl Store {
     pub fn load_into_app() {
         spawn(async move {
             let preferences = Preferences::load().await;
+            let secrets = SecretStore::load().await;
+            let tool_audit = ToolAuditLog::load().await;
+            let device_sync = DeviceSync::load().await;
 
             let server_port = std::env::var("MOLY_SERVER_PORT")
                 .ok()
@@ -102,14 +150,26 @@ impl Store {
                 chats,
                 moly_client,
                 preferences,
+                secrets,
+                tool_audit,
+                device_sync,
                 bot_context: None,
                 provider_syncing_status: ProviderSyncingStatus::NotSyncing,
                 provider_icons: vec![],
+                price_table: PriceTable::from_supported_providers(
+                    &supported_providers::load_supported_providers(),
+                ),
+                usage_cache: RefCell::new(None),
+                asked_mcp_inputs: RefCell::new(HashSet::new()),
+                synced_chat_revisions: HashMap::new(),
             };
 
             store.init_current_chat();
             store.sync_with_moly_server();
+            store.link_provider_secrets();
+            store.link_mcp_oauth_secrets();
             store.load_preference_connections();
+            store.start_device_sync();
 
             app_runner().defer(move |app, cx, _| {
                 app.store = Some(store);
@@ -154,12 +214,344 @@ impl Store {
         });
     }
 
+    /// Serves the devices paired to this one, if any, and periodically
+    /// exchanges changes with the paired devices.
+    fn start_device_sync(&mut self) {
+        // Payloads are not stored with the sync state, they must be observed
+        // again before a paired device asks for them.
+        self.reconcile_synced_records();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.device_sync.is_serving_pairings() {
+            let node = self.device_sync.node().clone();
+            let port = node.lock().listen_port;
+            let address = self.device_sync.listen_address();
+            spawn(async move {
+                match moly_sync::start_sync_server(node, address, port).await {
+                    Ok(server) => app_runner().defer(move |app, _, _| {
+                        if let Some(store) = app.store.as_mut() {
+                            store.device_sync.set_server(server);
+                        }
+                    }),
+                    Err(e) => {
+                        ::log::error!("Failed to start the sync server: {}", e);
+                        let error = format!(
+                            "The paired devices can't connect, the sync server failed to start: {}",
+                            e
+                        );
+                        app_runner().defer(move |app, cx, _| {
+                            if let Some(store) = app.store.as_mut() {
+                                store.device_sync.set_server_error(error);
+                                cx.redraw_all();
+                            }
+                        });
+                    }
+                }
+            });
+        }
+
+        spawn(async move {
+            loop {
+                sleep(DEVICE_SYNC_INTERVAL).await;
+                app_runner().defer(|app, cx, _| {
+                    if let Some(store) = app.store.as_mut() {
+                        if store.sync_paired_devices() {
+                            cx.redraw_all();
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Applies the changes received from the paired devices and sends them the
+    /// local ones, for the devices this one connects to.
+    ///
+    /// Returns if the local data changed.
+    pub fn sync_paired_devices(&mut self) -> bool {
+        let changed = self.reconcile_synced_records();
+
+        let node = self.device_sync.node().clone();
+        let peers = node
+            .lock()
+            .pairings
+            .iter()
+            .filter(|p| p.address.is_some())
+            .map(|p| p.device_id.clone())
+            .collect::<Vec<_>>();
+
+        for peer in peers {
+            let node = node.clone();
+            spawn(async move {
+                match moly_sync::sync_with(&node, &peer).await {
+                    Ok(_) => app_runner().defer(|app, cx, _| {
+                        if let Some(store) = app.store.as_mut() {
+                            if store.reconcile_synced_records() {
+                                cx.redraw_all();
+                            }
+                        }
+                    }),
+                    Err(e) => ::log::warn!("Failed to sync with device {}: {}", peer, e),
+                }
+            });
+        }
+
+        changed
+    }
+
+    /// Applies the changes received from the paired devices, then takes note
+    /// of the local changes so they can be sent.
+    ///
+    /// Returns if the local data changed.
+    pub fn reconcile_synced_records(&mut self) -> bool {
+        if !self.device_sync.is_active() {
+            return false;
+        }
+
+        let node = self.device_sync.node().clone();
+        let (changed, observed) = {
+            // Nothing can be received between applying and observing, or it
+            // would be overwritten by the stale local data.
+            let mut state = node.lock();
+            let received = state.take_pending();
+            let changed = !received.is_empty();
+            self.apply_synced_records(received);
+            (changed, self.observe_synced_records(&mut state))
+        };
+
+        if changed || observed > 0 {
+            self.device_sync.save();
+        }
+        changed
+    }
+
+    fn apply_synced_records(&mut self, mut records: Vec<Record>) {
+        let mut providers_changed = false;
+        let mut mcp_servers_changed = false;
+        let mut synced_messages = Vec::new();
+
+        // Chats must exist before their messages are added.
+        records.sort_by_key(|r| r.kind == RecordKind::ChatMessage);
+
+        for Record {
+            kind, id, payload, ..
+        } in records
+        {
+            let applied = match kind {
+                RecordKind::Provider => {
+                    parse_synced::<ProviderPreferences>(payload).map(|provider| {
+                        providers_changed = true;
+                        match provider {
+                            Some(provider) => self.preferences.set_synced_provider(provider),
+                            None => self.remove_provider(&id),
+                        }
+                    })
+                }
+                RecordKind::McpServer => parse_synced::<McpServer>(payload).map(|server| {
+                    mcp_servers_changed = true;
+                    self.preferences.set_synced_mcp_server(&id, server);
+                }),
+                RecordKind::PromptTemplate => {
+                    parse_synced::<PromptTemplate>(payload).map(|template| match template {
+                        Some(template) => self.preferences.save_prompt_template(template),
+                        None => self.preferences.remove_prompt_template(&id),
+                    })
+                }
+                RecordKind::Chat => parse_synced::<SyncedChat>(payload).map(|chat| match chat {
+                    Some(chat) => self.chats.set_synced_chat(chat),
+                    None => match id.parse::<ChatID>() {
+                        Ok(chat_id) if self.chats.get_chat_by_id(chat_id).is_some() => {
+                            self.delete_chat(chat_id)
+                        }
+                        _ => {}
+                    },
+                }),
+                RecordKind::ChatMessage => match parse_synced_message_id(&id) {
+                    Some((chat_id, key)) => parse_synced::<SyncedMessage>(payload).map(|message| {
+                        synced_messages.push((chat_id, key.to_string(), message));
+                    }),
+                    None => {
+                        ::log::error!("Invalid id of a synced message: {}", id);
+                        Ok(())
+                    }
+                },
+            };
+
+            if let Err(e) = applied {
+                ::log::error!("Failed to apply the synced {:?} {}: {}", kind, id, e);
+            }
+        }
+
+        self.chats.set_synced_messages(synced_messages);
+
+        if providers_changed {
+            self.link_provider_secrets();
+            self.bot_context = None;
+            self.load_preference_connections();
+        }
+
+        if mcp_servers_changed {
+            self.link_mcp_oauth_secrets();
+            self.update_mcp_tool_manager();
+        }
+    }
+
+    /// Takes note of the local records, returning how many changed.
+    ///
+    /// Only the messages of the chats saved since they were last observed are
+    /// compared again.
+    fn observe_synced_records(&mut self, state: &mut SyncState) -> usize {
+        let replica = &mut state.replica;
+        let preferences = &self.preferences;
+        let mut changed = 0;
+
+        changed += replica.observe(
+            RecordKind::Provider,
+            preferences
+                .providers_preferences
+                .iter()
+                .map(|p| (p.id.clone(), synced_value(&p.without_api_key()))),
+        );
+        changed += replica.observe(
+            RecordKind::McpServer,
+            preferences
+                .mcp_servers_config
+                .servers
+                .iter()
+                .map(|(id, server)| (id.clone(), synced_value(&server.without_local_settings()))),
+        );
+        changed += replica.observe(
+            RecordKind::PromptTemplate,
+            preferences
+                .prompt_templates
+                .iter()
+                .map(|t| (t.name.clone(), synced_value(t))),
+        );
+        // Empty chats, like the one opened on startup, are not worth syncing,
+        // unless they were synced before all their messages were removed.
+        let chats = self
+            .chats
+            .saved_chats
+            .iter()
+            .map(|c| c.borrow())
+            .filter(|c| {
+                !c.messages.is_empty()
+                    || replica
+                        .get(RecordKind::Chat, &c.id.to_string())
+                        .is_some_and(|r| !r.is_deleted())
+            })
+            .map(|c| (c.id.to_string(), synced_value(&c.to_synced())))
+            .collect::<Vec<_>>();
+        changed += replica.observe(RecordKind::Chat, chats);
+
+        // Incomplete messages would be removed and added again, so the
+        // messages of a chat are observed once none is being written.
+        let mut observed = HashMap::new();
+        let mut messages = Vec::new();
+        for chat in &self.chats.saved_chats {
+            let chat = chat.borrow();
+            let revision = chat.revision();
+            if self.synced_chat_revisions.get(&chat.id) == Some(&revision)
+                || chat.messages.iter().any(|m| m.metadata.is_writing())
+            {
+                continue;
+            }
+
+            observed.insert(chat.id, revision);
+            messages.extend(
+                chat.to_synced_messages()
+                    .into_iter()
+                    .map(|(id, message)| (id, synced_value(&message))),
+            );
+        }
+
+        // The messages of deleted chats are removed too.
+        let chat_ids = self
+            .chats
+            .saved_chats
+            .iter()
+            .map(|c| c.borrow().id)
+            .collect::<HashSet<_>>();
+        changed += replica.observe_where(RecordKind::ChatMessage, messages, |id| {
+            parse_synced_message_id(id).is_some_and(|(chat_id, _)| {
+                observed.contains_key(&chat_id) || !chat_ids.contains(&chat_id)
+            })
+        });
+
+        self.synced_chat_revisions
+            .retain(|id, _| chat_ids.contains(id));
+        self.synced_chat_revisions.extend(observed);
+        changed
+    }
+
     pub fn get_chat_associated_bot(&self, chat_id: ChatID) -> Option<BotId> {
         self.chats
             .get_chat_by_id(chat_id)
             .and_then(|chat| chat.borrow().associated_bot.clone())
     }
 
+    /// Token usage and cost of every chat, grouped by the provider of the bots.
+    pub fn get_usage_by_provider(&self) -> HashMap<ProviderID, UsageTotals> {
+        let mut totals: HashMap<ProviderID, UsageTotals> = HashMap::new();
+
+        for chat in &self.chats.saved_chats {
+            let chat = chat.borrow();
+            // Inactive branches were paid for too.
+            for message in chat.messages.iter().flat_map(Message::with_branches) {
+                let (Some(bot_id), Some(usage)) = (billed_bot(message), &message.metadata.usage)
+                else {
+                    continue;
+                };
+
+                let Some(provider_id) = self.get_usage_provider_id(bot_id) else {
+                    continue;
+                };
+
+                let pricing = self.price_table.get(&provider_id, bot_id.id());
+                totals.entry(provider_id).or_default().add(usage, pricing);
+            }
+        }
+
+        totals
+    }
+
+    /// Token usage and cost of every chat, for the bots of the given provider.
+    ///
+    /// Cheap to call on every draw, the totals are only recomputed after
+    /// messages or providers change.
+    pub fn get_provider_usage(&self, provider_id: &ProviderID) -> UsageTotals {
+        let key = (
+            self.chats.messages_revision(),
+            self.chats.providers.len(),
+            self.chats.available_bots.len(),
+        );
+
+        let mut cache = self.usage_cache.borrow_mut();
+        if !matches!(&*cache, Some((cached_key, _)) if *cached_key == key) {
+            *cache = Some((key, self.get_usage_by_provider()));
+        }
+
+        cache
+            .as_ref()
+            .and_then(|(_, totals)| totals.get(provider_id))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Finds the provider a bot belongs to, even if its models are not loaded
+    /// at the moment (e.g. disabled or unreachable provider).
+    fn get_usage_provider_id(&self, bot_id: &BotId) -> Option<ProviderID> {
+        if let Some(provider) = self.chats.get_bot_provider(bot_id) {
+            return Some(provider.id.clone());
+        }
+
+        self.chats
+            .providers
+            .values()
+            .find(|p| p.url.trim_start_matches('#') == bot_id.provider())
+            .map(|p| p.id.clone())
+    }
+
     /// This function combines the search results information for a given model
     /// with the download information for the files of that model.
     pub fn add_download_info_to_model(&self, model: &Model) -> ModelWithDownloadInfo {
@@ -331,7 +723,7 @@ impl Store {
                     },
                     name: s.name.clone(),
                     url: prefs.url.clone(),
-                    api_key: prefs.api_key.clone(),
+                    api_key: self.provider_api_key(prefs),
                     provider_type: s.provider_type.clone(),
                     connection_status: ProviderConnectionStatus::Disconnected,
                     enabled: prefs.enabled,
@@ -372,7 +764,7 @@ impl Store {
                     id: pp_clone.id.clone(),
                     name: pp_clone.name.clone(),
                     url: pp_clone.url.clone(),
-                    api_key: pp_clone.api_key.clone(),
+                    api_key: self.provider_api_key(&pp_clone),
                     provider_type: pp_clone.provider_type.clone(),
                     connection_status: ProviderConnectionStatus::Disconnected,
                     enabled: pp_clone.enabled,
@@ -400,7 +792,7 @@ impl Store {
             // TODO: If the provider requires an API key, we should fetch only if the API key is set
             .filter(|pp| {
                 pp.enabled
-                    && (pp.api_key.is_some()
+                    && (self.provider_api_key(pp).is_some()
                         || pp.provider_type == ProviderType::MoFa
                         || pp.provider_type == ProviderType::DeepInquire
                         || pp.provider_type == ProviderType::OpenAIRealtime
@@ -434,11 +826,18 @@ impl Store {
     }
 
     pub fn insert_or_update_provider(&mut self, provider: &Provider) {
+        // Keep the API key only in the secret store
+        let secret_name = api_key_secret_name(&provider.id);
+        match &provider.api_key {
+            Some(api_key) => self.secrets.set(&secret_name, api_key.clone()),
+            None => self.secrets.remove(&secret_name),
+        }
         // Update in memory
         self.chats
             .insert_or_update_provider(provider, &mut self.provider_syncing_status);
         // Update in preferences (persist in disk)
-        self.preferences.insert_or_update_provider(provider);
+        self.preferences
+            .insert_or_update_provider(provider, self.secrets.is_locked());
         // Update in MolyKit (to update the API key used by the client, if needed)
         if let Some(_bot_context) = &self.bot_context {
             // Because MolyKit does not currently expose an API to update the clients, we'll remove and recreate the entire bot context
@@ -447,9 +846,164 @@ impl Store {
         }
     }
 
+    /// Sets the group of equivalent bots the given bot belongs to, an empty name
+    /// removes it from its group.
+    pub fn set_bot_equivalence_group(&mut self, bot_id: &BotId, group: &str) {
+        self.preferences.set_bot_equivalence_group(bot_id, group);
+        // Fallbacks are configured when creating the clients.
+        if let Some(_bot_context) = &self.bot_context {
+            self.bot_context = None;
+        }
+    }
+
+    pub fn get_bot_equivalence_group(&self, bot_id: &BotId) -> Option<&str> {
+        self.preferences
+            .bot_equivalence_groups
+            .get(bot_id)
+            .map(|g| g.as_str())
+    }
+
+    /// The other bots in the equivalence group of the given bot, in the order
+    /// their providers were configured.
+    pub fn get_bot_fallbacks(&self, bot_id: &BotId) -> Vec<BotId> {
+        let Some(group) = self.get_bot_equivalence_group(bot_id) else {
+            return Vec::new();
+        };
+
+        let provider_order = |bot: &BotId| {
+            self.preferences
+                .providers_preferences
+                .iter()
+                .position(|p| p.url.trim_start_matches('#') == bot.provider())
+                .unwrap_or(usize::MAX)
+        };
+
+        let mut fallbacks = self
+            .preferences
+            .bot_equivalence_groups
+            .iter()
+            .filter(|(other, other_group)| *other != bot_id && other_group.as_str() == group)
+            .map(|(other, _)| other.clone())
+            .collect::<Vec<_>>();
+        fallbacks.sort_by_key(|bot| (provider_order(bot), bot.as_str().to_string()));
+        fallbacks
+    }
+
     pub fn remove_provider(&mut self, provider_id: &ProviderID) {
         self.chats.remove_provider(provider_id);
         self.preferences.remove_provider(provider_id);
+        self.secrets.remove(&api_key_secret_name(provider_id));
+    }
+
+    /// The API key of the provider, from the secret store or, while it's
+    /// locked, as stored in plain text by older versions.
+    fn provider_api_key(&self, provider: &ProviderPreferences) -> Option<String> {
+        provider.api_key.clone().or_else(|| {
+            provider
+                .api_key_secret
+                .as_deref()
+                .and_then(|name| self.secrets.get(name))
+                .map(|key| key.to_string())
+        })
+    }
+
+    /// Moves the API keys still in plain text in the preferences to the secret
+    /// store, and points each provider to its key there, if any.
+    ///
+    /// Nothing is done while the secret store is locked, so no key is lost.
+    fn link_provider_secrets(&mut self) {
+        if self.secrets.is_locked() {
+            return;
+        }
+
+        let mut changed = false;
+        for provider in &mut self.preferences.providers_preferences {
+            let name = api_key_secret_name(&provider.id);
+            if let Some(api_key) = provider.api_key.take() {
+                self.secrets.set(&name, api_key);
+                changed = true;
+            }
+
+            let secret = self.secrets.get(&name).is_some().then_some(name);
+            if provider.api_key_secret != secret {
+                provider.api_key_secret = secret;
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.preferences.save();
+        }
+    }
+
+    /// Moves the OAuth client secrets still in plain text in the MCP servers
+    /// config to the secret store.
+    ///
+    /// Nothing is done while the secret store is locked, so no secret is lost.
+    fn link_mcp_oauth_secrets(&mut self) {
+        if self.secrets.is_locked() {
+            return;
+        }
+
+        let secrets = self.preferences.mcp_servers_config.take_oauth_secrets();
+        if secrets.is_empty() {
+            return;
+        }
+
+        for (server_id, secret) in secrets {
+            self.secrets.set(&oauth_secret_name(&server_id), secret);
+        }
+        self.preferences.save();
+    }
+
+    /// Unlocks the secret store with the passphrase, or protects it with it
+    /// from now on, see [`SecretStore::unlock`].
+    pub fn unlock_secrets(&mut self, passphrase: &str) -> anyhow::Result<()> {
+        self.secrets.unlock(passphrase)?;
+        self.link_provider_secrets();
+        self.link_mcp_oauth_secrets();
+        // Clients and MCP servers are created again with the secrets.
+        self.bot_context = None;
+        self.load_preference_connections();
+        Ok(())
+    }
+
+    /// The API keys of the providers, by secret name, to be given to a device
+    /// importing the preferences.
+    pub fn shared_api_keys(&self) -> BTreeMap<String, String> {
+        self.preferences
+            .providers_preferences
+            .iter()
+            .filter_map(|p| Some((api_key_secret_name(&p.id), self.provider_api_key(p)?)))
+            .collect()
+    }
+
+    /// Keeps the API keys received along imported preferences, ignoring any
+    /// other secret, or the keys of providers that were not imported.
+    ///
+    /// While the secret store is locked, they are kept in plain text in the
+    /// preferences until it's unlocked, like the ones from older versions.
+    pub fn import_api_keys(&mut self, api_keys: BTreeMap<String, String>) {
+        let locked = self.secrets.is_locked();
+        for (name, api_key) in api_keys {
+            let provider = self
+                .preferences
+                .providers_preferences
+                .iter_mut()
+                .find(|p| api_key_secret_name(&p.id) == name);
+
+            match provider {
+                Some(provider) if locked => provider.api_key = Some(api_key),
+                Some(_) => self.secrets.set(&name, api_key),
+                None => ::log::warn!("Ignoring the imported secret {}", name),
+            }
+        }
+        if locked {
+            self.preferences.save();
+        }
+        self.link_provider_secrets();
+        // Imported MCP servers may come with their OAuth secrets in plain text.
+        self.link_mcp_oauth_secrets();
     }
 
     pub fn get_provider_icon(&self, provider_name: &str) -> Option<LiveDependency> {
@@ -486,13 +1040,70 @@ impl Store {
 
         #[cfg(not(target_arch = "wasm32"))]
         {
-            let mcp_config = self.get_mcp_servers_config().clone();
+            let mcp_config = self.get_mcp_servers_config();
             tool_manager.set_dangerous_mode_enabled(mcp_config.dangerous_mode_enabled);
+            if let Some(limit) = mcp_config.max_concurrent_tool_calls {
+                tool_manager.set_max_concurrent_tool_calls(limit);
+            }
+            self.apply_mcp_tool_settings(&tool_manager);
+            tool_manager.set_status_listener(|_, _| {
+                Cx::post_action(McpAction::ServerStatusChanged);
+            });
             let tool_manager_clone = tool_manager.clone();
 
+            if let Some(provider) = mcp_config.builtin_tools.to_provider() {
+                tool_manager.add_local_provider(provider);
+            }
+
+            // Servers using inputs without a value are left out until the user
+            // gives them, see `set_mcp_input`.
+            let mut missing_inputs: Vec<InputConfig> = Vec::new();
+            let mut servers = Vec::new();
+            for (server_id, server_config) in mcp_config.list_enabled_servers() {
+                let resolved = server_config.resolve_inputs(|input_id| {
+                    self.secrets
+                        .get(&input_secret_name(input_id))
+                        .map(|v| v.to_string())
+                });
+
+                match resolved {
+                    Ok(mut resolved) => {
+                        if let Some(oauth) = resolved
+                            .oauth
+                            .as_mut()
+                            .filter(|oauth| oauth.client_secret.is_empty())
+                        {
+                            oauth.client_secret = self
+                                .secrets
+                                .get(&oauth_secret_name(server_id))
+                                .unwrap_or_default()
+                                .to_string();
+                        }
+                        servers.push((server_id.clone(), resolved));
+                    }
+                    Err(missing) => {
+                        ::log::info!(
+                            "MCP server '{}' is waiting for inputs: {}",
+                            server_id,
+                            missing.join(", ")
+                        );
+                        let mut asked = self.asked_mcp_inputs.borrow_mut();
+                        for input_id in missing {
+                            if asked.insert(input_id.clone()) {
+                                missing_inputs.push(mcp_config.get_input_or_default(&input_id));
+                            }
+                        }
+                    }
+                }
+            }
+
+            if !missing_inputs.is_empty() {
+                Cx::post_action(McpAction::InputsRequired(missing_inputs));
+            }
+
             spawn(async move {
                 // Load MCP servers from configuration
-                for (server_id, server_config) in mcp_config.list_enabled_servers() {
+                for (server_id, server_config) in &servers {
                     if let Some(transport) = server_config.to_transport() {
                         match tool_manager_clone.add_server(server_id, transport).await {
                             Ok(()) => {
@@ -510,13 +1121,55 @@ impl Store {
         tool_manager
     }
 
+    /// Replaces the time limits and policies of the tool manager with the ones
+    /// in the config, so the ones removed from it don't stay around.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn apply_mcp_tool_settings(&self, tool_manager: &McpManagerClient) {
+        tool_manager.clear_tool_timeouts();
+        tool_manager.clear_tool_policies();
+        for (server_id, server_config) in self.get_mcp_servers_config().list_enabled_servers() {
+            tool_manager.set_server_tool_timeout(
+                server_id,
+                server_config.timeout.map(std::time::Duration::from_secs),
+            );
+            for (tool_name, timeout) in &server_config.tool_timeouts {
+                tool_manager.set_tool_timeout(
+                    server_id,
+                    tool_name,
+                    Some(std::time::Duration::from_secs(*timeout)),
+                );
+            }
+
+            tool_manager.set_server_tool_policy(server_id, server_config.tool_policy);
+            for (tool_name, policy) in &server_config.tool_policies {
+                tool_manager.set_tool_policy(server_id, tool_name, Some(*policy));
+            }
+        }
+    }
+
     pub fn update_mcp_servers_from_json(&mut self, json: &str) -> Result<(), serde_json::Error> {
-        self.preferences.update_mcp_servers_from_json(json)?;
+        let mut config = McpServersConfig::from_json(json)?;
+        // Written to the preferences only once they are out of the config.
+        if !self.secrets.is_locked() {
+            for (server_id, secret) in config.take_oauth_secrets() {
+                self.secrets.set(&oauth_secret_name(&server_id), secret);
+            }
+        }
+        self.preferences.set_mcp_servers_config(config);
         self.update_mcp_tool_manager();
 
         Ok(())
     }
 
+    /// The status of the MCP servers loaded by the current tool manager.
+    pub fn mcp_server_statuses(&self) -> HashMap<String, McpServerStatus> {
+        self.bot_context
+            .as_ref()
+            .and_then(|context| context.tool_manager())
+            .map(|tool_manager| tool_manager.server_statuses())
+            .unwrap_or_default()
+    }
+
     pub fn update_mcp_tool_manager(&mut self) {
         let new_tool_manager = self.create_and_load_mcp_tool_manager();
         if let Some(ref mut bot_context_mut) = self.bot_context {
@@ -532,9 +1185,67 @@ impl Store {
         }
     }
 
+    /// Keeps the value of an MCP input in the secret store.
+    ///
+    /// Servers are not reloaded, as the user may be asked for more inputs.
+    pub fn set_mcp_input(&mut self, input_id: &str, value: String) {
+        self.asked_mcp_inputs.borrow_mut().remove(input_id);
+        self.secrets.set(&input_secret_name(input_id), value);
+    }
+
     pub fn set_mcp_servers_dangerous_mode_enabled(&mut self, enabled: bool) {
         self.preferences
             .set_mcp_servers_dangerous_mode_enabled(enabled);
         self.update_mcp_tool_manager();
     }
+
+    pub fn set_mcp_builtin_tools(&mut self, builtin_tools: BuiltinToolsConfig) {
+        self.preferences.set_mcp_builtin_tools(builtin_tools);
+        self.update_mcp_tool_manager();
+    }
+
+    /// Persists a decision about a tool remembered by the user.
+    ///
+    /// The running tool manager already applies it, so it's not reloaded.
+    pub fn set_mcp_tool_policy(&mut self, server_id: &str, tool_name: &str, policy: ToolPolicy) {
+        self.preferences
+            .set_mcp_tool_policy(server_id, tool_name, policy);
+        Cx::post_action(McpAction::ToolPoliciesChanged);
+    }
+
+    /// Forgets the policy of a single tool, which falls back to the one of its
+    /// server, in the config and in the running tool manager.
+    pub fn remove_mcp_tool_policy(&mut self, server_id: &str, tool_name: &str) {
+        self.preferences
+            .remove_mcp_tool_policy(server_id, tool_name);
+        if let Some(tool_manager) = self
+            .bot_context
+            .as_ref()
+            .and_then(|context| context.tool_manager())
+        {
+            tool_manager.set_tool_policy(server_id, tool_name, None);
+        }
+        Cx::post_action(McpAction::ToolPoliciesChanged);
+    }
+}
+
+/// The bot that generated a message, which may be a fallback of the one it was
+/// sent to.
+fn synced_value(value: &impl Serialize) -> serde_json::Value {
+    // Synced records only contain serializable data.
+    serde_json::to_value(value).unwrap()
+}
+
+/// The content of a synced record, or `None` if it was removed.
+fn parse_synced<T: DeserializeOwned>(
+    payload: Option<serde_json::Value>,
+) -> Result<Option<T>, serde_json::Error> {
+    payload.map(serde_json::from_value).transpose()
+}
+
+fn billed_bot(message: &moly_kit::Message) -> Option<&BotId> {
+    match &message.from {
+        EntityId::Bot(bot_id) => Some(message.metadata.answered_by.as_ref().unwrap_or(bot_id)),
+        _ => None,
+    }
 }
@@ -1,6 +1,9 @@
+use std::collections::HashMap;
+
 use serde::Deserialize;
 
 use super::providers::ProviderType;
+use super::usage::ModelPricing;
 
 #[derive(Debug, Deserialize)]
 pub struct SupportedProvidersFile {
@@ -17,6 +20,9 @@ pub struct SupportedProvider {
     pub url: String,
     pub provider_type: ProviderType,
     pub supported_models: Option<Vec<String>>,
+    /// Known prices of the models, keyed by model id.
+    #[serde(default)]
+    pub pricing: HashMap<String, ModelPricing>,
 }
 
 /// Utility to load from the JSON file
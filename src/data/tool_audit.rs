@@ -0,0 +1,294 @@
+//! Append-only record of every tool call handled in the chats, kept so what
+//! agents did on this machine can be reviewed later.
+//!
+//! The log is stored as JSON Lines, one entry per line, which is also the
+//! format it's exported to.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use moly_kit::controllers::chat::{ToolApproval, ToolExecutionRecord};
+use moly_kit::mcp::mcp_manager::parse_namespaced_tool_name;
+use moly_kit::protocol::ToolCallPermissionStatus;
+use moly_kit::utils::asynchronous::spawn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::chats::chat::ChatID;
+use crate::shared::utils::{file_dialog, filesystem};
+
+const AUDIT_DIR: &str = "audit";
+const AUDIT_FILENAME: &str = "tool_calls.jsonl";
+
+/// Who decided whether a tool call could run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolAuditApproval {
+    Manual,
+    DangerousMode,
+    Policy,
+}
+
+impl From<ToolApproval> for ToolAuditApproval {
+    fn from(approval: ToolApproval) -> Self {
+        match approval {
+            ToolApproval::Manual => Self::Manual,
+            ToolApproval::DangerousMode => Self::DangerousMode,
+            ToolApproval::Policy => Self::Policy,
+        }
+    }
+}
+
+impl ToolAuditApproval {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Manual => "manual",
+            Self::DangerousMode => "dangerous mode",
+            Self::Policy => "policy",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ToolAuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub chat_id: ChatID,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bot_id: Option<String>,
+    pub server: String,
+    pub tool: String,
+    pub arguments: Map<String, Value>,
+    /// If the call was allowed to run.
+    pub approved: bool,
+    pub approval: ToolAuditApproval,
+    pub duration_ms: u64,
+    /// Size in bytes of the text given back to the model.
+    pub result_size: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ToolAuditEntry {
+    pub fn from_record(chat_id: ChatID, record: &ToolExecutionRecord) -> Self {
+        let (server, tool) = parse_namespaced_tool_name(&record.tool_call.name)
+            .unwrap_or_else(|_| (String::new(), record.tool_call.name.clone()));
+
+        Self {
+            timestamp: record.started_at,
+            chat_id,
+            bot_id: record.bot_id.as_ref().map(|id| id.as_str().to_string()),
+            server,
+            tool,
+            arguments: record.tool_call.arguments.clone(),
+            approved: record.tool_call.permission_status != ToolCallPermissionStatus::Denied,
+            approval: record.approval.into(),
+            duration_ms: record.duration.as_millis() as u64,
+            result_size: record.result.content.len(),
+            error: record
+                .result
+                .is_error
+                .then(|| record.result.content.clone()),
+        }
+    }
+
+    /// If the entry matches the text, case insensitively, in its server, tool,
+    /// bot or chat id.
+    fn matches_text(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        [
+            self.server.as_str(),
+            self.tool.as_str(),
+            self.bot_id.as_deref().unwrap_or_default(),
+        ]
+        .iter()
+        .any(|field| field.to_lowercase().contains(&text))
+            || self.chat_id.to_string().contains(&text)
+    }
+}
+
+/// Criteria to show or export only some of the entries.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ToolAuditFilter {
+    /// Matched against the server, tool, bot and chat id.
+    pub text: String,
+    pub errors_only: bool,
+    pub denied_only: bool,
+}
+
+impl ToolAuditFilter {
+    pub fn matches(&self, entry: &ToolAuditEntry) -> bool {
+        (self.text.trim().is_empty() || entry.matches_text(self.text.trim()))
+            && (!self.errors_only || entry.error.is_some())
+            && (!self.denied_only || !entry.approved)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ToolAuditLog {
+    entries: Vec<ToolAuditEntry>,
+}
+
+impl ToolAuditLog {
+    /// Reads the stored log. Lines which can't be parsed are skipped.
+    pub async fn load() -> Self {
+        let content = match filesystem::global().read_string(&audit_path()).await {
+            Ok(content) => content,
+            Err(e) => {
+                log::info!("No tool audit log loaded: {}", e);
+                return Self::default();
+            }
+        };
+
+        Self {
+            entries: parse_json_lines(&content),
+        }
+    }
+
+    /// All the entries, from oldest to newest.
+    pub fn entries(&self) -> &[ToolAuditEntry] {
+        &self.entries
+    }
+
+    /// Adds the entry, writing only its line at the end of the stored log.
+    pub fn append(&mut self, entry: ToolAuditEntry) {
+        let line = to_json_lines([&entry]);
+        self.entries.push(entry);
+
+        spawn(async move {
+            if let Err(e) = filesystem::global()
+                .queue_append_string(audit_path(), line)
+                .await
+            {
+                log::error!("Failed to write the tool audit log: {:?}", e);
+            }
+        });
+    }
+
+    pub fn filtered(&self, filter: &ToolAuditFilter) -> Vec<&ToolAuditEntry> {
+        self.entries.iter().filter(|e| filter.matches(e)).collect()
+    }
+
+    /// Asks the user where to save the entries matching the filter, as JSON
+    /// Lines.
+    ///
+    /// Must be called from the UI thread.
+    pub fn export(&self, filter: &ToolAuditFilter) {
+        let content = to_json_lines(self.filtered(filter));
+        let file_name = format!("moly-tool-calls-{}.jsonl", Utc::now().format("%Y-%m-%d"));
+        file_dialog::save_file(&file_name, content.as_bytes());
+    }
+}
+
+fn to_json_lines<'a>(entries: impl IntoIterator<Item = &'a ToolAuditEntry>) -> String {
+    let mut content = String::new();
+    for entry in entries {
+        // Entries only contain serializable data.
+        content.push_str(&serde_json::to_string(entry).unwrap());
+        content.push('\n');
+    }
+    content
+}
+
+fn parse_json_lines(content: &str) -> Vec<ToolAuditEntry> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                log::warn!("Skipping malformed tool audit entry: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn audit_path() -> PathBuf {
+    Path::new(AUDIT_DIR).join(AUDIT_FILENAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moly_kit::protocol::{BotId, ToolCall, ToolResult};
+    use std::time::Duration;
+
+    fn record(name: &str, status: ToolCallPermissionStatus, error: bool) -> ToolExecutionRecord {
+        ToolExecutionRecord {
+            bot_id: Some(BotId::new("gpt-4o", "openai")),
+            tool_call: ToolCall {
+                id: "call-1".into(),
+                name: name.into(),
+                arguments: serde_json::json!({"path": "notes.txt"})
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+                permission_status: status,
+            },
+            approval: ToolApproval::Policy,
+            started_at: Utc::now(),
+            duration: Duration::from_millis(1500),
+            result: ToolResult {
+                tool_call_id: "call-1".into(),
+                content: "hello".into(),
+                is_error: error,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_entries_round_trip_as_json_lines() {
+        let entry = ToolAuditEntry::from_record(
+            42,
+            &record(
+                "filesystem__read_file",
+                ToolCallPermissionStatus::Approved,
+                false,
+            ),
+        );
+
+        assert_eq!(entry.server, "filesystem");
+        assert_eq!(entry.tool, "read_file");
+        assert_eq!(entry.duration_ms, 1500);
+        assert_eq!(entry.result_size, 5);
+        assert!(entry.approved);
+        assert_eq!(entry.error, None);
+
+        let content = to_json_lines([&entry, &entry]);
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.contains(r#""approval":"policy""#));
+
+        let parsed = parse_json_lines(&format!("{}not json\n", content));
+        assert_eq!(parsed, vec![entry.clone(), entry]);
+    }
+
+    #[test]
+    fn test_filter() {
+        let read = ToolAuditEntry::from_record(
+            1,
+            &record(
+                "filesystem__read_file",
+                ToolCallPermissionStatus::Approved,
+                false,
+            ),
+        );
+        let denied = ToolAuditEntry::from_record(
+            2,
+            &record("web__fetch", ToolCallPermissionStatus::Denied, true),
+        );
+
+        let filter = |text: &str, errors_only, denied_only| ToolAuditFilter {
+            text: text.into(),
+            errors_only,
+            denied_only,
+        };
+
+        assert!(filter("", false, false).matches(&read));
+        assert!(filter("FileSystem", false, false).matches(&read));
+        assert!(!filter("FileSystem", false, false).matches(&denied));
+        assert!(filter("openai", false, false).matches(&denied));
+        assert!(!filter("", true, false).matches(&read));
+        assert!(filter("", true, true).matches(&denied));
+    }
+}
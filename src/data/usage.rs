@@ -0,0 +1,141 @@
+//! Token usage and cost accounting for the messages stored in chats.
+
+use std::collections::HashMap;
+
+use moly_kit::protocol::TokenUsage;
+use serde::Deserialize;
+
+use super::providers::ProviderID;
+use super::supported_providers::SupportedProvider;
+
+/// Price of a model, in USD per million tokens.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct ModelPricing {
+    pub input: f64,
+    /// Input tokens read from the prompt cache. Billed as `input` if unknown.
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+    /// Also applies to reasoning tokens, which are billed as output.
+    pub output: f64,
+}
+
+impl ModelPricing {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let cached = usage.cached_prompt_tokens.min(usage.prompt_tokens);
+        let uncached = usage.prompt_tokens - cached;
+
+        (uncached as f64 * self.input
+            + cached as f64 * self.cached_input.unwrap_or(self.input)
+            + usage.completion_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// Prices of the known models, per provider.
+#[derive(Clone, Debug, Default)]
+pub struct PriceTable(HashMap<ProviderID, HashMap<String, ModelPricing>>);
+
+impl PriceTable {
+    pub fn from_supported_providers(providers: &[SupportedProvider]) -> Self {
+        let table = providers
+            .iter()
+            .filter(|p| !p.pricing.is_empty())
+            .map(|p| (p.id.clone(), p.pricing.clone()))
+            .collect();
+
+        Self(table)
+    }
+
+    pub fn get(&self, provider_id: &ProviderID, model_id: &str) -> Option<&ModelPricing> {
+        self.0.get(provider_id)?.get(model_id)
+    }
+}
+
+/// Accumulated usage over a set of messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UsageTotals {
+    pub usage: TokenUsage,
+    /// Cost in USD of the messages with a known price.
+    pub cost: f64,
+    /// Amount of messages with usage but without a known price, which are not
+    /// included in `cost`.
+    pub unpriced_messages: usize,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, usage: &TokenUsage, pricing: Option<&ModelPricing>) {
+        self.usage += *usage;
+        match pricing {
+            Some(pricing) => self.cost += pricing.cost(usage),
+            None => self.unpriced_messages += 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.usage.total_tokens() == 0
+    }
+
+    pub fn to_human_readable(&self) -> String {
+        let mut text = format!("{} tokens (${:.4})", self.usage.total_tokens(), self.cost);
+
+        if self.unpriced_messages > 0 {
+            text.push_str(&format!(
+                ", {} messages without known price",
+                self.unpriced_messages
+            ));
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totals_only_price_known_models() {
+        let pricing = ModelPricing {
+            input: 2.0,
+            cached_input: None,
+            output: 10.0,
+        };
+        let usage = TokenUsage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 500_000,
+            reasoning_tokens: 100_000,
+            ..Default::default()
+        };
+
+        let mut totals = UsageTotals::default();
+        totals.add(&usage, Some(&pricing));
+        totals.add(&usage, None);
+
+        assert_eq!(totals.usage.prompt_tokens, 2_000_000);
+        assert_eq!(totals.usage.reasoning_tokens, 200_000);
+        assert_eq!(totals.cost, 7.0);
+        assert_eq!(totals.unpriced_messages, 1);
+    }
+
+    #[test]
+    fn test_cached_input_is_billed_at_its_own_price() {
+        let usage = TokenUsage {
+            prompt_tokens: 1_000_000,
+            cached_prompt_tokens: 800_000,
+            ..Default::default()
+        };
+
+        let pricing = ModelPricing {
+            input: 2.0,
+            cached_input: Some(0.5),
+            output: 10.0,
+        };
+        assert_eq!(pricing.cost(&usage), 0.8);
+
+        let pricing = ModelPricing {
+            cached_input: None,
+            ..pricing
+        };
+        assert_eq!(pricing.cost(&usage), 2.0);
+    }
+}
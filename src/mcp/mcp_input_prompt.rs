@@ -0,0 +1,235 @@
+use makepad_widgets::*;
+
+use crate::data::{mcp_servers::InputConfig, store::Store};
+
+live_design! {
+    use link::theme::*;
+    use link::shaders::*;
+    use link::widgets::*;
+
+    use crate::shared::styles::*;
+    use crate::shared::widgets::*;
+    use crate::shared::resource_imports::*;
+
+    PromptButton = <MolyButton> {
+        width: Fit,
+        height: Fit,
+        padding: {top: 10, bottom: 10, left: 14, right: 14}
+
+        draw_bg: {
+            instance border_radius: 2.0,
+            border_color_1: #D0D5DD,
+            border_size: 1.2,
+            color: #fff,
+        }
+
+        draw_text:{
+            text_style: <REGULAR_FONT>{font_size: 10},
+            color: #x0
+        }
+    }
+
+    pub McpInputPrompt = {{McpInputPrompt}} {
+        width: Fit
+        height: Fit
+
+        wrapper = <RoundedView> {
+            flow: Down
+            width: 500
+            height: Fit
+            padding: {top: 44, right: 30 bottom: 30 left: 50}
+            spacing: 10
+
+            show_bg: true
+            draw_bg: {
+                color: #fff
+                border_radius: 3
+            }
+
+            <View> {
+                width: Fill,
+                height: Fit,
+                padding: {top: 8, bottom: 20}
+
+                title = <Label> {
+                    text: "MCP server input"
+                    draw_text: {
+                        text_style: <BOLD_FONT>{font_size: 13},
+                        color: #000
+                    }
+                }
+            }
+
+            body = <View> {
+                width: Fill,
+                height: Fit,
+                flow: Down,
+                spacing: 20,
+
+                description = <Label> {
+                    width: Fill
+                    draw_text: {
+                        text_style: <REGULAR_FONT>{font_size: 10},
+                        color: #000
+                        wrap: Word
+                    }
+                }
+
+                value_input = <MolyTextInput> {
+                    width: Fill, height: Fit
+                    empty_text: "Value"
+                    draw_text: {
+                        text_style: <REGULAR_FONT>{font_size: 10},
+                    }
+                }
+
+                hint = <Label> {
+                    width: Fill
+                    text: "The value is stored encrypted on this device."
+                    draw_text: {
+                        text_style: <REGULAR_FONT>{font_size: 9},
+                        color: #667085
+                        wrap: Word
+                    }
+                }
+
+                actions = <View> {
+                    width: Fill, height: Fit
+                    flow: Right,
+                    align: {x: 1.0, y: 0.5}
+                    spacing: 20
+
+                    skip_button = <PromptButton> {
+                        text: "Skip"
+                    }
+
+                    save_button = <PromptButton> {
+                        text: "Save"
+                        draw_bg: {
+                            color: #099250,
+                            border_size: 0.0,
+                        }
+                        draw_text: {
+                            color: #fff
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, DefaultNone)]
+pub enum McpInputPromptAction {
+    None,
+    /// There are no more inputs to ask for.
+    Finished,
+}
+
+/// Asks the user, one at a time, for the values of the `${input:...}`
+/// placeholders used by the configured MCP servers.
+#[derive(Live, LiveHook, Widget)]
+pub struct McpInputPrompt {
+    #[deref]
+    view: View,
+
+    #[rust]
+    inputs: Vec<InputConfig>,
+
+    /// If any value was given, so the servers must be reloaded when finished.
+    #[rust]
+    answered: bool,
+}
+
+impl Widget for McpInputPrompt {
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        self.view.handle_event(cx, event, scope);
+        self.widget_match_event(cx, event, scope);
+    }
+
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        self.view
+            .draw_walk(cx, scope, walk.with_abs_pos(DVec2 { x: 0., y: 0. }))
+    }
+}
+
+impl WidgetMatchEvent for McpInputPrompt {
+    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
+        let value_input = self.text_input(ids!(value_input));
+        let submitted = value_input.returned(actions).is_some()
+            || self.button(ids!(save_button)).clicked(actions);
+
+        if submitted {
+            let value = value_input.text();
+            if value.is_empty() {
+                return;
+            }
+
+            if let Some(input) = self.inputs.first() {
+                let store = scope.data.get_mut::<Store>().unwrap();
+                store.set_mcp_input(&input.id, value);
+                self.answered = true;
+            }
+            self.next_input(cx, scope);
+        }
+
+        // Skipped inputs are not asked for again until the app is restarted.
+        if self.button(ids!(skip_button)).clicked(actions) {
+            self.next_input(cx, scope);
+        }
+    }
+}
+
+impl McpInputPrompt {
+    fn next_input(&mut self, cx: &mut Cx, scope: &mut Scope) {
+        if !self.inputs.is_empty() {
+            self.inputs.remove(0);
+        }
+
+        if self.inputs.is_empty() {
+            if std::mem::take(&mut self.answered) {
+                let store = scope.data.get_mut::<Store>().unwrap();
+                store.update_mcp_tool_manager();
+            }
+            cx.action(McpInputPromptAction::Finished);
+        } else {
+            self.show_current_input(cx);
+        }
+    }
+
+    fn show_current_input(&mut self, cx: &mut Cx) {
+        let Some(input) = self.inputs.first() else {
+            return;
+        };
+
+        self.label(ids!(description))
+            .set_text(cx, &input.description);
+
+        let value_input = self.text_input(ids!(value_input));
+        value_input.set_text(cx, "");
+        value_input.set_is_password(cx, input.password);
+        value_input.set_key_focus(cx);
+
+        self.redraw(cx);
+    }
+}
+
+impl McpInputPromptRef {
+    /// Queues inputs to ask for, skipping the ones already queued.
+    pub fn add_inputs(&mut self, cx: &mut Cx, inputs: Vec<InputConfig>) {
+        let Some(mut inner) = self.borrow_mut() else {
+            return;
+        };
+
+        let was_empty = inner.inputs.is_empty();
+        for input in inputs {
+            if !inner.inputs.iter().any(|i| i.id == input.id) {
+                inner.inputs.push(input);
+            }
+        }
+
+        if was_empty {
+            inner.show_current_input(cx);
+        }
+    }
+}
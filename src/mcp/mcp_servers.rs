@@ -1,10 +1,12 @@
-use crate::data::store::Store;
+use crate::data::store::{McpAction, Store};
 use crate::settings::sync_modal::SyncModalAction;
 use makepad_code_editor::code_editor::{CodeEditorAction, KeepCursorInView};
 use makepad_code_editor::decoration::DecorationSet;
 use makepad_code_editor::{CodeDocument, CodeEditor, CodeSession};
 
 use makepad_widgets::*;
+use moly_kit::McpServerStatus;
+use moly_kit::mcp::{BuiltinTool, local_tools::BUILTIN_TOOLS_ID};
 
 use crate::data::mcp_servers::McpServersConfig;
 
@@ -17,6 +19,9 @@ live_design! {
     use crate::shared::styles::*;
     use makepad_code_editor::code_editor::*;
 
+    use crate::mcp::tool_audit_view::ToolAuditView;
+    use crate::mcp::tool_policies_view::ToolPoliciesView;
+
     MolyCodeView = {{MolyCodeView}}{
         editor: <CodeEditor>{
             pad_left_top: vec2(0.0,-0.0)
@@ -211,6 +216,85 @@ Only enable if you trust all configured MCP servers completely."
         }
     }
 
+    BuiltinToolSwitch = <View> {
+        width: Fill, height: Fit
+        spacing: 12
+        align: {x: 0.0, y: 0.5}
+
+        label = <Label> {
+            draw_text: {
+                text_style: <REGULAR_FONT> {font_size: 10}
+                color: #000
+            }
+        }
+
+        switch = <MolySwitch> {
+            animator: {
+                selected = {
+                    default: off
+                }
+            }
+        }
+    }
+
+    BuiltinToolsWrapper = <View> {
+        width: Fill, height: Fit
+        flow: Down, spacing: 8
+
+        <Label> {
+            text: "Built-in tools"
+            draw_text: {
+                text_style: <BOLD_FONT> {font_size: 11}
+                color: #000
+            }
+        }
+
+        <Label> {
+            width: Fill
+            text: "Tools run by Moly itself, without installing a server. The file tools can only access the workspace directory."
+            draw_text: {
+                wrap: Word
+                text_style: <REGULAR_FONT>{font_size: 10}
+                color: #667085
+            }
+        }
+
+        read_file_tool = <BuiltinToolSwitch> { label = { text: "Read files" } }
+        list_directory_tool = <BuiltinToolSwitch> { label = { text: "List directories" } }
+        fetch_url_tool = <BuiltinToolSwitch> { label = { text: "Fetch web pages" } }
+        calculate_tool = <BuiltinToolSwitch> { label = { text: "Calculator" } }
+
+        workspace_input = <MolyTextInput> {
+            width: Fill, height: Fit
+            empty_text: "Workspace directory (press Enter to apply)"
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 10},
+            }
+        }
+    }
+
+    ServerStatusWrapper = <View> {
+        width: Fill, height: Fit
+        flow: Down, spacing: 8
+
+        <Label> {
+            text: "Server status"
+            draw_text: {
+                text_style: <BOLD_FONT> {font_size: 11}
+                color: #000
+            }
+        }
+
+        server_statuses = <Label> {
+            width: Fill
+            draw_text: {
+                wrap: Word
+                text_style: <REGULAR_FONT>{font_size: 10}
+                color: #000
+            }
+        }
+    }
+
     pub McpServers = {{McpServers}} {
         <AdaptiveView> {
             Desktop = {
@@ -224,7 +308,11 @@ Only enable if you trust all configured MCP servers completely."
                     <ToggleMCPWrapper> {}
                     <Instructions> {}
                     <DangerousModeWrapper> {}
+                    <BuiltinToolsWrapper> {}
+                    <ServerStatusWrapper> {}
+                    <ToolPoliciesView> {}
                     <SaveStatus> {}
+                    <ToolAuditView> {}
                 }
             }
             Mobile = {
@@ -245,8 +333,12 @@ Only enable if you trust all configured MCP servers completely."
                         }
                     }
                     <DangerousModeWrapper> {}
+                    <BuiltinToolsWrapper> {}
+                    <ServerStatusWrapper> {}
+                    <ToolPoliciesView> {}
                     <ServersEditor> { width: Fill }
                     <SaveStatus> {}
+                    <ToolAuditView> {}
                 }
             }
         }
@@ -291,6 +383,7 @@ impl Widget for McpServers {
                 store.preferences.get_mcp_servers_dangerous_mode_enabled();
 
             self.set_mcp_servers_config(cx, config);
+            self.update_server_statuses(cx, store);
         }
     }
 
@@ -316,6 +409,74 @@ impl McpServers {
         // Sync the dangerous mode toggle UI to match the config
         self.check_box(ids!(dangerous_mode_switch))
             .set_active(cx, self.mcp_servers_config.dangerous_mode_enabled);
+
+        let builtin_tools = &self.mcp_servers_config.builtin_tools;
+        for tool in BuiltinTool::ALL {
+            self.builtin_tool_switch(tool)
+                .set_active(cx, builtin_tools.is_enabled(tool));
+        }
+        self.text_input(ids!(workspace_input))
+            .set_text(cx, builtin_tools.workspace.as_deref().unwrap_or_default());
+    }
+
+    fn builtin_tool_switch(&self, tool: BuiltinTool) -> CheckBoxRef {
+        let row = match tool {
+            BuiltinTool::ReadFile => self.view(ids!(read_file_tool)),
+            BuiltinTool::ListDirectory => self.view(ids!(list_directory_tool)),
+            BuiltinTool::FetchUrl => self.view(ids!(fetch_url_tool)),
+            BuiltinTool::Calculate => self.view(ids!(calculate_tool)),
+        };
+        row.check_box(ids!(switch))
+    }
+
+    /// Applies a change to the built-in tools, reloading the tool manager.
+    fn update_builtin_tools(&mut self, cx: &mut Cx, scope: &mut Scope) {
+        let display_json = self
+            .mcp_servers_config
+            .to_json()
+            .unwrap_or_else(|_| "{}".to_string());
+        self.widget(ids!(mcp_code_view)).set_text(cx, &display_json);
+
+        let store = scope.data.get_mut::<Store>().unwrap();
+        store.set_mcp_builtin_tools(self.mcp_servers_config.builtin_tools.clone());
+        self.redraw(cx);
+    }
+
+    /// Shows the connection status of each enabled server, one per line.
+    fn update_server_statuses(&mut self, cx: &mut Cx, store: &Store) {
+        let statuses = store.mcp_server_statuses();
+        let config = store.get_mcp_servers_config();
+
+        let text = if !store.preferences.get_mcp_servers_enabled() {
+            "MCP servers are disabled.".to_string()
+        } else {
+            let builtin_tools = config.builtin_tools.to_provider().map(|_| BUILTIN_TOOLS_ID);
+
+            config
+                .list_enabled_servers()
+                .map(|(id, _)| id.as_str())
+                .chain(builtin_tools)
+                .map(|id| {
+                    let status = match statuses.get(id) {
+                        Some(McpServerStatus::Connecting) => "connecting...".to_string(),
+                        Some(McpServerStatus::Ready { tool_count: 1 }) => {
+                            "ready, 1 tool".to_string()
+                        }
+                        Some(McpServerStatus::Ready { tool_count }) => {
+                            format!("ready, {} tools", tool_count)
+                        }
+                        Some(McpServerStatus::Failed { error }) => {
+                            format!("failed, retrying ({})", error)
+                        }
+                        None => "not loaded".to_string(),
+                    };
+                    format!("{}: {}", id, status)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        self.label(ids!(server_statuses)).set_text(cx, &text);
     }
 }
 
@@ -394,12 +555,42 @@ impl WidgetMatchEvent for McpServers {
             self.redraw(cx);
         }
 
+        for tool in BuiltinTool::ALL {
+            if let Some(enabled) = self.builtin_tool_switch(tool).changed(actions) {
+                self.mcp_servers_config
+                    .builtin_tools
+                    .set_enabled(tool, enabled);
+                self.update_builtin_tools(cx, scope);
+            }
+        }
+
+        if let Some((workspace, _)) = self.text_input(ids!(workspace_input)).returned(actions) {
+            let workspace = workspace.trim();
+            self.mcp_servers_config.builtin_tools.workspace =
+                (!workspace.is_empty()).then(|| workspace.to_string());
+            self.update_builtin_tools(cx, scope);
+        }
+
         for action in actions {
             if let SyncModalAction::McpServersUpdated = action.cast() {
                 let store = scope.data.get_mut::<Store>().unwrap();
                 self.set_mcp_servers_config(cx, store.get_mcp_servers_config().clone());
                 self.redraw(cx);
             }
+
+            // Keeps the editor from saving back a policy that was just forgotten,
+            // or dropping one just remembered.
+            if let McpAction::ToolPoliciesChanged = action.cast() {
+                let store = scope.data.get::<Store>().unwrap();
+                self.set_mcp_servers_config(cx, store.get_mcp_servers_config().clone());
+                self.redraw(cx);
+            }
+
+            if let McpAction::ServerStatusChanged = action.cast() {
+                let store = scope.data.get::<Store>().unwrap();
+                self.update_server_statuses(cx, store);
+                self.redraw(cx);
+            }
         }
     }
 }
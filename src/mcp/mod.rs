@@ -1,9 +1,15 @@
+pub mod mcp_input_prompt;
 pub mod mcp_screen;
 pub mod mcp_servers;
+pub mod tool_audit_view;
+pub mod tool_policies_view;
 
 use makepad_widgets::Cx;
 
 pub fn live_design(cx: &mut Cx) {
+    mcp_input_prompt::live_design(cx);
     mcp_screen::live_design(cx);
+    tool_audit_view::live_design(cx);
+    tool_policies_view::live_design(cx);
     mcp_servers::live_design(cx);
 }
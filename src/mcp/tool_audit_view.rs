@@ -0,0 +1,235 @@
+use makepad_widgets::*;
+
+use crate::data::store::{McpAction, Store};
+use crate::data::tool_audit::{ToolAuditEntry, ToolAuditFilter};
+
+live_design! {
+    use link::theme::*;
+    use link::shaders::*;
+    use link::widgets::*;
+
+    use crate::shared::styles::*;
+    use crate::shared::widgets::*;
+
+    FilterSwitch = <View> {
+        width: Fit, height: Fit
+        spacing: 8
+        align: {x: 0.0, y: 0.5}
+
+        label = <Label> {
+            draw_text: {
+                text_style: <REGULAR_FONT> {font_size: 10}
+                color: #000
+            }
+        }
+
+        switch = <MolySwitch> {
+            animator: {
+                selected = {
+                    default: off
+                }
+            }
+        }
+    }
+
+    pub ToolAuditView = {{ToolAuditView}} {
+        width: Fill, height: Fit
+        flow: Down, spacing: 8
+
+        <Label> {
+            text: "Tool call audit log"
+            draw_text: {
+                text_style: <BOLD_FONT> {font_size: 11}
+                color: #000
+            }
+        }
+
+        filter_input = <MolyTextInput> {
+            width: Fill, height: Fit
+            empty_text: "Filter by server, tool, bot or chat"
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 10},
+            }
+        }
+
+        <View> {
+            width: Fill, height: Fit
+            spacing: 20
+            align: {x: 0.0, y: 0.5}
+
+            errors_only = <FilterSwitch> { label = { text: "Errors only" } }
+            denied_only = <FilterSwitch> { label = { text: "Denied only" } }
+
+            <View> { width: Fill, height: 1 }
+
+            export_button = <MolyButton> {
+                width: Fit, height: Fit
+                padding: {top: 8, bottom: 8, left: 12, right: 12}
+                text: "Export as JSON Lines"
+                draw_bg: {
+                    border_radius: 2.0,
+                    border_color_1: #D0D5DD,
+                    border_size: 1.2,
+                    color: #fff,
+                }
+                draw_text: {
+                    text_style: <REGULAR_FONT>{font_size: 10},
+                    color: #000
+                }
+            }
+        }
+
+        summary = <Label> {
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 9}
+                color: #667085
+            }
+        }
+
+        <ScrollYView> {
+            width: Fill, height: 250
+            entries = <Label> {
+                width: Fill
+                draw_text: {
+                    wrap: Word
+                    text_style: <REGULAR_FONT>{font_size: 9}
+                    color: #000
+                }
+            }
+        }
+    }
+}
+
+/// Only the latest entries are listed, exporting includes all of them.
+const MAX_SHOWN_ENTRIES: usize = 200;
+
+/// Arguments are cut to this amount of characters in the list.
+const MAX_SHOWN_ARGUMENTS_CHARS: usize = 200;
+
+/// Lists the tool calls recorded in the audit log, newest first.
+#[derive(Live, LiveHook, Widget)]
+pub struct ToolAuditView {
+    #[deref]
+    view: View,
+
+    #[rust]
+    filter: ToolAuditFilter,
+
+    #[rust]
+    initialized: bool,
+}
+
+impl Widget for ToolAuditView {
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        self.view.handle_event(cx, event, scope);
+        self.widget_match_event(cx, event, scope);
+
+        if !self.initialized {
+            self.initialized = true;
+            let store = scope.data.get::<Store>().unwrap();
+            self.update_entries(cx, store);
+        }
+    }
+
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        self.view.draw_walk(cx, scope, walk)
+    }
+}
+
+impl WidgetMatchEvent for ToolAuditView {
+    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
+        let mut filter_changed = false;
+
+        if let Some(text) = self.text_input(ids!(filter_input)).changed(actions) {
+            self.filter.text = text;
+            filter_changed = true;
+        }
+
+        let errors_only = self.view(ids!(errors_only)).check_box(ids!(switch));
+        if let Some(errors_only) = errors_only.changed(actions) {
+            self.filter.errors_only = errors_only;
+            filter_changed = true;
+        }
+
+        let denied_only = self.view(ids!(denied_only)).check_box(ids!(switch));
+        if let Some(denied_only) = denied_only.changed(actions) {
+            self.filter.denied_only = denied_only;
+            filter_changed = true;
+        }
+
+        let store = scope.data.get::<Store>().unwrap();
+
+        if self.button(ids!(export_button)).clicked(actions) {
+            store.tool_audit.export(&self.filter);
+        }
+
+        let audited = actions
+            .iter()
+            .any(|action| matches!(action.cast(), McpAction::ToolCallAudited));
+
+        if filter_changed || audited {
+            self.update_entries(cx, store);
+        }
+    }
+}
+
+impl ToolAuditView {
+    fn update_entries(&mut self, cx: &mut Cx, store: &Store) {
+        let entries = store.tool_audit.filtered(&self.filter);
+
+        let summary = if entries.len() > MAX_SHOWN_ENTRIES {
+            format!(
+                "Showing the latest {} of {} matching calls",
+                MAX_SHOWN_ENTRIES,
+                entries.len()
+            )
+        } else {
+            format!("{} matching calls", entries.len())
+        };
+
+        let text = entries
+            .iter()
+            .rev()
+            .take(MAX_SHOWN_ENTRIES)
+            .map(|entry| describe_entry(entry))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        self.label(ids!(summary)).set_text(cx, &summary);
+        self.label(ids!(entries)).set_text(cx, &text);
+        self.redraw(cx);
+    }
+}
+
+fn describe_entry(entry: &ToolAuditEntry) -> String {
+    let decision = if entry.approved { "approved" } else { "denied" };
+
+    let mut arguments = serde_json::to_string(&entry.arguments).unwrap_or_default();
+    if arguments.chars().count() > MAX_SHOWN_ARGUMENTS_CHARS {
+        arguments = arguments
+            .chars()
+            .take(MAX_SHOWN_ARGUMENTS_CHARS)
+            .chain("...".chars())
+            .collect();
+    }
+
+    let mut text = format!(
+        "{}  {}: {}  {} ({})  {} ms  {} bytes\nchat {}, bot {}\n{}",
+        entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+        entry.server,
+        entry.tool,
+        decision,
+        entry.approval.as_str(),
+        entry.duration_ms,
+        entry.result_size,
+        entry.chat_id,
+        entry.bot_id.as_deref().unwrap_or("unknown"),
+        arguments,
+    );
+
+    if let Some(error) = &entry.error {
+        text.push_str(&format!("\nError: {}", error));
+    }
+
+    text
+}
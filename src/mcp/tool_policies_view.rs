@@ -0,0 +1,164 @@
+use makepad_widgets::*;
+use moly_kit::ToolPolicy;
+
+use crate::data::store::{McpAction, Store};
+
+live_design! {
+    use link::theme::*;
+    use link::shaders::*;
+    use link::widgets::*;
+
+    use crate::shared::styles::*;
+    use crate::shared::widgets::*;
+
+    ToolPolicyRow = <View> {
+        width: Fill, height: Fit
+        padding: {top: 4, bottom: 4}
+        spacing: 12
+        align: {x: 0.0, y: 0.5}
+
+        label = <Label> {
+            width: Fill
+            draw_text: {
+                wrap: Word
+                text_style: <REGULAR_FONT> {font_size: 10}
+                color: #000
+            }
+        }
+
+        forget_button = <MolyButton> {
+            width: Fit, height: Fit
+            padding: {top: 6, bottom: 6, left: 10, right: 10}
+            text: "Forget"
+            draw_bg: {
+                border_radius: 2.0,
+                border_color_1: #D0D5DD,
+                border_size: 1.2,
+                color: #fff,
+            }
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 9},
+                color: #000
+            }
+        }
+    }
+
+    pub ToolPoliciesView = {{ToolPoliciesView}} {
+        width: Fill, height: Fit
+        flow: Down, spacing: 8
+
+        <Label> {
+            text: "Tool permissions"
+            draw_text: {
+                text_style: <BOLD_FONT> {font_size: 11}
+                color: #000
+            }
+        }
+
+        empty_label = <Label> {
+            width: Fill
+            text: "No decisions remembered. Tools follow the policy of their server."
+            draw_text: {
+                wrap: Word
+                text_style: <REGULAR_FONT>{font_size: 10}
+                color: #667085
+            }
+        }
+
+        list = <PortalList> {
+            width: Fill, height: 150
+            policy_row = <ToolPolicyRow> {}
+        }
+    }
+}
+
+/// Lists the policies of single tools, including the decisions remembered from
+/// the chat, and lets the user forget them.
+#[derive(Live, LiveHook, Widget)]
+pub struct ToolPoliciesView {
+    #[deref]
+    view: View,
+
+    /// Server id, tool name and policy of each row, as last drawn.
+    #[rust]
+    policies: Vec<(String, String, ToolPolicy)>,
+}
+
+impl Widget for ToolPoliciesView {
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        self.view.handle_event(cx, event, scope);
+        self.widget_match_event(cx, event, scope);
+    }
+
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let store = scope.data.get::<Store>().unwrap();
+        self.policies = store
+            .get_mcp_servers_config()
+            .servers
+            .iter()
+            .flat_map(|(server_id, server)| {
+                server
+                    .tool_policies
+                    .iter()
+                    .map(|(tool_name, policy)| (server_id.clone(), tool_name.clone(), *policy))
+            })
+            .collect();
+
+        self.label(ids!(empty_label))
+            .set_visible(cx, self.policies.is_empty());
+        self.portal_list(ids!(list))
+            .set_visible(cx, !self.policies.is_empty());
+
+        while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
+            if let Some(mut list) = item.as_portal_list().borrow_mut() {
+                let count = self.policies.len();
+                list.set_item_range(cx, 0, count);
+                while let Some(item_id) = list.next_visible_item(cx) {
+                    if item_id < count {
+                        let (server_id, tool_name, policy) = &self.policies[item_id];
+                        let item = list.item(cx, item_id, live_id!(policy_row));
+                        item.label(ids!(label)).set_text(
+                            cx,
+                            &format!("{}: {}  {}", server_id, tool_name, describe_policy(*policy)),
+                        );
+                        item.draw_all(cx, scope);
+                    }
+                }
+            }
+        }
+        DrawStep::done()
+    }
+}
+
+impl WidgetMatchEvent for ToolPoliciesView {
+    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
+        let forgotten = self
+            .portal_list(ids!(list))
+            .items_with_actions(actions)
+            .iter()
+            .find(|(_, item)| item.button(ids!(forget_button)).clicked(actions))
+            .and_then(|(item_id, _)| self.policies.get(*item_id).cloned());
+
+        if let Some((server_id, tool_name, _)) = forgotten {
+            let store = scope.data.get_mut::<Store>().unwrap();
+            store.remove_mcp_tool_policy(&server_id, &tool_name);
+        }
+
+        let changed = actions
+            .iter()
+            .any(|action| matches!(action.cast(), McpAction::ToolPoliciesChanged));
+
+        if changed {
+            self.redraw(cx);
+        }
+    }
+}
+
+fn describe_policy(policy: ToolPolicy) -> &'static str {
+    match policy {
+        ToolPolicy::Allow => "always allowed",
+        ToolPolicy::Ask => "always asks",
+        ToolPolicy::Deny => "always denied",
+        ToolPolicy::AllowForChat => "allowed once asked in each chat",
+    }
+}
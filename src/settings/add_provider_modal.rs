@@ -240,6 +240,7 @@ live_design! {
                         radio_deepinquire = <CustomProviderRadio> { text: "DeepInquire" }
                         radio_moly_server = <CustomProviderRadio> { text: "MolyServer" }
                         radio_openai_realtime = <CustomProviderRadio> { text: "OpenAI Realtime" }
+                        radio_anthropic = <CustomProviderRadio> { text: "Anthropic" }
                     }
                 }
 
@@ -450,6 +451,23 @@ impl WidgetMatchEvent for AddProviderModal {
                     system_prompt: None,
                     tools_enabled: true,
                 },
+                ProviderType::Anthropic => Provider {
+                    id: provider_id,
+                    name: name.clone(),
+                    url: api_host.clone(),
+                    api_key: if api_key.is_empty() {
+                        None
+                    } else {
+                        Some(api_key.clone())
+                    },
+                    provider_type: ProviderType::Anthropic,
+                    connection_status: ProviderConnectionStatus::Disconnected,
+                    enabled: true,
+                    models: vec![],
+                    was_customly_added: true,
+                    system_prompt: None,
+                    tools_enabled: true,
+                },
             };
 
             store.insert_or_update_provider(&provider);
@@ -464,7 +482,8 @@ impl WidgetMatchEvent for AddProviderModal {
                 radios.radio_mofa,
                 radios.radio_deepinquire,
                 radios.radio_moly_server,
-                radios.radio_openai_realtime
+                radios.radio_openai_realtime,
+                radios.radio_anthropic
             ))
             .selected(cx, actions);
         if let Some(selected) = selected {
@@ -474,6 +493,7 @@ impl WidgetMatchEvent for AddProviderModal {
                 2 => Some(ProviderType::DeepInquire),
                 3 => Some(ProviderType::MolyServer),
                 4 => Some(ProviderType::OpenAIRealtime),
+                5 => Some(ProviderType::Anthropic),
                 _ => Some(ProviderType::OpenAI),
             };
         }
@@ -75,6 +75,15 @@ live_design! {
                 height: Fit, width: Fill
                 align: {x: 1.0, y: 0.5}
                 spacing: 20
+                // Models sharing a group are used as fallbacks of each other.
+                equivalence_group = <MolyTextInput> {
+                    width: 150, height: 30
+                    empty_text: "Fallback group"
+                    draw_text: {
+                        text_style: <REGULAR_FONT>{font_size: 10}
+                        color: #000
+                    }
+                }
                 enabled_switch = <MolySwitch> {
                     // Match the default value to avoid the animation on start.
                     animator: {
@@ -143,6 +152,25 @@ live_design! {
                             }
                         }
                     }
+
+                    usage_row = <View> {
+                        visible: false
+                        width: Fit, height: Fit
+                        spacing: 4
+                        <Label> {
+                            text: "Usage:"
+                            draw_text: {
+                                text_style: <BOLD_FONT>{font_size: 11}
+                                color: #000
+                            }
+                        }
+                        usage = <Label> {
+                            draw_text: {
+                                text_style: {font_size: 11}
+                                color: #000
+                            }
+                        }
+                    }
                 }
 
 
@@ -423,6 +451,12 @@ impl Widget for ProviderView {
 
         self.update_connection_status(cx);
 
+        let usage = store.get_provider_usage(&self.provider.id);
+        self.view(ids!(usage_row))
+            .set_visible(cx, !usage.is_empty());
+        self.label(ids!(usage))
+            .set_text(cx, &usage.to_human_readable());
+
         if self.provider.enabled {
             self.view(ids!(refresh_button)).set_visible(cx, true);
         } else {
@@ -455,6 +489,10 @@ impl Widget for ProviderView {
                             .set_active(cx, model.enabled && self.provider.enabled);
 
                         item.as_model_entry().set_model_name(&model.name);
+
+                        let bot_id = BotId::new(&model.name, &self.provider.url);
+                        let group = store.get_bot_equivalence_group(&bot_id).unwrap_or_default();
+                        item.as_model_entry().set_equivalence_group(cx, group);
                         item.draw_all(cx, scope);
                     }
                 }
@@ -539,6 +577,13 @@ impl WidgetMatchEvent for ProviderView {
                         }
                         self.redraw(cx);
                     }
+                    ModelEntryAction::EquivalenceGroupChanged(model_name, group) => {
+                        store.set_bot_equivalence_group(
+                            &BotId::new(model_name, &self.provider.url),
+                            group,
+                        );
+                        self.redraw(cx);
+                    }
                     _ => {}
                 }
             }
@@ -670,6 +715,7 @@ impl ProviderViewRef {
 
             if provider.provider_type == ProviderType::OpenAIRealtime
                 || provider.provider_type == ProviderType::OpenAI
+                || provider.provider_type == ProviderType::Anthropic
             {
                 inner.view(ids!(tools_form_group)).set_visible(cx, true);
             } else {
@@ -702,6 +748,10 @@ struct ModelEntry {
 
     #[rust]
     model_name: String,
+
+    /// Last saved equivalence group, to avoid overwriting what's being typed.
+    #[rust]
+    equivalence_group: String,
 }
 
 impl Widget for ModelEntry {
@@ -748,6 +798,16 @@ impl WidgetMatchEvent for ModelEntry {
             ));
             self.redraw(cx);
         }
+
+        let equivalence_group = self.text_input(ids!(equivalence_group));
+        if let Some((group, _)) = equivalence_group.returned(actions) {
+            let group = group.trim().to_string();
+            self.equivalence_group = group.clone();
+            cx.action(ModelEntryAction::EquivalenceGroupChanged(
+                self.model_name.clone(),
+                group,
+            ));
+        }
     }
 }
 
@@ -757,10 +817,22 @@ impl ModelEntryRef {
             inner.model_name = name.to_string();
         }
     }
+
+    pub fn set_equivalence_group(&mut self, cx: &mut Cx, group: &str) {
+        if let Some(mut inner) = self.borrow_mut() {
+            if inner.equivalence_group != group {
+                inner.equivalence_group = group.to_string();
+                inner
+                    .text_input(ids!(equivalence_group))
+                    .set_text(cx, group);
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, DefaultNone)]
 enum ModelEntryAction {
     None,
     ModelEnabledChanged(String, bool),
+    EquivalenceGroupChanged(String, String),
 }
@@ -4,6 +4,7 @@ use crate::{
         providers::{Provider, ProviderConnectionStatus},
         store::Store,
     },
+    settings::secrets_vault::SecretsVaultAction,
     settings::sync_modal::{SyncModalAction, SyncModalWidgetExt},
 };
 use makepad_widgets::*;
@@ -19,6 +20,7 @@ live_design! {
     use crate::shared::styles::*;
     use crate::settings::add_provider_modal::*;
     use crate::settings::sync_modal::SyncModal;
+    use crate::settings::secrets_vault::SecretsVault;
     use crate::shared::modal::*;
 
     ICON_EDIT = dep("crate://self/resources/icons/edit.svg")
@@ -207,6 +209,8 @@ live_design! {
             }
         }
 
+        secrets_vault = <SecretsVault> {}
+
         provider_icons: [
             (ICON_OPENAI),
             (ICON_GEMINI),
@@ -366,6 +370,16 @@ impl WidgetMatchEvent for Providers {
                 self.redraw(cx);
             }
 
+            // Show the API key of the selected provider, now that it's known
+            if let SecretsVaultAction::Unlocked = action.cast() {
+                if let Some(provider_id) = self.selected_provider_id.clone() {
+                    if cx.display_context.is_desktop() {
+                        cx.action(ConnectionSettingsAction::ProviderSelected(provider_id));
+                    }
+                }
+                self.redraw(cx);
+            }
+
             // Handle the case where the modal is dismissed by the user clicking outside the modal
             // This is a hacky way to reset the modal state because the inner content never gets to
             // hear if it was dismissed from outside.
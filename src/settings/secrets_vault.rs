@@ -0,0 +1,137 @@
+use makepad_widgets::*;
+
+use crate::data::{secrets::SecretProtection, store::Store};
+
+live_design! {
+    use link::theme::*;
+    use link::shaders::*;
+    use link::widgets::*;
+
+    use crate::shared::styles::*;
+    use crate::shared::widgets::*;
+
+    pub SecretsVault = {{SecretsVault}}<RoundedView> {
+        width: Fill, height: Fit
+        flow: Down, spacing: 10
+        margin: {left: 10, right: 10}
+        padding: 12
+        show_bg: true
+        draw_bg: {
+            color: #FFFAEB
+            border_radius: 4.5
+        }
+
+        message = <Label> {
+            width: Fill
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 10},
+                color: #000
+                wrap: Word
+            }
+        }
+
+        passphrase = <MolyTextInput> {
+            width: Fill, height: Fit
+            is_password: true
+            empty_text: "Passphrase"
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 10},
+            }
+        }
+
+        error_message = <Label> {
+            width: Fill
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 9},
+                color: #B42318
+                wrap: Word
+            }
+        }
+
+        unlock_button = <MolyButton> {
+            width: Fit, height: Fit
+            padding: {top: 8, bottom: 8, left: 14, right: 14}
+            draw_bg: {
+                color: #099250,
+                border_radius: 2.0,
+            }
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 10},
+                color: #fff
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, DefaultNone)]
+pub enum SecretsVaultAction {
+    None,
+    /// The API keys can be read now.
+    Unlocked,
+}
+
+/// Asks for the passphrase of the secret store when there is no OS keyring to
+/// keep its key, to unlock it or to protect it.
+#[derive(Live, LiveHook, Widget)]
+pub struct SecretsVault {
+    #[deref]
+    view: View,
+}
+
+impl Widget for SecretsVault {
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        self.view.handle_event(cx, event, scope);
+        self.widget_match_event(cx, event, scope);
+    }
+
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        let Some(store) = scope.data.get::<Store>() else {
+            return DrawStep::done();
+        };
+
+        let secrets = &store.secrets;
+        let (message, button) = match secrets.protection() {
+            SecretProtection::Keyring => return DrawStep::done(),
+            SecretProtection::Passphrase if !secrets.is_locked() => return DrawStep::done(),
+            SecretProtection::Passphrase if secrets.has_locked_vault() => (
+                "API keys are locked. Enter your passphrase to use them.",
+                "Unlock",
+            ),
+            SecretProtection::Passphrase => (
+                "No OS keyring was found. Choose a passphrase to keep API keys \
+                 encrypted on this device, you will need it every time Moly starts.",
+                "Set passphrase",
+            ),
+        };
+
+        self.label(ids!(message)).set_text(cx, message);
+        self.button(ids!(unlock_button)).set_text(cx, button);
+        self.view.draw_walk(cx, scope, walk)
+    }
+}
+
+impl WidgetMatchEvent for SecretsVault {
+    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
+        let input = self.text_input(ids!(passphrase));
+        let submitted =
+            input.returned(actions).is_some() || self.button(ids!(unlock_button)).clicked(actions);
+
+        if !submitted {
+            return;
+        }
+
+        let store = scope.data.get_mut::<Store>().unwrap();
+        match store.unlock_secrets(&input.text()) {
+            Ok(()) => {
+                input.set_text(cx, "");
+                self.label(ids!(error_message)).set_text(cx, "");
+                cx.action(SecretsVaultAction::Unlocked);
+            }
+            Err(e) => {
+                ::log::warn!("Failed to unlock the secrets: {}", e);
+                self.label(ids!(error_message)).set_text(cx, &e.to_string());
+            }
+        }
+        self.redraw(cx);
+    }
+}
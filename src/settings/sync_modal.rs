@@ -1,13 +1,21 @@
 use std::net::UdpSocket;
 
+use std::collections::BTreeMap;
+
 use anyhow::Error;
 use makepad_widgets::*;
 use moly_kit::utils::asynchronous::spawn;
-use moly_sync::fetch_json;
+use moly_sync::{Pairing, SyncSession, TransferProgress, pair_with};
 
 #[cfg(not(target_arch = "wasm32"))]
-use moly_sync::{ServerHandle, start_server};
+use crate::data::chat_transfer::{ChatFileSource, transfer_manifest};
+#[cfg(not(target_arch = "wasm32"))]
+use moly_sync::start_sync_server;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
 
+use crate::app::app_runner;
+use crate::data::chat_transfer::receive_chats;
 use crate::data::store::Store;
 
 live_design! {
@@ -116,6 +124,20 @@ live_design! {
         align: {x: 0.0, y: 0.5}
         padding: 10
 
+        nearby_view = <FormGroup> {
+            visible: false
+            <ModalLabel> {
+                text: "Nearby devices sharing:"
+            }
+            nearby_list = <PortalList> {
+                width: Fill, height: 100
+                nearby_device = <ShadowButton> {
+                    margin: {bottom: 6}
+                    label = { text: "" }
+                }
+            }
+        }
+
         <FormGroup> {
             <ModalLabel> {
                 text: "Serving sync address:"
@@ -128,12 +150,10 @@ live_design! {
 
         <FormGroup> {
             <ModalLabel> {
-                text: "Access PIN:"
+                text: "Pairing code:"
             }
-            import_pin = <ModalTextInput> {
-                empty_text: "1234"
-                width: Fit
-                // text: "http://192.168.1.4:8080"
+            import_code = <ModalTextInput> {
+                empty_text: "XXXX-XXXX-XXXX"
             }
         }
 
@@ -173,6 +193,52 @@ live_design! {
             }
         }
 
+        <FormGroup> {
+            padding: {top: 8, bottom: 8}
+            keep_in_sync = <Toggle> {
+                text: "Keep this device in sync with the other one"
+                width: Fit, height: Fit
+                draw_text: {
+                    fn get_color(self) -> vec4 {
+                        return #222;
+                    }
+                    text_style: {font_size: 10}
+                }
+
+                label_walk: {
+                    margin: {left: 50}
+                }
+                draw_bg: {
+                    size: 25.
+                }
+
+                padding: {left: 5, right: 5, top: 5, bottom: 5}
+            }
+        }
+
+        <FormGroup> {
+            padding: {top: 8, bottom: 8}
+            include_chats = <Toggle> {
+                text: "Import chats and attachments (existing ones are kept)"
+                width: Fit, height: Fit
+                draw_text: {
+                    fn get_color(self) -> vec4 {
+                        return #222;
+                    }
+                    text_style: {font_size: 10}
+                }
+
+                label_walk: {
+                    margin: {left: 50}
+                }
+                draw_bg: {
+                    size: 25.
+                }
+
+                padding: {left: 5, right: 5, top: 5, bottom: 5}
+            }
+        }
+
         import = <ShadowButton> {
             label = { text: "Import" }
             width: Fill
@@ -196,10 +262,61 @@ live_design! {
         }
         <FormGroup> {
             <ModalLabel> {
-                text: "Access PIN:"
+                text: "Pairing code:"
             }
-            sync_pin = <ModalLabel> {
-                text: "1234"
+            pairing_code = <ModalLabel> {
+                text: "XXXX-XXXX-XXXX"
+            }
+            <Label> {
+                text: "It can be used once, within 10 minutes"
+                draw_text: {
+                    text_style: <REGULAR_FONT>{font_size: 9},
+                    color: #667085
+                }
+            }
+        }
+        <FormGroup> {
+            padding: {top: 8, bottom: 8}
+            share_chats = <Toggle> {
+                text: "Also share chats and attachments"
+                width: Fit, height: Fit
+                draw_text: {
+                    fn get_color(self) -> vec4 {
+                        return #222;
+                    }
+                    text_style: {font_size: 10}
+                }
+
+                label_walk: {
+                    margin: {left: 50}
+                }
+                draw_bg: {
+                    size: 25.
+                }
+
+                padding: {left: 5, right: 5, top: 5, bottom: 5}
+            }
+        }
+        <FormGroup> {
+            padding: {top: 8, bottom: 8}
+            exclude_api_keys = <Toggle> {
+                text: "Leave out API keys"
+                width: Fit, height: Fit
+                draw_text: {
+                    fn get_color(self) -> vec4 {
+                        return #222;
+                    }
+                    text_style: {font_size: 10}
+                }
+
+                label_walk: {
+                    margin: {left: 50}
+                }
+                draw_bg: {
+                    size: 25.
+                }
+
+                padding: {left: 5, right: 5, top: 5, bottom: 5}
             }
         }
         stop_server = <ShadowButton> {
@@ -294,6 +411,45 @@ live_design! {
                             label = { text: "Import from another" }
                         }
                     }
+
+                    listen_view = <FormGroup> {
+                        padding: {left: 10, right: 10}
+                        <ModalLabel> {
+                            text: "Share on address (empty for all interfaces):"
+                        }
+                        listen_address = <ModalTextInput> {
+                            empty_text: "0.0.0.0"
+                        }
+                    }
+                }
+
+                paired_view = <View> {
+                    visible: false
+                    width: Fill, height: Fit
+                    flow: Down
+                    spacing: 10
+                    padding: 10
+
+                    paired_devices = <ModalLabel> {
+                        width: Fill
+                        draw_text: {
+                            wrap: Word
+                        }
+                    }
+
+                    server_error = <ModalLabel> {
+                        visible: false
+                        width: Fill
+                        draw_text: {
+                            wrap: Word
+                            color: #B42318
+                        }
+                    }
+
+                    forget_devices = <ShadowButton> {
+                        label = { text: "Stop syncing with paired devices" }
+                        width: Fill
+                    }
                 }
 
                 import_view = <ImportView> {}
@@ -332,9 +488,18 @@ pub struct SyncModal {
     #[rust]
     sync_status: SyncStatus,
 
-    #[cfg(not(target_arch = "wasm32"))]
     #[rust]
-    server_handle: Option<ServerHandle>,
+    listen_address_loaded: bool,
+
+    #[rust]
+    nearby_devices: Vec<NearbyDevice>,
+}
+
+/// A device found sharing in the local network.
+#[derive(Clone, Debug)]
+struct NearbyDevice {
+    name: String,
+    address: String,
 }
 
 #[derive(Clone, Debug, DefaultNone)]
@@ -342,6 +507,7 @@ pub enum SyncStatus {
     None,
     Serving,
     Importing,
+    TransferringChats,
 }
 
 impl Widget for SyncModal {
@@ -355,13 +521,55 @@ impl Widget for SyncModal {
         #[cfg(target_arch = "wasm32")]
         {
             self.view(ids!(sync_buttons)).set_visible(cx, false);
+            self.view(ids!(listen_view)).set_visible(cx, false);
             self.view(ids!(import_view)).set_visible(cx, true);
             self.label(ids!(hint))
                 .set_text(cx, "Import your settings from another Moly instance");
         }
 
-        self.view
-            .draw_walk(cx, scope, walk.with_abs_pos(DVec2 { x: 0., y: 0. }))
+        if let Some(store) = scope.data.get::<Store>() {
+            if !self.listen_address_loaded {
+                self.listen_address_loaded = true;
+                let address = store.device_sync.node().lock().listen_address;
+                self.text_input(ids!(listen_address))
+                    .set_text(cx, &address.map(|a| a.to_string()).unwrap_or_default());
+            }
+
+            let pairings = store.device_sync.pairings();
+            let names = pairings
+                .iter()
+                .map(|p| p.device_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            self.view(ids!(paired_view))
+                .set_visible(cx, !pairings.is_empty());
+            self.label(ids!(paired_devices))
+                .set_text(cx, &format!("Kept in sync with: {}", names));
+
+            let server_error = store.device_sync.server_error();
+            self.label(ids!(server_error))
+                .set_visible(cx, server_error.is_some());
+            self.label(ids!(server_error))
+                .set_text(cx, server_error.unwrap_or_default());
+        }
+
+        let walk = walk.with_abs_pos(DVec2 { x: 0., y: 0. });
+        while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
+            if let Some(mut list) = item.as_portal_list().borrow_mut() {
+                let count = self.nearby_devices.len();
+                list.set_item_range(cx, 0, count);
+                while let Some(item_id) = list.next_visible_item(cx) {
+                    if item_id < count {
+                        let item = list.item(cx, item_id, live_id!(nearby_device));
+                        item.label(ids!(label))
+                            .set_text(cx, &self.nearby_devices[item_id].name);
+                        item.draw_all(cx, scope);
+                    }
+                }
+            }
+        }
+        DrawStep::done()
     }
 }
 
@@ -383,16 +591,50 @@ impl WidgetMatchEvent for SyncModal {
             self.stop_server(cx);
         }
 
+        if let Some(share_chats) = self.check_box(ids!(share_chats)).changed(actions) {
+            self.set_chats_shared(scope, share_chats);
+        }
+
+        if let Some(exclude) = self.check_box(ids!(exclude_api_keys)).changed(actions) {
+            self.set_api_keys_shared(scope, !exclude);
+        }
+
         if self.view(ids!(show_import)).finger_down(actions).is_some() {
             self.show_import(cx);
+            self.browse_nearby(scope);
+        }
+
+        let picked_device = self
+            .portal_list(ids!(nearby_list))
+            .items_with_actions(actions)
+            .iter()
+            .find(|(_, item)| item.as_view().finger_down(actions).is_some())
+            .and_then(|(item_id, _)| self.nearby_devices.get(*item_id).cloned());
+
+        if let Some(device) = picked_device {
+            self.text_input(ids!(import_view.import_url))
+                .set_text(cx, &device.address);
+            self.text_input(ids!(import_view.import_code))
+                .set_key_focus(cx);
+            self.redraw(cx);
         }
 
         if self.view(ids!(import)).finger_down(actions).is_some() {
             if let SyncStatus::None = self.sync_status {
-                self.import();
+                self.import(cx, scope);
             }
         }
 
+        if self
+            .view(ids!(forget_devices))
+            .finger_down(actions)
+            .is_some()
+        {
+            let store = scope.data.get_mut::<Store>().unwrap();
+            store.device_sync.forget_pairings();
+            self.redraw(cx);
+        }
+
         if let Some(selected_sync_mode) = self
             .radio_button_set(ids_array!(radios.radio_merge, radios.radio_replace))
             .selected(cx, actions)
@@ -411,45 +653,165 @@ impl WidgetMatchEvent for SyncModal {
 
 impl SyncModal {
     #[cfg(not(target_arch = "wasm32"))]
-    fn serve(&mut self, _cx: &mut Cx, scope: &mut Scope) {
-        let json_file = scope.data.get_mut::<Store>().unwrap().preferences.as_json();
+    fn serve(&mut self, cx: &mut Cx, scope: &mut Scope) {
+        let listen_address = self.text_input(ids!(listen_address)).text();
+        let listen_address = match listen_address.trim() {
+            "" => None,
+            address => match address.parse::<std::net::IpAddr>() {
+                Ok(address) => Some(address),
+                Err(_) => {
+                    let error = anyhow::anyhow!("invalid address {}", address);
+                    self.show_serve_error(cx, error);
+                    return;
+                }
+            },
+        };
+
+        let store = scope.data.get_mut::<Store>().unwrap();
+        let json_file = store.preferences.as_json();
+        store.device_sync.set_listen_address(listen_address);
+        let address = store.device_sync.listen_address();
+
+        // Already running to serve the paired devices.
+        if let Some(server) = store
+            .device_sync
+            .server()
+            .filter(|server| server.addr.ip() == address)
+        {
+            let addr = server.addr;
+            match server.share(json_file) {
+                Ok(code) => {
+                    self.show_serving(cx, addr, &code);
+                    store.reconcile_synced_records();
+                    let share_chats = self.check_box(ids!(share_chats)).active(cx);
+                    self.set_chats_shared(scope, share_chats);
+                    let exclude_api_keys = self.check_box(ids!(exclude_api_keys)).active(cx);
+                    self.set_api_keys_shared(scope, !exclude_api_keys);
+                }
+                Err(e) => self.show_serve_error(cx, e),
+            }
+            return;
+        }
+
+        let node = store.device_sync.node().clone();
+        let port = node.lock().listen_port;
 
         let ui = self.ui_runner();
         spawn(async move {
-            // Start moly-sync server
-            let server_result = start_server(json_file, None).await;
-            match server_result {
-                Ok(server_handle) => {
-                    let addr = server_handle.addr;
-                    ::log::info!("Sync server started at {:?}", addr);
-                    ui.defer_with_redraw(move |me, cx, _| {
-                        me.sync_status = SyncStatus::Serving;
-                        me.label(ids!(sync_pin)).set_text(cx, &server_handle.pin);
-                        me.server_handle = Some(server_handle);
+            // Paired devices know the previous port. Another one still allows
+            // new pairings, and the paired devices are told to pair again.
+            let server_result = match start_sync_server(node.clone(), address, port).await {
+                Err(e) if port.is_some() => {
+                    ::log::warn!("Failed to reuse the sync server port: {}", e);
+                    start_sync_server(node, address, None).await
+                }
+                result => result,
+            };
 
-                        let full_server_url =
-                            format!("http://{}:{}", get_local_ip_address(), addr.port());
-                        me.label(ids!(serving_url)).set_text(cx, &full_server_url);
+            match server_result {
+                Ok(server) => {
+                    ::log::info!("Sync server started at {:?}", server.addr);
+                    ui.defer_with_redraw(move |me, cx, scope| {
+                        let store = scope.data.get_mut::<Store>().unwrap();
+                        let addr = server.addr;
+                        let shared = server.share(json_file);
+                        store.device_sync.set_server(server);
+
+                        match shared {
+                            Ok(code) => {
+                                me.show_serving(cx, addr, &code);
+                                store.reconcile_synced_records();
+                                let share_chats = me.check_box(ids!(share_chats)).active(cx);
+                                me.set_chats_shared(scope, share_chats);
+                                let exclude_api_keys =
+                                    me.check_box(ids!(exclude_api_keys)).active(cx);
+                                me.set_api_keys_shared(scope, !exclude_api_keys);
+                            }
+                            Err(e) => me.show_serve_error(cx, e),
+                        }
                     });
                 }
                 Err(e) => {
                     ::log::error!("Failed to start sync server: {}", e);
                     ui.defer_with_redraw(move |me, cx, _| {
-                        me.sync_status = SyncStatus::None;
-                        me.label(ids!(status_message))
-                            .set_text(cx, &format!("Failed to start server: {}", e));
-                        me.view(ids!(status_view)).set_visible(cx, true);
+                        me.show_serve_error(cx, e);
                     });
                 }
             }
         });
     }
 
+    /// Offers the saved chats to the importing device, or stops offering them.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_chats_shared(&mut self, scope: &mut Scope, shared: bool) {
+        let store = scope.data.get::<Store>().unwrap();
+        let Some(server) = store.device_sync.server() else {
+            return;
+        };
+
+        if shared {
+            let manifest = transfer_manifest(&store.chats);
+            ::log::info!("Sharing {} chats", manifest.chats.len());
+            server.share_chats(manifest, Arc::new(ChatFileSource::default()));
+        } else {
+            server.stop_sharing_chats();
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn set_chats_shared(&mut self, _scope: &mut Scope, _shared: bool) {}
+
+    /// Gives the API keys to the importing device, encrypted for its session
+    /// only, or stops giving them.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set_api_keys_shared(&mut self, scope: &mut Scope, shared: bool) {
+        let store = scope.data.get::<Store>().unwrap();
+        let Some(server) = store.device_sync.server() else {
+            return;
+        };
+
+        if shared {
+            server.share_secrets(store.shared_api_keys());
+        } else {
+            server.stop_sharing_secrets();
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn set_api_keys_shared(&mut self, _scope: &mut Scope, _shared: bool) {}
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn show_serving(&mut self, cx: &mut Cx, addr: std::net::SocketAddr, code: &str) {
+        self.sync_status = SyncStatus::Serving;
+        self.label(ids!(pairing_code)).set_text(cx, code);
+
+        let ip = if addr.ip().is_unspecified() {
+            get_local_ip_address()
+        } else {
+            addr.ip().to_string()
+        };
+        let full_server_url = format!("http://{}:{}", ip, addr.port());
+        self.label(ids!(serving_url)).set_text(cx, &full_server_url);
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
+    fn show_serve_error(&mut self, cx: &mut Cx, error: Error) {
+        self.sync_status = SyncStatus::None;
+        self.label(ids!(status_message))
+            .set_text(cx, &format!("Failed to start server: {}", error));
+        self.view(ids!(status_view)).set_visible(cx, true);
+    }
+
+    /// Stops sharing the settings, the server keeps running for the devices
+    /// already paired.
     fn stop_server(&mut self, cx: &mut Cx) {
-        if let Some(server_handle) = self.server_handle.take() {
-            server_handle.stop();
-            ::log::info!("Sync server stopped");
+        if let SyncStatus::Serving = self.sync_status {
+            app_runner().defer(|app, _, _| {
+                if let Some(store) = app.store.as_mut() {
+                    store.device_sync.stop_sharing();
+                }
+            });
+            ::log::info!("Stopped sharing settings");
         }
         self.sync_status = SyncStatus::None;
         self.view(ids!(export_view)).set_visible(cx, false);
@@ -460,9 +822,45 @@ impl SyncModal {
         ::log::error!("Sync server is not supported on wasm32");
     }
 
+    /// Lists the devices sharing in the local network, so the address doesn't
+    /// have to be typed.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn browse_nearby(&mut self, scope: &mut Scope) {
+        let store = scope.data.get_mut::<Store>().unwrap();
+        let ui = self.ui_runner();
+
+        store.device_sync.browse_nearby(move |peers| {
+            let devices = peers
+                .into_iter()
+                .filter_map(|peer| {
+                    let address = peer.server_addr()?;
+                    Some(NearbyDevice {
+                        name: peer.device_name,
+                        address,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            ui.defer_with_redraw(move |me, cx, _| {
+                me.view(ids!(nearby_view))
+                    .set_visible(cx, !devices.is_empty());
+                me.nearby_devices = devices;
+            });
+        });
+    }
+
     #[cfg(target_arch = "wasm32")]
-    fn stop_server(&mut self, _cx: &mut Cx) {
-        ::log::error!("Sync server is not supported on wasm32");
+    fn browse_nearby(&mut self, _scope: &mut Scope) {}
+
+    fn stop_browsing(&mut self, cx: &mut Cx) {
+        #[cfg(not(target_arch = "wasm32"))]
+        app_runner().defer(|app, _, _| {
+            if let Some(store) = app.store.as_mut() {
+                store.device_sync.stop_browsing();
+            }
+        });
+        self.nearby_devices.clear();
+        self.view(ids!(nearby_view)).set_visible(cx, false);
     }
 
     fn show_export(&mut self, cx: &mut Cx) {
@@ -475,19 +873,72 @@ impl SyncModal {
         self.view(ids!(export_view)).set_visible(cx, false);
     }
 
-    fn import(&mut self) {
+    fn import(&mut self, cx: &mut Cx, scope: &mut Scope) {
         let url = self.text_input(ids!(import_view.import_url)).text();
-        let pin = self.text_input(ids!(import_view.import_pin)).text();
+        let code = self.text_input(ids!(import_view.import_code)).text();
+
+        let node = self.check_box(ids!(keep_in_sync)).active(cx).then(|| {
+            let store = scope.data.get::<Store>().unwrap();
+            store.device_sync.node().clone()
+        });
+
+        let known_chat_ids = self.check_box(ids!(include_chats)).active(cx).then(|| {
+            let store = scope.data.get::<Store>().unwrap();
+            store
+                .chats
+                .saved_chats
+                .iter()
+                .map(|chat| chat.borrow().id.to_string())
+                .collect::<Vec<_>>()
+        });
 
         let ui = self.ui_runner();
         self.sync_status = SyncStatus::Importing;
 
         spawn(async move {
-            match fetch_json(&url, &pin).await {
-                Ok(json) => {
+            let fetched = async {
+                let session = SyncSession::connect(&url, &code).await?;
+                let json = session.fetch_preferences().await?;
+                let api_keys = session.fetch_secrets().await?;
+                anyhow::Ok((session, json, api_keys))
+            }
+            .await;
+
+            match fetched {
+                Ok((session, json, api_keys)) => {
+                    let pairing = match &node {
+                        Some(node) => Some(pair_with(node, &session).await),
+                        None => None,
+                    };
+
+                    let transferring = known_chat_ids.is_some();
                     ui.defer_with_redraw(move |me, cx, scope| {
-                        me.handle_import_success(cx, &json, scope);
+                        me.handle_import_success(cx, &json, api_keys, scope);
+                        if let Some(pairing) = pairing {
+                            me.handle_pairing_result(cx, pairing, scope);
+                        }
+                        if transferring {
+                            me.sync_status = SyncStatus::TransferringChats;
+                        }
                     });
+
+                    if let Some(known_chat_ids) = known_chat_ids {
+                        let result =
+                            receive_chats(session.clone(), known_chat_ids, move |progress| {
+                                ui.defer_with_redraw(move |me, cx, _| {
+                                    me.show_transfer_progress(cx, progress);
+                                });
+                            })
+                            .await;
+
+                        ui.defer_with_redraw(move |me, cx, _| {
+                            me.handle_transfer_result(cx, result);
+                        });
+                    }
+
+                    if let Err(e) = session.close().await {
+                        ::log::warn!("Failed to close the sync session: {}", e);
+                    }
                 }
                 Err(e) => {
                     ui.defer_with_redraw(move |me, cx, _| {
@@ -498,7 +949,13 @@ impl SyncModal {
         });
     }
 
-    fn handle_import_success(&mut self, cx: &mut Cx, json: &str, scope: &mut Scope) {
+    fn handle_import_success(
+        &mut self,
+        cx: &mut Cx,
+        json: &str,
+        api_keys: BTreeMap<String, String>,
+        scope: &mut Scope,
+    ) {
         self.view(ids!(status_view)).set_visible(cx, true);
         self.sync_status = SyncStatus::None;
         let include_mcp_servers = self.check_box(ids!(include_mcp_servers)).active(cx);
@@ -511,6 +968,7 @@ impl SyncModal {
             Ok(_) => {
                 self.label(ids!(status_message))
                     .set_text(cx, "Import successful");
+                store.import_api_keys(api_keys);
                 store.bot_context = None;
                 store.load_preference_connections();
 
@@ -529,6 +987,66 @@ impl SyncModal {
         }
     }
 
+    fn handle_pairing_result(
+        &mut self,
+        cx: &mut Cx,
+        pairing: Result<Pairing, Error>,
+        scope: &mut Scope,
+    ) {
+        let store = scope.data.get_mut::<Store>().unwrap();
+        match pairing {
+            Ok(pairing) => {
+                ::log::info!("Paired with device {}", pairing.device_name);
+                self.label(ids!(status_message)).set_text(
+                    cx,
+                    &format!(
+                        "Import successful, kept in sync with {}",
+                        pairing.device_name
+                    ),
+                );
+                store.sync_paired_devices();
+            }
+            Err(e) => {
+                ::log::error!("Failed to pair: {:?}", e);
+                self.label(ids!(status_message))
+                    .set_text(cx, &format!("Imported, but failed to pair: {}", e));
+            }
+        }
+    }
+
+    fn show_transfer_progress(&mut self, cx: &mut Cx, progress: TransferProgress) {
+        self.label(ids!(status_message)).set_text(
+            cx,
+            &format!(
+                "Transferring chats: {} of {} files ({:.1} MB)",
+                progress.files_done,
+                progress.files_total,
+                progress.bytes_received as f64 / 1_000_000.0
+            ),
+        );
+    }
+
+    fn handle_transfer_result(&mut self, cx: &mut Cx, result: Result<usize, Error>) {
+        self.sync_status = SyncStatus::None;
+        match result {
+            Ok(received) => {
+                ::log::info!("Transferred {} chats", received);
+                self.label(ids!(status_message))
+                    .set_text(cx, &format!("Import successful, {} chats added", received));
+            }
+            Err(e) => {
+                ::log::error!("Failed to transfer chats: {:?}", e);
+                self.label(ids!(status_message)).set_text(
+                    cx,
+                    &format!(
+                        "Failed to transfer chats, share and import again to resume: {}",
+                        e
+                    ),
+                );
+            }
+        }
+    }
+
     fn handle_import_error(&mut self, cx: &mut Cx, error: Error) {
         ::log::error!("Failed to fetch settings: {:?}", error);
         self.view(ids!(status_view)).set_visible(cx, true);
@@ -543,6 +1061,7 @@ impl SyncModal {
         self.label(ids!(status_message)).set_text(cx, "");
         self.sync_status = SyncStatus::None;
         self.stop_server(cx);
+        self.stop_browsing(cx);
     }
 }
 
@@ -12,6 +12,8 @@ pub enum ChatAction {
     Start(BotId),
     // Select a chat from the chat history
     ChatSelected(ChatID),
+    // Scroll to a message of an already selected chat, e.g. from a search result
+    MessageSelected(ChatID, usize),
     None,
 }
 
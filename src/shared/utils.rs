@@ -1,4 +1,5 @@
 pub mod attachments;
+pub mod file_dialog;
 pub mod filesystem;
 pub mod unique;
 
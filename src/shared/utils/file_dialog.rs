@@ -0,0 +1,42 @@
+//! Native dialogs to choose the files to import or where to save exported ones.
+//!
+//! On macOS these dialogs must be opened from the main UI thread, so the
+//! functions here are synchronous and must be called from it on every platform.
+
+use std::path::PathBuf;
+
+/// Asks the user for files with one of the given extensions.
+///
+/// Returns nothing if the dialog is cancelled or not supported on this platform.
+pub fn pick_files(filter_name: &str, extensions: &[&str]) -> Vec<PathBuf> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))] {
+            rfd::FileDialog::new()
+                .add_filter(filter_name, extensions)
+                .pick_files()
+                .unwrap_or_default()
+        } else {
+            let _ = (filter_name, extensions);
+            log::warn!("Picking files is not supported on this platform");
+            Vec::new()
+        }
+    }
+}
+
+/// Asks the user where to save the content, suggesting the given file name.
+pub fn save_file(file_name: &str, content: &[u8]) {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))] {
+            let Some(path) = rfd::FileDialog::new().set_file_name(file_name).save_file() else {
+                return;
+            };
+
+            if let Err(e) = std::fs::write(&path, content) {
+                log::error!("Failed to save {}: {}", path.display(), e);
+            }
+        } else {
+            let _ = content;
+            log::warn!("Saving {} is not supported on this platform", file_name);
+        }
+    }
+}
@@ -27,6 +27,14 @@ pub trait Adapter: Send + Sync + 'static {
         content: &[u8],
     ) -> impl PlatformSendFuture<Output = Result<()>>;
 
+    /// Append some binary content to the end of a file, creating the file and
+    /// any necessary directories if they don't exist.
+    fn append(
+        &mut self,
+        path: &Path,
+        content: &[u8],
+    ) -> impl PlatformSendFuture<Output = Result<()>>;
+
     /// Read a file from the filesystem, returning its content as a byte vector.
     fn read(&mut self, path: &Path) -> impl PlatformSendFuture<Output = Result<Vec<u8>>>;
 
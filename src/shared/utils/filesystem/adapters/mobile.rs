@@ -32,7 +32,7 @@ use std::{
 
 use super::super::adapter::Adapter;
 use anyhow::Result;
-use futures::StreamExt;
+use futures::{AsyncWriteExt, StreamExt};
 
 /// Global storage for mobile data directory path
 static MOBILE_DATA_DIR: LazyLock<Mutex<Option<PathBuf>>> = LazyLock::new(|| Mutex::new(None));
@@ -130,4 +130,17 @@ impl Adapter for MobileAdapter {
         async_fs::write(path, content).await?;
         Ok(())
     }
+
+    async fn append(&mut self, path: &Path, content: &[u8]) -> Result<()> {
+        let path = validate_and_resolve(path);
+        async_fs::create_dir_all(path.parent().unwrap()).await?;
+        let mut file = async_fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(content).await?;
+        file.flush().await?;
+        Ok(())
+    }
 }
@@ -2,7 +2,7 @@ use std::path::Path;
 
 use super::super::adapter::Adapter;
 use anyhow::Result;
-use futures::StreamExt;
+use futures::{AsyncWriteExt, StreamExt};
 
 /// An [Adapter] for `FileSystem` built on top of OPFS for web environments.
 // Note: This uses the `web_fs` crate, which has a similar API to `async_fs`,
@@ -17,7 +17,11 @@ impl Adapter for WebAdapter {
     }
 
     async fn exists(&mut self, path: &Path) -> Result<bool> {
-        let file = web_fs::File::open(path).await?;
+        let file = match web_fs::File::open(path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
         let exists = file.metadata().await.map(|_| true).or_else(|e| {
             if e.kind() == std::io::ErrorKind::NotFound {
                 Ok(false)
@@ -50,4 +54,17 @@ impl Adapter for WebAdapter {
         web_fs::write(path, content).await?;
         Ok(())
     }
+
+    async fn append(&mut self, path: &Path, content: &[u8]) -> Result<()> {
+        web_fs::create_dir_all(path.parent().unwrap()).await?;
+        let mut file = web_fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .write(true)
+            .open(path)
+            .await?;
+        file.write_all(content).await?;
+        file.flush().await?;
+        Ok(())
+    }
 }
@@ -51,6 +51,17 @@ use std::{
     sync::{Arc, LazyLock},
 };
 
+/// How a queued write changes the file.
+#[derive(Clone, Copy, Debug)]
+enum WriteMode {
+    /// Replaces the content of the file.
+    Replace,
+    /// Adds the content to the end of the file.
+    Append,
+}
+
+type WriteRequest = (PathBuf, Vec<u8>, WriteMode, oneshot::Sender<Result<()>>);
+
 /// Filesystem implementation over abstract adapters.
 ///
 /// Its abstract but async nature allows it to be used on restrictive environments
@@ -61,7 +72,7 @@ use std::{
 #[derive(Debug)]
 pub struct FileSystem<A: Adapter> {
     adapter: Arc<futures::lock::Mutex<A>>,
-    write_queue: mpsc::Sender<(PathBuf, Vec<u8>, oneshot::Sender<Result<()>>)>,
+    write_queue: mpsc::Sender<WriteRequest>,
 }
 
 impl<A: Adapter> Clone for FileSystem<A> {
@@ -75,17 +86,20 @@ impl<A: Adapter> Clone for FileSystem<A> {
 
 impl<A: Adapter> FileSystem<A> {
     fn new(adapter: A) -> Self {
-        let (tx, mut rx) = mpsc::channel::<(PathBuf, Vec<u8>, oneshot::Sender<Result<()>>)>(0);
+        let (tx, mut rx) = mpsc::channel::<WriteRequest>(0);
         let adapter = Arc::new(futures::lock::Mutex::new(adapter));
 
         let adapter_clone = Arc::clone(&adapter);
         spawn(async move {
-            while let Some((path, content, response)) = rx.next().await {
+            while let Some((path, content, mode, response)) = rx.next().await {
                 let adapter_clone = Arc::clone(&adapter_clone);
                 let path_clone = path.clone();
                 let write_future = async move {
                     let mut adapter = adapter_clone.lock().await;
-                    adapter.write(&path_clone, &content).await
+                    match mode {
+                        WriteMode::Replace => adapter.write(&path_clone, &content).await,
+                        WriteMode::Append => adapter.append(&path_clone, &content).await,
+                    }
                 };
 
                 match write_future.await {
@@ -137,7 +151,6 @@ impl<A: Adapter> FileSystem<A> {
 
     /// Check existence of a file. Errors if it cannot be determined.
     // TODO: Consider using a `metadata` method instead.
-    #[allow(dead_code)]
     pub async fn exists(&self, path: &Path) -> Result<bool> {
         let mut adapter = self.adapter.lock().await;
         adapter.exists(path).await
@@ -158,9 +171,25 @@ impl<A: Adapter> FileSystem<A> {
     /// Write some bytes content to a given path, creating any necessary directories.
     // TODO: Is adapter responsability to create directories, but it shouldn't.
     pub async fn queue_write(&mut self, path: PathBuf, content: Vec<u8>) -> Result<()> {
+        self.queue(path, content, WriteMode::Replace).await
+    }
+
+    /// Append some bytes content to the end of a file, creating it (and any
+    /// necessary directories) if it doesn't exist.
+    pub async fn queue_append(&mut self, path: PathBuf, content: Vec<u8>) -> Result<()> {
+        self.queue(path, content, WriteMode::Append).await
+    }
+
+    /// Append a string content to the end of a file, creating it (and any
+    /// necessary directories) if it doesn't exist.
+    pub async fn queue_append_string(&mut self, path: PathBuf, content: String) -> Result<()> {
+        self.queue_append(path, content.into_bytes()).await
+    }
+
+    async fn queue(&mut self, path: PathBuf, content: Vec<u8>, mode: WriteMode) -> Result<()> {
         let (tx, rx) = oneshot::channel();
         self.write_queue
-            .send((path, content, tx))
+            .send((path, content, mode, tx))
             .await
             .map_err(|e| anyhow!("Failed to send write request: {:?}", e))?;
 